@@ -0,0 +1,42 @@
+//! Generate the conformance vectors from the current implementation.
+//!
+//! ```text
+//! cargo run --example generate_test_vectors -- test-vectors/conformance
+//! ```
+//!
+//! One `<kind>.json` file is written in the given directory for every kind
+//! of vector (see `tests/vectors/mod.rs` for the format).
+
+extern crate cardano;
+#[macro_use]
+extern crate cbor_event;
+#[macro_use]
+extern crate serde_json;
+
+#[path = "../tests/vectors/mod.rs"]
+mod vectors;
+
+use std::{env, fs, path::PathBuf};
+
+fn main() {
+    let dir = match env::args().nth(1) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!("usage: generate_test_vectors <output directory>");
+            ::std::process::exit(1);
+        }
+    };
+    fs::create_dir_all(&dir).unwrap();
+
+    for (kind, content) in vectors::generate() {
+        let path = dir.join(format!("{}.json", kind));
+        let mut json = serde_json::to_string_pretty(&content).unwrap();
+        json.push('\n');
+        fs::write(&path, json).unwrap();
+        println!(
+            "{}: {} vectors",
+            path.display(),
+            content["vectors"].as_array().unwrap().len()
+        );
+    }
+}
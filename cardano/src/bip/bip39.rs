@@ -52,7 +52,10 @@ use cryptoxide::hmac::Hmac;
 use cryptoxide::pbkdf2::pbkdf2;
 use cryptoxide::sha2::Sha512;
 use std::{error, fmt, ops::Deref, result, str};
-use util::{hex, securemem};
+use util::{
+    hex,
+    securemem::{self, SecureMem},
+};
 
 /// Error regarding BIP39 operations
 #[derive(Debug, PartialEq, Eq)]
@@ -131,7 +134,7 @@ pub type Result<T> = result::Result<T, Error>;
 ///
 /// See module documentation for mode details about how to use
 /// `Entropy`.
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Clone, PartialOrd, Ord)]
 pub enum Entropy {
     Entropy9([u8; 12]),
     Entropy12([u8; 16]),
@@ -297,9 +300,10 @@ impl Entropy {
 
         let mut r = to_validate.to_bytes();
 
-        let entropy_bytes = Vec::from(&r[..t.to_key_size() / 8]);
-        let entropy = Self::new(t, &entropy_bytes[..]);
-        if let Some(h) = r.pop() {
+        let entropy = Self::new(t, &r[..t.to_key_size() / 8]);
+        let checksum = r.pop();
+        securemem::zero(&mut r);
+        if let Some(h) = checksum {
             let h2 = h >> (8 - t.checksum_size_bits());
             let cs = entropy.checksum();
             if cs != h2 {
@@ -310,6 +314,20 @@ impl Entropy {
         Ok(entropy)
     }
 
+    /// export the entropy in hexadecimal. The returned string is not
+    /// wiped on drop, prefer `to_mnemonics` to show it to the user.
+    ///
+    /// ```
+    /// # use cardano::bip::bip39::*;
+    ///
+    /// let entropy = Entropy::Entropy12([0;16]);
+    ///
+    /// assert_eq!(entropy.to_hex(), "00000000000000000000000000000000");
+    /// ```
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.as_ref())
+    }
+
     /// convert the given `Entropy` into a mnemonic phrase.
     ///
     /// # Example
@@ -327,8 +345,9 @@ impl Entropy {
         use util::bits::BitReaderBy11;
 
         let t = self.get_type();
-        let mut combined = Vec::from(self.as_ref());
-        combined.extend(&self.hash()[..]);
+        let mut combined = SecureMem::new(self.as_ref().len() + 32);
+        combined[..self.as_ref().len()].copy_from_slice(self.as_ref());
+        combined[self.as_ref().len()..].copy_from_slice(&self.hash()[..]);
 
         let mut reader = BitReaderBy11::new(&combined);
 
@@ -352,16 +371,23 @@ impl Entropy {
         Mnemonics::from_mnemonics(words).unwrap()
     }
 }
+// never display the entropy, use `to_mnemonics` or `to_hex` to export it
 impl fmt::Display for Entropy {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", hex::encode(self.as_ref()))
+        write!(f, "<Entropy>")
     }
 }
 impl fmt::Debug for Entropy {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", hex::encode(self.as_ref()))
+        write!(f, "Entropy({:?})", self.get_type())
+    }
+}
+impl PartialEq for Entropy {
+    fn eq(&self, other: &Self) -> bool {
+        securemem::constant_time_eq(self.as_ref(), other.as_ref())
     }
 }
+impl Eq for Entropy {}
 impl AsRef<[u8]> for Entropy {
     fn as_ref(&self) -> &[u8] {
         match self {
@@ -401,8 +427,14 @@ pub const SEED_SIZE: usize = 64;
 ///
 /// See the module documentation for more details about how to use it
 /// within the `cardano` library.
-pub struct Seed([u8; SEED_SIZE]);
+pub struct Seed(SecureMem);
 impl Seed {
+    /// export the seed in hexadecimal. The returned string is not wiped
+    /// on drop.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.as_ref())
+    }
+
     /// create a Seed by taking ownership of the given array
     ///
     /// # Example
@@ -415,8 +447,8 @@ impl Seed {
     ///
     /// assert!(seed.as_ref().len() == SEED_SIZE);
     /// ```
-    pub fn from_bytes(buf: [u8; SEED_SIZE]) -> Self {
-        Seed(buf)
+    pub fn from_bytes(mut buf: [u8; SEED_SIZE]) -> Self {
+        Seed(SecureMem::take(&mut buf))
     }
 
     /// create a Seed by copying the given slice into a new array
@@ -442,9 +474,7 @@ impl Seed {
         if buf.len() != SEED_SIZE {
             return Err(Error::InvalidSeedSize(buf.len()));
         }
-        let mut v = [0u8; SEED_SIZE];
-        v[..].clone_from_slice(buf);
-        Ok(Seed::from_bytes(v))
+        Ok(Seed(SecureMem::from_slice(buf)))
     }
 
     /// get the seed from the given [`MnemonicString`] and the given password.
@@ -475,24 +505,26 @@ impl Seed {
         let mut salt = Vec::from("mnemonic".as_bytes());
         salt.extend_from_slice(password);
         let mut mac = Hmac::new(Sha512::new(), mnemonics.0.as_bytes());
-        let mut result = [0; SEED_SIZE];
+        let mut result = SecureMem::new(SEED_SIZE);
         pbkdf2(&mut mac, &salt, 2048, &mut result);
-        Self::from_bytes(result)
+        securemem::zero(&mut salt);
+        Seed(result)
     }
 }
 impl PartialEq for Seed {
     fn eq(&self, other: &Self) -> bool {
-        self.0.as_ref() == other.0.as_ref()
+        self.0 == other.0
     }
 }
 impl fmt::Debug for Seed {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", hex::encode(self.as_ref()))
+        f.debug_tuple("Seed").field(&self.0).finish()
     }
 }
+// never display the seed, use `to_hex` to export it
 impl fmt::Display for Seed {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", hex::encode(self.as_ref()))
+        write!(f, "<Seed>")
     }
 }
 impl AsRef<[u8]> for Seed {
@@ -506,11 +538,6 @@ impl Deref for Seed {
         self.as_ref()
     }
 }
-impl Drop for Seed {
-    fn drop(&mut self) {
-        self.0.copy_from_slice(&[0; SEED_SIZE][..]);
-    }
-}
 
 /// RAII for validated mnemonic words. This guarantee a given mnemonic phrase
 /// has been safely validated against a dictionary.
@@ -989,6 +1016,22 @@ pub mod dictionary {
 mod test {
     use super::*;
     use rand::random;
+    use util::hex;
+    use util::securemem::assert_not_leaked;
+
+    #[test]
+    fn secrets_are_not_formatted() {
+        let secret = [0x9d; 16];
+        let entropy = Entropy::from_slice(&secret).unwrap();
+        assert_not_leaked(
+            &format!("{} {:?} {:#?}", entropy, entropy, entropy),
+            &secret,
+        );
+
+        let secret = [0xc3; SEED_SIZE];
+        let seed = Seed::from_bytes(secret);
+        assert_not_leaked(&format!("{} {:?} {:#?}", seed, seed, seed), &secret);
+    }
 
     extern crate unicode_normalization;
     use self::unicode_normalization::UnicodeNormalization;
@@ -8,11 +8,13 @@ use std::{
 };
 
 use super::super::cbor::hs::util::decode_sum_type;
+use super::super::cbor::{canonical, preserved::Preserved};
 use super::super::config::ProtocolMagic;
 use super::boundary;
 use super::date::BlockDate;
 use super::normal;
 use super::types::{BlockVersion, ChainDifficulty, HeaderHash};
+use super::view::BlockView;
 use crate::tx::TxAux;
 use cbor_event::{self, de::Deserialize, de::Deserializer, se::Serializer};
 use chain_core;
@@ -43,6 +45,14 @@ impl RawBlockHeader {
         let mut de = Deserializer::from(Cursor::new(&self.0));
         de.deserialize_complete()
     }
+    /// decode the header, only accepting its canonical encoding
+    pub fn decode_canonical(&self) -> cbor_event::Result<BlockHeader> {
+        canonical::decode(&self.0)
+    }
+    /// decode the header, keeping the raw data to compute its hash
+    pub fn decode_preserved(&self) -> cbor_event::Result<Preserved<BlockHeader>> {
+        Preserved::decode(self.0.clone())
+    }
     pub fn compute_hash(&self) -> HeaderHash {
         HeaderHash::new(&self.0)
     }
@@ -55,10 +65,16 @@ impl RawBlock {
         let mut de = Deserializer::from(Cursor::new(&self.0));
         de.deserialize_complete()
     }
+    /// decode the block, only accepting its canonical encoding
+    pub fn decode_canonical(&self) -> cbor_event::Result<Block> {
+        canonical::decode(&self.0)
+    }
+    /// borrowed view of the block, without decoding it
+    pub fn view<'a>(&'a self) -> cbor_event::Result<BlockView<'a>> {
+        BlockView::new(&self.0)
+    }
     pub fn to_header(&self) -> cbor_event::Result<RawBlockHeader> {
-        // TODO optimise if possible with the CBOR structure by skipping some prefix and some suffix ...
-        let blk = self.decode()?;
-        Ok(blk.header().to_raw())
+        Ok(self.view()?.header().to_raw())
     }
 }
 
@@ -263,6 +279,21 @@ impl BlockHeader {
             BlockHeader::MainBlockHeader(h) => h.consensus.chain_difficulty,
         }
     }
+
+    pub fn get_protocol_magic(&self) -> ProtocolMagic {
+        match self {
+            BlockHeader::BoundaryBlockHeader(h) => h.protocol_magic,
+            BlockHeader::MainBlockHeader(h) => h.protocol_magic,
+        }
+    }
+}
+
+impl Preserved<BlockHeader> {
+    /// Computes the hash of the block header over the data it was
+    /// decoded from.
+    pub fn compute_hash(&self) -> HeaderHash {
+        HeaderHash::new(self.as_bytes())
+    }
 }
 
 impl fmt::Display for BlockHeader {
@@ -581,7 +612,10 @@ mod test {
         assert_eq!(hex::encode(header_raw), hex::encode(&got_raw[..]));
         let got_hash = header.compute_hash();
         let got_hex = hex::encode(got_hash.as_ref());
-        assert_eq!(hash, got_hex)
+        assert_eq!(hash, got_hex);
+
+        let view = super::super::view::HeaderView::new(header_raw).unwrap();
+        assert_eq!(got_hash, view.compute_hash());
     }
 
     #[test]
@@ -593,13 +627,37 @@ mod test {
     fn check_main_block() {
         check_blockheader_serialization(&MAINBLOCK_HEX[..], MAINBLOCK_HASH);
     }
+
+    #[test]
+    fn non_canonical_header() {
+        let raw = super::RawBlockHeader(MAINBLOCK_HEX.to_vec());
+        raw.decode_canonical().unwrap();
+        let preserved = raw.decode_preserved().unwrap();
+        assert_eq!(preserved.compute_hash(), raw.compute_hash());
+
+        // encode the protocol magic on 2 bytes instead of 1
+        let mut bytes = MAINBLOCK_HEX.to_vec();
+        assert_eq!(bytes[3], 0x00);
+        bytes.insert(3, 0x18);
+        let raw = super::RawBlockHeader(bytes);
+
+        let header = raw.decode().unwrap();
+        assert!(raw.decode_canonical().is_err());
+        assert_eq!(hex::encode(header.compute_hash().as_ref()), MAINBLOCK_HASH);
+
+        let preserved = raw.decode_preserved().unwrap();
+        assert_eq!(preserved.compute_hash(), raw.compute_hash());
+        assert_ne!(preserved.compute_hash(), header.compute_hash());
+    }
 }
 
 #[cfg(test)]
 #[cfg(feature = "with-bench")]
 mod bench {
+    use super::super::view::BlockView;
     use super::{Block, HeaderHash};
     use cbor_event::{self, de::RawCbor};
+    use std::io::Cursor;
     use test;
 
     const BLOCK: &'static [u8] = &[
@@ -699,6 +757,48 @@ mod bench {
         })
     }
 
+    fn decode_block() -> Block {
+        let mut de = cbor_event::de::Deserializer::from(Cursor::new(BLOCK));
+        de.deserialize_complete().unwrap()
+    }
+
+    #[bench]
+    fn decode_block_header_hash(b: &mut test::Bencher) {
+        b.iter(|| {
+            let _: HeaderHash = decode_block().header().compute_hash();
+        })
+    }
+
+    #[bench]
+    fn view_block_header_hash(b: &mut test::Bencher) {
+        b.iter(|| {
+            let _: HeaderHash = BlockView::new(BLOCK).unwrap().header().compute_hash();
+        })
+    }
+
+    #[bench]
+    fn decode_block_txids(b: &mut test::Bencher) {
+        b.iter(|| {
+            let _: Vec<_> = decode_block()
+                .get_transactions()
+                .unwrap()
+                .iter()
+                .map(|txaux| txaux.tx.id())
+                .collect();
+        })
+    }
+
+    #[bench]
+    fn view_block_txids(b: &mut test::Bencher) {
+        b.iter(|| {
+            let _: Vec<_> = BlockView::new(BLOCK)
+                .unwrap()
+                .transactions()
+                .map(|txaux| txaux.unwrap().id())
+                .collect();
+        })
+    }
+
     /*
     #[bench]
     #[ignore]
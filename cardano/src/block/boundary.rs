@@ -68,7 +68,7 @@ pub struct BlockHeader {
     pub previous_header: HeaderHash,
     pub body_proof: BodyProof,
     pub consensus: Consensus,
-    pub extra_data: types::BlockHeaderAttributes,
+    pub extra_data: types::ExtraData,
 }
 impl fmt::Display for BlockHeader {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -85,7 +85,7 @@ impl BlockHeader {
         pb: HeaderHash,
         bp: BodyProof,
         c: Consensus,
-        ed: types::BlockHeaderAttributes,
+        ed: types::ExtraData,
     ) -> Self {
         BlockHeader {
             protocol_magic: pm,
@@ -129,7 +129,7 @@ impl cbor_event::de::Deserialize for BlockHeader {
 pub struct Block {
     pub header: BlockHeader,
     pub body: Body,
-    pub extra: cbor_event::Value,
+    pub extra: types::ExtraData,
 }
 
 impl fmt::Display for Block {
@@ -0,0 +1,293 @@
+//! Block forging
+//!
+//! Build the blocks following the last block of a chain state: the
+//! previous hash, the chain difficulty, the protocol magic and the block
+//! version are taken from the chain state, the body proof and the extra
+//! data proof are computed from the content of the block, and the main
+//! blocks are signed by their slot leader or its heavyweight delegate.
+
+use address::StakeholderId;
+use hash::Blake2b256;
+use hdwallet::XPrv;
+use tx::TxAux;
+
+use super::block::Block;
+use super::boundary;
+use super::chain_state::ChainState;
+use super::normal;
+use super::sign::{MainToSign, ProxySecretKey};
+use super::types::{
+    BlockHeaderAttributes, ChainDifficulty, EpochId, EpochSlotId, ExtraData, HeaderExtraData,
+    SoftwareVersion,
+};
+use super::update;
+
+/// The key signing a main block.
+#[derive(Debug, Clone)]
+pub enum BlockIssuer<'a> {
+    /// the slot leader itself
+    SlotLeader(&'a XPrv),
+    /// the delegate of the slot leader, with the heavyweight delegation
+    /// certificate the slot leader issued to it
+    HeavyDelegate(&'a XPrv, ProxySecretKey),
+}
+
+/// Build a main block step by step.
+pub struct MainBlockBuilder {
+    slot_id: EpochSlotId,
+    txs: Vec<TxAux>,
+    ssc: normal::SscPayload,
+    delegation: Vec<ProxySecretKey>,
+    update: update::UpdatePayload,
+    software_version: SoftwareVersion,
+}
+
+impl MainBlockBuilder {
+    /// An empty block of the given slot.
+    pub fn new(slot_id: EpochSlotId) -> Self {
+        MainBlockBuilder {
+            slot_id,
+            txs: vec![],
+            ssc: normal::SscPayload::CertificatesPayload(normal::VssCertificates::new(vec![])),
+            delegation: vec![],
+            update: update::UpdatePayload {
+                proposal: None,
+                votes: vec![],
+            },
+            software_version: SoftwareVersion::new("cardano-sl", 1).unwrap(),
+        }
+    }
+
+    pub fn add_tx(&mut self, tx: TxAux) {
+        self.txs.push(tx)
+    }
+
+    /// Add a heavyweight delegation certificate.
+    pub fn add_delegation(&mut self, psk: ProxySecretKey) {
+        self.delegation.push(psk)
+    }
+
+    /// Set the SSC payload, which must be allowed at the slot of the
+    /// block. By default the block has an empty certificates payload.
+    pub fn set_ssc(&mut self, ssc: normal::SscPayload) {
+        self.ssc = ssc
+    }
+
+    pub fn set_update(&mut self, update: update::UpdatePayload) {
+        self.update = update
+    }
+
+    /// Set the software version of the issuer, `cardano-sl:1` by default.
+    pub fn set_software_version(&mut self, software_version: SoftwareVersion) {
+        self.software_version = software_version
+    }
+
+    /// Forge the block following the last block of `chain_state`.
+    pub fn make_block(self, chain_state: &ChainState, issuer: &BlockIssuer) -> Block {
+        let pm = chain_state.protocol_magic;
+        let body = normal::Body::new(
+            normal::TxPayload::new(self.txs),
+            self.ssc,
+            normal::DlgPayload(self.delegation),
+            self.update,
+        );
+        let extra = ExtraData::default();
+        let leader_key = match issuer {
+            BlockIssuer::SlotLeader(leader) => leader.public(),
+            BlockIssuer::HeavyDelegate(_, psk) => psk.issuer_pk,
+        };
+        let previous_header = chain_state.last_block.clone();
+        let body_proof = normal::BodyProof::generate_from_body(&body);
+        let chain_difficulty = ChainDifficulty::from(u64::from(chain_state.chain_difficulty) + 1);
+        let extra_data = HeaderExtraData::new(
+            chain_state.update.adopted_version,
+            self.software_version,
+            BlockHeaderAttributes::new(),
+            Blake2b256::new(&cbor!(&extra).unwrap()),
+        );
+        let block_signature = {
+            let to_sign = MainToSign::new(
+                &previous_header,
+                &body_proof,
+                &self.slot_id,
+                &chain_difficulty,
+                &extra_data,
+            );
+            match issuer {
+                BlockIssuer::SlotLeader(leader) => to_sign.sign(pm, leader),
+                BlockIssuer::HeavyDelegate(delegate, psk) => {
+                    to_sign.sign_heavy(pm, psk.clone(), delegate)
+                }
+            }
+        };
+        let header = normal::BlockHeader::new(
+            pm,
+            previous_header,
+            body_proof,
+            normal::Consensus {
+                slot_id: self.slot_id,
+                leader_key,
+                chain_difficulty,
+                block_signature,
+            },
+            extra_data,
+        );
+        Block::MainBlock(normal::Block::new(header, body, extra))
+    }
+}
+
+/// Forge the boundary block of `epoch` following the last block of
+/// `chain_state`, with the given slot leaders.
+pub fn make_boundary_block(
+    chain_state: &ChainState,
+    epoch: EpochId,
+    slot_leaders: Vec<StakeholderId>,
+) -> Block {
+    let body = boundary::Body { slot_leaders };
+    let header = boundary::BlockHeader::new(
+        chain_state.protocol_magic,
+        chain_state.last_block.clone(),
+        boundary::BodyProof(Blake2b256::new(&cbor!(&body).unwrap())),
+        boundary::Consensus {
+            epoch,
+            chain_difficulty: chain_state.chain_difficulty,
+        },
+        ExtraData::default(),
+    );
+    Block::BoundaryBlock(boundary::Block {
+        header,
+        body,
+        extra: ExtraData::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use address;
+    use block::fts::SharedSeed;
+    use block::update::BlockVersionData;
+    use block::verify::Error;
+    use block::HeaderHash;
+    use coin::Coin;
+    use config::{GenesisData, ProtocolMagic};
+    use fee;
+    use hdwallet::Seed;
+    use redeem;
+    use std::collections::BTreeMap;
+    use std::time::SystemTime;
+    use tx::{self, Tx, TxInWitness, TxOut, TxWitness, TxoPointer};
+
+    fn xprv(seed: u8) -> XPrv {
+        XPrv::generate_from_seed(&Seed::from_bytes([seed; 32]))
+    }
+
+    fn stakeholder(seed: u8) -> StakeholderId {
+        StakeholderId::new(&xprv(seed).public())
+    }
+
+    fn redeem_key() -> redeem::PrivateKey {
+        redeem::PrivateKey::generate(&[7; 32]).unwrap()
+    }
+
+    fn genesis_data() -> GenesisData {
+        let mut avvm_distr = BTreeMap::new();
+        avvm_distr.insert(redeem_key().public(), Coin::new(1000).unwrap());
+        GenesisData {
+            genesis_prev: HeaderHash::new(b"genesis"),
+            epoch_stability_depth: 10,
+            vss_min_ttl: 2,
+            vss_max_ttl: 6,
+            start_time: SystemTime::UNIX_EPOCH,
+            protocol_magic: ProtocolMagic::from(633343913),
+            block_version_data: BlockVersionData::mainnet(20_000, fee::LinearFee::default()),
+            avvm_distr,
+            non_avvm_balances: BTreeMap::new(),
+            boot_stakeholders: BTreeMap::new(),
+            fts_seed: SharedSeed::default(),
+        }
+    }
+
+    /// a transaction sending the AVVM utxo to another address
+    fn redeem_tx(genesis: &GenesisData) -> TxAux {
+        let pm = genesis.protocol_magic;
+        let (id, _) = tx::redeem_pubkey_to_txid(&redeem_key().public(), pm);
+        let tx = Tx::new_with(
+            vec![TxoPointer::new(id, 0)],
+            vec![TxOut::new(
+                address::ExtendedAddr::new(
+                    address::AddrType::ATPubKey,
+                    address::SpendingData::PubKeyASD(xprv(2).public()),
+                    address::Attributes::new_bootstrap_era(None, pm.into()),
+                ),
+                Coin::new(1000).unwrap(),
+            )],
+        );
+        let witness = TxInWitness::new_redeem_pk(pm, &redeem_key(), &tx.id());
+        TxAux::new(tx, TxWitness::from(vec![witness]))
+    }
+
+    fn slot(slotid: u16) -> EpochSlotId {
+        EpochSlotId { epoch: 0, slotid }
+    }
+
+    /// a chain state after the boundary block of the epoch 0, with
+    /// slot leaders that are not elected
+    fn chain_state(genesis: &GenesisData) -> ChainState {
+        let mut chain_state = ChainState::new(genesis);
+        chain_state.ssc.seed = None;
+        let blk = make_boundary_block(&chain_state, 0, vec![stakeholder(1), stakeholder(2)]);
+        chain_state
+            .verify_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+        chain_state
+    }
+
+    #[test]
+    fn forged_blocks_are_valid() {
+        let genesis = genesis_data();
+        let mut chain_state = chain_state(&genesis);
+
+        let mut builder = MainBlockBuilder::new(slot(0));
+        builder.add_tx(redeem_tx(&genesis));
+        let blk = builder.make_block(&chain_state, &BlockIssuer::SlotLeader(&xprv(1)));
+        let hash = blk.header().compute_hash();
+        chain_state.verify_block(&hash, &blk).unwrap();
+        assert_eq!(chain_state.last_block, hash);
+        assert_eq!(chain_state.nr_transactions, 1);
+
+        let blk = MainBlockBuilder::new(slot(1))
+            .make_block(&chain_state, &BlockIssuer::SlotLeader(&xprv(2)));
+        chain_state
+            .verify_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+        assert_eq!(u64::from(chain_state.chain_difficulty), 2);
+    }
+
+    #[test]
+    fn forged_blocks_can_be_signed_by_a_delegate() {
+        let genesis = genesis_data();
+        let mut chain_state = chain_state(&genesis);
+        chain_state
+            .delegation
+            .insert(stakeholder(1), xprv(4).public());
+        let psk = ProxySecretKey::sign(&xprv(1), xprv(4).public(), 0, genesis.protocol_magic);
+
+        let blk = MainBlockBuilder::new(slot(0)).make_block(
+            &chain_state,
+            &BlockIssuer::HeavyDelegate(&xprv(4), psk.clone()),
+        );
+        chain_state
+            .clone()
+            .verify_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+
+        // the delegate is not the slot leader of the next slot
+        let blk = MainBlockBuilder::new(slot(1))
+            .make_block(&chain_state, &BlockIssuer::HeavyDelegate(&xprv(4), psk));
+        match chain_state.verify_block(&blk.header().compute_hash(), &blk) {
+            Err(Error::WrongSlotLeader) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
@@ -0,0 +1,542 @@
+//! Chain selection
+//!
+//! Keep track of the candidate chains of headers received from the
+//! peers, and select the one with the highest chain difficulty. The
+//! selected chain can only fork from the current one in its last `k`
+//! blocks (`k` being the epoch stability depth): the headers that are
+//! deeper than this are forgotten, so a candidate forking before them is
+//! rejected.
+//!
+//! Switching to the selected chain is described by a list of
+//! `ChainUpdate`: the blocks to roll back, with `ChainState::revert_block`
+//! and moving the storage `HEAD` tag back, then the blocks to roll
+//! forward, with `ChainState::verify_block_with_undo`.
+
+use address::StakeholderId;
+use config::{GenesisData, ProtocolMagic};
+use std::collections::{BTreeMap, BTreeSet};
+use std::{error, fmt};
+
+use super::block::BlockHeader;
+use super::chain_state::ChainState;
+use super::date::BlockDate;
+use super::header_chain_state::verify_slot_leader;
+use super::types::{ChainDifficulty, EpochId, HeaderHash};
+use super::verify::{self, verify_block_date, verify_header};
+
+#[derive(Debug)]
+pub enum Error {
+    /// the previous header is neither known nor the last stable block
+    UnknownPreviousHeader(HeaderHash),
+    /// the header is not valid
+    Invalid(verify::Error),
+}
+impl From<verify::Error> for Error {
+    fn from(e: verify::Error) -> Self {
+        Error::Invalid(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnknownPreviousHeader(hash) => {
+                write!(f, "Unknown or too old previous header {}", hash)
+            }
+            Error::Invalid(_) => write!(f, "Invalid header"),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self {
+            Error::Invalid(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A step to switch from the current chain to the selected one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainUpdate {
+    /// revert the given block, the last one of the current chain
+    RollBack(HeaderHash),
+    /// apply the given block on top of the current chain
+    RollForward(HeaderHash),
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainSelection {
+    protocol_magic: ProtocolMagic,
+    epoch_stability_depth: usize,
+    /// the slot leaders of the current and upcoming epochs, if known
+    slot_leaders: BTreeMap<EpochId, Vec<StakeholderId>>,
+
+    /// the last block that cannot be rolled back: all the known headers
+    /// descend from it
+    anchor: HeaderHash,
+    anchor_date: Option<BlockDate>,
+    anchor_difficulty: ChainDifficulty,
+
+    /// the known headers following the anchor
+    headers: BTreeMap<HeaderHash, BlockHeader>,
+    /// the headers without a known successor
+    tips: BTreeSet<HeaderHash>,
+    /// the current chain, from the block following the anchor to the tip
+    current: Vec<HeaderHash>,
+    /// the position of the headers of the current chain in `current`
+    current_index: BTreeMap<HeaderHash, usize>,
+}
+
+impl ChainSelection {
+    /// The selection before the first block of the chain.
+    pub fn new(genesis_data: &GenesisData) -> Self {
+        ChainSelection::new_from(
+            genesis_data.protocol_magic,
+            genesis_data.epoch_stability_depth,
+            genesis_data.genesis_prev.clone(),
+            None,
+            ChainDifficulty::from(0),
+        )
+    }
+
+    fn new_from(
+        protocol_magic: ProtocolMagic,
+        epoch_stability_depth: usize,
+        anchor: HeaderHash,
+        anchor_date: Option<BlockDate>,
+        anchor_difficulty: ChainDifficulty,
+    ) -> Self {
+        ChainSelection {
+            protocol_magic,
+            epoch_stability_depth,
+            slot_leaders: BTreeMap::new(),
+            anchor,
+            anchor_date,
+            anchor_difficulty,
+            headers: BTreeMap::new(),
+            tips: BTreeSet::new(),
+            current: vec![],
+            current_index: BTreeMap::new(),
+        }
+    }
+
+    /// Provide the slot leaders of an epoch.
+    pub fn set_slot_leaders(&mut self, epoch: EpochId, slot_leaders: Vec<StakeholderId>) {
+        self.slot_leaders.insert(epoch, slot_leaders);
+    }
+
+    /// The last block of the current chain.
+    pub fn tip(&self) -> &HeaderHash {
+        self.current.last().unwrap_or(&self.anchor)
+    }
+
+    pub fn tip_difficulty(&self) -> ChainDifficulty {
+        self.difficulty(self.tip())
+    }
+
+    /// The blocks of the current chain that can still be rolled back,
+    /// from the oldest to the tip.
+    pub fn current_chain(&self) -> &[HeaderHash] {
+        &self.current
+    }
+
+    pub fn get_header(&self, hash: &HeaderHash) -> Option<&BlockHeader> {
+        self.headers.get(hash)
+    }
+
+    /// Whether the block is known, i.e. is the anchor or one of the
+    /// headers following it.
+    pub fn contains(&self, hash: &HeaderHash) -> bool {
+        *hash == self.anchor || self.headers.contains_key(hash)
+    }
+
+    fn difficulty(&self, hash: &HeaderHash) -> ChainDifficulty {
+        match self.headers.get(hash) {
+            Some(hdr) => hdr.difficulty(),
+            None => self.anchor_difficulty,
+        }
+    }
+
+    /// Verify a header and add it to the candidate chains. Unlike
+    /// `HeaderChainState::verify_header`, invalid headers are not added.
+    pub fn add_header(&mut self, header_hash: &HeaderHash, hdr: &BlockHeader) -> Result<(), Error> {
+        if self.contains(header_hash) {
+            return Ok(());
+        }
+
+        let prev_header = hdr.get_previous_header();
+        let (last_date, last_difficulty) = if prev_header == self.anchor {
+            (self.anchor_date, self.anchor_difficulty)
+        } else {
+            match self.headers.get(&prev_header) {
+                Some(prev) => (Some(prev.get_blockdate()), prev.difficulty()),
+                None => return Err(Error::UnknownPreviousHeader(prev_header)),
+            }
+        };
+
+        verify_header(header_hash, hdr)?;
+        if hdr.get_protocol_magic() != self.protocol_magic {
+            return Err(verify::Error::WrongMagic.into());
+        }
+        verify_block_date(last_date, hdr.get_blockdate())?;
+        let expected_difficulty = match hdr {
+            BlockHeader::BoundaryBlockHeader(_) => u64::from(last_difficulty),
+            BlockHeader::MainBlockHeader(_) => u64::from(last_difficulty) + 1,
+        };
+        if u64::from(hdr.difficulty()) != expected_difficulty {
+            return Err(verify::Error::WrongChainDifficulty.into());
+        }
+        verify_slot_leader(&self.slot_leaders, hdr)?;
+
+        self.tips.remove(&prev_header);
+        self.tips.insert(header_hash.clone());
+        self.headers.insert(header_hash.clone(), hdr.clone());
+        Ok(())
+    }
+
+    /// The last block of the current chain that the given known block
+    /// descends from, or `None` if the block is not known.
+    pub fn intersection(&self, hash: &HeaderHash) -> Option<HeaderHash> {
+        let mut hash = hash.clone();
+        loop {
+            if hash == self.anchor || self.current_index.contains_key(&hash) {
+                return Some(hash);
+            }
+            hash = self.headers.get(&hash)?.get_previous_header();
+        }
+    }
+
+    /// Select the candidate chain with the highest chain difficulty and
+    /// make it the current chain. On ties, the current chain is kept,
+    /// or extended if a candidate follows its tip (as the boundary
+    /// blocks do not increase the difficulty). Returns the steps to
+    /// switch to the selected chain, which are empty if the current
+    /// chain is kept.
+    pub fn select(&mut self) -> Vec<ChainUpdate> {
+        let tip = self.tip().clone();
+        let mut best = tip.clone();
+        let mut best_key = (self.tip_difficulty(), false);
+        for candidate in self.tips.iter() {
+            let extends_tip =
+                *candidate != tip && self.intersection(candidate) == Some(tip.clone());
+            let key = (self.difficulty(candidate), extends_tip);
+            if key > best_key {
+                best = candidate.clone();
+                best_key = key;
+            }
+        }
+        if best == tip {
+            return vec![];
+        }
+
+        let intersection = self.intersection(&best).unwrap();
+        let mut forward = vec![];
+        let mut hash = best;
+        while hash != intersection {
+            let prev = self.headers[&hash].get_previous_header();
+            forward.push(hash);
+            hash = prev;
+        }
+        forward.reverse();
+
+        let keep = if intersection == self.anchor {
+            0
+        } else {
+            self.current_index[&intersection] + 1
+        };
+        let mut updates = vec![];
+        for hash in self.current.drain(keep..).rev() {
+            self.current_index.remove(&hash);
+            updates.push(ChainUpdate::RollBack(hash));
+        }
+        for hash in forward {
+            self.current_index.insert(hash.clone(), self.current.len());
+            self.current.push(hash.clone());
+            updates.push(ChainUpdate::RollForward(hash));
+        }
+
+        self.prune();
+        updates
+    }
+
+    /// Move the anchor to the last block of the current chain that is
+    /// `k` blocks deep, and forget the headers not descending from it.
+    fn prune(&mut self) {
+        let tip_difficulty = u64::from(self.tip_difficulty());
+        let stable = self
+            .current
+            .iter()
+            .rposition(|hash| {
+                u64::from(self.difficulty(hash)) + self.epoch_stability_depth as u64
+                    <= tip_difficulty
+            })
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        if stable == 0 {
+            return;
+        }
+
+        let stable_blocks: Vec<HeaderHash> = self.current.drain(..stable).collect();
+        let anchor = stable_blocks.last().unwrap();
+        let anchor_header = &self.headers[anchor];
+        self.anchor = anchor.clone();
+        self.anchor_date = Some(anchor_header.get_blockdate());
+        self.anchor_difficulty = anchor_header.difficulty();
+        self.slot_leaders = self
+            .slot_leaders
+            .split_off(&anchor_header.get_blockdate().get_epochid());
+        self.current_index = self
+            .current
+            .iter()
+            .enumerate()
+            .map(|(i, hash)| (hash.clone(), i))
+            .collect();
+
+        // Keep the headers whose previous header is kept, the previous
+        // headers being kept first as they have an earlier date.
+        let mut headers: Vec<(HeaderHash, BlockHeader)> =
+            std::mem::take(&mut self.headers).into_iter().collect();
+        headers.sort_by_key(|(_, hdr)| hdr.get_blockdate());
+        for (hash, hdr) in headers {
+            if self.contains(&hdr.get_previous_header()) {
+                self.headers.insert(hash, hdr);
+            }
+        }
+        let headers = &self.headers;
+        self.tips.retain(|hash| headers.contains_key(hash));
+    }
+}
+
+impl<'a> From<&'a ChainState> for ChainSelection {
+    /// Start from the last block of a chain state, which cannot be
+    /// rolled back.
+    fn from(chain_state: &'a ChainState) -> Self {
+        let mut selection = ChainSelection::new_from(
+            chain_state.protocol_magic,
+            chain_state.epoch_stability_depth,
+            chain_state.last_block.clone(),
+            chain_state.last_date,
+            chain_state.chain_difficulty,
+        );
+        if let (Some(date), Some(leaders)) = (chain_state.last_date, &chain_state.slot_leaders) {
+            selection.set_slot_leaders(date.get_epochid(), leaders.clone());
+        }
+        selection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::fts::SharedSeed;
+    use block::update::BlockVersionData;
+    use block::{boundary, normal, sign, update};
+    use block::{
+        BlockHeaderAttributes, BlockVersion, EpochSlotId, ExtraData, HeaderExtraData,
+        SoftwareVersion,
+    };
+    use fee;
+    use hash::Blake2b256;
+    use hdwallet::{Seed, XPrv};
+    use std::time::SystemTime;
+
+    fn xprv(seed: u8) -> XPrv {
+        XPrv::generate_from_seed(&Seed::from_bytes([seed; 32]))
+    }
+
+    fn genesis_data() -> GenesisData {
+        GenesisData {
+            genesis_prev: HeaderHash::new(b"genesis"),
+            epoch_stability_depth: 3,
+            vss_min_ttl: 2,
+            vss_max_ttl: 6,
+            start_time: SystemTime::UNIX_EPOCH,
+            protocol_magic: ProtocolMagic::from(633343913),
+            block_version_data: BlockVersionData::mainnet(20_000, fee::LinearFee::default()),
+            avvm_distr: BTreeMap::new(),
+            non_avvm_balances: BTreeMap::new(),
+            boot_stakeholders: BTreeMap::new(),
+            fts_seed: SharedSeed::default(),
+        }
+    }
+
+    fn boundary_header(genesis: &GenesisData) -> (HeaderHash, BlockHeader) {
+        let hdr = BlockHeader::BoundaryBlockHeader(boundary::BlockHeader::new(
+            genesis.protocol_magic,
+            genesis.genesis_prev.clone(),
+            boundary::BodyProof(Blake2b256::new(b"body")),
+            boundary::Consensus {
+                epoch: 0,
+                chain_difficulty: ChainDifficulty::from(0),
+            },
+            ExtraData::default(),
+        ));
+        (hdr.compute_hash(), hdr)
+    }
+
+    /// a main block header of `slotid` with the given chain difficulty,
+    /// issued by `leader`
+    fn main_header(
+        genesis: &GenesisData,
+        previous: &HeaderHash,
+        slotid: u16,
+        difficulty: u64,
+        leader: u8,
+    ) -> (HeaderHash, BlockHeader) {
+        let pm = genesis.protocol_magic;
+        let body = normal::Body::new(
+            normal::TxPayload::new(vec![]),
+            normal::SscPayload::CertificatesPayload(normal::VssCertificates::new(vec![])),
+            normal::DlgPayload(vec![]),
+            update::UpdatePayload {
+                proposal: None,
+                votes: vec![],
+            },
+        );
+        let mut hdr = normal::BlockHeader::new(
+            pm,
+            previous.clone(),
+            normal::BodyProof::generate_from_body(&body),
+            normal::Consensus {
+                slot_id: EpochSlotId { epoch: 0, slotid },
+                leader_key: xprv(leader).public(),
+                chain_difficulty: ChainDifficulty::from(difficulty),
+                block_signature: sign::BlockSignature::Signature(xprv(0).sign(b"")),
+            },
+            HeaderExtraData::new(
+                BlockVersion::new(0, 0, 0),
+                SoftwareVersion::new("cardano-sl", 1).unwrap(),
+                BlockHeaderAttributes::new(),
+                Blake2b256::new(b"extra"),
+            ),
+        );
+        hdr.consensus.block_signature = sign::MainToSign::from_header(&hdr).sign(pm, &xprv(leader));
+        let hdr = BlockHeader::MainBlockHeader(hdr);
+        (hdr.compute_hash(), hdr)
+    }
+
+    /// add a chain of main headers following `previous`, one per slot
+    /// from `first_slot`, and return their hashes
+    fn add_chain(
+        selection: &mut ChainSelection,
+        genesis: &GenesisData,
+        previous: &HeaderHash,
+        first_slot: u16,
+        len: u16,
+        leader: u8,
+    ) -> Vec<HeaderHash> {
+        let mut hashes = vec![];
+        let mut previous = previous.clone();
+        for slotid in first_slot..first_slot + len {
+            let (hash, hdr) =
+                main_header(genesis, &previous, slotid, u64::from(slotid) + 1, leader);
+            selection.add_header(&hash, &hdr).unwrap();
+            hashes.push(hash.clone());
+            previous = hash;
+        }
+        hashes
+    }
+
+    #[test]
+    fn the_chain_with_the_highest_difficulty_is_selected() {
+        let genesis = genesis_data();
+        let mut selection = ChainSelection::new(&genesis);
+        let (hash0, hdr0) = boundary_header(&genesis);
+        selection.add_header(&hash0, &hdr0).unwrap();
+        let chain_a = add_chain(&mut selection, &genesis, &hash0, 0, 3, 1);
+
+        let updates = selection.select();
+        assert_eq!(updates.len(), 4);
+        assert_eq!(updates[0], ChainUpdate::RollForward(hash0.clone()));
+        assert_eq!(selection.tip(), &chain_a[2]);
+        assert_eq!(u64::from(selection.tip_difficulty()), 3);
+
+        // a fork with the same difficulty does not replace the current
+        // chain
+        let chain_b = add_chain(&mut selection, &genesis, &chain_a[0], 1, 2, 2);
+        assert_eq!(selection.select(), vec![]);
+        assert_eq!(
+            selection.intersection(&chain_b[1]),
+            Some(chain_a[0].clone())
+        );
+
+        // until it gets longer
+        let chain_b_end = add_chain(&mut selection, &genesis, &chain_b[1], 3, 1, 2);
+        assert_eq!(
+            selection.select(),
+            vec![
+                ChainUpdate::RollBack(chain_a[2].clone()),
+                ChainUpdate::RollBack(chain_a[1].clone()),
+                ChainUpdate::RollForward(chain_b[0].clone()),
+                ChainUpdate::RollForward(chain_b[1].clone()),
+                ChainUpdate::RollForward(chain_b_end[0].clone()),
+            ]
+        );
+        assert_eq!(selection.tip(), &chain_b_end[0]);
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected() {
+        let genesis = genesis_data();
+        let mut selection = ChainSelection::new(&genesis);
+        let (hash0, hdr0) = boundary_header(&genesis);
+        selection.add_header(&hash0, &hdr0).unwrap();
+
+        let (hash, hdr) = main_header(&genesis, &hash0, 0, 2, 1);
+        match selection.add_header(&hash, &hdr) {
+            Err(Error::Invalid(verify::Error::WrongChainDifficulty)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        let (hash1, hdr1) = main_header(&genesis, &hash, 1, 2, 1);
+        match selection.add_header(&hash1, &hdr1) {
+            Err(Error::UnknownPreviousHeader(ref prev)) if *prev == hash => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        selection.set_slot_leaders(0, vec![StakeholderId::new(&xprv(2).public())]);
+        let (hash, hdr) = main_header(&genesis, &hash0, 0, 1, 1);
+        match selection.add_header(&hash, &hdr) {
+            Err(Error::Invalid(verify::Error::WrongSlotLeader)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(selection.select(), vec![ChainUpdate::RollForward(hash0)]);
+    }
+
+    #[test]
+    fn forks_deeper_than_k_are_rejected() {
+        let genesis = genesis_data();
+        let mut selection = ChainSelection::new(&genesis);
+        let (hash0, hdr0) = boundary_header(&genesis);
+        selection.add_header(&hash0, &hdr0).unwrap();
+        let chain_a = add_chain(&mut selection, &genesis, &hash0, 0, 6, 1);
+        let chain_b = add_chain(&mut selection, &genesis, &chain_a[0], 1, 2, 2);
+        selection.select();
+
+        // the blocks up to the difficulty 3 are stable
+        assert_eq!(selection.current_chain(), &chain_a[3..]);
+        assert!(selection.contains(&chain_a[2]));
+        assert!(!selection.contains(&chain_a[1]));
+        assert!(!selection.contains(&chain_b[1]));
+
+        let (hash, hdr) = main_header(&genesis, &chain_a[1], 2, 3, 2);
+        match selection.add_header(&hash, &hdr) {
+            Err(Error::UnknownPreviousHeader(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // the last k blocks can be rolled back
+        let chain_c = add_chain(&mut selection, &genesis, &chain_a[2], 3, 4, 3);
+        let updates = selection.select();
+        assert_eq!(
+            updates[..3].to_vec(),
+            vec![
+                ChainUpdate::RollBack(chain_a[5].clone()),
+                ChainUpdate::RollBack(chain_a[4].clone()),
+                ChainUpdate::RollBack(chain_a[3].clone()),
+            ]
+        );
+        assert_eq!(updates.len(), 7);
+        assert_eq!(selection.tip(), &chain_c[3]);
+    }
+}
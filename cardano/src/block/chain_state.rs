@@ -1,19 +1,30 @@
 use address;
 use block::*;
 use config::{GenesisData, ProtocolMagic};
-use fee;
 use hash;
+use hdwallet::XPub;
 use std::collections::BTreeMap;
 use tx::{self, TxOut, TxoPointer};
 
+use block::fts;
+use block::ssc_state::{self, SscState};
+use block::stake_state::StakeState;
+use block::update::BlockVersionData;
+use block::update_state::UpdateState;
+
 pub type Utxos = BTreeMap<TxoPointer, TxOut>;
 
+/// Heavyweight delegation: the key each stakeholder delegated its
+/// block issuing rights to.
+pub type Delegation = BTreeMap<address::StakeholderId, XPub>;
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ChainState {
-    // FIXME: maybe we should just keep a ref to GenesisData?  Though
-    // I guess at least the fee policy could change in an update.
+    // FIXME: maybe we should just keep a ref to GenesisData?
     pub protocol_magic: ProtocolMagic,
-    pub fee_policy: fee::LinearFee,
+    pub epoch_stability_depth: usize,
+    pub vss_min_ttl: EpochId,
+    pub vss_max_ttl: EpochId,
 
     pub last_block: HeaderHash,
     pub last_date: Option<super::BlockDate>,
@@ -21,6 +32,25 @@ pub struct ChainState {
     pub slot_leaders: Option<Vec<address::StakeholderId>>,
     pub utxos: Utxos,
     pub chain_length: u64,
+    /// The difficulty of the last block: the number of main blocks
+    /// in the chain.
+    pub chain_difficulty: ChainDifficulty,
+
+    /// The delegation in effect in the current epoch.
+    pub delegation: Delegation,
+    /// The certificates issued in the current epoch, taking effect at
+    /// the start of the next one. A stakeholder delegating to itself
+    /// revokes its delegation.
+    pub pending_delegation: Delegation,
+
+    /// The update proposals and the adopted protocol parameters.
+    pub update: UpdateState,
+
+    /// The VSS certificates of the shared seed computation.
+    pub ssc: SscState,
+
+    /// The stake distribution of the utxos.
+    pub stakes: StakeState,
 
     // Some stats.
     pub nr_transactions: u64,
@@ -56,17 +86,68 @@ impl ChainState {
             );
         }
 
+        let stakes = StakeState::from_utxos(genesis_data, &utxos);
+        let update = UpdateState::new(genesis_data);
+        let richmen = ssc_state::richmen(&stakes.stakes, &update.parameters.mpc_thd);
+
+        // The genesis stakeholders start out delegating to the keys
+        // given in the genesis data.
+        let delegation = genesis_data
+            .boot_stakeholders
+            .iter()
+            .map(|(stakeholder_id, stakeholder)| (*stakeholder_id, stakeholder.delegate_pk))
+            .collect();
+
         ChainState {
             protocol_magic: genesis_data.protocol_magic,
-            fee_policy: genesis_data.fee_policy,
+            epoch_stability_depth: genesis_data.epoch_stability_depth,
+            vss_min_ttl: EpochId::from(genesis_data.vss_min_ttl),
+            vss_max_ttl: EpochId::from(genesis_data.vss_max_ttl),
             last_block: genesis_data.genesis_prev.clone(),
             last_date: None,
             last_boundary_block: None,
             slot_leaders: None,
             utxos,
             chain_length: 0,
+            chain_difficulty: ChainDifficulty::from(0),
+            delegation,
+            pending_delegation: BTreeMap::new(),
+            update,
+            ssc: SscState::new(genesis_data.fts_seed.clone(), richmen),
+            stakes,
             nr_transactions: 0,
             spent_txos: 0,
         }
     }
+
+    /// The protocol parameters to validate blocks with.
+    pub fn parameters(&self) -> &BlockVersionData {
+        &self.update.parameters
+    }
+
+    /// The key allowed to issue blocks on behalf of the given slot
+    /// leader, if it delegated its rights.
+    pub fn delegate_of(&self, slot_leader: &address::StakeholderId) -> Option<&XPub> {
+        self.delegation.get(slot_leader)
+    }
+
+    /// The number of slots in an epoch.
+    pub fn epoch_slots(&self) -> usize {
+        self.epoch_stability_depth * 10
+    }
+
+    /// Check the slot leaders of a boundary block against the ones
+    /// elected with the seed computed during the previous epoch. The
+    /// check is skipped if the seed is unknown.
+    pub fn verify_slot_leaders(&self, blk: &boundary::Block) -> Result<(), Error> {
+        match &self.ssc.seed {
+            Some(seed) => fts::verify_slot_leaders(
+                self.epoch_slots(),
+                seed,
+                self.stakes.leaders_stakes(blk.header.consensus.epoch),
+                &blk.body.slot_leaders,
+            ),
+            None => Ok(()),
+        }
+    }
 }
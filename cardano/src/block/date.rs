@@ -27,13 +27,6 @@ impl property::BlockDate for BlockDate {
     }
 }
 
-impl ::std::ops::Sub<BlockDate> for BlockDate {
-    type Output = usize;
-    fn sub(self, rhs: Self) -> Self::Output {
-        self.slot_number() - rhs.slot_number()
-    }
-}
-
 impl PartialOrd for BlockDate {
     fn partial_cmp(&self, other: &BlockDate) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -74,13 +67,22 @@ impl BlockDate {
     pub fn epoch_and_slot(&self) -> (EpochId, Option<SlotId>) {
         (self.get_epochid(), self.slotid())
     }
-    pub fn next(&self) -> Self {
+    /// The next date, in epochs of `epoch_slots` slots: the last slot of
+    /// an epoch is followed by the boundary of the next one.
+    pub fn next(&self, epoch_slots: u64) -> Self {
         match self {
             &BlockDate::Boundary(e) => BlockDate::Normal(EpochSlotId {
                 epoch: e,
                 slotid: 0,
             }),
-            &BlockDate::Normal(ref s) => BlockDate::Normal(s.next()), // TODO next should wrap after full epoch
+            &BlockDate::Normal(ref s) => {
+                let next = s.next(epoch_slots);
+                if next.epoch != s.epoch {
+                    BlockDate::Boundary(next.epoch)
+                } else {
+                    BlockDate::Normal(next)
+                }
+            }
         }
     }
 
@@ -90,10 +92,13 @@ impl BlockDate {
             _ => false,
         }
     }
-    pub fn slot_number(&self) -> usize {
+    /// The number of slots since the start of the chain, in epochs of
+    /// `epoch_slots` slots. A boundary block shares the slot number of
+    /// the first slot of its epoch.
+    pub fn slot_number(&self, epoch_slots: u64) -> u64 {
         match self {
-            BlockDate::Boundary(eid) => (*eid as usize) * 21600, // TODO de-hardcode this value
-            BlockDate::Normal(sid) => sid.slot_number(),
+            BlockDate::Boundary(eid) => eid * epoch_slots,
+            BlockDate::Normal(sid) => sid.slot_number(epoch_slots),
         }
     }
 }
@@ -179,6 +184,10 @@ mod tests {
     use block::EpochSlotId;
     use std::error::Error;
 
+    fn slot(epoch: u64, slotid: u16) -> EpochSlotId {
+        EpochSlotId { epoch, slotid }
+    }
+
     #[test]
     fn parse_bare_epoch() {
         let date = "42".parse::<BlockDate>().unwrap();
@@ -214,4 +223,18 @@ mod tests {
         let err = "42.INVALID".parse::<BlockDate>().unwrap_err();
         println!("{}: {}", err, err.cause().unwrap());
     }
+
+    #[test]
+    fn dates_wrap_at_the_end_of_epochs() {
+        let date = BlockDate::Normal(slot(3, 21598));
+        assert_eq!(date.next(21600), BlockDate::Normal(slot(3, 21599)));
+        assert_eq!(date.next(21600).next(21600), BlockDate::Boundary(4));
+        assert_eq!(
+            BlockDate::Boundary(4).next(21600),
+            BlockDate::Normal(slot(4, 0))
+        );
+        assert_eq!(slot(0, 9).next(10), slot(1, 0));
+        assert_eq!(BlockDate::Normal(slot(2, 3)).slot_number(10), 23);
+        assert_eq!(BlockDate::Boundary(2).slot_number(10), 20);
+    }
 }
@@ -0,0 +1,352 @@
+//! Slot leader election (follow the satoshi)
+//!
+//! The slot leaders of an epoch are elected among the coins of the
+//! stake distribution: for each slot of the epoch, a coin is drawn at
+//! random with a generator seeded by the shared seed, and the slot goes
+//! to the stakeholder owning that coin, the coins being numbered
+//! following the order of the stakeholder identifiers.
+//!
+//! The generator and the drawing of the coins are the ones of
+//! cardano-sl (cryptonite's `ChaChaDRG` and `randomNumber`) so that
+//! the computed schedule can be compared with the one of the boundary
+//! blocks.
+
+use address::StakeholderId;
+use cbor_event::{self, de::Deserializer, se::Serializer};
+use std::{
+    cmp, fmt,
+    io::{BufRead, Write},
+};
+use util::hex;
+
+use super::update_state::Stakes;
+use super::verify::Error;
+
+/// The size of the seeds computed by the SSC. The seed of the genesis
+/// epoch, given by the genesis data, can be of any size.
+pub const SHARED_SEED_SIZE: usize = 32;
+
+/// The seed of the slot leader election, the result of the shared seed
+/// computation of the previous epoch.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SharedSeed(Vec<u8>);
+impl SharedSeed {
+    pub fn from_bytes<B: AsRef<[u8]>>(bytes: B) -> Self {
+        SharedSeed(bytes.as_ref().to_vec())
+    }
+
+    /// Combine the seeds of two participants of the computation. The
+    /// shared seed is the combination of the seeds revealed by every
+    /// participant, in any order. As in cardano-sl, the result is as
+    /// long as the shortest seed.
+    pub fn combine(&self, other: &Self) -> Self {
+        SharedSeed(
+            self.0
+                .iter()
+                .zip(other.0.iter())
+                .map(|(a, b)| a ^ b)
+                .collect(),
+        )
+    }
+}
+impl Default for SharedSeed {
+    fn default() -> Self {
+        SharedSeed(vec![0; SHARED_SEED_SIZE])
+    }
+}
+impl AsRef<[u8]> for SharedSeed {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+impl fmt::Display for SharedSeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.as_ref()))
+    }
+}
+impl cbor_event::se::Serialize for SharedSeed {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        serializer.write_bytes(&self.0)
+    }
+}
+impl cbor_event::de::Deserialize for SharedSeed {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        Ok(SharedSeed(raw.bytes()?))
+    }
+}
+
+/// The number of rounds of the ChaCha generator of cryptonite.
+const CHACHA_DRG_ROUNDS: usize = 8;
+
+/// The ChaCha block function with a zero block counter, as used by
+/// cryptonite, with the given number of rounds.
+fn chacha_block(rounds: usize, key: &[u8; 32], nonce: &[u8; 8]) -> [u8; 64] {
+    fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[a] = x[a].wrapping_add(x[b]);
+        x[d] = (x[d] ^ x[a]).rotate_left(16);
+        x[c] = x[c].wrapping_add(x[d]);
+        x[b] = (x[b] ^ x[c]).rotate_left(12);
+        x[a] = x[a].wrapping_add(x[b]);
+        x[d] = (x[d] ^ x[a]).rotate_left(8);
+        x[c] = x[c].wrapping_add(x[d]);
+        x[b] = (x[b] ^ x[c]).rotate_left(7);
+    }
+
+    let word = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let mut state = [0u32; 16];
+    // "expand 32-byte k"
+    state[0..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for i in 0..8 {
+        state[4 + i] = word(&key[4 * i..]);
+    }
+    state[14] = word(&nonce[0..]);
+    state[15] = word(&nonce[4..]);
+
+    let mut x = state;
+    for _ in 0..rounds / 2 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    let mut block = [0; 64];
+    for i in 0..16 {
+        block[4 * i..4 * i + 4].copy_from_slice(&x[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    block
+}
+
+/// The deterministic random generator of cryptonite (`ChaChaDRG`):
+/// every draw consumes a fresh ChaCha8 block, the generator being
+/// rekeyed with the part of the block not returned.
+struct ChaChaDrg {
+    key: [u8; 32],
+    nonce: [u8; 8],
+}
+impl ChaChaDrg {
+    fn new(seed: &SharedSeed) -> Self {
+        // The seed is read as a big endian integer, encoded back on 40
+        // bytes modulo 2^320: the key and the nonce of the generator.
+        let mut drg_seed = [0; 40];
+        let seed = seed.as_ref();
+        let len = cmp::min(seed.len(), 40);
+        drg_seed[40 - len..].copy_from_slice(&seed[seed.len() - len..]);
+        let mut key = [0; 32];
+        let mut nonce = [0; 8];
+        key.copy_from_slice(&drg_seed[0..32]);
+        nonce.copy_from_slice(&drg_seed[32..40]);
+        ChaChaDrg { key, nonce }
+    }
+
+    /// Fill `dst`, which must be at most 16 bytes long, with random
+    /// bytes.
+    fn generate(&mut self, dst: &mut [u8]) {
+        assert!(dst.len() <= 16);
+        let block = chacha_block(CHACHA_DRG_ROUNDS, &self.key, &self.nonce);
+        dst.copy_from_slice(&block[40..40 + dst.len()]);
+        self.key.copy_from_slice(&block[0..32]);
+        self.nonce.copy_from_slice(&block[32..40]);
+    }
+}
+
+/// Draw a number uniformly in `[0, n)`, rejecting the draws that would
+/// bias the result.
+fn random_number(drg: &mut ChaChaDrg, n: u64) -> u64 {
+    assert!(n > 0);
+    let size = cmp::max(4, 8 - n.leading_zeros() as usize / 8);
+    let range_mod = ((1u128 << (size * 8)) % u128::from(n)) as u64;
+    loop {
+        let mut bytes = [0; 8];
+        drg.generate(&mut bytes[8 - size..]);
+        let x = u64::from_be_bytes(bytes);
+        if x >= range_mod {
+            return x % n;
+        }
+    }
+}
+
+/// Elect the slot leaders of the `epoch_slots` slots of an epoch. Returns
+/// `None` if there is no stake to elect the leaders from.
+pub fn follow_the_satoshi(
+    epoch_slots: usize,
+    seed: &SharedSeed,
+    stakes: &Stakes,
+) -> Option<Vec<StakeholderId>> {
+    let total_stake: u64 = stakes.values().sum();
+    if total_stake == 0 {
+        return None;
+    }
+
+    let mut drg = ChaChaDrg::new(seed);
+    let mut coins: Vec<(u64, usize)> = (0..epoch_slots)
+        .map(|slot| (random_number(&mut drg, total_stake) + 1, slot))
+        .collect();
+    coins.sort();
+
+    // Walk the stakeholders and the drawn coins in order, the coins
+    // of a stakeholder being the ones up to the cumulated stake.
+    let mut leaders = vec![None; epoch_slots];
+    let mut stakes = stakes.iter();
+    let mut cumulated_stake = 0;
+    let mut owner = None;
+    for (coin, slot) in coins {
+        while coin > cumulated_stake {
+            let (stakeholder, stake) = stakes.next().expect("coin drawn out of the stake");
+            cumulated_stake += stake;
+            owner = Some(*stakeholder);
+        }
+        leaders[slot] = owner;
+    }
+
+    Some(leaders.into_iter().map(|leader| leader.unwrap()).collect())
+}
+
+/// Check the slot leaders of a boundary block against the ones elected
+/// from the stake distribution and the shared seed.
+pub fn verify_slot_leaders(
+    epoch_slots: usize,
+    seed: &SharedSeed,
+    stakes: &Stakes,
+    slot_leaders: &[StakeholderId],
+) -> Result<(), Error> {
+    match follow_the_satoshi(epoch_slots, seed, stakes) {
+        Some(ref leaders) if leaders.as_slice() == slot_leaders => Ok(()),
+        _ => Err(Error::WrongSlotLeaders),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hdwallet::{Seed, XPrv};
+
+    const EPOCH_SLOTS: usize = 21600;
+
+    fn stakeholder(n: u8) -> StakeholderId {
+        StakeholderId::new(&XPrv::generate_from_seed(&Seed::from_bytes([n; 32])).public())
+    }
+
+    fn seed(n: u8) -> SharedSeed {
+        SharedSeed::from_bytes([n; SHARED_SEED_SIZE])
+    }
+
+    #[test]
+    fn chacha_block_matches_test_vectors() {
+        // the zero key and nonce, with 20 rounds: RFC 7539 test vector #1
+        assert_eq!(
+            hex::encode(&chacha_block(20, &[0; 32], &[0; 8])[..]),
+            "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7\
+             da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586"
+        );
+        // and with 8 rounds: the ChaCha8 test vector TC1 of
+        // draft-strombergson-chacha-test-vectors
+        assert_eq!(
+            hex::encode(&chacha_block(8, &[0; 32], &[0; 8])[..]),
+            "3e00ef2f895f40d67f5bb8e81f09a5a12c840ec3ce9a7f3b181be188ef711a1e\
+             984ce172b9216f419f445367456d5619314a42a3da86b001387bfdb80e0cfe42"
+        );
+    }
+
+    #[test]
+    fn drg_is_cryptonite_chacha() {
+        // the zero key and nonce of the zero seed give the ChaCha8 block
+        // of the test vector above
+        let mut drg = ChaChaDrg::new(&SharedSeed::default());
+        let mut bytes = [0; 16];
+        drg.generate(&mut bytes);
+        assert_eq!(hex::encode(&bytes), "9f445367456d5619314a42a3da86b001");
+        assert_eq!(
+            hex::encode(&drg.key),
+            "3e00ef2f895f40d67f5bb8e81f09a5a12c840ec3ce9a7f3b181be188ef711a1e"
+        );
+        assert_eq!(hex::encode(&drg.nonce), "984ce172b9216f41");
+    }
+
+    #[test]
+    fn drg_seed_is_taken_modulo_2_320() {
+        let mut long_seed = vec![0xff; 2];
+        long_seed.extend_from_slice(&[1; 40]);
+        let mut drg = ChaChaDrg::new(&SharedSeed::from_bytes(&long_seed));
+        assert_eq!(drg.key, [1; 32]);
+        assert_eq!(drg.nonce, [1; 8]);
+
+        drg = ChaChaDrg::new(&SharedSeed::from_bytes([1; 2]));
+        assert_eq!(drg.key, [0; 32]);
+        assert_eq!(drg.nonce, [0, 0, 0, 0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn random_numbers_are_in_range() {
+        let mut drg = ChaChaDrg::new(&seed(1));
+        for n in &[1, 2, 3, 255, 256, 1 << 40, u64::MAX] {
+            for _ in 0..100 {
+                assert!(random_number(&mut drg, *n) < *n);
+            }
+        }
+    }
+
+    #[test]
+    fn seeds_are_combined() {
+        assert_eq!(seed(3).combine(&seed(5)), seed(6));
+        assert_eq!(seed(3).combine(&seed(3)), SharedSeed::default());
+        assert_eq!(
+            seed(3).combine(&SharedSeed::from_bytes([5; 2])),
+            SharedSeed::from_bytes([6; 2])
+        );
+    }
+
+    #[test]
+    fn leaders_are_elected_by_stake() {
+        let mut stakes = Stakes::new();
+        assert_eq!(follow_the_satoshi(EPOCH_SLOTS, &seed(1), &stakes), None);
+
+        stakes.insert(stakeholder(1), 3_000_000);
+        stakes.insert(stakeholder(2), 0);
+        stakes.insert(stakeholder(3), 1_000_000);
+        let leaders = follow_the_satoshi(EPOCH_SLOTS, &seed(1), &stakes).unwrap();
+        assert_eq!(leaders.len(), EPOCH_SLOTS);
+        let elected = |n| {
+            let id = stakeholder(n);
+            leaders.iter().filter(|leader| **leader == id).count()
+        };
+        assert_eq!(elected(2), 0);
+        assert_eq!(elected(1) + elected(3), EPOCH_SLOTS);
+        assert!(elected(1) > 2 * elected(3));
+        assert!(elected(1) < 4 * elected(3));
+
+        let mut single = Stakes::new();
+        single.insert(stakeholder(2), 1);
+        assert_eq!(
+            follow_the_satoshi(10, &seed(1), &single).unwrap(),
+            vec![stakeholder(2); 10]
+        );
+    }
+
+    #[test]
+    fn leaders_are_verified() {
+        let mut stakes = Stakes::new();
+        stakes.insert(stakeholder(1), 500);
+        stakes.insert(stakeholder(2), 500);
+        let leaders = follow_the_satoshi(100, &seed(1), &stakes).unwrap();
+        assert_eq!(follow_the_satoshi(100, &seed(1), &stakes).unwrap(), leaders);
+        assert_ne!(follow_the_satoshi(100, &seed(2), &stakes).unwrap(), leaders);
+
+        verify_slot_leaders(100, &seed(1), &stakes, &leaders).unwrap();
+        match verify_slot_leaders(100, &seed(2), &stakes, &leaders) {
+            Err(Error::WrongSlotLeaders) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        match verify_slot_leaders(100, &seed(1), &Stakes::new(), &leaders) {
+            Err(Error::WrongSlotLeaders) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
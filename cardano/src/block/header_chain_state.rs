@@ -0,0 +1,314 @@
+//! Header-only chain validation
+//!
+//! Validates a chain of block headers without their bodies, as received
+//! during the initial header sync: the linkage, dates and protocol magic
+//! of the headers, their signatures and the slot leaders that issued
+//! them.
+//!
+//! The slot leaders of an epoch are in the body of its boundary block
+//! (or computed with `fts`), so they are provided separately with
+//! `set_slot_leaders`; the headers of an epoch without slot leaders are
+//! not checked against them. The heavyweight delegation certificates
+//! are also in the block bodies: the one a header was signed with is
+//! checked from the header itself.
+
+use address::StakeholderId;
+use config::{GenesisData, ProtocolMagic};
+use std::collections::BTreeMap;
+
+use super::block::BlockHeader;
+use super::chain_state::ChainState;
+use super::date::BlockDate;
+use super::sign::BlockSignature;
+use super::types::{EpochId, HeaderHash};
+use super::verify::{
+    first_error, verify_block_date, verify_header_all_errors, Error, ErrorLocation, Errors,
+    LocatedError,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderChainState {
+    pub protocol_magic: ProtocolMagic,
+    pub last_header: HeaderHash,
+    pub last_date: Option<BlockDate>,
+    /// the slot leaders of the current and upcoming epochs, if known
+    pub slot_leaders: BTreeMap<EpochId, Vec<StakeholderId>>,
+    pub chain_length: u64,
+}
+
+impl HeaderChainState {
+    /// The state before the first header of the chain.
+    pub fn new(genesis_data: &GenesisData) -> Self {
+        HeaderChainState {
+            protocol_magic: genesis_data.protocol_magic,
+            last_header: genesis_data.genesis_prev.clone(),
+            last_date: None,
+            slot_leaders: BTreeMap::new(),
+            chain_length: 0,
+        }
+    }
+
+    /// Provide the slot leaders of an epoch.
+    pub fn set_slot_leaders(&mut self, epoch: EpochId, slot_leaders: Vec<StakeholderId>) {
+        self.slot_leaders.insert(epoch, slot_leaders);
+    }
+
+    /// Verify a header and append it to the chain. As for
+    /// `ChainState::verify_block`, the header is appended even if it
+    /// is invalid.
+    pub fn verify_header(
+        &mut self,
+        header_hash: &HeaderHash,
+        hdr: &BlockHeader,
+    ) -> Result<(), Error> {
+        first_error(self.verify_header_all_errors(header_hash, hdr))
+    }
+
+    /// Like `verify_header`, but returning every error found in the
+    /// header rather than the first one.
+    pub fn verify_header_all_errors(
+        &mut self,
+        header_hash: &HeaderHash,
+        hdr: &BlockHeader,
+    ) -> Errors {
+        // Perform stateless checks.
+        let mut errors = verify_header_all_errors(header_hash, hdr);
+
+        self.do_verify(hdr, &mut errors);
+
+        let date = hdr.get_blockdate();
+        // Forget the slot leaders of the past epochs.
+        self.slot_leaders = self.slot_leaders.split_off(&date.get_epochid());
+        self.last_header = header_hash.clone();
+        self.last_date = Some(date);
+        self.chain_length += 1;
+
+        errors
+    }
+
+    fn do_verify(&self, hdr: &BlockHeader, errors: &mut Errors) {
+        let mut error = |error| errors.push(LocatedError::new(ErrorLocation::Header, error));
+
+        // Check the protocol magic.
+        if hdr.get_protocol_magic() != self.protocol_magic {
+            error(Error::WrongMagic);
+        }
+
+        let prev_header = hdr.get_previous_header();
+        if prev_header != self.last_header {
+            error(Error::WrongPreviousBlock(
+                prev_header,
+                self.last_header.clone(),
+            ));
+        }
+
+        // Check the block date.
+        if let Err(err) = verify_block_date(self.last_date, hdr.get_blockdate()) {
+            error(err);
+        }
+
+        // Check that the header was issued by the appointed slot leader.
+        if let Err(err) = verify_slot_leader(&self.slot_leaders, hdr) {
+            error(err);
+        }
+    }
+}
+
+/// Check that a main block header was issued by the slot leader of its
+/// slot, if the slot leaders of its epoch are known.
+pub(super) fn verify_slot_leader(
+    slot_leaders: &BTreeMap<EpochId, Vec<StakeholderId>>,
+    hdr: &BlockHeader,
+) -> Result<(), Error> {
+    if let BlockHeader::MainBlockHeader(hdr) = hdr {
+        let slot_id = &hdr.consensus.slot_id;
+        if let Some(slot_leaders) = slot_leaders.get(&slot_id.epoch) {
+            match slot_leaders.get(slot_id.slotid as usize) {
+                None => return Err(Error::NonExistentSlot),
+                Some(slot_leader) => {
+                    let issuer = match &hdr.consensus.block_signature {
+                        BlockSignature::ProxyHeavy(proxy_sig) => &proxy_sig.psk.issuer_pk,
+                        _ => &hdr.consensus.leader_key,
+                    };
+                    if slot_leader != &StakeholderId::new(issuer) {
+                        return Err(Error::WrongSlotLeader);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<'a> From<&'a ChainState> for HeaderChainState {
+    /// Continue the header chain from the last block of a chain state.
+    fn from(chain_state: &'a ChainState) -> Self {
+        let mut slot_leaders = BTreeMap::new();
+        if let (Some(date), Some(leaders)) = (chain_state.last_date, &chain_state.slot_leaders) {
+            slot_leaders.insert(date.get_epochid(), leaders.clone());
+        }
+        HeaderChainState {
+            protocol_magic: chain_state.protocol_magic,
+            last_header: chain_state.last_block.clone(),
+            last_date: chain_state.last_date,
+            slot_leaders,
+            chain_length: chain_state.chain_length,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::fts::SharedSeed;
+    use block::update::BlockVersionData;
+    use block::{boundary, normal, sign, update};
+    use block::{
+        BlockHeaderAttributes, BlockVersion, ChainDifficulty, EpochSlotId, ExtraData,
+        HeaderExtraData, SoftwareVersion,
+    };
+    use fee;
+    use hash::Blake2b256;
+    use hdwallet::{Seed, XPrv};
+    use std::time::SystemTime;
+
+    fn xprv(seed: u8) -> XPrv {
+        XPrv::generate_from_seed(&Seed::from_bytes([seed; 32]))
+    }
+
+    fn stakeholder(seed: u8) -> StakeholderId {
+        StakeholderId::new(&xprv(seed).public())
+    }
+
+    fn genesis_data() -> GenesisData {
+        GenesisData {
+            genesis_prev: HeaderHash::new(b"genesis"),
+            epoch_stability_depth: 10,
+            vss_min_ttl: 2,
+            vss_max_ttl: 6,
+            start_time: SystemTime::UNIX_EPOCH,
+            protocol_magic: ProtocolMagic::from(633343913),
+            block_version_data: BlockVersionData::mainnet(20_000, fee::LinearFee::default()),
+            avvm_distr: BTreeMap::new(),
+            non_avvm_balances: BTreeMap::new(),
+            boot_stakeholders: BTreeMap::new(),
+            fts_seed: SharedSeed::default(),
+        }
+    }
+
+    fn boundary_header(genesis: &GenesisData, epoch: EpochId) -> (HeaderHash, BlockHeader) {
+        let hdr = BlockHeader::BoundaryBlockHeader(boundary::BlockHeader::new(
+            genesis.protocol_magic,
+            genesis.genesis_prev.clone(),
+            boundary::BodyProof(Blake2b256::new(b"body")),
+            boundary::Consensus {
+                epoch,
+                chain_difficulty: ChainDifficulty::from(0),
+            },
+            ExtraData::default(),
+        ));
+        (hdr.compute_hash(), hdr)
+    }
+
+    /// a main block header of `slotid` issued by `leader` and signed by
+    /// `signer`
+    fn main_header(
+        genesis: &GenesisData,
+        previous: &HeaderHash,
+        slotid: u16,
+        leader: u8,
+        signer: u8,
+    ) -> (HeaderHash, BlockHeader) {
+        let pm = genesis.protocol_magic;
+        let body = normal::Body::new(
+            normal::TxPayload::new(vec![]),
+            normal::SscPayload::CertificatesPayload(normal::VssCertificates::new(vec![])),
+            normal::DlgPayload(vec![]),
+            update::UpdatePayload {
+                proposal: None,
+                votes: vec![],
+            },
+        );
+        let mut hdr = normal::BlockHeader::new(
+            pm,
+            previous.clone(),
+            normal::BodyProof::generate_from_body(&body),
+            normal::Consensus {
+                slot_id: EpochSlotId { epoch: 0, slotid },
+                leader_key: xprv(leader).public(),
+                chain_difficulty: ChainDifficulty::from(u64::from(slotid) + 1),
+                block_signature: BlockSignature::Signature(xprv(0).sign(b"")),
+            },
+            HeaderExtraData::new(
+                BlockVersion::new(0, 0, 0),
+                SoftwareVersion::new("cardano-sl", 1).unwrap(),
+                BlockHeaderAttributes::new(),
+                Blake2b256::new(b"extra"),
+            ),
+        );
+        hdr.consensus.block_signature = sign::MainToSign::from_header(&hdr).sign(pm, &xprv(signer));
+        let hdr = BlockHeader::MainBlockHeader(hdr);
+        (hdr.compute_hash(), hdr)
+    }
+
+    fn errors(errors: Errors) -> Vec<String> {
+        errors
+            .into_iter()
+            .map(|err| {
+                let name = format!("{:?}", err.error);
+                name.split('(').next().unwrap().to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn valid_headers_are_accepted() {
+        let genesis = genesis_data();
+        let mut state = HeaderChainState::new(&genesis);
+        state.set_slot_leaders(0, vec![stakeholder(1), stakeholder(2)]);
+
+        let (hash0, hdr0) = boundary_header(&genesis, 0);
+        state.verify_header(&hash0, &hdr0).unwrap();
+        let (hash1, hdr1) = main_header(&genesis, &hash0, 0, 1, 1);
+        state.verify_header(&hash1, &hdr1).unwrap();
+        let (hash2, hdr2) = main_header(&genesis, &hash1, 1, 2, 2);
+        state.verify_header(&hash2, &hdr2).unwrap();
+
+        assert_eq!(state.last_header, hash2);
+        assert_eq!(state.chain_length, 3);
+    }
+
+    #[test]
+    fn slot_leaders_are_optional() {
+        let genesis = genesis_data();
+        let mut state = HeaderChainState::new(&genesis);
+        let (hash0, hdr0) = boundary_header(&genesis, 0);
+        state.verify_header(&hash0, &hdr0).unwrap();
+        let (hash1, hdr1) = main_header(&genesis, &hash0, 5, 3, 3);
+        state.verify_header(&hash1, &hdr1).unwrap();
+    }
+
+    #[test]
+    fn invalid_headers_are_reported() {
+        let genesis = genesis_data();
+        let mut state = HeaderChainState::new(&genesis);
+        state.set_slot_leaders(0, vec![stakeholder(1), stakeholder(2)]);
+        let (hash0, hdr0) = boundary_header(&genesis, 0);
+        state.verify_header(&hash0, &hdr0).unwrap();
+
+        // issued by the leader of another slot, and signed with
+        // another key
+        let (hash, hdr) = main_header(&genesis, &hash0, 0, 2, 3);
+        assert_eq!(
+            errors(state.clone().verify_header_all_errors(&hash, &hdr)),
+            vec!["BadBlockSig", "WrongSlotLeader"]
+        );
+
+        // not linked to the last header, in a slot without a leader
+        let (hash, hdr) = main_header(&genesis, &hash, 2, 1, 1);
+        assert_eq!(
+            errors(state.clone().verify_header_all_errors(&hash, &hdr)),
+            vec!["WrongPreviousBlock", "NonExistentSlot"]
+        );
+    }
+}
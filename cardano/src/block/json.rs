@@ -0,0 +1,1076 @@
+//! JSON representation of the blocks, for explorers
+//!
+//! The types of this module are the stable representation of the blocks
+//! and of their content, serialized with serde. They are built from the
+//! block types with `From`, and transactions can be rendered with their
+//! inputs resolved against the UTxOs they spend, in which case their fee
+//! is computed.
+//!
+//! The representation follows these rules:
+//!
+//! * the sum types are objects with a `type` field naming the variant,
+//!   in snake case;
+//! * hashes, public keys and signatures are lowercase hexadecimal strings;
+//! * addresses are base58 strings;
+//! * coin values, chain difficulties and coin portions are integers (of
+//!   lovelaces for the coin values);
+//! * the attributes are objects mapping the attribute keys to the
+//!   hexadecimal of their value;
+//! * the values this library does not decode (the PVSS proofs and
+//!   shares, the scripts) are the hexadecimal of their CBOR encoding.
+
+use cbor_event::se;
+use coin;
+use std::collections::BTreeMap;
+use tx::{self, TxAux, TxoPointer};
+use util::hex;
+
+use super::block;
+use super::boundary;
+use super::chain_state::Utxos;
+use super::normal;
+use super::sign;
+use super::types::{self, HeaderHash};
+use super::update;
+
+fn hex_cbor<T: se::Serialize>(x: &T) -> String {
+    hex::encode(&cbor!(x).unwrap())
+}
+
+fn attributes(attributes: &types::Attributes) -> BTreeMap<u8, String> {
+    attributes
+        .unknown
+        .iter()
+        .map(|(key, value)| (*key, hex::encode(value)))
+        .collect()
+}
+
+/// A boundary or a main block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Boundary(BoundaryBlock),
+    Main(Box<MainBlock>),
+}
+
+impl Block {
+    /// The block with the inputs of its transactions resolved against
+    /// `utxos` and the outputs of the previous transactions of the block.
+    pub fn with_utxos(blk: &block::Block, utxos: &Utxos) -> Self {
+        Block::new(blk, Some(utxos))
+    }
+
+    fn new(blk: &block::Block, utxos: Option<&Utxos>) -> Self {
+        let hash = blk.header().compute_hash();
+        match blk {
+            block::Block::BoundaryBlock(blk) => Block::Boundary(BoundaryBlock {
+                header: BoundaryBlockHeader::new(hash, &blk.header),
+                body: BoundaryBody::from(&blk.body),
+                extra_attributes: attributes(&blk.extra.attributes),
+            }),
+            block::Block::MainBlock(blk) => Block::Main(Box::new(MainBlock {
+                header: MainBlockHeader::new(hash, &blk.header),
+                body: MainBody::new(&blk.body, utxos),
+                extra_attributes: attributes(&blk.extra.attributes),
+            })),
+        }
+    }
+}
+
+impl<'a> From<&'a block::Block> for Block {
+    fn from(blk: &'a block::Block) -> Self {
+        Block::new(blk, None)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoundaryBlock {
+    pub header: BoundaryBlockHeader,
+    pub body: BoundaryBody,
+    pub extra_attributes: BTreeMap<u8, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MainBlock {
+    pub header: MainBlockHeader,
+    pub body: MainBody,
+    pub extra_attributes: BTreeMap<u8, String>,
+}
+
+/// The header of a boundary or of a main block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlockHeader {
+    Boundary(BoundaryBlockHeader),
+    Main(Box<MainBlockHeader>),
+}
+
+impl<'a> From<&'a block::BlockHeader> for BlockHeader {
+    fn from(header: &'a block::BlockHeader) -> Self {
+        let hash = header.compute_hash();
+        match header {
+            block::BlockHeader::BoundaryBlockHeader(header) => {
+                BlockHeader::Boundary(BoundaryBlockHeader::new(hash, header))
+            }
+            block::BlockHeader::MainBlockHeader(header) => {
+                BlockHeader::Main(Box::new(MainBlockHeader::new(hash, header)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoundaryBlockHeader {
+    pub hash: String,
+    pub protocol_magic: u32,
+    pub previous_header: String,
+    /// the hash of the body
+    pub body_proof: String,
+    pub epoch: types::EpochId,
+    pub chain_difficulty: u64,
+    pub attributes: BTreeMap<u8, String>,
+}
+
+impl BoundaryBlockHeader {
+    fn new(hash: HeaderHash, header: &boundary::BlockHeader) -> Self {
+        BoundaryBlockHeader {
+            hash: hash.to_string(),
+            protocol_magic: *header.protocol_magic,
+            previous_header: header.previous_header.to_string(),
+            body_proof: header.body_proof.0.to_string(),
+            epoch: header.consensus.epoch,
+            chain_difficulty: header.consensus.chain_difficulty.into(),
+            attributes: attributes(&header.extra_data.attributes),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MainBlockHeader {
+    pub hash: String,
+    pub protocol_magic: u32,
+    pub previous_header: String,
+    pub body_proof: MainBodyProof,
+    pub epoch: types::EpochId,
+    pub slot: types::SlotId,
+    pub leader_key: String,
+    pub chain_difficulty: u64,
+    pub signature: BlockSignature,
+    pub block_version: BlockVersion,
+    pub software_version: SoftwareVersion,
+    pub attributes: BTreeMap<u8, String>,
+    /// the hash of the extra data of the block
+    pub extra_data_proof: String,
+}
+
+impl MainBlockHeader {
+    fn new(hash: HeaderHash, header: &normal::BlockHeader) -> Self {
+        MainBlockHeader {
+            hash: hash.to_string(),
+            protocol_magic: *header.protocol_magic,
+            previous_header: header.previous_header.to_string(),
+            body_proof: MainBodyProof::from(&header.body_proof),
+            epoch: header.consensus.slot_id.epoch,
+            slot: header.consensus.slot_id.slotid,
+            leader_key: header.consensus.leader_key.to_string(),
+            chain_difficulty: header.consensus.chain_difficulty.into(),
+            signature: BlockSignature::from(&header.consensus.block_signature),
+            block_version: BlockVersion::from(&header.extra_data.block_version),
+            software_version: SoftwareVersion::from(&header.extra_data.software_version),
+            attributes: attributes(&header.extra_data.attributes),
+            extra_data_proof: header.extra_data.extra_data_proof.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MainBodyProof {
+    pub tx: TxProof,
+    pub ssc: SscProof,
+    /// the hash of the delegation payload
+    pub delegation: String,
+    /// the hash of the update payload
+    pub update: String,
+}
+
+impl<'a> From<&'a normal::BodyProof> for MainBodyProof {
+    fn from(proof: &'a normal::BodyProof) -> Self {
+        MainBodyProof {
+            tx: TxProof::from(&proof.tx),
+            ssc: SscProof::from(&proof.mpc),
+            delegation: proof.delegation.0.to_string(),
+            update: proof.update.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxProof {
+    /// the number of transactions
+    pub number: u32,
+    /// the root of the merkle tree of the transactions
+    pub root: String,
+    pub witnesses_hash: String,
+}
+
+impl<'a> From<&'a tx::TxProof> for TxProof {
+    fn from(proof: &'a tx::TxProof) -> Self {
+        TxProof {
+            number: proof.number,
+            root: proof.root.to_string(),
+            witnesses_hash: proof.witnesses_hash.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SscProof {
+    Commitments {
+        payload_hash: String,
+        certificates_hash: String,
+    },
+    Openings {
+        payload_hash: String,
+        certificates_hash: String,
+    },
+    Shares {
+        payload_hash: String,
+        certificates_hash: String,
+    },
+    Certificates {
+        certificates_hash: String,
+    },
+}
+
+impl<'a> From<&'a types::SscProof> for SscProof {
+    fn from(proof: &'a types::SscProof) -> Self {
+        match proof {
+            types::SscProof::Commitments(payload, certs) => SscProof::Commitments {
+                payload_hash: payload.to_string(),
+                certificates_hash: certs.to_string(),
+            },
+            types::SscProof::Openings(payload, certs) => SscProof::Openings {
+                payload_hash: payload.to_string(),
+                certificates_hash: certs.to_string(),
+            },
+            types::SscProof::Shares(payload, certs) => SscProof::Shares {
+                payload_hash: payload.to_string(),
+                certificates_hash: certs.to_string(),
+            },
+            types::SscProof::Certificate(certs) => SscProof::Certificates {
+                certificates_hash: certs.to_string(),
+            },
+        }
+    }
+}
+
+/// The signature of a main block, by its slot leader or by a delegate of
+/// the slot leader.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlockSignature {
+    Signature {
+        signature: String,
+    },
+    ProxyLight {
+        delegation: LightDelegationCertificate,
+        signature: String,
+    },
+    ProxyHeavy {
+        delegation: DelegationCertificate,
+        signature: String,
+    },
+}
+
+impl<'a> From<&'a sign::BlockSignature> for BlockSignature {
+    fn from(signature: &'a sign::BlockSignature) -> Self {
+        match signature {
+            sign::BlockSignature::Signature(sig) => BlockSignature::Signature {
+                signature: sig.to_string(),
+            },
+            sign::BlockSignature::ProxyLight(sig) => BlockSignature::ProxyLight {
+                delegation: LightDelegationCertificate::from(&sig.psk),
+                signature: sig.sig.to_string(),
+            },
+            sign::BlockSignature::ProxyHeavy(sig) => BlockSignature::ProxyHeavy {
+                delegation: DelegationCertificate::from(&sig.psk),
+                signature: sig.sig.to_string(),
+            },
+        }
+    }
+}
+
+/// A heavyweight delegation certificate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DelegationCertificate {
+    /// the epoch the delegation starts at
+    pub omega: u64,
+    pub issuer_pk: String,
+    pub delegate_pk: String,
+    pub certificate: String,
+}
+
+impl<'a> From<&'a sign::ProxySecretKey> for DelegationCertificate {
+    fn from(psk: &'a sign::ProxySecretKey) -> Self {
+        DelegationCertificate {
+            omega: psk.omega,
+            issuer_pk: psk.issuer_pk.to_string(),
+            delegate_pk: psk.delegate_pk.to_string(),
+            certificate: psk.cert.to_string(),
+        }
+    }
+}
+
+/// A lightweight delegation certificate, valid from the epoch
+/// `epoch_start` to the epoch `epoch_end` (inclusive).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightDelegationCertificate {
+    pub epoch_start: types::EpochId,
+    pub epoch_end: types::EpochId,
+    pub issuer_pk: String,
+    pub delegate_pk: String,
+    pub certificate: String,
+}
+
+impl<'a> From<&'a sign::LightProxySecretKey> for LightDelegationCertificate {
+    fn from(psk: &'a sign::LightProxySecretKey) -> Self {
+        LightDelegationCertificate {
+            epoch_start: psk.omega.start,
+            epoch_end: psk.omega.end,
+            issuer_pk: psk.issuer_pk.to_string(),
+            delegate_pk: psk.delegate_pk.to_string(),
+            certificate: psk.cert.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub alt: u8,
+}
+
+impl<'a> From<&'a types::BlockVersion> for BlockVersion {
+    fn from(version: &'a types::BlockVersion) -> Self {
+        BlockVersion {
+            major: version.major,
+            minor: version.minor,
+            alt: version.alt,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SoftwareVersion {
+    pub application_name: String,
+    pub application_version: u32,
+}
+
+impl<'a> From<&'a types::SoftwareVersion> for SoftwareVersion {
+    fn from(version: &'a types::SoftwareVersion) -> Self {
+        SoftwareVersion {
+            application_name: version.application_name().clone(),
+            application_version: version.application_version,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoundaryBody {
+    /// the stakeholder ids of the slot leaders of the epoch
+    pub slot_leaders: Vec<String>,
+}
+
+impl<'a> From<&'a boundary::Body> for BoundaryBody {
+    fn from(body: &'a boundary::Body) -> Self {
+        BoundaryBody {
+            slot_leaders: body.slot_leaders.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MainBody {
+    pub transactions: Vec<Transaction>,
+    pub ssc: SscPayload,
+    pub delegation: Vec<DelegationCertificate>,
+    pub update: UpdatePayload,
+}
+
+impl MainBody {
+    fn new(body: &normal::Body, utxos: Option<&Utxos>) -> Self {
+        let mut transactions = vec![];
+        // the outputs of the previous transactions of the block
+        let mut outputs = Utxos::new();
+        for txaux in body.tx.iter() {
+            transactions.push(Transaction::new(txaux, |ptr| {
+                utxos.and_then(|utxos| outputs.get(ptr).or_else(|| utxos.get(ptr)))
+            }));
+            let id = txaux.tx.id();
+            for (index, output) in txaux.tx.outputs.iter().enumerate() {
+                outputs.insert(TxoPointer::new(id, index as u32), output.clone());
+            }
+        }
+        MainBody {
+            transactions,
+            ssc: SscPayload::from(&body.ssc),
+            delegation: body
+                .delegation
+                .iter()
+                .map(DelegationCertificate::from)
+                .collect(),
+            update: UpdatePayload::from(&body.update),
+        }
+    }
+}
+
+/// A transaction with its witnesses.
+///
+/// When its inputs are resolved, each input holds the output it spends,
+/// and `fee` is the difference between the inputs and the outputs.
+/// Otherwise they are `null`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: String,
+    pub inputs: Vec<TxInput>,
+    pub outputs: Vec<TxOutput>,
+    pub witnesses: Vec<TxInWitness>,
+    pub fee: Option<u64>,
+}
+
+impl Transaction {
+    /// The transaction with its inputs resolved against `utxos`.
+    pub fn with_utxos(txaux: &TxAux, utxos: &Utxos) -> Self {
+        Transaction::new(txaux, |ptr| utxos.get(ptr))
+    }
+
+    fn new<'u, F>(txaux: &TxAux, resolve: F) -> Self
+    where
+        F: Fn(&TxoPointer) -> Option<&'u tx::TxOut>,
+    {
+        let resolved: Vec<_> = txaux.tx.inputs.iter().map(resolve).collect();
+        let fee = if resolved.iter().all(Option::is_some) {
+            let inputs = resolved.iter().filter_map(|output| output.map(|o| o.value));
+            let outputs = txaux.tx.outputs.iter().map(|output| output.value);
+            match (coin::sum_coins(inputs), coin::sum_coins(outputs)) {
+                (Ok(inputs), Ok(outputs)) => (inputs - outputs).ok().map(u64::from),
+                _ => None,
+            }
+        } else {
+            None
+        };
+        Transaction {
+            id: txaux.tx.id().to_string(),
+            inputs: txaux
+                .tx
+                .inputs
+                .iter()
+                .zip(resolved)
+                .map(|(ptr, output)| TxInput {
+                    id: ptr.id.to_string(),
+                    index: ptr.index,
+                    output: output.map(TxOutput::from),
+                })
+                .collect(),
+            outputs: txaux.tx.outputs.iter().map(TxOutput::from).collect(),
+            witnesses: txaux.witness.iter().map(TxInWitness::from).collect(),
+            fee,
+        }
+    }
+}
+
+impl<'a> From<&'a TxAux> for Transaction {
+    fn from(txaux: &'a TxAux) -> Self {
+        Transaction::new(txaux, |_| None)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxInput {
+    /// the id of the transaction of the spent output
+    pub id: String,
+    pub index: u32,
+    /// the spent output, if resolved
+    pub output: Option<TxOutput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxOutput {
+    pub address: String,
+    pub value: u64,
+}
+
+impl<'a> From<&'a tx::TxOut> for TxOutput {
+    fn from(output: &'a tx::TxOut) -> Self {
+        TxOutput {
+            address: output.address.to_string(),
+            value: output.value.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TxInWitness {
+    Pk {
+        public_key: String,
+        signature: String,
+    },
+    Script {
+        validator: String,
+        redeemer: String,
+    },
+    Redeem {
+        public_key: String,
+        signature: String,
+    },
+}
+
+impl<'a> From<&'a tx::TxInWitness> for TxInWitness {
+    fn from(witness: &'a tx::TxInWitness) -> Self {
+        match witness {
+            tx::TxInWitness::PkWitness(xpub, signature) => TxInWitness::Pk {
+                public_key: xpub.to_string(),
+                signature: signature.to_string(),
+            },
+            tx::TxInWitness::ScriptWitness(validator, redeemer) => TxInWitness::Script {
+                validator: hex_cbor(validator),
+                redeemer: hex_cbor(redeemer),
+            },
+            tx::TxInWitness::RedeemWitness(public_key, signature) => TxInWitness::Redeem {
+                public_key: public_key.to_string(),
+                signature: signature.to_string(),
+            },
+        }
+    }
+}
+
+/// The shared seed computation payload, with the VSS certificates of the
+/// block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SscPayload {
+    Commitments {
+        commitments: Vec<SignedCommitment>,
+        certificates: Vec<VssCertificate>,
+    },
+    Openings {
+        /// the openings by stakeholder id
+        openings: BTreeMap<String, String>,
+        certificates: Vec<VssCertificate>,
+    },
+    Shares {
+        /// the decrypted shares by stakeholder id, then by stakeholder id
+        /// of the sender
+        shares: BTreeMap<String, BTreeMap<String, String>>,
+        certificates: Vec<VssCertificate>,
+    },
+    Certificates {
+        certificates: Vec<VssCertificate>,
+    },
+}
+
+impl<'a> From<&'a normal::SscPayload> for SscPayload {
+    fn from(ssc: &'a normal::SscPayload) -> Self {
+        let certificates = ssc
+            .get_vss_certificates()
+            .iter()
+            .map(VssCertificate::from)
+            .collect();
+        match ssc {
+            normal::SscPayload::CommitmentsPayload(commitments, _) => SscPayload::Commitments {
+                commitments: commitments.iter().map(SignedCommitment::from).collect(),
+                certificates,
+            },
+            normal::SscPayload::OpeningsPayload(openings, _) => SscPayload::Openings {
+                openings: openings
+                    .iter()
+                    .map(|(id, opening)| (id.to_string(), hex_cbor(opening)))
+                    .collect(),
+                certificates,
+            },
+            normal::SscPayload::SharesPayload(shares, _) => SscPayload::Shares {
+                shares: shares
+                    .iter()
+                    .map(|(id, shares)| {
+                        let shares = shares
+                            .iter()
+                            .map(|(id, share)| (id.to_string(), hex_cbor(share)))
+                            .collect();
+                        (id.to_string(), shares)
+                    })
+                    .collect(),
+                certificates,
+            },
+            normal::SscPayload::CertificatesPayload(_) => SscPayload::Certificates { certificates },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedCommitment {
+    pub public_key: String,
+    pub proof: SecretProof,
+    /// the encrypted shares by VSS public key
+    pub shares: BTreeMap<String, String>,
+    pub signature: String,
+}
+
+impl<'a> From<&'a normal::SignedCommitment> for SignedCommitment {
+    fn from(commitment: &'a normal::SignedCommitment) -> Self {
+        SignedCommitment {
+            public_key: commitment.public_key.to_string(),
+            proof: SecretProof::from(&commitment.commitment.proof),
+            shares: commitment
+                .commitment
+                .shares
+                .iter()
+                .map(|(key, share)| (hex::encode(&key.0), hex_cbor(share)))
+                .collect(),
+            signature: commitment.signature.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretProof {
+    pub extra_gen: String,
+    pub proof: String,
+    pub parallel_proofs: String,
+    pub commitments: Vec<String>,
+}
+
+impl<'a> From<&'a normal::SecretProof> for SecretProof {
+    fn from(proof: &'a normal::SecretProof) -> Self {
+        SecretProof {
+            extra_gen: hex_cbor(&proof.extra_gen),
+            proof: hex_cbor(&proof.proof),
+            parallel_proofs: hex_cbor(&proof.parallel_proofs),
+            commitments: proof.commitments.iter().map(hex_cbor).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VssCertificate {
+    pub vss_key: String,
+    pub expiry_epoch: types::EpochId,
+    pub signature: String,
+    pub signing_key: String,
+}
+
+impl<'a> From<&'a normal::VssCertificate> for VssCertificate {
+    fn from(cert: &'a normal::VssCertificate) -> Self {
+        VssCertificate {
+            vss_key: hex::encode(&cert.vss_key.0),
+            expiry_epoch: cert.expiry_epoch,
+            signature: cert.signature.to_string(),
+            signing_key: cert.signing_key.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdatePayload {
+    pub proposal: Option<UpdateProposal>,
+    pub votes: Vec<UpdateVote>,
+}
+
+impl<'a> From<&'a update::UpdatePayload> for UpdatePayload {
+    fn from(payload: &'a update::UpdatePayload) -> Self {
+        UpdatePayload {
+            proposal: payload.proposal.as_ref().map(UpdateProposal::from),
+            votes: payload.votes.iter().map(UpdateVote::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateProposal {
+    /// the identifier the votes refer to the proposal with
+    pub id: String,
+    pub block_version: BlockVersion,
+    pub block_version_mod: BlockVersionModifier,
+    pub software_version: SoftwareVersion,
+    /// the update data by system tag
+    pub data: BTreeMap<String, UpdateData>,
+    pub attributes: BTreeMap<u8, String>,
+    pub from: String,
+    pub signature: String,
+}
+
+impl<'a> From<&'a update::UpdateProposal> for UpdateProposal {
+    fn from(proposal: &'a update::UpdateProposal) -> Self {
+        UpdateProposal {
+            id: proposal.id().to_string(),
+            block_version: BlockVersion::from(&proposal.block_version),
+            block_version_mod: BlockVersionModifier::from(&proposal.block_version_mod),
+            software_version: SoftwareVersion::from(&proposal.software_version),
+            data: proposal
+                .data
+                .iter()
+                .map(|(tag, data)| (tag.clone(), UpdateData::from(data)))
+                .collect(),
+            attributes: attributes(&proposal.attributes),
+            from: proposal.from.to_string(),
+            signature: proposal.signature.to_string(),
+        }
+    }
+}
+
+/// The changes of the block version parameters proposed by an update,
+/// the unchanged parameters being `null`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockVersionModifier {
+    pub script_version: Option<update::ScriptVersion>,
+    /// in milliseconds
+    pub slot_duration: Option<update::Millisecond>,
+    pub max_block_size: Option<u64>,
+    pub max_header_size: Option<u64>,
+    pub max_tx_size: Option<u64>,
+    pub max_proposal_size: Option<u64>,
+    pub mpc_thd: Option<u64>,
+    pub heavy_del_thd: Option<u64>,
+    pub update_vote_thd: Option<u64>,
+    pub update_proposal_thd: Option<u64>,
+    pub update_implicit: Option<update::FlatSlotId>,
+    pub softfork_rule: Option<SoftforkRule>,
+    pub tx_fee_policy: Option<TxFeePolicy>,
+    pub unlock_stake_epoch: Option<types::EpochId>,
+}
+
+impl<'a> From<&'a update::BlockVersionModifier> for BlockVersionModifier {
+    fn from(modifier: &'a update::BlockVersionModifier) -> Self {
+        BlockVersionModifier {
+            script_version: modifier.script_version,
+            slot_duration: modifier.slot_duration,
+            max_block_size: modifier.max_block_size,
+            max_header_size: modifier.max_header_size,
+            max_tx_size: modifier.max_tx_size,
+            max_proposal_size: modifier.max_proposal_size,
+            mpc_thd: modifier.mpc_thd.map(u64::from),
+            heavy_del_thd: modifier.heavy_del_thd.map(u64::from),
+            update_vote_thd: modifier.update_vote_thd.map(u64::from),
+            update_proposal_thd: modifier.update_proposal_thd.map(u64::from),
+            update_implicit: modifier.update_implicit,
+            softfork_rule: modifier.softfork_rule.as_ref().map(SoftforkRule::from),
+            tx_fee_policy: modifier.tx_fee_policy.as_ref().map(TxFeePolicy::from),
+            unlock_stake_epoch: modifier.unlock_stake_epoch,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SoftforkRule {
+    pub init_thd: u64,
+    pub min_thd: u64,
+    pub thd_decrement: u64,
+}
+
+impl<'a> From<&'a update::SoftforkRule> for SoftforkRule {
+    fn from(rule: &'a update::SoftforkRule) -> Self {
+        SoftforkRule {
+            init_thd: rule.init_thd.into(),
+            min_thd: rule.min_thd.into(),
+            thd_decrement: rule.thd_decrement.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TxFeePolicy {
+    /// `summand + multiplier * size_in_bytes`, both expressed in 10^-9
+    /// lovelace.
+    TxSizeLinear { summand: u64, multiplier: u64 },
+    /// a policy this library does not know about, with the hexadecimal
+    /// of its encoded parameters.
+    Unknown { tag: u64, parameters: String },
+}
+
+impl<'a> From<&'a update::TxFeePolicy> for TxFeePolicy {
+    fn from(policy: &'a update::TxFeePolicy) -> Self {
+        match policy {
+            update::TxFeePolicy::TxSizeLinear {
+                summand,
+                multiplier,
+            } => TxFeePolicy::TxSizeLinear {
+                summand: *summand,
+                multiplier: *multiplier,
+            },
+            update::TxFeePolicy::Unknown(tag, parameters) => TxFeePolicy::Unknown {
+                tag: *tag,
+                parameters: hex::encode(parameters),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateData {
+    pub app_diff_hash: String,
+    pub pkg_hash: String,
+    pub updater_hash: String,
+    pub metadata_hash: String,
+}
+
+impl<'a> From<&'a update::UpdateData> for UpdateData {
+    fn from(data: &'a update::UpdateData) -> Self {
+        UpdateData {
+            app_diff_hash: data.app_diff_hash.to_string(),
+            pkg_hash: data.pkg_hash.to_string(),
+            updater_hash: data.updater_hash.to_string(),
+            metadata_hash: data.metadata_hash.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateVote {
+    pub key: String,
+    pub proposal_id: String,
+    pub decision: bool,
+    pub signature: String,
+}
+
+impl<'a> From<&'a update::UpdateVote> for UpdateVote {
+    fn from(vote: &'a update::UpdateVote) -> Self {
+        UpdateVote {
+            key: vote.key.to_string(),
+            proposal_id: vote.proposal_id.to_string(),
+            decision: vote.decision,
+            signature: vote.signature.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use address::{self, StakeholderId};
+    use block::builder::{make_boundary_block, BlockIssuer, MainBlockBuilder};
+    use block::fts::SharedSeed;
+    use block::update::BlockVersionData;
+    use block::{ChainState, EpochSlotId};
+    use cbor_event::Value;
+    use coin::Coin;
+    use config::{GenesisData, ProtocolMagic};
+    use fee;
+    use hash::Blake2b256;
+    use hdwallet::{Seed, XPrv};
+    use redeem;
+    use serde;
+    use serde_json;
+    use std::time::SystemTime;
+    use tx::{Tx, TxOut, TxWitness};
+    use vss;
+
+    fn xprv(seed: u8) -> XPrv {
+        XPrv::generate_from_seed(&Seed::from_bytes([seed; 32]))
+    }
+
+    fn stakeholder(seed: u8) -> StakeholderId {
+        StakeholderId::new(&xprv(seed).public())
+    }
+
+    fn redeem_key() -> redeem::PrivateKey {
+        redeem::PrivateKey::generate(&[7; 32]).unwrap()
+    }
+
+    fn genesis_data() -> GenesisData {
+        let mut avvm_distr = BTreeMap::new();
+        avvm_distr.insert(redeem_key().public(), Coin::new(1000).unwrap());
+        GenesisData {
+            genesis_prev: HeaderHash::new(b"genesis"),
+            epoch_stability_depth: 10,
+            vss_min_ttl: 2,
+            vss_max_ttl: 6,
+            start_time: SystemTime::UNIX_EPOCH,
+            protocol_magic: ProtocolMagic::from(633343913),
+            block_version_data: BlockVersionData::mainnet(20_000, fee::LinearFee::default()),
+            avvm_distr,
+            non_avvm_balances: BTreeMap::new(),
+            boot_stakeholders: BTreeMap::new(),
+            fts_seed: SharedSeed::default(),
+        }
+    }
+
+    fn output(seed: u8, pm: ProtocolMagic, value: u64) -> TxOut {
+        TxOut::new(
+            address::ExtendedAddr::new(
+                address::AddrType::ATPubKey,
+                address::SpendingData::PubKeyASD(xprv(seed).public()),
+                address::Attributes::new_bootstrap_era(None, pm.into()),
+            ),
+            Coin::new(value).unwrap(),
+        )
+    }
+
+    /// a transaction redeeming the AVVM utxo, and a transaction spending
+    /// its output
+    fn transactions(pm: ProtocolMagic) -> Vec<TxAux> {
+        let (id, _) = tx::redeem_pubkey_to_txid(&redeem_key().public(), pm);
+        let tx = Tx::new_with(vec![TxoPointer::new(id, 0)], vec![output(2, pm, 900)]);
+        let witness = tx::TxInWitness::new_redeem_pk(pm, &redeem_key(), &tx.id());
+        let redeem = TxAux::new(tx, TxWitness::from(vec![witness]));
+
+        let tx = Tx::new_with(
+            vec![TxoPointer::new(redeem.tx.id(), 0)],
+            vec![output(3, pm, 500), output(2, pm, 300)],
+        );
+        let witness = tx::TxInWitness::new_extended_pk(pm, &xprv(2), &tx.id());
+        vec![redeem, TxAux::new(tx, TxWitness::from(vec![witness]))]
+    }
+
+    fn ssc() -> normal::SscPayload {
+        let mut openings = BTreeMap::new();
+        openings.insert(stakeholder(1), Value::U64(42));
+        normal::SscPayload::OpeningsPayload(
+            normal::OpeningsMap::new(openings),
+            normal::VssCertificates::new(vec![normal::VssCertificate {
+                vss_key: vss::PublicKey(vec![1; 35]),
+                expiry_epoch: 3,
+                signature: vss::Signature::from_bytes([2; 64]),
+                signing_key: xprv(1).public(),
+            }]),
+        )
+    }
+
+    fn update() -> update::UpdatePayload {
+        let mut data = BTreeMap::new();
+        data.insert(
+            "linux".to_string(),
+            update::UpdateData {
+                app_diff_hash: Blake2b256::new(b"app diff"),
+                pkg_hash: Blake2b256::new(b"pkg"),
+                updater_hash: Blake2b256::new(b"updater"),
+                metadata_hash: Blake2b256::new(b"metadata"),
+            },
+        );
+        let proposal = update::UpdateProposal {
+            block_version: types::BlockVersion::new(0, 1, 0),
+            block_version_mod: update::BlockVersionModifier {
+                script_version: None,
+                slot_duration: None,
+                max_block_size: Some(2_000_000),
+                max_header_size: None,
+                max_tx_size: None,
+                max_proposal_size: None,
+                mpc_thd: None,
+                heavy_del_thd: None,
+                update_vote_thd: None,
+                update_proposal_thd: None,
+                update_implicit: None,
+                softfork_rule: None,
+                tx_fee_policy: Some(update::TxFeePolicy::TxSizeLinear {
+                    summand: 155_381_000_000_000,
+                    multiplier: 43_946_000_000,
+                }),
+                unlock_stake_epoch: None,
+            },
+            software_version: types::SoftwareVersion::new("cardano-sl", 2).unwrap(),
+            data,
+            attributes: types::Attributes::new(),
+            from: xprv(1).public(),
+            signature: xprv(1).sign(b"not checked"),
+        };
+        let vote = update::UpdateVote {
+            key: xprv(2).public(),
+            proposal_id: proposal.id(),
+            decision: true,
+            signature: xprv(2).sign(b"not checked"),
+        };
+        update::UpdatePayload {
+            proposal: Some(proposal),
+            votes: vec![vote],
+        }
+    }
+
+    fn check_golden<T: serde::Serialize>(value: &T, golden: &str) -> serde_json::Value {
+        let expected: serde_json::Value = serde_json::from_str(golden).unwrap();
+        let got = serde_json::to_value(value).unwrap();
+        assert!(
+            got == expected,
+            "unexpected JSON:\n{}",
+            serde_json::to_string_pretty(value).unwrap()
+        );
+        got
+    }
+
+    #[test]
+    fn boundary_block_json() {
+        let chain_state = ChainState::new(&genesis_data());
+        let blk = make_boundary_block(&chain_state, 0, vec![stakeholder(1), stakeholder(2)]);
+        let json = Block::from(&blk);
+        let value = check_golden(
+            &json,
+            include_str!("../../test-vectors/json/boundary_block.json"),
+        );
+        assert_eq!(serde_json::from_value::<Block>(value).unwrap(), json);
+    }
+
+    #[test]
+    fn main_block_json() {
+        let genesis = genesis_data();
+        let pm = genesis.protocol_magic;
+        let mut chain_state = ChainState::new(&genesis);
+        // the slot leaders of the test blocks are not elected
+        chain_state.ssc.seed = None;
+        let blk = make_boundary_block(&chain_state, 0, vec![stakeholder(1), stakeholder(2)]);
+        chain_state
+            .verify_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+
+        let psk = sign::ProxySecretKey::sign(&xprv(1), xprv(4).public(), 0, pm);
+        let mut builder = MainBlockBuilder::new(EpochSlotId {
+            epoch: 0,
+            slotid: 0,
+        });
+        for txaux in transactions(pm) {
+            builder.add_tx(txaux);
+        }
+        builder.add_delegation(psk.clone());
+        builder.set_ssc(ssc());
+        builder.set_update(update());
+        let blk = builder.make_block(&chain_state, &BlockIssuer::HeavyDelegate(&xprv(4), psk));
+
+        let json = Block::with_utxos(&blk, &chain_state.utxos);
+        let value = check_golden(
+            &json,
+            include_str!("../../test-vectors/json/main_block.json"),
+        );
+        assert_eq!(serde_json::from_value::<Block>(value).unwrap(), json);
+
+        match (&json, Block::from(&blk)) {
+            (Block::Main(resolved), Block::Main(unresolved)) => {
+                let header = BlockHeader::from(&blk.header().into());
+                assert_eq!(header, BlockHeader::Main(Box::new(resolved.header.clone())));
+                assert_eq!(resolved.header, unresolved.header);
+                for tx in unresolved.body.transactions.iter() {
+                    assert_eq!(tx.fee, None);
+                    assert!(tx.inputs.iter().all(|input| input.output.is_none()));
+                }
+            }
+            _ => panic!("expected a main block"),
+        }
+    }
+
+    #[test]
+    fn transaction_fee_needs_all_inputs() {
+        let genesis = genesis_data();
+        let chain_state = ChainState::new(&genesis);
+        let txs = transactions(genesis.protocol_magic);
+
+        let redeem = Transaction::with_utxos(&txs[0], &chain_state.utxos);
+        assert_eq!(redeem.fee, Some(100));
+        assert_eq!(redeem.inputs[0].output.as_ref().unwrap().value, 1000);
+
+        // the output spent by the second transaction is not in the utxos
+        let spend = Transaction::with_utxos(&txs[1], &chain_state.utxos);
+        assert_eq!(spend.fee, None);
+        assert_eq!(spend.inputs[0].output, None);
+        assert_eq!(spend, Transaction::from(&txs[1]));
+    }
+}
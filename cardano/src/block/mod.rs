@@ -5,14 +5,25 @@
 
 pub mod block;
 pub mod boundary; /* boundary block related value */
+pub mod builder;
+pub mod chain_selection;
 pub mod chain_state;
 pub mod date;
+pub mod fts;
+pub mod header_chain_state;
+#[cfg(feature = "generic-serialization")]
+pub mod json;
 pub mod normal; /* normal block related value */
 pub mod sign;
+pub mod slotting;
+pub mod ssc_state;
+pub mod stake_state;
 pub mod types;
 pub mod update;
+pub mod update_state;
 pub mod verify;
 pub mod verify_chain;
+pub mod view;
 
 pub use block::block::*;
 pub use block::chain_state::*;
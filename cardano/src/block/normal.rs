@@ -4,9 +4,9 @@ use std::{
     fmt,
     io::{BufRead, Write},
 };
-use {address, hash::Blake2b256, hdwallet, tx, vss};
+use {address, hash::Blake2b256, hdwallet, merkle, tx, vss};
 
-use super::sign::BlockSignature;
+use super::sign::{self, BlockSignature};
 use super::types;
 use super::types::{ChainDifficulty, EpochSlotId, HeaderExtraData, HeaderHash, SscProof};
 use super::update;
@@ -127,6 +127,14 @@ impl TxPayload {
     pub fn iter<'a>(&'a self) -> std::slice::Iter<'a, tx::TxAux> {
         self.txaux.iter()
     }
+
+    /// The proof that the transaction at `index` is included in the
+    /// payload, to be checked with `TxProof::verify_inclusion` against
+    /// the body proof of the header.
+    pub fn make_inclusion_proof(&self, index: usize) -> Option<merkle::MerkleProof> {
+        let txs: Vec<&tx::Tx> = self.txaux.iter().map(|txaux| &txaux.tx).collect();
+        merkle::MerkleTree::new(&txs[..]).make_proof(index)
+    }
 }
 impl IntoIterator for TxPayload {
     type Item = <Vec<tx::TxAux> as IntoIterator>::Item;
@@ -318,8 +326,15 @@ impl cbor_event::de::Deserialize for SscPayload {
     }
 }
 
+/// Heavyweight delegation certificates issued in a block
 #[derive(Debug, Clone)]
-pub struct DlgPayload(pub cbor_event::Value);
+pub struct DlgPayload(pub Vec<sign::ProxySecretKey>);
+
+impl DlgPayload {
+    pub fn iter(&self) -> ::std::slice::Iter<'_, sign::ProxySecretKey> {
+        self.0.iter()
+    }
+}
 
 impl cbor_event::de::Deserialize for DlgPayload {
     fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
@@ -332,13 +347,17 @@ impl cbor_event::se::Serialize for DlgPayload {
         &self,
         serializer: &'se mut Serializer<W>,
     ) -> cbor_event::Result<&'se mut Serializer<W>> {
-        serializer.serialize(&self.0)
+        cbor_event::se::serialize_indefinite_array(self.0.iter(), serializer)
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Commitments(Vec<SignedCommitment>);
 impl Commitments {
+    pub fn new(commitments: Vec<SignedCommitment>) -> Self {
+        Commitments(commitments)
+    }
+
     pub fn iter(&self) -> ::std::slice::Iter<SignedCommitment> {
         self.0.iter()
     }
@@ -476,6 +495,10 @@ impl cbor_event::de::Deserialize for EncShare {
 #[derive(Debug, Clone)]
 pub struct OpeningsMap(BTreeMap<address::StakeholderId, cbor_event::Value>);
 impl OpeningsMap {
+    pub fn new(openings: BTreeMap<address::StakeholderId, cbor_event::Value>) -> Self {
+        OpeningsMap(openings)
+    }
+
     pub fn iter(&self) -> btree_map::Iter<address::StakeholderId, cbor_event::Value> {
         self.0.iter()
     }
@@ -498,6 +521,10 @@ impl cbor_event::de::Deserialize for OpeningsMap {
 pub struct SharesMap(BTreeMap<address::StakeholderId, SharesSubMap>);
 pub type SharesSubMap = BTreeMap<address::StakeholderId, DecShare>;
 impl SharesMap {
+    pub fn new(shares: BTreeMap<address::StakeholderId, SharesSubMap>) -> Self {
+        SharesMap(shares)
+    }
+
     pub fn iter(&self) -> btree_map::Iter<address::StakeholderId, SharesSubMap> {
         self.0.iter()
     }
@@ -600,7 +627,7 @@ impl cbor_event::de::Deserialize for VssCertificates {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct VssCertificate {
     pub vss_key: vss::PublicKey,
     pub expiry_epoch: types::EpochId,
@@ -705,10 +732,10 @@ impl cbor_event::de::Deserialize for BlockHeader {
 pub struct Block {
     pub header: BlockHeader,
     pub body: Body,
-    pub extra: cbor_event::Value, // TODO: decode
+    pub extra: types::ExtraData,
 }
 impl Block {
-    pub fn new(h: BlockHeader, b: Body, e: cbor_event::Value) -> Self {
+    pub fn new(h: BlockHeader, b: Body, e: types::ExtraData) -> Self {
         Block {
             header: h,
             body: b,
@@ -793,4 +820,24 @@ mod tests {
             "DlgProof(Blake2b256(0x89eb0d6a8a691dae2cd15ed0369931ce0a949ecafa5c3f93f8121833646e15c3))",
         );
     }
+
+    #[test]
+    fn tx_inclusion_proofs() {
+        let txs: Vec<tx::TxAux> = (0..5u32)
+            .map(|i| {
+                let input = tx::TxoPointer::new(tx::TxId::new(&[i as u8]), i);
+                let tx = tx::Tx::new_with(vec![input], vec![]);
+                tx::TxAux::new(tx, tx::TxWitness::new())
+            })
+            .collect();
+        let payload = TxPayload::new(txs.clone());
+        let proof = tx::TxProof::generate(&payload);
+
+        for (i, txaux) in txs.iter().enumerate() {
+            let inclusion = payload.make_inclusion_proof(i).unwrap();
+            assert!(proof.verify_inclusion(&txaux.tx, &inclusion));
+            assert!(!proof.verify_inclusion(&txs[(i + 1) % 5].tx, &inclusion));
+        }
+        assert!(payload.make_inclusion_proof(5).is_none());
+    }
 }
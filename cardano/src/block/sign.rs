@@ -35,6 +35,22 @@ impl<'a> cbor_event::se::Serialize for MainToSign<'a> {
 }
 
 impl<'a> MainToSign<'a> {
+    pub fn new(
+        previous_header: &'a HeaderHash,
+        body_proof: &'a BodyProof,
+        slot: &'a EpochSlotId,
+        chain_difficulty: &'a ChainDifficulty,
+        extra_data: &'a HeaderExtraData,
+    ) -> Self {
+        MainToSign {
+            previous_header,
+            body_proof,
+            slot,
+            chain_difficulty,
+            extra_data,
+        }
+    }
+
     pub fn from_header(hdr: &'a normal::BlockHeader) -> Self {
         MainToSign {
             previous_header: &hdr.previous_header,
@@ -53,20 +69,81 @@ impl<'a> MainToSign<'a> {
     ) -> bool {
         verify_signature_with(protocol_magic, tag, proxy_sig, self)
     }
+
+    /// the bytes the delegate signs to create the given proxy signature
+    pub fn proxy_sig_data(
+        &self,
+        protocol_magic: ProtocolMagic,
+        tag: tags::SigningTag,
+        proxy_sig: &ProxySignature,
+    ) -> Vec<u8> {
+        signature_data_with(protocol_magic, tag, &proxy_sig.psk.issuer_pk, self)
+    }
+
+    /// the bytes the delegate signs to create the given lightweight
+    /// proxy signature
+    pub fn light_proxy_sig_data(
+        &self,
+        protocol_magic: ProtocolMagic,
+        proxy_sig: &LightProxySignature,
+    ) -> Vec<u8> {
+        signature_data_with(
+            protocol_magic,
+            tags::SigningTag::MainBlockLight,
+            &proxy_sig.psk.issuer_pk,
+            self,
+        )
+    }
+
+    /// the bytes the slot leader signs to create a plain block signature
+    pub fn signature_data(&self, protocol_magic: ProtocolMagic) -> Vec<u8> {
+        let mut buf = vec![tags::SigningTag::MainBlock as u8];
+        se::Serializer::new(&mut buf)
+            .serialize(&protocol_magic)
+            .unwrap()
+            .serialize(self)
+            .unwrap();
+        buf
+    }
+
+    /// create the plain signature of the block by its slot leader
+    pub fn sign(&self, protocol_magic: ProtocolMagic, leader: &hdwallet::XPrv) -> BlockSignature {
+        BlockSignature::Signature(leader.sign(&self.signature_data(protocol_magic)))
+    }
+
+    /// create the signature of the block by the heavyweight delegate of
+    /// its slot leader
+    pub fn sign_heavy(
+        &self,
+        protocol_magic: ProtocolMagic,
+        psk: ProxySecretKey,
+        delegate: &hdwallet::XPrv,
+    ) -> BlockSignature {
+        let data = signature_data_with(
+            protocol_magic,
+            tags::SigningTag::MainBlockHeavy,
+            &psk.issuer_pk,
+            self,
+        );
+        BlockSignature::ProxyHeavy(ProxySignature {
+            psk,
+            sig: delegate.sign(&data),
+        })
+    }
 }
 
-fn verify_signature_with<T>(
+fn signature_data_with<T>(
     protocol_magic: ProtocolMagic,
     tag: tags::SigningTag,
-    proxy_sig: &ProxySignature,
+    issuer_pk: &hdwallet::XPub,
     data: &T,
-) -> bool
+) -> Vec<u8>
 where
     T: se::Serialize,
 {
     let mut buf = vec!['0' as u8, '1' as u8];
 
-    buf.extend(proxy_sig.psk.issuer_pk.as_ref());
+    buf.extend(issuer_pk.as_ref());
     buf.push(tag as u8);
 
     se::Serializer::new(&mut buf)
@@ -75,6 +152,20 @@ where
         .serialize(data)
         .unwrap();
 
+    buf
+}
+
+fn verify_signature_with<T>(
+    protocol_magic: ProtocolMagic,
+    tag: tags::SigningTag,
+    proxy_sig: &ProxySignature,
+    data: &T,
+) -> bool
+where
+    T: se::Serialize,
+{
+    let buf = signature_data_with(protocol_magic, tag, &proxy_sig.psk.issuer_pk, data);
+
     proxy_sig.psk.delegate_pk.verify(
         &buf,
         &hdwallet::Signature::<()>::from_bytes(*proxy_sig.sig.to_bytes()),
@@ -129,8 +220,19 @@ impl ProxySecretKey {
     /// Verify that 'cert' is a signature from 'issuer_pk' over
     /// 'delegate_pk' and 'omega'.
     pub fn verify(&self, protocol_magic: ProtocolMagic) -> bool {
-        let buf = Self::data_to_sign(&self.delegate_pk, self.omega, protocol_magic);
-        self.issuer_pk.verify(&buf, &self.cert)
+        self.issuer_pk
+            .verify(&self.signed_data(protocol_magic), &self.cert)
+    }
+
+    /// the bytes 'cert' is a signature of
+    pub fn signed_data(&self, protocol_magic: ProtocolMagic) -> Vec<u8> {
+        Self::data_to_sign(&self.delegate_pk, self.omega, protocol_magic)
+    }
+
+    /// Whether this certificate revokes the issuer's previous
+    /// delegation rather than delegating to another key.
+    pub fn is_revocation(&self) -> bool {
+        self.issuer_pk == self.delegate_pk
     }
 
     /// Use 'issuer_prv' to sign 'delegate_pk' and 'omega' to create a
@@ -156,21 +258,142 @@ impl ProxySecretKey {
         omega: u64,
         protocol_magic: ProtocolMagic,
     ) -> Vec<u8> {
-        // Yes, this really is
-        // CBOR-in-byte-vector-in-CBOR-in-byte-vector...
-        let mut buf2 = vec!['0' as u8, '0' as u8];
-        buf2.extend(delegate_pk.as_ref());
-        se::Serializer::new(&mut buf2).serialize(&omega).unwrap();
-
-        let mut buf = vec![];
-        buf.push(tags::SigningTag::ProxySK as u8);
-        se::Serializer::new(&mut buf)
-            .serialize(&protocol_magic)
-            .unwrap()
-            .write_bytes(buf2)
-            .unwrap();
+        psk_data_to_sign(delegate_pk, &omega, protocol_magic)
+    }
+}
 
-        buf
+fn psk_data_to_sign<W: se::Serialize>(
+    delegate_pk: &hdwallet::XPub,
+    omega: &W,
+    protocol_magic: ProtocolMagic,
+) -> Vec<u8> {
+    // Yes, this really is
+    // CBOR-in-byte-vector-in-CBOR-in-byte-vector...
+    let mut buf2 = vec!['0' as u8, '0' as u8];
+    buf2.extend(delegate_pk.as_ref());
+    se::Serializer::new(&mut buf2).serialize(omega).unwrap();
+
+    let mut buf = vec![];
+    buf.push(tags::SigningTag::ProxySK as u8);
+    se::Serializer::new(&mut buf)
+        .serialize(&protocol_magic)
+        .unwrap()
+        .write_bytes(buf2)
+        .unwrap();
+
+    buf
+}
+
+/// The epochs (inclusive) a lightweight delegation certificate is
+/// valid for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LightDlgIndices {
+    pub start: EpochId,
+    pub end: EpochId,
+}
+
+impl LightDlgIndices {
+    pub fn new(start: EpochId, end: EpochId) -> Self {
+        LightDlgIndices { start, end }
+    }
+
+    pub fn contains(&self, epoch: EpochId) -> bool {
+        self.start <= epoch && epoch <= self.end
+    }
+}
+
+impl cbor_event::se::Serialize for LightDlgIndices {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        serializer
+            .write_array(cbor_event::Len::Len(2))?
+            .serialize(&self.start)?
+            .serialize(&self.end)
+    }
+}
+
+impl cbor_event::de::Deserialize for LightDlgIndices {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(2, "LightDlgIndices")?;
+        let start = cbor_event::de::Deserialize::deserialize(raw)?;
+        let end = cbor_event::de::Deserialize::deserialize(raw)?;
+        Ok(LightDlgIndices { start, end })
+    }
+}
+
+/// A lightweight delegation certificate: unlike `ProxySecretKey`, it
+/// is not published on the chain but only valid for a range of epochs.
+#[derive(Debug, Clone)]
+pub struct LightProxySecretKey {
+    pub omega: LightDlgIndices,
+    pub issuer_pk: hdwallet::XPub,
+    pub delegate_pk: hdwallet::XPub,
+    pub cert: ProxyCert,
+}
+
+impl cbor_event::se::Serialize for LightProxySecretKey {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        serializer
+            .write_array(cbor_event::Len::Len(4))?
+            .serialize(&self.omega)?
+            .serialize(&self.issuer_pk)?
+            .serialize(&self.delegate_pk)?
+            .serialize(&self.cert)
+    }
+}
+
+impl cbor_event::de::Deserialize for LightProxySecretKey {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(4, "LightProxySecretKey")?;
+
+        let omega = cbor_event::de::Deserialize::deserialize(raw)?;
+        let issuer_pk = cbor_event::de::Deserialize::deserialize(raw)?;
+        let delegate_pk = cbor_event::de::Deserialize::deserialize(raw)?;
+        let cert = cbor_event::de::Deserialize::deserialize(raw)?;
+
+        Ok(LightProxySecretKey {
+            omega,
+            issuer_pk,
+            delegate_pk,
+            cert,
+        })
+    }
+}
+
+impl LightProxySecretKey {
+    /// Verify that 'cert' is a signature from 'issuer_pk' over
+    /// 'delegate_pk' and 'omega'.
+    pub fn verify(&self, protocol_magic: ProtocolMagic) -> bool {
+        self.issuer_pk
+            .verify(&self.signed_data(protocol_magic), &self.cert)
+    }
+
+    /// the bytes 'cert' is a signature of
+    pub fn signed_data(&self, protocol_magic: ProtocolMagic) -> Vec<u8> {
+        psk_data_to_sign(&self.delegate_pk, &self.omega, protocol_magic)
+    }
+
+    /// Use 'issuer_prv' to sign 'delegate_pk' and 'omega' to create a
+    /// LightProxySecretKey.
+    pub fn sign(
+        issuer_prv: &hdwallet::XPrv,
+        delegate_pk: hdwallet::XPub,
+        omega: LightDlgIndices,
+        protocol_magic: ProtocolMagic,
+    ) -> Self {
+        let buf = psk_data_to_sign(&delegate_pk, &omega, protocol_magic);
+
+        Self {
+            omega,
+            issuer_pk: issuer_prv.public(),
+            delegate_pk,
+            cert: issuer_prv.sign(&buf),
+        }
     }
 }
 
@@ -203,10 +426,39 @@ impl cbor_event::de::Deserialize for ProxySignature {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct LightProxySignature {
+    pub psk: LightProxySecretKey,
+    pub sig: hdwallet::Signature<()>,
+}
+
+impl cbor_event::se::Serialize for LightProxySignature {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        serializer
+            .write_array(cbor_event::Len::Len(2))?
+            .serialize(&self.psk)?
+            .serialize(&self.sig)
+    }
+}
+
+impl cbor_event::de::Deserialize for LightProxySignature {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(2, "LightProxySignature")?;
+
+        let psk = cbor_event::de::Deserialize::deserialize(raw)?;
+        let sig = cbor_event::de::Deserialize::deserialize(raw)?;
+
+        Ok(LightProxySignature { psk, sig })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum BlockSignature {
     Signature(hdwallet::Signature<SignData>),
-    ProxyLight(Vec<cbor_event::Value>), // TODO: decode
+    ProxyLight(LightProxySignature),
     ProxyHeavy(ProxySignature),
 }
 impl BlockSignature {
@@ -227,12 +479,10 @@ impl cbor_event::se::Serialize for BlockSignature {
                 .write_array(cbor_event::Len::Len(2))?
                 .write_unsigned_integer(0)?
                 .serialize(sig),
-            &BlockSignature::ProxyLight(ref v) => {
-                let serializer = serializer
-                    .write_array(cbor_event::Len::Len(2))?
-                    .write_unsigned_integer(1)?;
-                cbor_event::se::serialize_fixed_array(v.iter(), serializer)
-            }
+            &BlockSignature::ProxyLight(ref v) => serializer
+                .write_array(cbor_event::Len::Len(2))?
+                .write_unsigned_integer(1)?
+                .serialize(v),
             &BlockSignature::ProxyHeavy(ref v) => serializer
                 .write_array(cbor_event::Len::Len(2))?
                 .write_unsigned_integer(2)?
@@ -246,7 +496,9 @@ impl cbor_event::de::Deserialize for BlockSignature {
         let sum_type_idx = raw.unsigned_integer()?;
         match sum_type_idx {
             0 => Ok(BlockSignature::Signature(raw.deserialize()?)),
-            1 => Ok(BlockSignature::ProxyLight(raw.deserialize()?)),
+            1 => Ok(BlockSignature::ProxyLight(
+                cbor_event::de::Deserialize::deserialize(raw)?,
+            )),
             2 => Ok(BlockSignature::ProxyHeavy(
                 cbor_event::de::Deserialize::deserialize(raw)?,
             )),
@@ -0,0 +1,237 @@
+//! Slotting
+//!
+//! Conversions between block dates and wall-clock time. An epoch has
+//! `10 * k` slots, `k` being the security parameter, and the first one
+//! starts at the `start_time` of the genesis data.
+//!
+//! The slot duration is a protocol parameter: a duration adopted through
+//! the update system applies from the first slot of the epoch it was
+//! adopted at, so the chain is divided into eras of constant slot
+//! duration.
+
+use config::GenesisData;
+use std::time::{Duration, SystemTime};
+use std::{error, fmt};
+
+use super::date::BlockDate;
+use super::types::{EpochId, EpochSlotId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// a slot duration was adopted at the given epoch, before the start
+    /// of the current era
+    EpochBeforeCurrentEra(EpochId, EpochId),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::EpochBeforeCurrentEra(epoch, era_start) => write!(
+                f,
+                "Slot duration adopted at epoch {}, before the current era starting at epoch {}",
+                epoch, era_start
+            ),
+        }
+    }
+}
+impl error::Error for Error {}
+
+/// A run of epochs with the same slot duration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlottingEra {
+    pub start_epoch: EpochId,
+    pub start_time: SystemTime,
+    pub slot_duration: Duration,
+}
+
+impl SlottingEra {
+    fn duration_of(&self, slots: u64) -> Duration {
+        let nanos = self.slot_duration.as_nanos() * u128::from(slots);
+        Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slotting {
+    pub epoch_slots: u64,
+    /// the eras, in chronological order. There is always at least one.
+    eras: Vec<SlottingEra>,
+}
+
+impl Slotting {
+    pub fn new(genesis_data: &GenesisData) -> Self {
+        Slotting {
+            epoch_slots: genesis_data.epoch_stability_depth as u64 * 10,
+            eras: vec![SlottingEra {
+                start_epoch: 0,
+                start_time: genesis_data.start_time,
+                slot_duration: genesis_data.slot_duration(),
+            }],
+        }
+    }
+
+    pub fn eras(&self) -> &[SlottingEra] {
+        &self.eras
+    }
+
+    /// Use `slot_duration` from the first slot of `epoch`, typically
+    /// the slot duration of the parameters adopted at its boundary
+    /// (`ChainState::parameters`). Durations must be adopted in the
+    /// order of their epochs: an epoch before the start of the current
+    /// era is an error, leaving the eras unchanged.
+    pub fn adopt_slot_duration(
+        &mut self,
+        epoch: EpochId,
+        slot_duration: Duration,
+    ) -> Result<(), Error> {
+        let last = *self.eras.last().unwrap();
+        if epoch < last.start_epoch {
+            return Err(Error::EpochBeforeCurrentEra(epoch, last.start_epoch));
+        }
+        if slot_duration == last.slot_duration {
+            return Ok(());
+        }
+        if epoch == last.start_epoch {
+            self.eras.last_mut().unwrap().slot_duration = slot_duration;
+            return Ok(());
+        }
+        self.eras.push(SlottingEra {
+            start_epoch: epoch,
+            start_time: last.start_time
+                + last.duration_of((epoch - last.start_epoch) * self.epoch_slots),
+            slot_duration,
+        });
+        Ok(())
+    }
+
+    fn era_of_epoch(&self, epoch: EpochId) -> &SlottingEra {
+        self.eras
+            .iter()
+            .rev()
+            .find(|era| era.start_epoch <= epoch)
+            .unwrap()
+    }
+
+    /// The number of slots since the start of the chain.
+    pub fn slot_number(&self, date: &BlockDate) -> u64 {
+        date.slot_number(self.epoch_slots)
+    }
+
+    /// The slot with the given number of slots since the start of the
+    /// chain.
+    pub fn slot_of_number(&self, slot_number: u64) -> EpochSlotId {
+        EpochSlotId {
+            epoch: slot_number / self.epoch_slots,
+            slotid: (slot_number % self.epoch_slots) as u16,
+        }
+    }
+
+    /// The time at which a slot starts. A boundary block is dated at
+    /// the start of the first slot of its epoch.
+    pub fn start_time(&self, date: &BlockDate) -> SystemTime {
+        let era = self.era_of_epoch(date.get_epochid());
+        let era_start = era.start_epoch * self.epoch_slots;
+        era.start_time + era.duration_of(self.slot_number(date) - era_start)
+    }
+
+    /// The slot in progress at `time`, if the chain had started.
+    pub fn slot_at(&self, time: SystemTime) -> Option<EpochSlotId> {
+        let era = self.eras.iter().rev().find(|era| era.start_time <= time)?;
+        let elapsed = time.duration_since(era.start_time).unwrap();
+        let slots = (elapsed.as_nanos() / era.slot_duration.as_nanos()) as u64;
+        Some(self.slot_of_number(era.start_epoch * self.epoch_slots + slots))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::fts::SharedSeed;
+    use block::update::BlockVersionData;
+    use block::HeaderHash;
+    use config::ProtocolMagic;
+    use fee;
+    use std::collections::BTreeMap;
+
+    fn genesis_data() -> GenesisData {
+        GenesisData {
+            genesis_prev: HeaderHash::new(b"genesis"),
+            epoch_stability_depth: 2160,
+            vss_min_ttl: 2,
+            vss_max_ttl: 6,
+            start_time: SystemTime::UNIX_EPOCH + Duration::from_secs(1506203091),
+            protocol_magic: ProtocolMagic::default(),
+            block_version_data: BlockVersionData::mainnet(20_000, fee::LinearFee::default()),
+            avvm_distr: BTreeMap::new(),
+            non_avvm_balances: BTreeMap::new(),
+            boot_stakeholders: BTreeMap::new(),
+            fts_seed: SharedSeed::default(),
+        }
+    }
+
+    fn slot(epoch: EpochId, slotid: u16) -> EpochSlotId {
+        EpochSlotId { epoch, slotid }
+    }
+
+    fn secs(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn mainnet_slots_and_times() {
+        let slotting = Slotting::new(&genesis_data());
+        assert_eq!(slotting.epoch_slots, 21600);
+        assert_eq!(slotting.slot_of_number(21601), slot(1, 1));
+
+        // the first block of the mainnet epoch 1
+        let date = BlockDate::Normal(slot(1, 0));
+        assert_eq!(slotting.start_time(&date), secs(1506635091));
+        assert_eq!(
+            slotting.start_time(&BlockDate::Boundary(1)),
+            secs(1506635091)
+        );
+        assert_eq!(slotting.slot_at(secs(1506635091)), Some(slot(1, 0)));
+        assert_eq!(slotting.slot_at(secs(1506635110)), Some(slot(1, 0)));
+        assert_eq!(slotting.slot_at(secs(1506635111)), Some(slot(1, 1)));
+        assert_eq!(slotting.slot_at(secs(1506203090)), None);
+    }
+
+    #[test]
+    fn slot_duration_changes_start_new_eras() {
+        let mut slotting = Slotting::new(&genesis_data());
+        slotting
+            .adopt_slot_duration(2, Duration::from_secs(20))
+            .unwrap();
+        assert_eq!(slotting.eras().len(), 1);
+
+        slotting
+            .adopt_slot_duration(2, Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(slotting.eras().len(), 2);
+        let era_start = secs(1506203091 + 2 * 21600 * 20);
+        assert_eq!(slotting.eras()[1].start_time, era_start);
+
+        let date = BlockDate::Normal(slot(2, 3));
+        assert_eq!(
+            slotting.start_time(&date),
+            era_start + Duration::from_secs(30)
+        );
+        assert_eq!(
+            slotting.slot_at(era_start + Duration::from_secs(35)),
+            Some(slot(2, 3))
+        );
+        assert_eq!(
+            slotting.slot_at(era_start - Duration::from_secs(1)),
+            Some(slot(1, 21599))
+        );
+
+        let before = slotting.clone();
+        assert_eq!(
+            slotting.adopt_slot_duration(1, Duration::from_secs(5)),
+            Err(Error::EpochBeforeCurrentEra(1, 2))
+        );
+        assert_eq!(slotting, before);
+    }
+}
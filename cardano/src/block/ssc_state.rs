@@ -0,0 +1,541 @@
+//! Shared seed computation (SSC)
+//!
+//! Every epoch, the richmen (the stakeholders holding at least
+//! `mpc_thd` of the stake the slot leaders of the epoch are elected
+//! from) run a multi-party computation of the seed of the next slot
+//! leader schedule. The payloads of the computation are
+//! only accepted during their phase of the epoch, `k` being the
+//! security parameter:
+//!
+//! * commitments during the slots `[0, 2k)`;
+//! * openings during the slots `[4k, 6k)`;
+//! * shares during the slots `[8k, 10k)`.
+//!
+//! The VSS certificates, binding the VSS key used to encrypt the shares
+//! of a stakeholder to its signing key, can be published at any time.
+//! They are kept until the end of their expiry epoch.
+//!
+//! At the end of the epoch, the seed is the combination of the secrets
+//! opened by the participants that committed to one. As in cardano-sl,
+//! the seed of the previous epoch is kept if there was no commitment or
+//! if someone opened a secret it did not commit to. The secrets of the
+//! participants that committed but did not open can only be recovered
+//! from the decrypted shares, which is not supported: the seed is then
+//! unknown.
+
+use address::StakeholderId;
+use cbor_event::{self, de::Deserializer, se::Serializer};
+use cryptoxide::{digest::Digest, sha2::Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{BufRead, Write},
+    mem,
+    ops::RangeInclusive,
+};
+
+use super::fts::{SharedSeed, SHARED_SEED_SIZE};
+use super::normal::{SscPayload, VssCertificate};
+use super::types::{CoinPortion, EpochId, EpochSlotId};
+use super::update_state::Stakes;
+use super::verify::{first_error, Error, ErrorLocation, Errors, LocatedError};
+
+/// The minimum number of epochs a VSS certificate is valid for,
+/// including the one it is published in, on the mainnet. The chain
+/// uses the `vss_min_ttl` of its genesis data.
+pub const VSS_MIN_TTL: EpochId = 2;
+/// The maximum number of epochs a VSS certificate is valid for,
+/// including the one it is published in, on the mainnet. The chain
+/// uses the `vss_max_ttl` of its genesis data.
+pub const VSS_MAX_TTL: EpochId = 6;
+
+/// The stakeholders allowed to take part in the computation.
+pub fn richmen(stakes: &Stakes, mpc_thd: &CoinPortion) -> BTreeSet<StakeholderId> {
+    let total = stakes.values().sum();
+    stakes
+        .iter()
+        .filter(|(_, stake)| mpc_thd.is_reached_by(**stake, total))
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+/// Check the payload is allowed at the given slot of the epoch, `k`
+/// being the security parameter.
+pub fn is_in_phase(payload: &SscPayload, k: usize, slot: &EpochSlotId) -> bool {
+    let slotid = slot.slotid as usize;
+    let in_window = |start: usize| slotid >= start * k && slotid < (start + 2) * k;
+    match payload {
+        SscPayload::CommitmentsPayload(_, _) => in_window(0),
+        SscPayload::OpeningsPayload(_, _) => in_window(4),
+        SscPayload::SharesPayload(_, _) => in_window(8),
+        SscPayload::CertificatesPayload(_) => true,
+    }
+}
+
+/// The seed revealed by an opening: the hash of the opened secret.
+/// Returns `None` if the opening is not a byte string.
+pub fn opening_seed(opening: &cbor_event::Value) -> Option<SharedSeed> {
+    match opening {
+        cbor_event::Value::Bytes(secret) => {
+            let mut seed = [0; SHARED_SEED_SIZE];
+            let mut hasher = Sha256::new();
+            hasher.input(secret);
+            hasher.result(&mut seed);
+            Some(SharedSeed::from_bytes(seed))
+        }
+        _ => None,
+    }
+}
+
+/// A change of the SSC state.
+#[derive(Debug, Clone)]
+pub enum SscChange {
+    /// the certificate of a stakeholder was added or removed, replacing
+    /// the given one if any
+    VssCertificate(StakeholderId, Option<VssCertificate>),
+    /// a stakeholder committed to a secret
+    Commitment(StakeholderId),
+    /// a stakeholder opened its secret
+    Opening(StakeholderId),
+    /// the epoch ended, dropping the given commitments and openings and
+    /// replacing the given seed and richmen
+    Epoch(
+        BTreeSet<StakeholderId>,
+        BTreeMap<StakeholderId, Option<SharedSeed>>,
+        Option<SharedSeed>,
+        BTreeSet<StakeholderId>,
+    ),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SscState {
+    /// the VSS certificates in effect, by the stakeholder that issued
+    /// them
+    pub vss_certificates: BTreeMap<StakeholderId, VssCertificate>,
+    /// the stakeholders that committed to a secret during the epoch
+    pub commitments: BTreeSet<StakeholderId>,
+    /// the seeds opened during the epoch, `None` if the opening could
+    /// not be decoded
+    pub openings: BTreeMap<StakeholderId, Option<SharedSeed>>,
+    /// the seed of the slot leader election of the current epoch, if
+    /// known
+    pub seed: Option<SharedSeed>,
+    /// the stakeholders allowed to take part in the computation of the
+    /// current epoch
+    pub richmen: BTreeSet<StakeholderId>,
+}
+
+impl SscState {
+    /// The state at the start of the chain, `seed` being the seed of the
+    /// genesis epoch and `richmen` its richmen.
+    pub fn new(seed: SharedSeed, richmen: BTreeSet<StakeholderId>) -> Self {
+        SscState {
+            vss_certificates: BTreeMap::new(),
+            commitments: BTreeSet::new(),
+            openings: BTreeMap::new(),
+            seed: Some(seed),
+            richmen,
+        }
+    }
+
+    /// The seed computed from the commitments and openings of the
+    /// epoch, if known.
+    pub fn next_seed(&self) -> Option<SharedSeed> {
+        if self.commitments.is_empty()
+            || self
+                .openings
+                .keys()
+                .any(|id| !self.commitments.contains(id))
+        {
+            return self.seed.clone();
+        }
+        let mut seed: Option<SharedSeed> = None;
+        for id in &self.commitments {
+            let opened = self.openings.get(id)?.as_ref()?;
+            seed = Some(match seed {
+                None => opened.clone(),
+                Some(seed) => seed.combine(opened),
+            });
+        }
+        seed
+    }
+
+    /// Check the payload of a block issued at `slot` against the phase
+    /// of the epoch and its richmen, and record its VSS certificates,
+    /// which must be valid for a number of epochs within `vss_ttl`. As for the rest of the chain state, the valid
+    /// certificates are recorded even if another part of the payload is
+    /// invalid.
+    pub fn apply_payload(
+        &mut self,
+        k: usize,
+        vss_ttl: &RangeInclusive<EpochId>,
+        slot: &EpochSlotId,
+        payload: &SscPayload,
+    ) -> Result<(), Error> {
+        let mut errors = vec![];
+        self.apply_payload_all_errors(k, vss_ttl, slot, payload, &mut errors);
+        first_error(errors)
+    }
+
+    /// Like `apply_payload`, recording every error of the payload.
+    /// Returns the changes made.
+    pub fn apply_payload_all_errors(
+        &mut self,
+        k: usize,
+        vss_ttl: &RangeInclusive<EpochId>,
+        slot: &EpochSlotId,
+        payload: &SscPayload,
+        errors: &mut Errors,
+    ) -> Vec<SscChange> {
+        let mut error = |error| errors.push(LocatedError::new(ErrorLocation::SscPayload, error));
+
+        if !is_in_phase(payload, k, slot) {
+            error(Error::WrongSscPhase);
+        }
+
+        let participants: Vec<StakeholderId> = match payload {
+            SscPayload::CommitmentsPayload(commitments, _) => commitments
+                .iter()
+                .map(|commitment| StakeholderId::new(&commitment.public_key))
+                .collect(),
+            SscPayload::OpeningsPayload(openings, _) => {
+                openings.iter().map(|(id, _)| *id).collect()
+            }
+            SscPayload::SharesPayload(shares, _) => shares.iter().map(|(id, _)| *id).collect(),
+            SscPayload::CertificatesPayload(_) => vec![],
+        };
+        if participants.iter().any(|id| !self.richmen.contains(id)) {
+            error(Error::SscParticipantNotRichman);
+        }
+
+        // Record the commitments and openings, the first ones of a
+        // stakeholder being the ones taken into account.
+        let mut changes = vec![];
+        match payload {
+            SscPayload::CommitmentsPayload(_, _) => {
+                for id in participants {
+                    if self.commitments.insert(id) {
+                        changes.push(SscChange::Commitment(id));
+                    }
+                }
+            }
+            SscPayload::OpeningsPayload(openings, _) => {
+                for (id, opening) in openings.iter() {
+                    if !self.openings.contains_key(id) {
+                        self.openings.insert(*id, opening_seed(opening));
+                        changes.push(SscChange::Opening(*id));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        for cert in payload.get_vss_certificates().iter() {
+            let ttl = (cert.expiry_epoch + 1).saturating_sub(slot.epoch);
+            if !vss_ttl.contains(&ttl) {
+                error(Error::WrongVssCertTTL);
+                continue;
+            }
+            let id = StakeholderId::new(&cert.signing_key);
+            let replaced = self.vss_certificates.insert(id, cert.clone());
+            changes.push(SscChange::VssCertificate(id, replaced));
+        }
+        changes
+    }
+
+    /// Compute the seed of `epoch` from the secrets of the previous
+    /// one, set `richmen` as the ones of `epoch` and drop the
+    /// certificates that expired before its start. Returns the changes
+    /// made.
+    pub fn apply_epoch_boundary(
+        &mut self,
+        epoch: EpochId,
+        richmen: BTreeSet<StakeholderId>,
+    ) -> Vec<SscChange> {
+        let seed = self.next_seed();
+        let mut changes = vec![SscChange::Epoch(
+            mem::take(&mut self.commitments),
+            mem::take(&mut self.openings),
+            mem::replace(&mut self.seed, seed),
+            mem::replace(&mut self.richmen, richmen),
+        )];
+
+        let expired: Vec<StakeholderId> = self
+            .vss_certificates
+            .iter()
+            .filter(|(_, cert)| cert.expiry_epoch < epoch)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            let cert = self.vss_certificates.remove(&id);
+            changes.push(SscChange::VssCertificate(id, cert));
+        }
+        changes
+    }
+
+    /// Undo the changes of the SSC state, in the reverse order they
+    /// were made.
+    pub fn revert_changes(&mut self, changes: Vec<SscChange>) {
+        for change in changes.into_iter().rev() {
+            match change {
+                SscChange::VssCertificate(id, Some(cert)) => {
+                    self.vss_certificates.insert(id, cert);
+                }
+                SscChange::VssCertificate(id, None) => {
+                    self.vss_certificates.remove(&id);
+                }
+                SscChange::Commitment(id) => {
+                    self.commitments.remove(&id);
+                }
+                SscChange::Opening(id) => {
+                    self.openings.remove(&id);
+                }
+                SscChange::Epoch(commitments, openings, seed, richmen) => {
+                    self.commitments = commitments;
+                    self.openings = openings;
+                    self.seed = seed;
+                    self.richmen = richmen;
+                }
+            }
+        }
+    }
+}
+
+impl cbor_event::se::Serialize for SscState {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        let serializer = serializer.write_array(cbor_event::Len::Len(5))?;
+        let serializer =
+            cbor_event::se::serialize_fixed_map(self.vss_certificates.iter(), serializer)?;
+        let serializer =
+            cbor_event::se::serialize_fixed_array(self.commitments.iter(), serializer)?;
+        let serializer = cbor_event::se::serialize_fixed_map(self.openings.iter(), serializer)?;
+        let serializer = serializer.serialize(&self.seed)?;
+        cbor_event::se::serialize_fixed_array(self.richmen.iter(), serializer)
+    }
+}
+
+impl cbor_event::de::Deserialize for SscState {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(5, "SscState")?;
+        Ok(SscState {
+            vss_certificates: raw.deserialize()?,
+            commitments: raw.deserialize::<Vec<_>>()?.into_iter().collect(),
+            openings: raw.deserialize()?,
+            seed: raw.deserialize()?,
+            richmen: raw.deserialize::<Vec<_>>()?.into_iter().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::normal::{OpeningsMap, VssCertificates};
+    use hdwallet::{Seed, XPrv};
+    use util::hex;
+    use vss;
+
+    const K: usize = 10;
+    const VSS_TTL: RangeInclusive<EpochId> = VSS_MIN_TTL..=VSS_MAX_TTL;
+
+    fn key(n: u8) -> XPrv {
+        XPrv::generate_from_seed(&Seed::from_bytes([n; 32]))
+    }
+
+    fn stakeholder(n: u8) -> StakeholderId {
+        StakeholderId::new(&key(n).public())
+    }
+
+    fn slot(epoch: EpochId, slotid: u16) -> EpochSlotId {
+        EpochSlotId { epoch, slotid }
+    }
+
+    fn vss_cert(from: u8, expiry_epoch: EpochId) -> VssCertificate {
+        VssCertificate {
+            vss_key: vss::PublicKey(vec![from; 35]),
+            expiry_epoch,
+            signature: vss::Signature::from_bytes([0; 64]),
+            signing_key: key(from).public(),
+        }
+    }
+
+    fn openings(from: &[u8], certs: Vec<VssCertificate>) -> SscPayload {
+        let openings = from
+            .iter()
+            .map(|n| (stakeholder(*n), cbor_event::Value::Bytes(vec![*n])))
+            .collect();
+        SscPayload::OpeningsPayload(OpeningsMap::new(openings), VssCertificates::new(certs))
+    }
+
+    fn certificates(certs: Vec<VssCertificate>) -> SscPayload {
+        SscPayload::CertificatesPayload(VssCertificates::new(certs))
+    }
+
+    fn expect_error(res: Result<(), Error>, expected: &str) {
+        match res {
+            Err(err) => assert_eq!(format!("{:?}", err), expected),
+            Ok(()) => panic!("expected {}", expected),
+        }
+    }
+
+    #[test]
+    fn payloads_are_checked_against_the_phase() {
+        let payload = openings(&[], vec![]);
+        assert!(!is_in_phase(&payload, K, &slot(0, 39)));
+        assert!(is_in_phase(&payload, K, &slot(0, 40)));
+        assert!(is_in_phase(&payload, K, &slot(0, 59)));
+        assert!(!is_in_phase(&payload, K, &slot(0, 60)));
+        assert!(is_in_phase(&SscPayload::fake(), K, &slot(0, 0)));
+        assert!(!is_in_phase(&SscPayload::fake(), K, &slot(0, 20)));
+        assert!(is_in_phase(&certificates(vec![]), K, &slot(0, 99)));
+
+        let mut state = SscState::new(SharedSeed::default(), BTreeSet::new());
+        expect_error(
+            state.apply_payload(K, &VSS_TTL, &slot(0, 10), &payload),
+            "WrongSscPhase",
+        );
+    }
+
+    #[test]
+    fn participants_must_be_richmen() {
+        let mut stakes = Stakes::new();
+        stakes.insert(stakeholder(1), 98);
+        stakes.insert(stakeholder(2), 2);
+        stakes.insert(stakeholder(3), 1);
+        let richmen = richmen(&stakes, &CoinPortion::new(10_000_000_000_000).unwrap());
+        assert_eq!(
+            richmen,
+            vec![stakeholder(1), stakeholder(2)].into_iter().collect()
+        );
+
+        let mut state = SscState::new(SharedSeed::default(), richmen.clone());
+        state
+            .apply_payload(K, &VSS_TTL, &slot(0, 40), &openings(&[1, 2], vec![]))
+            .unwrap();
+        expect_error(
+            state.apply_payload(K, &VSS_TTL, &slot(0, 41), &openings(&[1, 3], vec![])),
+            "SscParticipantNotRichman",
+        );
+
+        // the richmen change with the epoch
+        let next_richmen: BTreeSet<_> = vec![stakeholder(3)].into_iter().collect();
+        let changes = state.apply_epoch_boundary(1, next_richmen.clone());
+        assert_eq!(state.richmen, next_richmen);
+        state
+            .apply_payload(K, &VSS_TTL, &slot(1, 40), &openings(&[3], vec![]))
+            .unwrap();
+        state.openings.clear();
+        state.revert_changes(changes);
+        assert_eq!(state.richmen, richmen);
+    }
+
+    #[test]
+    fn vss_certificates_are_tracked() {
+        let mut state = SscState::new(SharedSeed::default(), BTreeSet::new());
+        state
+            .apply_payload(
+                K,
+                &VSS_TTL,
+                &slot(3, 50),
+                &certificates(vec![vss_cert(1, 4), vss_cert(2, 8)]),
+            )
+            .unwrap();
+        expect_error(
+            state.apply_payload(
+                K,
+                &VSS_TTL,
+                &slot(3, 51),
+                &certificates(vec![vss_cert(3, 3), vss_cert(4, 9)]),
+            ),
+            "WrongVssCertTTL",
+        );
+        assert_eq!(state.vss_certificates.len(), 2);
+        assert!(state.vss_certificates.contains_key(&stakeholder(1)));
+        assert!(state.vss_certificates.contains_key(&stakeholder(2)));
+
+        state.apply_epoch_boundary(4, BTreeSet::new());
+        assert_eq!(state.vss_certificates.len(), 2);
+        let before = state.clone();
+        let changes = state.apply_epoch_boundary(5, BTreeSet::new());
+        assert_eq!(state.vss_certificates.len(), 1);
+        assert!(state.vss_certificates.contains_key(&stakeholder(2)));
+
+        state.revert_changes(changes);
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn seed_is_computed_from_the_openings() {
+        let genesis_seed = SharedSeed::from_bytes([1; 40]);
+        let mut state = SscState::new(genesis_seed.clone(), BTreeSet::new());
+        // no commitments: the previous seed is kept
+        assert_eq!(state.next_seed(), Some(genesis_seed.clone()));
+
+        state.commitments = vec![stakeholder(1), stakeholder(2)].into_iter().collect();
+        state.richmen = state.commitments.clone();
+        let changes = state.apply_payload_all_errors(
+            K,
+            &VSS_TTL,
+            &slot(0, 40),
+            &openings(&[1], vec![]),
+            &mut vec![],
+        );
+        assert_eq!(state.openings.len(), 1);
+        // the secret of the stakeholder 2 is unknown
+        assert_eq!(state.next_seed(), None);
+
+        state
+            .apply_payload(K, &VSS_TTL, &slot(0, 41), &openings(&[2], vec![]))
+            .unwrap();
+        let seed = opening_seed(&cbor_event::Value::Bytes(vec![1]))
+            .unwrap()
+            .combine(&opening_seed(&cbor_event::Value::Bytes(vec![2])).unwrap());
+        assert_eq!(
+            hex::encode(
+                opening_seed(&cbor_event::Value::Bytes(vec![1]))
+                    .unwrap()
+                    .as_ref()
+            ),
+            "4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785459a"
+        );
+        assert_eq!(state.next_seed(), Some(seed.clone()));
+
+        let before = state.clone();
+        let boundary_changes = state.apply_epoch_boundary(1, BTreeSet::new());
+        assert_eq!(state.seed, Some(seed));
+        assert!(state.commitments.is_empty());
+        assert!(state.openings.is_empty());
+        state.revert_changes(boundary_changes);
+        assert_eq!(state, before);
+
+        // an opening without a commitment: the previous seed is kept
+        state.openings.insert(stakeholder(3), None);
+        assert_eq!(state.next_seed(), Some(genesis_seed.clone()));
+
+        state.openings.remove(&stakeholder(3));
+        state.revert_changes(changes);
+        assert_eq!(state.openings.len(), 1);
+        assert!(state.openings.contains_key(&stakeholder(2)));
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut state = SscState::new(SharedSeed::default(), BTreeSet::new());
+        state
+            .vss_certificates
+            .insert(stakeholder(1), vss_cert(1, 4));
+        state.commitments.insert(stakeholder(1));
+        state.richmen.insert(stakeholder(1));
+        state.openings.insert(stakeholder(1), None);
+        state
+            .openings
+            .insert(stakeholder(2), Some(SharedSeed::default()));
+        let bytes = cbor!(&state).unwrap();
+        let decoded: SscState = Deserializer::from(::std::io::Cursor::new(&bytes))
+            .deserialize_complete()
+            .unwrap();
+        assert_eq!(decoded, state);
+    }
+}
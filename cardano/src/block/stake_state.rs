@@ -0,0 +1,353 @@
+//! Stake distribution
+//!
+//! The stake of an unspent output goes to the stakeholders given by the
+//! stake distribution of its address:
+//!
+//! * a single key distribution gives it all to one stakeholder;
+//! * the bootstrap era distribution splits it among the boot
+//!   stakeholders of the genesis data, in proportion to their weight.
+//!   The remainder of the division goes to the first boot stakeholder.
+//!
+//! The distribution is updated with every output added or spent.
+//!
+//! The slot leaders of an epoch are elected from the stake distribution
+//! at the crucial slot of the previous epoch, the last slot before its
+//! last `2k` slots, which is kept until then. That distribution is the
+//! snapshot of the epoch: the SSC richmen and the update system use it
+//! for the whole epoch, however the stakes change during it.
+//!
+//! The block issued at the crucial slot counts in the distribution, the
+//! first block of the last `2k` slots does not. This has not been
+//! checked against the slot leaders of a real epoch yet.
+
+use address::{StakeDistribution, StakeholderId};
+use cbor_event::{self, de::Deserializer, se::Serializer};
+use config::{BootStakeWeight, GenesisData};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+    mem,
+};
+use tx::TxOut;
+
+use super::chain_state::Utxos;
+use super::types::EpochId;
+use super::update_state::Stakes;
+
+/// The stake distribution at the crucial slot of an epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrucialStakes {
+    pub epoch: EpochId,
+    pub stakes: Stakes,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StakeState {
+    /// the weights of the boot stakeholders, sharing the stake of the
+    /// bootstrap era outputs
+    pub boot_stakeholders: BTreeMap<StakeholderId, BootStakeWeight>,
+    /// the stake of every stakeholder holding some
+    pub stakes: Stakes,
+    /// the stake distribution at the last crucial slot with a block
+    /// after it
+    pub crucial: Option<CrucialStakes>,
+    /// the snapshot of the current epoch
+    pub epoch_stakes: Stakes,
+}
+
+impl StakeState {
+    pub fn new(boot_stakeholders: BTreeMap<StakeholderId, BootStakeWeight>) -> Self {
+        StakeState {
+            boot_stakeholders,
+            stakes: Stakes::new(),
+            crucial: None,
+            epoch_stakes: Stakes::new(),
+        }
+    }
+
+    /// Compute the stake distribution of the given utxos.
+    pub fn from_utxos(genesis_data: &GenesisData, utxos: &Utxos) -> Self {
+        let mut state = StakeState::new(
+            genesis_data
+                .boot_stakeholders
+                .iter()
+                .map(|(id, stakeholder)| (*id, stakeholder.weight))
+                .collect(),
+        );
+        for txout in utxos.values() {
+            state.add_output(txout);
+        }
+        state.epoch_stakes = state.stakes.clone();
+        state
+    }
+
+    /// The stakeholders the value of an output is distributed to.
+    pub fn output_stakes(&self, txout: &TxOut) -> Vec<(StakeholderId, u64)> {
+        let value = u64::from(txout.value);
+        match txout.address.attributes.stake_distribution {
+            StakeDistribution::SingleKeyDistr(id) => vec![(id, value)],
+            StakeDistribution::BootstrapEraDistr => {
+                let total_weight: u64 = self
+                    .boot_stakeholders
+                    .values()
+                    .map(|weight| u64::from(*weight))
+                    .sum();
+                if total_weight == 0 {
+                    return vec![];
+                }
+                let share = value / total_weight;
+                let mut remainder = value % total_weight;
+                self.boot_stakeholders
+                    .iter()
+                    .map(|(id, weight)| {
+                        let stake = share * u64::from(*weight) + remainder;
+                        remainder = 0;
+                        (*id, stake)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Add the stake of a new unspent output.
+    pub fn add_output(&mut self, txout: &TxOut) {
+        for (id, stake) in self.output_stakes(txout) {
+            if stake > 0 {
+                *self.stakes.entry(id).or_insert(0) += stake;
+            }
+        }
+    }
+
+    /// Remove the stake of a spent output. The output must have been
+    /// added before: otherwise the stakes are inconsistent with the
+    /// utxos, and whatever stake is left is removed.
+    pub fn remove_output(&mut self, txout: &TxOut) {
+        for (id, stake) in self.output_stakes(txout) {
+            if stake == 0 {
+                continue;
+            }
+            let remaining = self.stake_of(&id).saturating_sub(stake);
+            if remaining == 0 {
+                self.stakes.remove(&id);
+            } else {
+                self.stakes.insert(id, remaining);
+            }
+        }
+    }
+
+    pub fn stake_of(&self, id: &StakeholderId) -> u64 {
+        self.stakes.get(id).cloned().unwrap_or(0)
+    }
+
+    pub fn total_stake(&self) -> u64 {
+        self.stakes.values().sum()
+    }
+
+    /// Record the current stakes as the ones of the crucial slot of
+    /// `epoch`, before the first block after that slot is applied.
+    /// Returns the stakes previously recorded.
+    pub fn record_crucial(&mut self, epoch: EpochId) -> Option<CrucialStakes> {
+        let stakes = self.stakes.clone();
+        mem::replace(&mut self.crucial, Some(CrucialStakes { epoch, stakes }))
+    }
+
+    /// The stakes the slot leaders of `epoch` are elected from: the
+    /// stakes at the crucial slot of the previous epoch, which are the
+    /// current ones if no block was applied after that slot.
+    pub fn leaders_stakes(&self, epoch: EpochId) -> &Stakes {
+        match &self.crucial {
+            Some(crucial) if crucial.epoch + 1 == epoch => &crucial.stakes,
+            _ => &self.stakes,
+        }
+    }
+
+    /// Take the snapshot of `epoch`, at its start. Returns the snapshot
+    /// of the previous epoch.
+    pub fn start_epoch(&mut self, epoch: EpochId) -> Stakes {
+        let stakes = self.leaders_stakes(epoch).clone();
+        mem::replace(&mut self.epoch_stakes, stakes)
+    }
+}
+
+impl cbor_event::se::Serialize for StakeState {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        let serializer = serializer.write_array(cbor_event::Len::Len(4))?;
+        let serializer =
+            cbor_event::se::serialize_fixed_map(self.boot_stakeholders.iter(), serializer)?;
+        let serializer = cbor_event::se::serialize_fixed_map(self.stakes.iter(), serializer)?;
+        let serializer = serializer.serialize(&self.crucial)?;
+        cbor_event::se::serialize_fixed_map(self.epoch_stakes.iter(), serializer)
+    }
+}
+
+impl cbor_event::de::Deserialize for StakeState {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(4, "StakeState")?;
+        Ok(StakeState {
+            boot_stakeholders: raw.deserialize()?,
+            stakes: raw.deserialize()?,
+            crucial: raw.deserialize()?,
+            epoch_stakes: raw.deserialize()?,
+        })
+    }
+}
+
+impl cbor_event::se::Serialize for CrucialStakes {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        let serializer = serializer
+            .write_array(cbor_event::Len::Len(2))?
+            .serialize(&self.epoch)?;
+        cbor_event::se::serialize_fixed_map(self.stakes.iter(), serializer)
+    }
+}
+
+impl cbor_event::de::Deserialize for CrucialStakes {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(2, "CrucialStakes")?;
+        Ok(CrucialStakes {
+            epoch: raw.deserialize()?,
+            stakes: raw.deserialize()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use address::{AddrType, Attributes, ExtendedAddr, SpendingData};
+    use block::ssc_state;
+    use block::types::CoinPortion;
+    use coin::Coin;
+    use config::NetworkMagic;
+    use hdwallet::{Seed, XPrv, XPub};
+
+    fn key(n: u8) -> XPub {
+        XPrv::generate_from_seed(&Seed::from_bytes([n; 32])).public()
+    }
+
+    fn stakeholder(n: u8) -> StakeholderId {
+        StakeholderId::new(&key(n))
+    }
+
+    fn output(attributes: Attributes, value: u64) -> TxOut {
+        TxOut {
+            address: ExtendedAddr::new(
+                AddrType::ATPubKey,
+                SpendingData::PubKeyASD(key(9)),
+                attributes,
+            ),
+            value: Coin::new(value).unwrap(),
+        }
+    }
+
+    fn bootstrap_output(value: u64) -> TxOut {
+        output(
+            Attributes::new_bootstrap_era(None, NetworkMagic::NoMagic),
+            value,
+        )
+    }
+
+    fn single_key_output(n: u8, value: u64) -> TxOut {
+        output(
+            Attributes::new_single_key(&key(n), None, NetworkMagic::NoMagic),
+            value,
+        )
+    }
+
+    fn state() -> StakeState {
+        StakeState::new(
+            vec![(stakeholder(1), 1), (stakeholder(2), 3)]
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn bootstrap_stake_is_split_by_weight() {
+        let state = state();
+        let stakes: BTreeMap<_, _> = state
+            .output_stakes(&bootstrap_output(4003))
+            .into_iter()
+            .collect();
+        // the remainder goes to the first stakeholder
+        let (first, second) = if stakeholder(1) < stakeholder(2) {
+            (3, 0)
+        } else {
+            (0, 3)
+        };
+        assert_eq!(stakes[&stakeholder(1)], 1000 + first);
+        assert_eq!(stakes[&stakeholder(2)], 3000 + second);
+        assert!(StakeState::default()
+            .output_stakes(&bootstrap_output(10))
+            .is_empty());
+    }
+
+    #[test]
+    fn stakes_follow_the_outputs() {
+        let mut state = state();
+        state.add_output(&bootstrap_output(400));
+        state.add_output(&single_key_output(3, 600));
+        assert_eq!(state.stake_of(&stakeholder(1)), 100);
+        assert_eq!(state.stake_of(&stakeholder(2)), 300);
+        assert_eq!(state.stake_of(&stakeholder(3)), 600);
+        assert_eq!(state.total_stake(), 1000);
+        assert_eq!(
+            ssc_state::richmen(
+                &state.stakes,
+                &CoinPortion::new(200_000_000_000_000).unwrap()
+            ),
+            vec![stakeholder(2), stakeholder(3)].into_iter().collect()
+        );
+
+        state.remove_output(&single_key_output(3, 600));
+        assert_eq!(state.stake_of(&stakeholder(3)), 0);
+        assert!(!state.stakes.contains_key(&stakeholder(3)));
+        state.remove_output(&bootstrap_output(400));
+        assert!(state.stakes.is_empty());
+
+        // an unknown output only removes the stake left
+        state.add_output(&single_key_output(3, 100));
+        state.remove_output(&single_key_output(3, 600));
+        assert!(state.stakes.is_empty());
+    }
+
+    #[test]
+    fn leaders_are_elected_from_the_crucial_stakes() {
+        let mut state = state();
+        state.add_output(&single_key_output(3, 600));
+        let crucial = state.stakes.clone();
+        assert_eq!(state.record_crucial(4), None);
+        state.add_output(&single_key_output(4, 400));
+
+        assert_eq!(state.leaders_stakes(5), &crucial);
+        // the crucial slot of the epoch 5 has no block after it yet
+        assert_eq!(state.leaders_stakes(6), &state.stakes);
+        assert_eq!(state.leaders_stakes(4), &state.stakes);
+
+        // the snapshot of the epoch 5 is kept during the whole epoch
+        state.start_epoch(5);
+        assert_eq!(state.epoch_stakes, crucial);
+        state.add_output(&single_key_output(5, 100));
+        state.record_crucial(5);
+        assert_eq!(state.epoch_stakes, crucial);
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut state = state();
+        state.add_output(&single_key_output(3, 600));
+        state.record_crucial(4);
+        state.start_epoch(5);
+        let bytes = cbor!(&state).unwrap();
+        let decoded: StakeState = Deserializer::from(::std::io::Cursor::new(&bytes))
+            .deserialize_complete()
+            .unwrap();
+        assert_eq!(decoded, state);
+    }
+}
@@ -4,6 +4,7 @@ use hash::Blake2b256;
 use util::{hex, try_from_slice::TryFromSlice};
 
 use std::{
+    collections::BTreeMap,
     fmt,
     io::{BufRead, Write},
     str::FromStr,
@@ -151,6 +152,12 @@ impl SoftwareVersion {
                 name
             )));
         }
+        if !name.is_ascii() {
+            return Err(cbor_event::Error::CustomError(format!(
+                "Received application name '{}' contains non-ASCII characters",
+                name
+            )));
+        }
         Ok(SoftwareVersion {
             application_name: name.to_string(),
             application_version: version,
@@ -171,8 +178,8 @@ impl Default for SoftwareVersion {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct BlockHeaderAttributes(pub cbor_event::Value);
+/// The attributes of the block headers, which have no known fields.
+pub type BlockHeaderAttributes = Attributes;
 
 #[derive(Debug, Clone)]
 pub struct HeaderExtraData {
@@ -231,7 +238,7 @@ impl SscProof {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ChainDifficulty(u64);
 
 impl fmt::Display for ChainDifficulty {
@@ -260,14 +267,24 @@ pub struct EpochSlotId {
     pub slotid: SlotId,
 }
 impl EpochSlotId {
-    pub fn next(&self) -> Self {
-        EpochSlotId {
-            epoch: self.epoch,
-            slotid: self.slotid + 1,
+    /// The next slot, in epochs of `epoch_slots` slots.
+    pub fn next(&self, epoch_slots: u64) -> Self {
+        if u64::from(self.slotid) + 1 >= epoch_slots {
+            EpochSlotId {
+                epoch: self.epoch + 1,
+                slotid: 0,
+            }
+        } else {
+            EpochSlotId {
+                epoch: self.epoch,
+                slotid: self.slotid + 1,
+            }
         }
     }
-    pub fn slot_number(&self) -> usize {
-        (self.epoch as usize) * 21600 + (self.slotid as usize)
+    /// The number of slots since the start of the chain, in epochs of
+    /// `epoch_slots` slots.
+    pub fn slot_number(&self, epoch_slots: u64) -> u64 {
+        self.epoch * epoch_slots + u64::from(self.slotid)
     }
 }
 impl fmt::Display for EpochSlotId {
@@ -276,13 +293,6 @@ impl fmt::Display for EpochSlotId {
     }
 }
 
-impl ::std::ops::Sub<EpochSlotId> for EpochSlotId {
-    type Output = usize;
-    fn sub(self, rhs: Self) -> Self::Output {
-        self.slot_number() - rhs.slot_number()
-    }
-}
-
 // **************************************************************************
 // CBOR implementations
 // **************************************************************************
@@ -363,20 +373,6 @@ impl cbor_event::de::Deserialize for HeaderHash {
     }
 }
 
-impl cbor_event::se::Serialize for BlockHeaderAttributes {
-    fn serialize<'se, W: Write>(
-        &self,
-        serializer: &'se mut Serializer<W>,
-    ) -> cbor_event::Result<&'se mut Serializer<W>> {
-        serializer.serialize(&self.0)
-    }
-}
-impl cbor_event::de::Deserialize for BlockHeaderAttributes {
-    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
-        Ok(BlockHeaderAttributes(raw.deserialize()?))
-    }
-}
-
 impl cbor_event::se::Serialize for HeaderExtraData {
     fn serialize<'se, W: Write>(
         &self,
@@ -510,7 +506,95 @@ impl cbor_event::de::Deserialize for EpochSlotId {
     }
 }
 
-pub type Attributes = cbor_event::Value; // TODO
+/// Attributes of the block headers, block bodies and update proposals:
+/// a map from small integers to CBOR encoded values, none of which are
+/// known by this version of the protocol. The unknown attributes are
+/// kept to be serialized back as received.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Attributes {
+    pub unknown: BTreeMap<u8, Vec<u8>>,
+}
+impl Attributes {
+    pub fn new() -> Self {
+        Attributes::default()
+    }
+
+    /// Whether there is no unknown attribute, as required in the blocks
+    /// of the adopted block version.
+    pub fn has_unknown(&self) -> bool {
+        !self.unknown.is_empty()
+    }
+}
+impl cbor_event::se::Serialize for Attributes {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        let mut serializer =
+            serializer.write_map(cbor_event::Len::Len(self.unknown.len() as u64))?;
+        for (key, value) in self.unknown.iter() {
+            serializer = serializer
+                .write_unsigned_integer(u64::from(*key))?
+                .write_bytes(value)?;
+        }
+        Ok(serializer)
+    }
+}
+impl cbor_event::de::Deserialize for Attributes {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        let len = match raw.map()? {
+            cbor_event::Len::Len(len) => len,
+            cbor_event::Len::Indefinite => {
+                return Err(cbor_event::Error::CustomError(
+                    "Invalid Attributes: received map of indefinite length".to_owned(),
+                ));
+            }
+        };
+        let mut unknown = BTreeMap::new();
+        for _ in 0..len {
+            let key = raw.unsigned_integer()?;
+            if key > u64::from(u8::MAX) {
+                return Err(cbor_event::Error::CustomError(format!(
+                    "Invalid Attributes: key {} is not a byte",
+                    key
+                )));
+            }
+            if unknown.insert(key as u8, raw.bytes()?).is_some() {
+                return Err(cbor_event::Error::CustomError(format!(
+                    "Invalid Attributes: duplicate key {}",
+                    key
+                )));
+            }
+        }
+        Ok(Attributes { unknown })
+    }
+}
+
+/// The extra data of the block bodies and of the boundary block headers,
+/// which only consists of attributes. The hash of the extra data of the
+/// main block bodies is the extra data proof of their headers.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ExtraData {
+    pub attributes: Attributes,
+}
+impl cbor_event::se::Serialize for ExtraData {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        serializer
+            .write_array(cbor_event::Len::Len(1))?
+            .serialize(&self.attributes)
+    }
+}
+impl cbor_event::de::Deserialize for ExtraData {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(1, "ExtraData")?;
+        Ok(ExtraData {
+            attributes: raw.deserialize()?,
+        })
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct CoinPortion(u64);
@@ -527,6 +611,18 @@ impl CoinPortion {
         }
         Ok(CoinPortion(n))
     }
+
+    /// whether `stake` is at least this portion of `total`
+    pub fn is_reached_by(&self, stake: u64, total: u64) -> bool {
+        u128::from(stake) * u128::from(COIN_PORTION_DENOMINATOR)
+            >= u128::from(self.0) * u128::from(total)
+    }
+}
+
+impl From<CoinPortion> for u64 {
+    fn from(portion: CoinPortion) -> Self {
+        portion.0
+    }
 }
 
 impl cbor_event::se::Serialize for CoinPortion {
@@ -587,6 +683,38 @@ mod tests {
     use super::*;
     use hash::Blake2b256;
 
+    #[test]
+    fn attributes_are_round_tripped() {
+        let mut attributes = Attributes::new();
+        attributes.unknown.insert(0, vec![0x01]);
+        attributes.unknown.insert(42, vec![0x43, 0x61, 0x62, 0x63]);
+        let extra = ExtraData { attributes };
+
+        let bytes = cbor!(&extra).unwrap();
+        assert_eq!(
+            bytes,
+            vec![0x81, 0xa2, 0x00, 0x41, 0x01, 0x18, 0x2a, 0x44, 0x43, 0x61, 0x62, 0x63]
+        );
+        let decoded: ExtraData = Deserializer::from(std::io::Cursor::new(&bytes))
+            .deserialize()
+            .unwrap();
+        assert_eq!(decoded, extra);
+        assert!(decoded.attributes.has_unknown());
+
+        // the keys are bytes
+        let bytes = vec![0xa1, 0x19, 0x01, 0x00, 0x40];
+        assert!(Deserializer::from(std::io::Cursor::new(&bytes))
+            .deserialize::<Attributes>()
+            .is_err());
+    }
+
+    #[test]
+    fn application_names_are_short_ascii_strings() {
+        assert!(SoftwareVersion::new("cardano-sl", 1).is_ok());
+        assert!(SoftwareVersion::new("cardano-sl-13", 1).is_err());
+        assert!(SoftwareVersion::new("cardanø", 1).is_err());
+    }
+
     #[test]
     fn debug_header_hash() {
         let h = HeaderHash(Blake2b256::new(&[0; 32]));
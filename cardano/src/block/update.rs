@@ -1,5 +1,6 @@
 use super::types;
 use cbor_event::{self, de::Deserializer, se::Serializer};
+use fee;
 use hash::{self, Blake2b256};
 use hdwallet;
 
@@ -84,6 +85,13 @@ pub struct UpdateProposal {
 pub type UpAttributes = types::Attributes;
 pub type SystemTag = String;
 
+impl UpdateProposal {
+    /// the identifier votes refer to this proposal with
+    pub fn id(&self) -> UpId {
+        Blake2b256::new(&cbor!(self).unwrap())
+    }
+}
+
 impl cbor_event::se::Serialize for UpdateProposal {
     fn serialize<'se, W: Write>(
         &self,
@@ -116,7 +124,7 @@ impl cbor_event::de::Deserialize for UpdateProposal {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BlockVersionModifier {
     pub script_version: Option<ScriptVersion>,
     pub slot_duration: Option<Millisecond>,
@@ -139,7 +147,6 @@ impl cbor_event::se::Serialize for BlockVersionModifier {
         &self,
         serializer: &'se mut Serializer<W>,
     ) -> cbor_event::Result<&'se mut Serializer<W>> {
-        assert!(self.tx_fee_policy.is_none()); // not tested yet
         serializer
             .write_array(cbor_event::Len::Len(14))?
             .serialize(&self.script_version)?
@@ -181,10 +188,244 @@ impl cbor_event::de::Deserialize for BlockVersionModifier {
     }
 }
 
+/// The protocol parameters in use on the chain, updated by adopting
+/// the `BlockVersionModifier` of an update proposal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockVersionData {
+    pub script_version: ScriptVersion,
+    pub slot_duration: Millisecond,
+    pub max_block_size: u64,
+    pub max_header_size: u64,
+    pub max_tx_size: u64,
+    pub max_proposal_size: u64,
+    pub mpc_thd: types::CoinPortion,
+    pub heavy_del_thd: types::CoinPortion,
+    pub update_vote_thd: types::CoinPortion,
+    pub update_proposal_thd: types::CoinPortion,
+    pub update_implicit: FlatSlotId,
+    pub softfork_rule: SoftforkRule,
+    pub tx_fee_policy: fee::LinearFee,
+    pub unlock_stake_epoch: types::EpochId,
+}
+
+impl BlockVersionData {
+    /// the parameters of the mainnet genesis, with the given slot
+    /// duration and fee policy.
+    pub fn mainnet(slot_duration: Millisecond, tx_fee_policy: fee::LinearFee) -> Self {
+        let portion = |n| types::CoinPortion::new(n).unwrap();
+        BlockVersionData {
+            script_version: 0,
+            slot_duration,
+            max_block_size: 2_000_000,
+            max_header_size: 2_000_000,
+            max_tx_size: 4096,
+            max_proposal_size: 700,
+            mpc_thd: portion(20_000_000_000_000),
+            heavy_del_thd: portion(300_000_000_000),
+            update_vote_thd: portion(1_000_000_000_000),
+            update_proposal_thd: portion(100_000_000_000_000),
+            update_implicit: 10_000,
+            softfork_rule: SoftforkRule {
+                init_thd: portion(900_000_000_000_000),
+                min_thd: portion(600_000_000_000_000),
+                thd_decrement: portion(50_000_000_000_000),
+            },
+            tx_fee_policy,
+            unlock_stake_epoch: u64::MAX,
+        }
+    }
+
+    /// the parameters after adopting the given modifier
+    pub fn apply(&self, modifier: &BlockVersionModifier) -> Self {
+        let mut bvd = self.clone();
+        macro_rules! modify {
+            ($($field:ident),*) => {
+                $(if let Some(value) = &modifier.$field {
+                    bvd.$field = value.clone();
+                })*
+            };
+        }
+        modify!(
+            script_version,
+            slot_duration,
+            max_block_size,
+            max_header_size,
+            max_tx_size,
+            max_proposal_size,
+            mpc_thd,
+            heavy_del_thd,
+            update_vote_thd,
+            update_proposal_thd,
+            update_implicit,
+            softfork_rule,
+            unlock_stake_epoch
+        );
+        // policies we do not know, or cannot represent exactly, keep the
+        // current one in use
+        if let Some(fee_policy) = modifier
+            .tx_fee_policy
+            .as_ref()
+            .and_then(TxFeePolicy::to_linear_fee)
+        {
+            bvd.tx_fee_policy = fee_policy;
+        }
+        bvd
+    }
+}
+
+impl cbor_event::se::Serialize for BlockVersionData {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        serializer
+            .write_array(cbor_event::Len::Len(15))?
+            .serialize(&self.script_version)?
+            .serialize(&self.slot_duration)?
+            .serialize(&self.max_block_size)?
+            .serialize(&self.max_header_size)?
+            .serialize(&self.max_tx_size)?
+            .serialize(&self.max_proposal_size)?
+            .serialize(&self.mpc_thd)?
+            .serialize(&self.heavy_del_thd)?
+            .serialize(&self.update_vote_thd)?
+            .serialize(&self.update_proposal_thd)?
+            .serialize(&self.update_implicit)?
+            .serialize(&self.softfork_rule)?
+            .serialize(&self.tx_fee_policy.constant.as_millis())?
+            .serialize(&self.tx_fee_policy.coefficient.as_millis())?
+            .serialize(&self.unlock_stake_epoch)
+    }
+}
+
+impl cbor_event::de::Deserialize for BlockVersionData {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(15, "BlockVersionData")?;
+        let milli = |n: u64| fee::Milli::new(n / 1000, n % 1000);
+        Ok(Self {
+            script_version: raw.deserialize()?,
+            slot_duration: raw.deserialize()?,
+            max_block_size: raw.deserialize()?,
+            max_header_size: raw.deserialize()?,
+            max_tx_size: raw.deserialize()?,
+            max_proposal_size: raw.deserialize()?,
+            mpc_thd: raw.deserialize()?,
+            heavy_del_thd: raw.deserialize()?,
+            update_vote_thd: raw.deserialize()?,
+            update_proposal_thd: raw.deserialize()?,
+            update_implicit: raw.deserialize()?,
+            softfork_rule: raw.deserialize()?,
+            tx_fee_policy: fee::LinearFee::new(
+                milli(raw.deserialize()?),
+                milli(raw.deserialize()?),
+            ),
+            unlock_stake_epoch: raw.deserialize()?,
+        })
+    }
+}
+
 pub type ScriptVersion = u16;
 pub type Millisecond = u64;
 pub type FlatSlotId = u64;
-pub type TxFeePolicy = cbor_event::Value; // TODO
+
+/// The number of `TxFeePolicy` coefficient units in a lovelace.
+pub const TX_FEE_POLICY_UNITS_PER_LOVELACE: u64 = 1_000_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxFeePolicy {
+    /// `summand + multiplier * size_in_bytes`, both expressed in
+    /// 10^-9 lovelace.
+    TxSizeLinear { summand: u64, multiplier: u64 },
+    /// a policy this library does not know about, with its encoded
+    /// parameters.
+    Unknown(u64, Vec<u8>),
+}
+
+impl TxFeePolicy {
+    /// the equivalent `LinearFee`, if this is a linear policy whose
+    /// coefficients `LinearFee` can represent exactly.
+    ///
+    /// `LinearFee` has a precision of 10^-3 lovelace, so `None` is
+    /// returned for coefficients with a finer precision.
+    pub fn to_linear_fee(&self) -> Option<fee::LinearFee> {
+        let units_per_milli = TX_FEE_POLICY_UNITS_PER_LOVELACE / 1000;
+        let to_milli = |n: u64| {
+            let millis = n / units_per_milli;
+            if millis * units_per_milli != n {
+                return None;
+            }
+            Some(fee::Milli::new(millis / 1000, millis % 1000))
+        };
+        match self {
+            TxFeePolicy::TxSizeLinear {
+                summand,
+                multiplier,
+            } => Some(fee::LinearFee::new(
+                to_milli(*summand)?,
+                to_milli(*multiplier)?,
+            )),
+            TxFeePolicy::Unknown(_, _) => None,
+        }
+    }
+}
+
+impl From<fee::LinearFee> for TxFeePolicy {
+    fn from(fee: fee::LinearFee) -> Self {
+        let from_milli = |n: fee::Milli| n.as_millis() * (TX_FEE_POLICY_UNITS_PER_LOVELACE / 1000);
+        TxFeePolicy::TxSizeLinear {
+            summand: from_milli(fee.constant),
+            multiplier: from_milli(fee.coefficient),
+        }
+    }
+}
+
+impl cbor_event::se::Serialize for TxFeePolicy {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        let (idx, bytes) = match self {
+            TxFeePolicy::TxSizeLinear {
+                summand,
+                multiplier,
+            } => (0, cbor!(&(summand, multiplier))?),
+            TxFeePolicy::Unknown(idx, bytes) => (*idx, bytes.clone()),
+        };
+        serializer
+            .write_array(cbor_event::Len::Len(2))?
+            .write_unsigned_integer(idx)?
+            .write_tag(24)?
+            .write_bytes(&bytes)
+    }
+}
+
+impl cbor_event::de::Deserialize for TxFeePolicy {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(2, "TxFeePolicy")?;
+        let idx = raw.unsigned_integer()?;
+        let tag = raw.tag()?;
+        if tag != 24 {
+            return Err(cbor_event::Error::CustomError(format!(
+                "Invalid Tag: {} but expected 24",
+                tag
+            )));
+        }
+        let bytes = raw.bytes()?;
+        match idx {
+            0 => {
+                let mut raw = Deserializer::from(::std::io::Cursor::new(bytes));
+                raw.tuple(2, "TxFeePolicy::TxSizeLinear")?;
+                let summand = raw.deserialize()?;
+                let multiplier = raw.deserialize()?;
+                Ok(TxFeePolicy::TxSizeLinear {
+                    summand,
+                    multiplier,
+                })
+            }
+            _ => Ok(TxFeePolicy::Unknown(idx, bytes)),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct UpdateData {
@@ -220,13 +461,24 @@ impl cbor_event::de::Deserialize for UpdateData {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SoftforkRule {
     pub init_thd: types::CoinPortion,
     pub min_thd: types::CoinPortion,
     pub thd_decrement: types::CoinPortion,
 }
 
+impl SoftforkRule {
+    /// the portion of stake that must have issued blocks of a
+    /// confirmed version for it to be adopted, `epochs` epochs after
+    /// its confirmation.
+    pub fn threshold(&self, epochs: u64) -> types::CoinPortion {
+        let decrement = u64::from(self.thd_decrement).saturating_mul(epochs);
+        let thd = u64::from(self.init_thd).saturating_sub(decrement);
+        types::CoinPortion::new(thd.max(u64::from(self.min_thd))).unwrap()
+    }
+}
+
 impl cbor_event::se::Serialize for SoftforkRule {
     fn serialize<'se, W: Write>(
         &self,
@@ -312,6 +564,55 @@ mod tests {
     use super::*;
     use hash::Blake2b256;
 
+    fn mainnet_fee_policy() -> TxFeePolicy {
+        TxFeePolicy::TxSizeLinear {
+            summand: 155_381_000_000_000,
+            multiplier: 43_946_000_000,
+        }
+    }
+
+    fn decode_fee_policy(bytes: &[u8]) -> TxFeePolicy {
+        Deserializer::from(::std::io::Cursor::new(bytes))
+            .deserialize_complete()
+            .unwrap()
+    }
+
+    #[test]
+    fn tx_fee_policy_encode_decode() {
+        let bytes = [
+            0x82, 0x00, 0xd8, 0x18, 0x53, 0x82, 0x1b, 0x00, 0x00, 0x8d, 0x51, 0x75, 0x4f, 0x52,
+            0x00, 0x1b, 0x00, 0x00, 0x00, 0x0a, 0x3b, 0x62, 0xbe, 0x80,
+        ];
+        let policy = mainnet_fee_policy();
+        assert_eq!(cbor!(&policy).unwrap(), &bytes[..]);
+        assert_eq!(decode_fee_policy(&bytes), policy);
+
+        let unknown = [0x82, 0x07, 0xd8, 0x18, 0x43, 0x82, 0x01, 0x02];
+        let policy = decode_fee_policy(&unknown);
+        assert_eq!(policy, TxFeePolicy::Unknown(7, vec![0x82, 0x01, 0x02]));
+        assert_eq!(cbor!(&policy).unwrap(), &unknown[..]);
+        assert!(policy.to_linear_fee().is_none());
+    }
+
+    #[test]
+    fn tx_fee_policy_to_linear_fee() {
+        let fee = fee::LinearFee::new(fee::Milli::integral(155381), fee::Milli::new(43, 946));
+        assert_eq!(mainnet_fee_policy().to_linear_fee(), Some(fee));
+        assert_eq!(TxFeePolicy::from(fee), mainnet_fee_policy());
+
+        // the coefficients are not truncated to the precision of a fee
+        let policy = TxFeePolicy::TxSizeLinear {
+            summand: 155381000000001,
+            multiplier: 43946000000,
+        };
+        assert!(policy.to_linear_fee().is_none());
+        let policy = TxFeePolicy::TxSizeLinear {
+            summand: 155381000000000,
+            multiplier: 43946100000,
+        };
+        assert!(policy.to_linear_fee().is_none());
+    }
+
     #[test]
     fn debug_update_proof() {
         let h = UpdateProof(Blake2b256::new(&[0; 32]));
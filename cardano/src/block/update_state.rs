@@ -0,0 +1,779 @@
+//! Update system
+//!
+//! Tracks the update proposals and votes of the chain and the block
+//! version (and protocol parameters) they lead to:
+//!
+//! * a proposal is accepted if its proposer holds at least
+//!   `update_proposal_thd` of the stake;
+//! * it is approved (or rejected) once the stake voting for (or
+//!   against) it reaches `update_vote_thd`. If neither happened
+//!   `update_implicit` slots after it was proposed, it is approved if
+//!   more stake voted for it than against it;
+//! * the block version of an approved proposal is confirmed at the
+//!   next epoch boundary, after which the slot leaders signal their
+//!   support by issuing blocks of that version;
+//! * at every epoch boundary, a confirmed version is adopted if the
+//!   stake of its issuers reaches the threshold of the `softfork_rule`,
+//!   which decreases with every epoch since its confirmation.
+
+use address::StakeholderId;
+use cbor_event::{self, de::Deserializer, se::Serializer};
+use config::GenesisData;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{BufRead, Write},
+    mem,
+};
+
+use super::types::{BlockVersion, EpochId};
+use super::update::{BlockVersionData, BlockVersionModifier, FlatSlotId, UpId, UpdatePayload};
+use super::verify::{first_error, Error, ErrorLocation, Errors, LocatedError};
+
+/// The stake of every stakeholder, used to weigh proposers, votes and
+/// block issuers.
+pub type Stakes = BTreeMap<StakeholderId, u64>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProposalState {
+    pub block_version: BlockVersion,
+    pub modifier: BlockVersionModifier,
+    /// the slot of the block the proposal was included in
+    pub slot: FlatSlotId,
+    pub votes: BTreeMap<StakeholderId, bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfirmedVersion {
+    pub modifier: BlockVersionModifier,
+    /// the epoch at the start of which the version was confirmed
+    pub epoch: EpochId,
+    /// the slot leaders that issued blocks of this version
+    pub issuers: BTreeSet<StakeholderId>,
+}
+
+/// A change of the update state, to revert it.
+#[derive(Debug, Clone)]
+pub enum UpdateChange {
+    /// a proposal was added or removed, replacing the given one if any
+    Proposal(UpId, Option<ProposalState>),
+    /// a vote was recorded, replacing the given decision if any
+    Vote(UpId, StakeholderId, Option<bool>),
+    /// a version was approved or its approval dropped, replacing the
+    /// given modifier if any
+    Approved(BlockVersion, Option<BlockVersionModifier>),
+    /// a version was confirmed or its confirmation dropped, replacing
+    /// the given one if any
+    Confirmed(BlockVersion, Option<ConfirmedVersion>),
+    /// an issuer was recorded for a confirmed version
+    Issuer(BlockVersion, StakeholderId),
+    /// a version was adopted, replacing the given version and parameters
+    Adopted(BlockVersion, BlockVersionData),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateState {
+    pub adopted_version: BlockVersion,
+    pub parameters: BlockVersionData,
+    /// the proposals being voted on
+    pub proposals: BTreeMap<UpId, ProposalState>,
+    /// the versions approved during the current epoch
+    pub approved: BTreeMap<BlockVersion, BlockVersionModifier>,
+    /// the versions waiting to be adopted
+    pub confirmed: BTreeMap<BlockVersion, ConfirmedVersion>,
+}
+
+impl UpdateState {
+    /// The initial state: the genesis parameters are adopted.
+    pub fn new(genesis_data: &GenesisData) -> Self {
+        UpdateState {
+            adopted_version: BlockVersion::new(0, 0, 0),
+            parameters: genesis_data.block_version_data.clone(),
+            proposals: BTreeMap::new(),
+            approved: BTreeMap::new(),
+            confirmed: BTreeMap::new(),
+        }
+    }
+
+    /// Record the proposal and votes of a block issued at `slot`, the
+    /// thresholds being computed with the given stake distribution, the
+    /// snapshot of the epoch of `slot`. As
+    /// for the rest of the chain state, the valid parts of the payload
+    /// are recorded even if another part is invalid.
+    pub fn apply_payload(
+        &mut self,
+        slot: FlatSlotId,
+        stakes: &Stakes,
+        payload: &UpdatePayload,
+    ) -> Result<(), Error> {
+        let mut errors = vec![];
+        self.apply_payload_all_errors(slot, stakes, payload, &mut vec![], &mut errors);
+        first_error(errors)
+    }
+
+    /// Like `apply_payload`, recording every error of the payload and
+    /// the changes made in `changes`.
+    pub fn apply_payload_all_errors(
+        &mut self,
+        slot: FlatSlotId,
+        stakes: &Stakes,
+        payload: &UpdatePayload,
+        changes: &mut Vec<UpdateChange>,
+        errors: &mut Errors,
+    ) {
+        let mut error = |error| errors.push(LocatedError::new(ErrorLocation::UpdatePayload, error));
+
+        if let Some(proposal) = &payload.proposal {
+            let proposer = StakeholderId::new(&proposal.from);
+            let version = proposal.block_version;
+            if version <= self.adopted_version || self.confirmed.contains_key(&version) {
+                error(Error::WrongProposedBlockVersion);
+            } else if self.approved.contains_key(&version)
+                || self.proposals.values().any(|p| p.block_version == version)
+            {
+                error(Error::DuplicateUpdateProposal);
+            } else if !reaches(
+                stakes,
+                &self.parameters.update_proposal_thd,
+                stake_of(stakes, Some(&proposer)),
+            ) {
+                error(Error::ProposerStakeTooLow);
+            } else {
+                let replaced = self.proposals.insert(
+                    proposal.id(),
+                    ProposalState {
+                        block_version: version,
+                        modifier: proposal.block_version_mod.clone(),
+                        slot,
+                        votes: BTreeMap::new(),
+                    },
+                );
+                changes.push(UpdateChange::Proposal(proposal.id(), replaced));
+            }
+        }
+
+        for vote in payload.votes.iter() {
+            let voter = StakeholderId::new(&vote.key);
+            match self.proposals.get_mut(&vote.proposal_id) {
+                None => error(Error::UnknownUpdateProposal),
+                Some(proposal) => {
+                    let previous = proposal.votes.insert(voter, vote.decision);
+                    if previous == Some(vote.decision) {
+                        error(Error::DuplicateUpdateVote);
+                    }
+                    changes.push(UpdateChange::Vote(vote.proposal_id, voter, previous));
+                }
+            };
+        }
+
+        self.tally(slot, stakes, changes);
+    }
+
+    /// Decide the proposals that reached the vote threshold, or the
+    /// implicit agreement delay, at `slot`.
+    fn tally(&mut self, slot: FlatSlotId, stakes: &Stakes, changes: &mut Vec<UpdateChange>) {
+        let mut decided = vec![];
+        for (id, proposal) in self.proposals.iter() {
+            let for_stake = stake_of(stakes, proposal.votes.iter().filter(|v| *v.1).map(|v| v.0));
+            let against_stake =
+                stake_of(stakes, proposal.votes.iter().filter(|v| !*v.1).map(|v| v.0));
+            let threshold = &self.parameters.update_vote_thd;
+
+            if reaches(stakes, threshold, for_stake) {
+                decided.push((*id, true));
+            } else if reaches(stakes, threshold, against_stake) {
+                decided.push((*id, false));
+            } else if slot >= proposal.slot + self.parameters.update_implicit {
+                decided.push((*id, for_stake > against_stake));
+            }
+        }
+
+        for (id, approved) in decided {
+            let proposal = self.proposals.remove(&id).unwrap();
+            if approved {
+                let replaced = self
+                    .approved
+                    .insert(proposal.block_version, proposal.modifier.clone());
+                changes.push(UpdateChange::Approved(proposal.block_version, replaced));
+            }
+            changes.push(UpdateChange::Proposal(id, Some(proposal)));
+        }
+    }
+
+    /// Record that `issuer` issued a block of the given version.
+    pub fn record_issuer(
+        &mut self,
+        version: &BlockVersion,
+        issuer: StakeholderId,
+        changes: &mut Vec<UpdateChange>,
+    ) {
+        if let Some(confirmed) = self.confirmed.get_mut(version) {
+            if confirmed.issuers.insert(issuer) {
+                changes.push(UpdateChange::Issuer(*version, issuer));
+            }
+        }
+    }
+
+    /// Adopt the best confirmed version supported by enough issuers,
+    /// weighed with `stakes`, the snapshot of `epoch`, then confirm the
+    /// versions approved during the last epoch. Returns the newly adopted
+    /// version, if any.
+    pub fn apply_epoch_boundary(
+        &mut self,
+        epoch: EpochId,
+        stakes: &Stakes,
+        changes: &mut Vec<UpdateChange>,
+    ) -> Option<BlockVersion> {
+        let adopted = self
+            .confirmed
+            .iter()
+            .rev()
+            .find(|(_, confirmed)| {
+                let epochs = epoch.saturating_sub(confirmed.epoch + 1);
+                let threshold = self.parameters.softfork_rule.threshold(epochs);
+                confirmed.epoch < epoch
+                    && reaches(stakes, &threshold, stake_of(stakes, &confirmed.issuers))
+            })
+            .map(|(version, confirmed)| (*version, confirmed.modifier.clone()));
+
+        if let Some((version, modifier)) = &adopted {
+            let parameters = self.parameters.apply(modifier);
+            changes.push(UpdateChange::Adopted(
+                mem::replace(&mut self.adopted_version, *version),
+                mem::replace(&mut self.parameters, parameters),
+            ));
+
+            // Drop the adopted version and the older ones.
+            let mut dropped = mem::take(&mut self.confirmed);
+            self.confirmed = dropped.split_off(version);
+            dropped.extend(self.confirmed.remove_entry(version));
+            for (version, confirmed) in dropped {
+                changes.push(UpdateChange::Confirmed(version, Some(confirmed)));
+            }
+
+            let outdated: Vec<UpId> = self
+                .proposals
+                .iter()
+                .filter(|(_, p)| p.block_version <= *version)
+                .map(|(id, _)| *id)
+                .collect();
+            for id in outdated {
+                let proposal = self.proposals.remove(&id);
+                changes.push(UpdateChange::Proposal(id, proposal));
+            }
+        }
+
+        for (version, modifier) in mem::take(&mut self.approved) {
+            if version > self.adopted_version {
+                let replaced = self.confirmed.insert(
+                    version,
+                    ConfirmedVersion {
+                        modifier: modifier.clone(),
+                        epoch,
+                        issuers: BTreeSet::new(),
+                    },
+                );
+                changes.push(UpdateChange::Confirmed(version, replaced));
+            }
+            changes.push(UpdateChange::Approved(version, Some(modifier)));
+        }
+
+        adopted.map(|(version, _)| version)
+    }
+
+    /// Undo the changes of the update state, in the reverse order they
+    /// were made.
+    pub fn revert_changes(&mut self, changes: Vec<UpdateChange>) {
+        fn restore<K: Ord, V>(map: &mut BTreeMap<K, V>, key: K, value: Option<V>) {
+            match value {
+                Some(value) => map.insert(key, value),
+                None => map.remove(&key),
+            };
+        }
+
+        for change in changes.into_iter().rev() {
+            match change {
+                UpdateChange::Proposal(id, proposal) => restore(&mut self.proposals, id, proposal),
+                UpdateChange::Vote(id, voter, decision) => {
+                    if let Some(proposal) = self.proposals.get_mut(&id) {
+                        restore(&mut proposal.votes, voter, decision);
+                    }
+                }
+                UpdateChange::Approved(version, modifier) => {
+                    restore(&mut self.approved, version, modifier)
+                }
+                UpdateChange::Confirmed(version, confirmed) => {
+                    restore(&mut self.confirmed, version, confirmed)
+                }
+                UpdateChange::Issuer(version, issuer) => {
+                    if let Some(confirmed) = self.confirmed.get_mut(&version) {
+                        confirmed.issuers.remove(&issuer);
+                    }
+                }
+                UpdateChange::Adopted(version, parameters) => {
+                    self.adopted_version = version;
+                    self.parameters = parameters;
+                }
+            }
+        }
+    }
+}
+
+fn stake_of<'a, I>(stakes: &Stakes, stakeholders: I) -> u64
+where
+    I: IntoIterator<Item = &'a StakeholderId>,
+{
+    stakeholders
+        .into_iter()
+        .filter_map(|id| stakes.get(id))
+        .sum()
+}
+
+fn reaches(stakes: &Stakes, threshold: &super::types::CoinPortion, stake: u64) -> bool {
+    let total: u64 = stakes.values().sum();
+    total > 0 && threshold.is_reached_by(stake, total)
+}
+
+impl cbor_event::se::Serialize for ProposalState {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        let serializer = serializer
+            .write_array(cbor_event::Len::Len(4))?
+            .serialize(&self.block_version)?
+            .serialize(&self.modifier)?
+            .serialize(&self.slot)?;
+        cbor_event::se::serialize_fixed_map(self.votes.iter(), serializer)
+    }
+}
+
+impl cbor_event::de::Deserialize for ProposalState {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(4, "ProposalState")?;
+        Ok(ProposalState {
+            block_version: raw.deserialize()?,
+            modifier: raw.deserialize()?,
+            slot: raw.deserialize()?,
+            votes: raw.deserialize()?,
+        })
+    }
+}
+
+impl cbor_event::se::Serialize for ConfirmedVersion {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        let serializer = serializer
+            .write_array(cbor_event::Len::Len(3))?
+            .serialize(&self.modifier)?
+            .serialize(&self.epoch)?;
+        cbor_event::se::serialize_fixed_array(self.issuers.iter(), serializer)
+    }
+}
+
+impl cbor_event::de::Deserialize for ConfirmedVersion {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(3, "ConfirmedVersion")?;
+        let modifier = raw.deserialize()?;
+        let epoch = raw.deserialize()?;
+        let issuers: Vec<StakeholderId> = raw.deserialize()?;
+        Ok(ConfirmedVersion {
+            modifier,
+            epoch,
+            issuers: issuers.into_iter().collect(),
+        })
+    }
+}
+
+impl cbor_event::se::Serialize for UpdateState {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        let serializer = serializer
+            .write_array(cbor_event::Len::Len(5))?
+            .serialize(&self.adopted_version)?
+            .serialize(&self.parameters)?;
+        let serializer = cbor_event::se::serialize_fixed_map(self.proposals.iter(), serializer)?;
+        let serializer = cbor_event::se::serialize_fixed_map(self.approved.iter(), serializer)?;
+        cbor_event::se::serialize_fixed_map(self.confirmed.iter(), serializer)
+    }
+}
+
+impl cbor_event::de::Deserialize for UpdateState {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(5, "UpdateState")?;
+        Ok(UpdateState {
+            adopted_version: raw.deserialize()?,
+            parameters: raw.deserialize()?,
+            proposals: raw.deserialize()?,
+            approved: raw.deserialize()?,
+            confirmed: raw.deserialize()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::types::{Attributes, CoinPortion, SoftwareVersion};
+    use block::update::{SoftforkRule, TxFeePolicy, UpdateProposal, UpdateVote};
+    use fee;
+    use hdwallet::{Seed, XPrv};
+
+    fn key(n: u8) -> XPrv {
+        XPrv::generate_from_seed(&Seed::from_bytes([n; 32]))
+    }
+
+    fn stakeholder(n: u8) -> StakeholderId {
+        StakeholderId::new(&key(n).public())
+    }
+
+    fn portion(percent: u64) -> CoinPortion {
+        CoinPortion::new(percent * 10_000_000_000_000).unwrap()
+    }
+
+    fn state() -> UpdateState {
+        let mut parameters = BlockVersionData::mainnet(
+            20_000,
+            fee::LinearFee::new(fee::Milli::integral(1), fee::Milli::integral(1)),
+        );
+        parameters.update_proposal_thd = portion(20);
+        parameters.update_vote_thd = portion(50);
+        parameters.update_implicit = 100;
+        parameters.softfork_rule = SoftforkRule {
+            init_thd: portion(75),
+            min_thd: portion(50),
+            thd_decrement: portion(25),
+        };
+        UpdateState {
+            adopted_version: BlockVersion::new(0, 0, 0),
+            parameters,
+            proposals: BTreeMap::new(),
+            approved: BTreeMap::new(),
+            confirmed: BTreeMap::new(),
+        }
+    }
+
+    /// stakeholders 1 to 4 hold 1 stake each
+    fn stakes() -> Stakes {
+        (1..5).map(|n| (stakeholder(n), 1)).collect()
+    }
+
+    fn modifier(max_tx_size: u64) -> BlockVersionModifier {
+        BlockVersionModifier {
+            script_version: None,
+            slot_duration: None,
+            max_block_size: None,
+            max_header_size: None,
+            max_tx_size: Some(max_tx_size),
+            max_proposal_size: None,
+            mpc_thd: None,
+            heavy_del_thd: None,
+            update_vote_thd: None,
+            update_proposal_thd: None,
+            update_implicit: None,
+            softfork_rule: None,
+            tx_fee_policy: None,
+            unlock_stake_epoch: None,
+        }
+    }
+
+    fn proposal(from: u8, minor: u16) -> UpdateProposal {
+        UpdateProposal {
+            block_version: BlockVersion::new(0, minor, 0),
+            block_version_mod: modifier(u64::from(minor) * 1000),
+            software_version: SoftwareVersion::new("cardano-sl", 1).unwrap(),
+            data: BTreeMap::new(),
+            attributes: Attributes::new(),
+            from: key(from).public(),
+            signature: key(from).sign(b"not checked"),
+        }
+    }
+
+    fn vote(from: u8, proposal: &UpdateProposal, decision: bool) -> UpdateVote {
+        UpdateVote {
+            key: key(from).public(),
+            proposal_id: proposal.id(),
+            decision,
+            signature: key(from).sign(b"not checked"),
+        }
+    }
+
+    fn payload(proposal: Option<UpdateProposal>, votes: Vec<UpdateVote>) -> UpdatePayload {
+        UpdatePayload { proposal, votes }
+    }
+
+    fn expect_error(res: Result<(), Error>, expected: &str) {
+        match res {
+            Err(err) => assert_eq!(format!("{:?}", err), expected),
+            Ok(()) => panic!("expected {}", expected),
+        }
+    }
+
+    #[test]
+    fn proposals_are_checked() {
+        let mut state = state();
+        let p = proposal(1, 1);
+        state
+            .apply_payload(10, &stakes(), &payload(Some(p.clone()), vec![]))
+            .unwrap();
+        assert_eq!(state.proposals[&p.id()].slot, 10);
+
+        expect_error(
+            state.apply_payload(11, &stakes(), &payload(Some(proposal(2, 1)), vec![])),
+            "DuplicateUpdateProposal",
+        );
+        expect_error(
+            state.apply_payload(11, &stakes(), &payload(Some(proposal(5, 2)), vec![])),
+            "ProposerStakeTooLow",
+        );
+        expect_error(
+            state.apply_payload(11, &stakes(), &payload(Some(proposal(1, 0)), vec![])),
+            "WrongProposedBlockVersion",
+        );
+        expect_error(
+            state.apply_payload(
+                11,
+                &stakes(),
+                &payload(None, vec![vote(1, &proposal(2, 2), true)]),
+            ),
+            "UnknownUpdateProposal",
+        );
+
+        state
+            .apply_payload(12, &stakes(), &payload(None, vec![vote(1, &p, false)]))
+            .unwrap();
+        expect_error(
+            state.apply_payload(13, &stakes(), &payload(None, vec![vote(1, &p, false)])),
+            "DuplicateUpdateVote",
+        );
+        assert_eq!(state.proposals.len(), 1);
+    }
+
+    #[test]
+    fn proposal_is_adopted() {
+        let mut state = state();
+        let p = proposal(1, 1);
+        let version = p.block_version;
+
+        state
+            .apply_payload(
+                10,
+                &stakes(),
+                &payload(Some(p.clone()), vec![vote(1, &p, true)]),
+            )
+            .unwrap();
+        assert!(state.approved.is_empty());
+        state
+            .apply_payload(11, &stakes(), &payload(None, vec![vote(2, &p, true)]))
+            .unwrap();
+        assert!(state.proposals.is_empty());
+        assert!(state.approved.contains_key(&version));
+
+        // confirmed at the next epoch boundary
+        assert_eq!(state.apply_epoch_boundary(1, &stakes(), &mut vec![]), None);
+        assert!(state.approved.is_empty());
+        assert_eq!(state.confirmed[&version].epoch, 1);
+
+        // 2 issuers out of 4 is below the initial threshold (75%)
+        state.record_issuer(&version, stakeholder(1), &mut vec![]);
+        state.record_issuer(&version, stakeholder(2), &mut vec![]);
+        state.record_issuer(&BlockVersion::new(0, 0, 0), stakeholder(3), &mut vec![]);
+        assert_eq!(state.apply_epoch_boundary(2, &stakes(), &mut vec![]), None);
+        assert_eq!(state.adopted_version, BlockVersion::new(0, 0, 0));
+
+        // but not the decremented one
+        assert_eq!(
+            state.apply_epoch_boundary(3, &stakes(), &mut vec![]),
+            Some(version)
+        );
+        assert_eq!(state.adopted_version, version);
+        assert_eq!(state.parameters.max_tx_size, 1000);
+        assert!(state.confirmed.is_empty());
+    }
+
+    #[test]
+    fn fee_policy_is_adopted() {
+        let mut state = state();
+        let mut p = proposal(1, 1);
+        let fee = fee::LinearFee::new(fee::Milli::integral(2), fee::Milli::new(0, 500));
+        p.block_version_mod.tx_fee_policy = Some(TxFeePolicy::from(fee));
+        let version = p.block_version;
+
+        state
+            .apply_payload(
+                10,
+                &stakes(),
+                &payload(Some(p.clone()), vec![vote(1, &p, true)]),
+            )
+            .unwrap();
+        state
+            .apply_payload(11, &stakes(), &payload(None, vec![vote(2, &p, true)]))
+            .unwrap();
+        assert_eq!(state.apply_epoch_boundary(1, &stakes(), &mut vec![]), None);
+        for n in 1..4 {
+            state.record_issuer(&version, stakeholder(n), &mut vec![]);
+        }
+        assert_eq!(
+            state.apply_epoch_boundary(2, &stakes(), &mut vec![]),
+            Some(version)
+        );
+        assert_eq!(state.parameters.tx_fee_policy, fee);
+
+        // a policy we do not know leaves the current one in place
+        let mut p = proposal(1, 2);
+        p.block_version_mod.tx_fee_policy = Some(TxFeePolicy::Unknown(1, vec![0x80]));
+        let version = p.block_version;
+        state
+            .apply_payload(
+                20,
+                &stakes(),
+                &payload(Some(p.clone()), vec![vote(1, &p, true)]),
+            )
+            .unwrap();
+        state
+            .apply_payload(21, &stakes(), &payload(None, vec![vote(2, &p, true)]))
+            .unwrap();
+        assert_eq!(state.apply_epoch_boundary(3, &stakes(), &mut vec![]), None);
+        for n in 1..4 {
+            state.record_issuer(&version, stakeholder(n), &mut vec![]);
+        }
+        assert_eq!(
+            state.apply_epoch_boundary(4, &stakes(), &mut vec![]),
+            Some(version)
+        );
+        assert_eq!(state.parameters.max_tx_size, 2000);
+        assert_eq!(state.parameters.tx_fee_policy, fee);
+    }
+
+    #[test]
+    fn proposal_is_rejected() {
+        let mut state = state();
+        let p = proposal(1, 1);
+        let votes = vec![vote(2, &p, false), vote(3, &p, false)];
+        state
+            .apply_payload(10, &stakes(), &payload(Some(p), votes))
+            .unwrap();
+        assert!(state.proposals.is_empty());
+        assert!(state.approved.is_empty());
+    }
+
+    #[test]
+    fn implicit_agreement() {
+        let mut state = state();
+        let p1 = proposal(1, 1);
+        let p2 = proposal(2, 2);
+        state
+            .apply_payload(
+                10,
+                &stakes(),
+                &payload(Some(p1.clone()), vec![vote(1, &p1, true)]),
+            )
+            .unwrap();
+        state
+            .apply_payload(
+                20,
+                &stakes(),
+                &payload(Some(p2.clone()), vec![vote(2, &p2, false)]),
+            )
+            .unwrap();
+
+        state
+            .apply_payload(109, &stakes(), &payload(None, vec![]))
+            .unwrap();
+        assert_eq!(state.proposals.len(), 2);
+        state
+            .apply_payload(110, &stakes(), &payload(None, vec![]))
+            .unwrap();
+        assert!(state.approved.contains_key(&p1.block_version));
+        state
+            .apply_payload(120, &stakes(), &payload(None, vec![]))
+            .unwrap();
+        assert!(state.proposals.is_empty());
+        assert!(!state.approved.contains_key(&p2.block_version));
+    }
+
+    #[test]
+    fn softfork_threshold() {
+        let rule = state().parameters.softfork_rule;
+        assert_eq!(rule.threshold(0), portion(75));
+        assert_eq!(rule.threshold(1), portion(50));
+        assert_eq!(rule.threshold(2), portion(50));
+        assert_eq!(rule.threshold(u64::MAX), portion(50));
+    }
+
+    #[test]
+    fn changes_are_reverted() {
+        let mut state = state();
+        let p1 = proposal(1, 1);
+        let p2 = proposal(2, 2);
+        let mut steps: Vec<(UpdateState, Vec<UpdateChange>)> = vec![];
+        let mut step =
+            |state: &mut UpdateState, apply: &dyn Fn(&mut UpdateState, &mut Vec<UpdateChange>)| {
+                let before = state.clone();
+                let mut changes = vec![];
+                apply(state, &mut changes);
+                assert_ne!(*state, before);
+                steps.push((before, changes));
+            };
+
+        step(&mut state, &|state, changes| {
+            let payload = payload(Some(p1.clone()), vec![vote(1, &p1, true)]);
+            state.apply_payload_all_errors(10, &stakes(), &payload, changes, &mut vec![]);
+        });
+        step(&mut state, &|state, changes| {
+            let payload = payload(Some(p2.clone()), vec![vote(2, &p1, true)]);
+            state.apply_payload_all_errors(11, &stakes(), &payload, changes, &mut vec![]);
+        });
+        step(&mut state, &|state, changes| {
+            state.apply_epoch_boundary(1, &stakes(), changes);
+        });
+        step(&mut state, &|state, changes| {
+            for n in 1..4 {
+                state.record_issuer(&p1.block_version, stakeholder(n), changes);
+            }
+        });
+        step(&mut state, &|state, changes| {
+            assert_eq!(
+                state.apply_epoch_boundary(2, &stakes(), changes),
+                Some(p1.block_version)
+            );
+        });
+
+        for (before, changes) in steps.into_iter().rev() {
+            state.revert_changes(changes);
+            assert_eq!(state, before);
+        }
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut state = state();
+        let p = proposal(1, 1);
+        state
+            .apply_payload(
+                10,
+                &stakes(),
+                &payload(Some(p.clone()), vec![vote(1, &p, true)]),
+            )
+            .unwrap();
+        state
+            .apply_payload(
+                11,
+                &stakes(),
+                &payload(Some(proposal(3, 2)), vec![vote(2, &p, true)]),
+            )
+            .unwrap();
+        state.apply_epoch_boundary(1, &stakes(), &mut vec![]);
+        state.record_issuer(&p.block_version, stakeholder(4), &mut vec![]);
+
+        let bytes = cbor!(&state).unwrap();
+        let decoded: UpdateState =
+            cbor_event::de::Deserializer::from(::std::io::Cursor::new(&bytes))
+                .deserialize_complete()
+                .unwrap();
+        assert_eq!(decoded, state);
+    }
+}
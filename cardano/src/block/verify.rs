@@ -1,4 +1,4 @@
-use self::normal::{BodyProof, VssCertificates};
+use self::normal::{BodyProof, DlgPayload, VssCertificates};
 use self::sign::{BlockSignature, MainToSign};
 use self::update;
 use address;
@@ -8,32 +8,38 @@ use coin;
 use config::ProtocolMagic;
 use fee;
 use hash;
-use hdwallet::Signature;
+use hdwallet::{self, XPub};
+use redeem;
 use std::{
     collections::{BTreeSet, HashSet},
     error, fmt,
 };
 use tags;
 use tx;
+use util::ed25519_batch;
 
 #[derive(Debug)]
 pub enum Error {
     BadBlockSig,
+    BadDlgCertSig,
     BadTxWitness,
     BadUpdateProposalSig,
     BadUpdateVoteSig,
     BadVssCertSig,
+    DuplicateDlgIssuers,
     DuplicateInputs,
     DuplicateSigningKeys,
     DuplicateVSSKeys,
     EncodingError(cbor_event::Error),
     UnexpectedWitnesses,
     MissingWitnesses,
+    UnsupportedScriptWitness,
     RedeemOutput,
     NoInputs,
     NoOutputs,
     SelfSignedPSK,
     WrongBlockHash,
+    WrongDelegationEpoch,
     WrongDelegationProof,
     WrongExtraDataProof,
     WrongBoundaryProof,
@@ -50,6 +56,7 @@ pub enum Error {
     BlockDateInPast,
     BlockDateInFuture,
     WrongSlotLeader,
+    WrongChainDifficulty,
     MissingUtxo,
     InputsTooBig,
     OutputsTooBig,
@@ -57,6 +64,19 @@ pub enum Error {
     FeeError(fee::Error),
     AddressMismatch,
     DuplicateTxo,
+    BlockTooBig,
+    HeaderTooBig,
+    UnknownAttributes,
+    TxTooBig,
+    WrongProposedBlockVersion,
+    DuplicateUpdateProposal,
+    ProposerStakeTooLow,
+    UnknownUpdateProposal,
+    DuplicateUpdateVote,
+    WrongSscPhase,
+    SscParticipantNotRichman,
+    WrongVssCertTTL,
+    WrongSlotLeaders,
 }
 
 impl fmt::Display for Error {
@@ -64,21 +84,27 @@ impl fmt::Display for Error {
         use self::Error::*;
         match self {
             BadBlockSig => write!(f, "invalid block signature"),
+            BadDlgCertSig => write!(f, "invalid delegation certificate signature"),
             BadTxWitness => write!(f, "invalid transaction witness"),
             BadUpdateProposalSig => write!(f, "invalid update proposal signature"),
             BadUpdateVoteSig => write!(f, "invalid update vote signature"),
             BadVssCertSig => write!(f, "invalid VSS certificate signature"),
+            DuplicateDlgIssuers => write!(f, "duplicated delegation certificate issuers"),
             DuplicateInputs => write!(f, "duplicated inputs"),
             DuplicateSigningKeys => write!(f, "duplicated signing keys"),
             DuplicateVSSKeys => write!(f, "duplicated VSS keys"),
             EncodingError(_error) => write!(f, "encoding error"),
             UnexpectedWitnesses => write!(f, "transaction has more witnesses than inputs"),
             MissingWitnesses => write!(f, "transaction has more inputs than witnesses"),
+            UnsupportedScriptWitness => write!(f, "script witnesses are not supported"),
             RedeemOutput => write!(f, "invalid redeem output"),
             NoInputs => write!(f, "transaction has no inputs"),
             NoOutputs => write!(f, "transaction has no outputs"),
             SelfSignedPSK => write!(f, "invalid self signing PSK"),
             WrongBlockHash => write!(f, "block hash is invalid"),
+            WrongDelegationEpoch => {
+                write!(f, "delegation certificate is not for the block's epoch")
+            }
             WrongDelegationProof => write!(f, "delegation proof is invalid"),
             WrongExtraDataProof => write!(f, "extra data proof is invalid"),
             WrongBoundaryProof => write!(f, "boundary proof is invalid"),
@@ -95,9 +121,12 @@ impl fmt::Display for Error {
             NonExistentSlot => write!(f, "slot does not have a leader"),
             BlockDateInPast => write!(f, "block's slot or epoch is earlier than its parent"),
             BlockDateInFuture => write!(f, "block is in a future epoch"),
+            WrongChainDifficulty => {
+                write!(f, "block's chain difficulty does not follow its parent's")
+            }
             WrongSlotLeader => write!(
                 f,
-                "block was not signed by the slot leader indicated in the genesis block"
+                "block was not signed by the slot leader or the key it delegated to"
             ),
             MissingUtxo => write!(
                 f,
@@ -110,6 +139,26 @@ impl fmt::Display for Error {
             WrongRedeemTxId => write!(f, "transaction input's ID does not match redeem public key"),
             AddressMismatch => write!(f, "transaction input witness does not match utxo address"),
             DuplicateTxo => write!(f, "transaction has an output that already exists"),
+            BlockTooBig => write!(f, "block is larger than the maximum block size"),
+            HeaderTooBig => write!(f, "header is larger than the maximum header size"),
+            UnknownAttributes => write!(f, "block of the adopted version has unknown attributes"),
+            TxTooBig => write!(f, "transaction is larger than the maximum transaction size"),
+            WrongProposedBlockVersion => {
+                write!(
+                    f,
+                    "update proposal's block version is not above the adopted one"
+                )
+            }
+            DuplicateUpdateProposal => write!(f, "block version was already proposed"),
+            ProposerStakeTooLow => write!(f, "update proposer does not have enough stake"),
+            UnknownUpdateProposal => write!(f, "vote for an unknown update proposal"),
+            DuplicateUpdateVote => write!(f, "stakeholder already cast this vote"),
+            WrongSscPhase => write!(f, "SSC payload is not allowed at this slot of the epoch"),
+            SscParticipantNotRichman => {
+                write!(f, "SSC payload from a stakeholder without enough stake")
+            }
+            WrongVssCertTTL => write!(f, "VSS certificate expiry epoch is out of bounds"),
+            WrongSlotLeaders => write!(f, "slot leaders do not match the elected ones"),
         }
     }
 }
@@ -130,43 +179,322 @@ impl error::Error for Error {
     }
 }
 
+/// The part of a block a validation error was found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorLocation {
+    /// the header of the block, or the block as a whole
+    Header,
+    /// the transaction at the given index
+    Tx(usize),
+    /// transaction index, input index
+    TxInput(usize, usize),
+    /// transaction index, output index
+    TxOutput(usize, usize),
+    /// transaction index, witness index
+    TxWitness(usize, usize),
+    SscPayload,
+    DelegationPayload,
+    UpdatePayload,
+}
+
+impl fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::ErrorLocation::*;
+        match self {
+            Header => write!(f, "block header"),
+            Tx(tx) => write!(f, "transaction {}", tx),
+            TxInput(tx, input) => write!(f, "input {} of transaction {}", input, tx),
+            TxOutput(tx, output) => write!(f, "output {} of transaction {}", output, tx),
+            TxWitness(tx, witness) => write!(f, "witness {} of transaction {}", witness, tx),
+            SscPayload => write!(f, "SSC payload"),
+            DelegationPayload => write!(f, "delegation payload"),
+            UpdatePayload => write!(f, "update payload"),
+        }
+    }
+}
+
+/// A validation error and where it was found.
+#[derive(Debug)]
+pub struct LocatedError {
+    pub location: ErrorLocation,
+    pub error: Error,
+}
+
+impl LocatedError {
+    pub fn new(location: ErrorLocation, error: Error) -> Self {
+        LocatedError { location, error }
+    }
+}
+
+impl fmt::Display for LocatedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.error)
+    }
+}
+
+/// The errors found while verifying an object, in the order they were
+/// found.
+pub type Errors = Vec<LocatedError>;
+
+/// the first of the given errors, if any
+pub fn first_error(errors: Errors) -> Result<(), Error> {
+    match errors.into_iter().next() {
+        None => Ok(()),
+        Some(err) => Err(err.error),
+    }
+}
+
 pub trait Verify {
     fn verify(&self, protocol_magic: ProtocolMagic) -> Result<(), Error>;
 }
 
+/// signatures collected while verifying an object, checked all at once
+/// with `ed25519_batch` once everything else has been checked.
+#[derive(Default)]
+struct SignatureBatch {
+    entries: Vec<BatchEntry>,
+}
+
+/// public key, message, signature and the error to report if invalid
+type BatchEntry = (BatchKey, Vec<u8>, Vec<u8>, LocatedError);
+
+/// the kind of public key a signature of the batch is checked against
+enum BatchKey {
+    XPub(XPub),
+    Redeem(redeem::PublicKey),
+}
+
+impl BatchKey {
+    fn public_key(&self) -> &[u8] {
+        match self {
+            BatchKey::XPub(xpub) => &xpub.as_ref()[0..32],
+            BatchKey::Redeem(public_key) => public_key.as_ref(),
+        }
+    }
+
+    /// the single signature verification of this kind of key
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match self {
+            BatchKey::XPub(xpub) => match hdwallet::Signature::<()>::from_slice(signature) {
+                Ok(signature) => xpub.verify(message, &signature),
+                Err(_) => false,
+            },
+            BatchKey::Redeem(public_key) => match redeem::Signature::from_slice(signature) {
+                Ok(signature) => public_key.verify(&signature, message),
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+impl SignatureBatch {
+    /// add a signature to the batch, `error` being the error to report
+    /// at `location` if this signature is invalid.
+    fn push(
+        &mut self,
+        key: BatchKey,
+        message: Vec<u8>,
+        signature: &[u8],
+        location: ErrorLocation,
+        error: Error,
+    ) {
+        self.entries.push((
+            key,
+            message,
+            signature.to_vec(),
+            LocatedError::new(location, error),
+        ));
+    }
+
+    fn push_xpub(
+        &mut self,
+        xpub: &XPub,
+        message: Vec<u8>,
+        signature: &[u8],
+        location: ErrorLocation,
+        error: Error,
+    ) {
+        self.push(BatchKey::XPub(*xpub), message, signature, location, error)
+    }
+
+    fn push_redeem(
+        &mut self,
+        public_key: &redeem::PublicKey,
+        message: Vec<u8>,
+        signature: &[u8],
+        location: ErrorLocation,
+        error: Error,
+    ) {
+        self.push(
+            BatchKey::Redeem(*public_key),
+            message,
+            signature,
+            location,
+            error,
+        )
+    }
+
+    /// verify all the signatures of the batch.
+    ///
+    /// If the batch is invalid, the signatures are checked one by one (in
+    /// the order they were added) with `XPub::verify` or
+    /// `redeem::PublicKey::verify`, to report the error of every invalid
+    /// one.
+    fn check(self, errors: &mut Errors) {
+        let valid = {
+            let entries: Vec<_> = self
+                .entries
+                .iter()
+                .map(|(key, message, signature, _)| {
+                    (key.public_key(), &message[..], &signature[..])
+                })
+                .collect();
+            ed25519_batch::verify_batch(&entries)
+        };
+        if valid {
+            return;
+        }
+
+        for (key, message, signature, error) in self.entries {
+            if !key.verify(&message, &signature) {
+                errors.push(error);
+            }
+        }
+    }
+}
+
+/// like `Verify`, but deferring the signature checks to the given batch
+/// and recording all the errors found.
+trait VerifyBatched {
+    fn verify_batched(
+        &self,
+        protocol_magic: ProtocolMagic,
+        batch: &mut SignatureBatch,
+        errors: &mut Errors,
+    );
+}
+
+fn verify_with_batch<T: VerifyBatched>(x: &T, protocol_magic: ProtocolMagic) -> Result<(), Error> {
+    let mut batch = SignatureBatch::default();
+    let mut errors = vec![];
+    x.verify_batched(protocol_magic, &mut batch, &mut errors);
+    batch.check(&mut errors);
+    first_error(errors)
+}
+
 pub fn verify_block(block_hash: &HeaderHash, blk: &Block) -> Result<(), Error> {
+    first_error(verify_block_all_errors(block_hash, blk))
+}
+
+/// Like `verify_block`, but returning every error found in the block
+/// rather than the first one.
+pub fn verify_block_all_errors(block_hash: &HeaderHash, blk: &Block) -> Errors {
+    let mut errors = vec![];
+
     match blk {
         Block::BoundaryBlock(blk) => {
-            blk.verify()?;
+            blk.verify(&mut errors);
         }
 
         Block::MainBlock(blk) => {
-            blk.verify()?;
+            blk.verify(&mut errors);
         }
     };
 
     if block_hash != &blk.header().compute_hash() {
-        return Err(Error::WrongBlockHash);
+        errors.push(LocatedError::new(
+            ErrorLocation::Header,
+            Error::WrongBlockHash,
+        ));
     }
 
+    errors
+}
+
+/// Check the date of a block against the date of the previous one, if
+/// any: dates must increase, and each epoch must start with its
+/// boundary block.
+pub fn verify_block_date(last_date: Option<BlockDate>, date: BlockDate) -> Result<(), Error> {
+    match last_date {
+        Some(last_date) => {
+            if date <= last_date {
+                return Err(Error::BlockDateInPast);
+            } else if date.is_boundary() {
+                if date.get_epochid() == last_date.get_epochid() {
+                    return Err(Error::BlockDateInPast);
+                } else if date.get_epochid() > last_date.get_epochid() + 1 {
+                    return Err(Error::BlockDateInFuture);
+                }
+            }
+        }
+
+        None => {
+            if date != BlockDate::Boundary(0) {
+                // FIXME: use epoch_start
+                return Err(Error::BlockDateInFuture);
+            }
+        }
+    }
     Ok(())
 }
 
+/// Verify a block header on its own, without its body: its hash and
+/// the signature of its issuer, including the delegation certificate
+/// it was signed with.
+pub fn verify_header(header_hash: &HeaderHash, hdr: &BlockHeader) -> Result<(), Error> {
+    first_error(verify_header_all_errors(header_hash, hdr))
+}
+
+/// Like `verify_header`, but returning every error found in the
+/// header rather than the first one.
+pub fn verify_header_all_errors(header_hash: &HeaderHash, hdr: &BlockHeader) -> Errors {
+    let mut errors = vec![];
+
+    if let BlockHeader::MainBlockHeader(hdr) = hdr {
+        let mut batch = SignatureBatch::default();
+        hdr.push_signatures(&mut batch);
+        // the heavyweight delegation certificates are published in the
+        // block bodies, so without them the certificate is checked
+        // along with the signature
+        if let BlockSignature::ProxyHeavy(proxy_sig) = &hdr.consensus.block_signature {
+            batch.push_xpub(
+                &proxy_sig.psk.issuer_pk,
+                proxy_sig.psk.signed_data(hdr.protocol_magic),
+                proxy_sig.psk.cert.as_ref(),
+                ErrorLocation::Header,
+                Error::BadBlockSig,
+            );
+        }
+        batch.check(&mut errors);
+        hdr.verify_consensus(&mut errors);
+    }
+
+    if header_hash != &hdr.compute_hash() {
+        errors.push(LocatedError::new(
+            ErrorLocation::Header,
+            Error::WrongBlockHash,
+        ));
+    }
+
+    errors
+}
+
 impl boundary::Block {
-    fn verify(&self) -> Result<(), Error> {
+    fn verify(&self, errors: &mut Errors) {
         let hdr = &self.header;
 
         // check body proof
         if hash::Blake2b256::new(&cbor!(&self.body).unwrap()) != hdr.body_proof.0 {
-            return Err(Error::WrongBoundaryProof);
+            errors.push(LocatedError::new(
+                ErrorLocation::Header,
+                Error::WrongBoundaryProof,
+            ));
         }
-
-        Ok(())
     }
 }
 
 impl normal::Block {
-    fn verify(&self) -> Result<(), Error> {
+    fn verify(&self, errors: &mut Errors) {
         let hdr = &self.header;
         let body = &self.body;
 
@@ -175,188 +503,413 @@ impl normal::Block {
         // Note: the application name length restriction is
         // enforced by the SoftwareVersion constructor.
 
+        // all the signatures are checked at once, after the structural
+        // checks of the body.
+        let mut batch = SignatureBatch::default();
+        let mut body_errors = vec![];
+
         // check tx
-        body.tx
-            .iter()
-            .try_for_each(|txaux| txaux.verify(hdr.protocol_magic))?;
+        for (index, txaux) in body.tx.iter().enumerate() {
+            verify_tx_batched(
+                txaux,
+                index,
+                hdr.protocol_magic,
+                &mut batch,
+                &mut body_errors,
+            );
+        }
 
         // check ssc
-        body.ssc.get_vss_certificates().verify(hdr.protocol_magic)?;
+        body.ssc.get_vss_certificates().verify_batched(
+            hdr.protocol_magic,
+            &mut batch,
+            &mut body_errors,
+        );
 
         // check delegation
-        // TODO
+        body.delegation
+            .verify_batched(hdr.protocol_magic, &mut batch, &mut body_errors);
+        if body
+            .delegation
+            .iter()
+            .any(|psk| psk.omega != hdr.consensus.slot_id.epoch)
+        {
+            body_errors.push(LocatedError::new(
+                ErrorLocation::DelegationPayload,
+                Error::WrongDelegationEpoch,
+            ));
+        }
 
         // check update
-        body.update.verify(hdr.protocol_magic)?;
+        body.update
+            .verify_batched(hdr.protocol_magic, &mut batch, &mut body_errors);
+
+        // the block signature goes last: an invalid block signature is
+        // only reported after the proofs below.
+        hdr.push_signatures(&mut batch);
+
+        let mut block_sig_errors = vec![];
+        let mut sig_errors = vec![];
+        batch.check(&mut sig_errors);
+        for err in sig_errors {
+            match err.error {
+                Error::BadBlockSig => block_sig_errors.push(err),
+                _ => body_errors.push(err),
+            }
+        }
+        errors.append(&mut body_errors);
+        let mut header_error = |error| errors.push(LocatedError::new(ErrorLocation::Header, error));
 
         // compare the proofs generated from the body directly
         let proof = BodyProof::generate_from_body(&body);
 
         if proof.tx != hdr.body_proof.tx {
-            return Err(Error::WrongTxProof);
+            header_error(Error::WrongTxProof);
         }
         if proof.mpc != hdr.body_proof.mpc {
-            return Err(Error::WrongMpcProof);
+            header_error(Error::WrongMpcProof);
         }
         if proof.delegation != hdr.body_proof.delegation {
-            return Err(Error::WrongDelegationProof);
+            header_error(Error::WrongDelegationProof);
         }
         if proof.update != hdr.body_proof.update {
-            return Err(Error::WrongUpdateProof);
+            header_error(Error::WrongUpdateProof);
         }
 
         // check extra data proof
         if hash::Blake2b256::new(&cbor!(&self.extra).unwrap()) != hdr.extra_data.extra_data_proof {
-            return Err(Error::WrongExtraDataProof);
+            header_error(Error::WrongExtraDataProof);
         }
 
         // check consensus
-        // FIXME: check slotid?
-        match &hdr.consensus.block_signature {
-            BlockSignature::Signature(_) => panic!("not implemented"),
-            BlockSignature::ProxyLight(_) => panic!("not implemented"),
+        hdr.verify_consensus(errors);
+
+        // the signature itself has been checked with the batch
+        errors.append(&mut block_sig_errors);
+    }
+}
+
+impl normal::BlockHeader {
+    /// Queue the checks of the block signature.
+    fn push_signatures(&self, batch: &mut SignatureBatch) {
+        let to_sign = MainToSign::from_header(self);
+        match &self.consensus.block_signature {
+            BlockSignature::Signature(sig) => {
+                batch.push_xpub(
+                    &self.consensus.leader_key,
+                    to_sign.signature_data(self.protocol_magic),
+                    sig.as_ref(),
+                    ErrorLocation::Header,
+                    Error::BadBlockSig,
+                );
+            }
+            BlockSignature::ProxyLight(proxy_sig) => {
+                // the certificate is not published on the chain, so it
+                // is checked along with the signature
+                batch.push_xpub(
+                    &proxy_sig.psk.issuer_pk,
+                    proxy_sig.psk.signed_data(self.protocol_magic),
+                    proxy_sig.psk.cert.as_ref(),
+                    ErrorLocation::Header,
+                    Error::BadBlockSig,
+                );
+                batch.push_xpub(
+                    &proxy_sig.psk.delegate_pk,
+                    to_sign.light_proxy_sig_data(self.protocol_magic, proxy_sig),
+                    proxy_sig.sig.as_ref(),
+                    ErrorLocation::Header,
+                    Error::BadBlockSig,
+                );
+            }
             BlockSignature::ProxyHeavy(proxy_sig) => {
+                batch.push_xpub(
+                    &proxy_sig.psk.delegate_pk,
+                    to_sign.proxy_sig_data(
+                        self.protocol_magic,
+                        tags::SigningTag::MainBlockHeavy,
+                        proxy_sig,
+                    ),
+                    proxy_sig.sig.as_ref(),
+                    ErrorLocation::Header,
+                    Error::BadBlockSig,
+                );
+            }
+        }
+    }
+
+    /// Check the delegation the block was signed with.
+    // FIXME: check slotid?
+    fn verify_consensus(&self, errors: &mut Errors) {
+        let mut error = |error| errors.push(LocatedError::new(ErrorLocation::Header, error));
+        match &self.consensus.block_signature {
+            BlockSignature::Signature(_) => {}
+            BlockSignature::ProxyLight(proxy_sig) => {
                 // check against self-signed PSKs
                 if proxy_sig.psk.issuer_pk == proxy_sig.psk.delegate_pk {
-                    return Err(Error::SelfSignedPSK);
+                    error(Error::SelfSignedPSK);
                 }
-
-                // verify the signature
-                let to_sign = MainToSign::from_header(&hdr);
-
-                if !to_sign.verify_proxy_sig(
-                    hdr.protocol_magic,
-                    tags::SigningTag::MainBlockHeavy,
-                    proxy_sig,
-                ) {
-                    return Err(Error::BadBlockSig);
+                // the slot leader delegates for the epochs of the
+                // certificate only
+                if proxy_sig.psk.issuer_pk != self.consensus.leader_key
+                    || !proxy_sig.psk.omega.contains(self.consensus.slot_id.epoch)
+                {
+                    error(Error::BadBlockSig);
+                }
+            }
+            BlockSignature::ProxyHeavy(proxy_sig) => {
+                // check against self-signed PSKs
+                if proxy_sig.psk.issuer_pk == proxy_sig.psk.delegate_pk {
+                    error(Error::SelfSignedPSK);
                 }
             }
         }
-
-        Ok(())
     }
 }
 
 impl Verify for update::UpdatePayload {
     fn verify(&self, protocol_magic: ProtocolMagic) -> Result<(), Error> {
+        verify_with_batch(self, protocol_magic)
+    }
+}
+
+impl VerifyBatched for update::UpdatePayload {
+    fn verify_batched(
+        &self,
+        protocol_magic: ProtocolMagic,
+        batch: &mut SignatureBatch,
+        errors: &mut Errors,
+    ) {
         if let Some(proposal) = &self.proposal {
-            proposal.verify(protocol_magic)?;
+            proposal.verify_batched(protocol_magic, batch, errors);
         }
 
-        self.votes
-            .iter()
-            .try_for_each(|vote| vote.verify(protocol_magic))?;
-
-        Ok(())
+        for vote in self.votes.iter() {
+            vote.verify_batched(protocol_magic, batch, errors);
+        }
     }
 }
 
 impl Verify for tx::TxAux {
     fn verify(&self, protocol_magic: ProtocolMagic) -> Result<(), Error> {
-        // check that there are inputs
-        if self.tx.inputs.is_empty() {
-            return Err(Error::NoInputs);
-        }
+        verify_with_batch(self, protocol_magic)
+    }
+}
 
-        // check that there are outputs
-        if self.tx.outputs.is_empty() {
-            return Err(Error::NoOutputs);
-        }
+impl VerifyBatched for tx::TxAux {
+    fn verify_batched(
+        &self,
+        protocol_magic: ProtocolMagic,
+        batch: &mut SignatureBatch,
+        errors: &mut Errors,
+    ) {
+        verify_tx_batched(self, 0, protocol_magic, batch, errors)
+    }
+}
+
+/// verify the transaction at `index` in its block
+fn verify_tx_batched(
+    txaux: &tx::TxAux,
+    index: usize,
+    protocol_magic: ProtocolMagic,
+    batch: &mut SignatureBatch,
+    errors: &mut Errors,
+) {
+    let mut error = |location, error| errors.push(LocatedError::new(location, error));
+
+    // check that there are inputs
+    if txaux.tx.inputs.is_empty() {
+        error(ErrorLocation::Tx(index), Error::NoInputs);
+    }
 
-        // check that there are no duplicate inputs
-        let mut inputs = BTreeSet::new();
-        if !self.tx.inputs.iter().all(|x| inputs.insert(x)) {
-            return Err(Error::DuplicateInputs);
+    // check that there are outputs
+    if txaux.tx.outputs.is_empty() {
+        error(ErrorLocation::Tx(index), Error::NoOutputs);
+    }
+
+    // check that there are no duplicate inputs
+    let mut inputs = BTreeSet::new();
+    for (input_index, input) in txaux.tx.inputs.iter().enumerate() {
+        if !inputs.insert(input) {
+            error(
+                ErrorLocation::TxInput(index, input_index),
+                Error::DuplicateInputs,
+            );
         }
+    }
 
-        // check that all outputs have a non-zero amount
-        if !self.tx.outputs.iter().all(|x| x.value > coin::Coin::zero()) {
-            return Err(Error::ZeroCoin);
+    // check that all outputs have a non-zero amount
+    for (output_index, output) in txaux.tx.outputs.iter().enumerate() {
+        if output.value == coin::Coin::zero() {
+            error(
+                ErrorLocation::TxOutput(index, output_index),
+                Error::ZeroCoin,
+            );
         }
+    }
 
-        // Note: we don't need to check against MAX_COIN because Coin's
-        // constructor already has.
+    // Note: we don't need to check against MAX_COIN because Coin's
+    // constructor already has.
 
-        // check that none of the outputs are redeem addresses
-        if self
-            .tx
-            .outputs
-            .iter()
-            .any(|x| x.address.addr_type == address::AddrType::ATRedeem)
-        {
-            return Err(Error::RedeemOutput);
+    // check that none of the outputs are redeem addresses
+    for (output_index, output) in txaux.tx.outputs.iter().enumerate() {
+        if output.address.addr_type == address::AddrType::ATRedeem {
+            error(
+                ErrorLocation::TxOutput(index, output_index),
+                Error::RedeemOutput,
+            );
         }
+    }
 
-        // TODO: check address attributes?
+    // TODO: check address attributes?
 
-        // verify transaction witnesses
-        if self.tx.inputs.len() < self.witness.len() {
-            return Err(Error::UnexpectedWitnesses);
-        }
+    // verify transaction witnesses
+    if txaux.tx.inputs.len() < txaux.witness.len() {
+        error(ErrorLocation::Tx(index), Error::UnexpectedWitnesses);
+    }
 
-        if self.tx.inputs.len() > self.witness.len() {
-            return Err(Error::MissingWitnesses);
-        }
+    if txaux.tx.inputs.len() > txaux.witness.len() {
+        error(ErrorLocation::Tx(index), Error::MissingWitnesses);
+    }
 
-        self.witness.iter().try_for_each(|in_witness| {
-            if !in_witness.verify_tx(protocol_magic, &self.tx) {
-                return Err(Error::BadTxWitness);
+    for (witness_index, in_witness) in txaux.witness.iter().enumerate() {
+        let location = ErrorLocation::TxWitness(index, witness_index);
+        match in_witness {
+            tx::TxInWitness::PkWitness(xpub, signature) => batch.push_xpub(
+                xpub,
+                in_witness.signed_data(protocol_magic, &txaux.tx),
+                signature.as_ref(),
+                location,
+                Error::BadTxWitness,
+            ),
+            tx::TxInWitness::ScriptWitness(_, _) => {
+                error(location, Error::UnsupportedScriptWitness)
             }
-            Ok(())
-        })?;
+            tx::TxInWitness::RedeemWitness(pubkey, signature) => batch.push_redeem(
+                pubkey,
+                in_witness.signed_data(protocol_magic, &txaux.tx),
+                signature.as_ref(),
+                location,
+                Error::BadTxWitness,
+            ),
+        }
+    }
 
-        // verify that txids of redeem inputs correspond to the redeem pubkey
-        for (txin, in_witness) in self.tx.inputs.iter().zip(self.witness.iter()) {
-            if let tx::TxInWitness::RedeemWitness(pubkey, _) = in_witness {
-                if tx::redeem_pubkey_to_txid(&pubkey, protocol_magic).0 != txin.id {
-                    return Err(Error::WrongRedeemTxId);
-                }
+    // verify that txids of redeem inputs correspond to the redeem pubkey
+    for (input_index, (txin, in_witness)) in
+        txaux.tx.inputs.iter().zip(txaux.witness.iter()).enumerate()
+    {
+        if let tx::TxInWitness::RedeemWitness(pubkey, _) = in_witness {
+            if tx::redeem_pubkey_to_txid(&pubkey, protocol_magic).0 != txin.id {
+                error(
+                    ErrorLocation::TxInput(index, input_index),
+                    Error::WrongRedeemTxId,
+                );
             }
         }
+    }
+}
+
+impl Verify for DlgPayload {
+    fn verify(&self, protocol_magic: ProtocolMagic) -> Result<(), Error> {
+        verify_with_batch(self, protocol_magic)
+    }
+}
+
+impl VerifyBatched for DlgPayload {
+    fn verify_batched(
+        &self,
+        protocol_magic: ProtocolMagic,
+        batch: &mut SignatureBatch,
+        errors: &mut Errors,
+    ) {
+        // check that a stakeholder issues at most one certificate
+        let mut issuers = HashSet::new();
+        if !self.iter().all(|psk| issuers.insert(psk.issuer_pk)) {
+            errors.push(LocatedError::new(
+                ErrorLocation::DelegationPayload,
+                Error::DuplicateDlgIssuers,
+            ));
+        }
 
-        Ok(())
+        // verify every certificate's signature
+        for psk in self.iter() {
+            batch.push_xpub(
+                &psk.issuer_pk,
+                psk.signed_data(protocol_magic),
+                psk.cert.as_ref(),
+                ErrorLocation::DelegationPayload,
+                Error::BadDlgCertSig,
+            );
+        }
     }
 }
 
 impl Verify for VssCertificates {
     fn verify(&self, protocol_magic: ProtocolMagic) -> Result<(), Error> {
+        verify_with_batch(self, protocol_magic)
+    }
+}
+
+impl VerifyBatched for VssCertificates {
+    fn verify_batched(
+        &self,
+        protocol_magic: ProtocolMagic,
+        batch: &mut SignatureBatch,
+        errors: &mut Errors,
+    ) {
+        let mut error = |error| errors.push(LocatedError::new(ErrorLocation::SscPayload, error));
+
         // check that there are no duplicate VSS keys
         let mut vss_keys = BTreeSet::new();
         if !self.iter().all(|x| vss_keys.insert(x.vss_key.clone())) {
-            return Err(Error::DuplicateVSSKeys);
+            error(Error::DuplicateVSSKeys);
         }
 
         // check that there are no duplicate signing keys
         let mut signing_keys = HashSet::new();
         if !self.iter().all(|x| signing_keys.insert(x.signing_key)) {
-            return Err(Error::DuplicateSigningKeys);
+            error(Error::DuplicateSigningKeys);
         }
 
         // verify every certificate's signature
         for vss_cert in self.iter() {
             let mut buf = vec![];
             buf.push(tags::SigningTag::VssCert as u8);
-            se::Serializer::new(&mut buf)
-                .serialize(&protocol_magic)?
-                .write_array(cbor_event::Len::Len(2))?
-                .serialize(&vss_cert.vss_key)?
-                .serialize(&vss_cert.expiry_epoch)?;
-
-            if !vss_cert.signing_key.verify(
-                &buf,
-                &Signature::<()>::from_bytes(*vss_cert.signature.to_bytes()),
-            ) {
-                return Err(Error::BadVssCertSig);
+            let res = se::Serializer::new(&mut buf)
+                .serialize(&protocol_magic)
+                .and_then(|s| s.write_array(cbor_event::Len::Len(2)))
+                .and_then(|s| s.serialize(&vss_cert.vss_key))
+                .and_then(|s| s.serialize(&vss_cert.expiry_epoch))
+                .map(|_| ());
+            if let Err(err) = res {
+                error(Error::EncodingError(err));
+                continue;
             }
-        }
 
-        Ok(())
+            batch.push_xpub(
+                &vss_cert.signing_key,
+                buf,
+                vss_cert.signature.to_bytes(),
+                ErrorLocation::SscPayload,
+                Error::BadVssCertSig,
+            );
+        }
     }
 }
 
 impl Verify for update::UpdateProposal {
     fn verify(&self, protocol_magic: ProtocolMagic) -> Result<(), Error> {
+        verify_with_batch(self, protocol_magic)
+    }
+}
+
+impl VerifyBatched for update::UpdateProposal {
+    fn verify_batched(
+        &self,
+        protocol_magic: ProtocolMagic,
+        batch: &mut SignatureBatch,
+        errors: &mut Errors,
+    ) {
         // CoinPortion fields in block_version_mod and
         // block_version_mod.softfork_rule are checked by
         // CoinPortion::new().
@@ -378,23 +931,41 @@ impl Verify for update::UpdateProposal {
 
         buf.push(tags::SigningTag::USProposal as u8);
 
-        se::Serializer::new(&mut buf)
-            .serialize(&protocol_magic)?
-            .serialize(&to_sign)?;
-
-        if !self.from.verify(
-            &buf,
-            &Signature::<()>::from_bytes(*self.signature.to_bytes()),
-        ) {
-            return Err(Error::BadUpdateProposalSig);
+        let res = se::Serializer::new(&mut buf)
+            .serialize(&protocol_magic)
+            .and_then(|s| s.serialize(&to_sign))
+            .map(|_| ());
+        if let Err(err) = res {
+            errors.push(LocatedError::new(
+                ErrorLocation::UpdatePayload,
+                Error::EncodingError(err),
+            ));
+            return;
         }
 
-        Ok(())
+        batch.push_xpub(
+            &self.from,
+            buf,
+            self.signature.as_ref(),
+            ErrorLocation::UpdatePayload,
+            Error::BadUpdateProposalSig,
+        );
     }
 }
 
 impl Verify for update::UpdateVote {
     fn verify(&self, protocol_magic: ProtocolMagic) -> Result<(), Error> {
+        verify_with_batch(self, protocol_magic)
+    }
+}
+
+impl VerifyBatched for update::UpdateVote {
+    fn verify_batched(
+        &self,
+        protocol_magic: ProtocolMagic,
+        batch: &mut SignatureBatch,
+        _errors: &mut Errors,
+    ) {
         let mut buf = vec![];
         se::Serializer::new(&mut buf)
             .serialize(&(tags::SigningTag::USVote as u8))
@@ -404,14 +975,13 @@ impl Verify for update::UpdateVote {
             .serialize(&(&self.proposal_id, &self.decision))
             .unwrap();
 
-        if !self.key.verify(
-            &buf,
-            &Signature::<()>::from_bytes(*self.signature.to_bytes()),
-        ) {
-            return Err(Error::BadUpdateVoteSig);
-        }
-
-        Ok(())
+        batch.push_xpub(
+            &self.key,
+            buf,
+            self.signature.as_ref(),
+            ErrorLocation::UpdatePayload,
+            Error::BadUpdateVoteSig,
+        );
     }
 }
 
@@ -422,6 +992,9 @@ mod tests {
     use block::*;
     use cbor_event;
     use coin;
+    use config::ProtocolMagic;
+    use hash;
+    use hdwallet;
     use merkle;
     use std::fmt::Debug;
     use std::mem;
@@ -457,6 +1030,83 @@ mod tests {
         coin::Coin::new(45_000_000_000_000_001).unwrap();
     }
 
+    #[test]
+    fn test_signature_batch_reports_invalid() {
+        use super::{ErrorLocation, SignatureBatch};
+        use hdwallet::{Seed, Signature, XPrv};
+
+        let xprv = XPrv::generate_from_seed(&Seed::from_bytes([0; 32]));
+        let xpub = xprv.public();
+        let signature: Signature<()> = xprv.sign(b"valid");
+
+        let mut batch = SignatureBatch::default();
+        batch.push_xpub(
+            &xpub,
+            b"valid".to_vec(),
+            signature.as_ref(),
+            ErrorLocation::Tx(0),
+            Error::BadTxWitness,
+        );
+        let mut errors = vec![];
+        batch.check(&mut errors);
+        assert!(errors.is_empty());
+
+        let mut batch = SignatureBatch::default();
+        batch.push_xpub(
+            &xpub,
+            b"valid".to_vec(),
+            signature.as_ref(),
+            ErrorLocation::Tx(0),
+            Error::BadTxWitness,
+        );
+        batch.push_xpub(
+            &xpub,
+            b"invalid".to_vec(),
+            signature.as_ref(),
+            ErrorLocation::SscPayload,
+            Error::BadVssCertSig,
+        );
+        batch.push_xpub(
+            &xpub,
+            b"other".to_vec(),
+            signature.as_ref(),
+            ErrorLocation::Header,
+            Error::BadBlockSig,
+        );
+        let mut errors = vec![];
+        batch.check(&mut errors);
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|err| (err.location, format!("{:?}", err.error)))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (ErrorLocation::SscPayload, "BadVssCertSig".to_owned()),
+                (ErrorLocation::Header, "BadBlockSig".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_script_witness_is_rejected() {
+        use config::NetworkMagic;
+        use hdwallet::{Seed, XPrv};
+        use tx::{Tx, TxAux, TxInWitness, TxOut, TxWitness, TxoPointer};
+
+        let xprv = XPrv::generate_from_seed(&Seed::from_bytes([0; 32]));
+        let address = address::ExtendedAddr::new_simple(xprv.public(), NetworkMagic::NoMagic);
+        let tx = Tx::new_with(
+            vec![TxoPointer::new(hash::Blake2b256::new(b"input"), 0)],
+            vec![TxOut::new(address, coin::Coin::new(1).unwrap())],
+        );
+        let txaux = TxAux::new(tx, TxWitness::from(vec![TxInWitness::ScriptWitness(0, 0)]));
+        expect_error(
+            &txaux.verify(ProtocolMagic::from(633343913)),
+            Error::UnsupportedScriptWitness,
+        );
+    }
+
     fn expect_error<T, Error>(res: &Result<T, Error>, expected: Error)
     where
         Error: Debug,
@@ -468,6 +1118,197 @@ mod tests {
         }
     }
 
+    fn test_psk(pm: ProtocolMagic, issuer: u8, delegate: u8, omega: u64) -> sign::ProxySecretKey {
+        use hdwallet::{Seed, XPrv};
+        let issuer_prv = XPrv::generate_from_seed(&Seed::from_bytes([issuer; 32]));
+        let delegate_pk = XPrv::generate_from_seed(&Seed::from_bytes([delegate; 32])).public();
+        sign::ProxySecretKey::sign(&issuer_prv, delegate_pk, omega, pm)
+    }
+
+    #[test]
+    fn test_verify_dlg_payload() {
+        use super::Verify;
+        let pm = ProtocolMagic::from(633343913);
+
+        let payload = DlgPayload(vec![test_psk(pm, 1, 2, 3), test_psk(pm, 2, 2, 3)]);
+        assert!(payload.verify(pm).is_ok());
+        assert!(DlgPayload(vec![]).verify(pm).is_ok());
+
+        // a certificate signed for another protocol magic
+        expect_error(&payload.verify(1.into()), Error::BadDlgCertSig);
+
+        // a certificate whose omega was modified
+        let mut payload = payload.clone();
+        payload.0[1].omega = 4;
+        expect_error(&payload.verify(pm), Error::BadDlgCertSig);
+
+        // two certificates from the same issuer
+        let payload = DlgPayload(vec![test_psk(pm, 1, 2, 3), test_psk(pm, 1, 3, 3)]);
+        expect_error(&payload.verify(pm), Error::DuplicateDlgIssuers);
+    }
+
+    fn xprv(seed: u8) -> hdwallet::XPrv {
+        hdwallet::XPrv::generate_from_seed(&hdwallet::Seed::from_bytes([seed; 32]))
+    }
+
+    /// an empty main block of epoch 3, signed with the signature
+    /// returned by `sign`
+    fn main_block<F>(pm: ProtocolMagic, leader_key: hdwallet::XPub, sign: F) -> Block
+    where
+        F: FnOnce(&sign::MainToSign) -> sign::BlockSignature,
+    {
+        let body = normal::Body::new(
+            normal::TxPayload::new(vec![]),
+            normal::SscPayload::CertificatesPayload(normal::VssCertificates::new(vec![])),
+            DlgPayload(vec![]),
+            update::UpdatePayload {
+                proposal: None,
+                votes: vec![],
+            },
+        );
+        let extra = ExtraData::default();
+        let mut header = normal::BlockHeader::new(
+            pm,
+            HeaderHash::new(b"previous"),
+            normal::BodyProof::generate_from_body(&body),
+            normal::Consensus {
+                slot_id: EpochSlotId {
+                    epoch: 3,
+                    slotid: 12,
+                },
+                leader_key,
+                chain_difficulty: ChainDifficulty::from(100),
+                block_signature: sign::BlockSignature::Signature(xprv(0).sign(b"")),
+            },
+            HeaderExtraData::new(
+                BlockVersion::new(0, 1, 0),
+                SoftwareVersion::new("cardano-sl", 1).unwrap(),
+                BlockHeaderAttributes::new(),
+                hash::Blake2b256::new(&cbor!(&extra).unwrap()),
+            ),
+        );
+        header.consensus.block_signature = sign(&sign::MainToSign::from_header(&header));
+        Block::MainBlock(normal::Block::new(header, body, extra))
+    }
+
+    fn light_psk(
+        pm: ProtocolMagic,
+        issuer: &hdwallet::XPrv,
+        delegate: &hdwallet::XPrv,
+        start: EpochId,
+        end: EpochId,
+    ) -> sign::LightProxySecretKey {
+        sign::LightProxySecretKey::sign(
+            issuer,
+            delegate.public(),
+            sign::LightDlgIndices::new(start, end),
+            pm,
+        )
+    }
+
+    fn light_signed_block(pm: ProtocolMagic, psk: sign::LightProxySecretKey) -> Block {
+        let delegate = xprv(2);
+        main_block(pm, psk.issuer_pk, |to_sign| {
+            let mut proxy_sig = sign::LightProxySignature {
+                psk,
+                sig: delegate.sign(b""),
+            };
+            proxy_sig.sig = delegate.sign(&to_sign.light_proxy_sig_data(pm, &proxy_sig));
+            sign::BlockSignature::ProxyLight(proxy_sig)
+        })
+    }
+
+    fn verify(blk: &Block) -> Result<(), Error> {
+        verify_block(&blk.header().compute_hash(), blk)
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let pm = ProtocolMagic::from(633343913);
+        let leader = xprv(1);
+
+        let blk = main_block(pm, leader.public(), |to_sign| to_sign.sign(pm, &leader));
+        assert!(verify(&blk).is_ok());
+
+        // the block is encoded and decoded with its signature
+        let rblk = RawBlock::from_dat(cbor!(&blk).unwrap());
+        match rblk.to_header().unwrap().decode().unwrap() {
+            BlockHeader::MainBlockHeader(hdr) => match hdr.consensus.block_signature {
+                sign::BlockSignature::Signature(_) => {}
+                sig => panic!("unexpected signature {:?}", sig),
+            },
+            _ => panic!("expected a main block header"),
+        }
+
+        // signed for another protocol magic
+        let blk = main_block(pm, leader.public(), |to_sign| {
+            to_sign.sign(1.into(), &leader)
+        });
+        expect_error(&verify(&blk), Error::BadBlockSig);
+
+        // not signed by the leader
+        let blk = main_block(pm, leader.public(), |to_sign| to_sign.sign(pm, &xprv(2)));
+        expect_error(&verify(&blk), Error::BadBlockSig);
+
+        // the header changed after signing
+        let mut blk = main_block(pm, leader.public(), |to_sign| to_sign.sign(pm, &leader));
+        if let Block::MainBlock(mblk) = &mut blk {
+            mblk.header.consensus.chain_difficulty = ChainDifficulty::from(101);
+        }
+        expect_error(&verify(&blk), Error::BadBlockSig);
+    }
+
+    #[test]
+    fn test_verify_light_proxy_signature() {
+        let pm = ProtocolMagic::from(633343913);
+        let issuer = xprv(1);
+        let delegate = xprv(2);
+
+        for &(start, end) in &[(3, 3), (0, 3), (3, 10)] {
+            let psk = light_psk(pm, &issuer, &delegate, start, end);
+            let blk = light_signed_block(pm, psk);
+            assert!(verify(&blk).is_ok());
+        }
+
+        // the block is encoded and decoded with its signature
+        let blk = light_signed_block(pm, light_psk(pm, &issuer, &delegate, 3, 3));
+        let rblk = RawBlock::from_dat(cbor!(&blk).unwrap());
+        match rblk.to_header().unwrap().decode().unwrap() {
+            BlockHeader::MainBlockHeader(hdr) => match hdr.consensus.block_signature {
+                sign::BlockSignature::ProxyLight(proxy_sig) => {
+                    assert_eq!(proxy_sig.psk.omega, sign::LightDlgIndices::new(3, 3));
+                    assert!(proxy_sig.psk.verify(pm));
+                }
+                sig => panic!("unexpected signature {:?}", sig),
+            },
+            _ => panic!("expected a main block header"),
+        }
+
+        // the block's epoch is not covered by the certificate
+        for &(start, end) in &[(0, 2), (4, 10), (4, 2)] {
+            let psk = light_psk(pm, &issuer, &delegate, start, end);
+            let blk = light_signed_block(pm, psk);
+            expect_error(&verify(&blk), Error::BadBlockSig);
+        }
+
+        // a forged certificate
+        let mut psk = light_psk(pm, &issuer, &delegate, 3, 3);
+        psk.omega.end = 4;
+        expect_error(&verify(&light_signed_block(pm, psk)), Error::BadBlockSig);
+
+        // a certificate not issued by the slot leader
+        let psk = light_psk(pm, &issuer, &delegate, 3, 3);
+        let mut blk = light_signed_block(pm, psk);
+        if let Block::MainBlock(mblk) = &mut blk {
+            mblk.header.consensus.leader_key = xprv(3).public();
+        }
+        expect_error(&verify(&blk), Error::BadBlockSig);
+
+        // a self signed certificate
+        let psk = light_psk(pm, &delegate, &delegate, 3, 3);
+        expect_error(&verify(&light_signed_block(pm, psk)), Error::SelfSignedPSK);
+    }
+
     #[test]
     fn test_verify() {
         let hash = HeaderHash::from_str(&HEADER_HASH1).unwrap();
@@ -686,7 +1527,7 @@ mod tests {
         {
             let mut blk = blk2.clone();
             if let Block::MainBlock(mblk) = &mut blk {
-                mblk.extra = cbor_event::Value::U64(123);
+                mblk.extra.attributes.unknown.insert(0, vec![123]);
             }
             expect_error(&verify_block(&hash2, &blk), Error::WrongExtraDataProof);
         }
@@ -695,7 +1536,9 @@ mod tests {
         {
             let mut blk = blk2.clone();
             if let Block::MainBlock(mblk) = &mut blk {
-                mblk.body.delegation = DlgPayload(cbor_event::Value::U64(123));
+                let hdr = &mblk.header;
+                let psk = test_psk(hdr.protocol_magic, 1, 2, hdr.consensus.slot_id.epoch);
+                mblk.body.delegation = DlgPayload(vec![psk]);
             }
             expect_error(&verify_block(&hash2, &blk), Error::WrongDelegationProof);
         }
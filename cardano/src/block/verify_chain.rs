@@ -1,16 +1,135 @@
 use address;
+use block::sign::BlockSignature;
+use block::ssc_state;
+use block::ssc_state::SscChange;
+use block::stake_state::CrucialStakes;
+use block::update_state::{Stakes, UpdateChange};
 use block::*;
 use coin;
 use fee::FeeAlgorithm;
-use tx::{TxAux, TxInWitness, TxoPointer};
+use hdwallet::XPub;
+use std::mem;
+use tx::{TxAux, TxInWitness, TxOut, TxoPointer};
+
+/// A change of the utxo state.
+#[derive(Debug, Clone)]
+enum UtxoChange {
+    Removed(TxoPointer, TxOut),
+    /// an output was added, replacing the given one if any
+    Added(TxoPointer, Option<TxOut>),
+}
+
+/// A change of the delegation state.
+#[derive(Debug, Clone)]
+enum DelegationChange {
+    /// the delegate of a stakeholder changed, from the given one if any
+    Delegated(address::StakeholderId, Option<XPub>),
+    /// a certificate was recorded for the next epoch, replacing the
+    /// given one if any
+    Pending(address::StakeholderId, Option<XPub>),
+}
+
+/// The state a block changed, to revert it from the chain state.
+#[derive(Debug, Clone)]
+pub struct BlockUndo {
+    block_hash: HeaderHash,
+    last_block: HeaderHash,
+    last_date: Option<BlockDate>,
+    last_boundary_block: Option<HeaderHash>,
+    /// the slot leaders, if the block changed them
+    slot_leaders: Option<Option<Vec<address::StakeholderId>>>,
+    chain_length: u64,
+    chain_difficulty: ChainDifficulty,
+    nr_transactions: u64,
+    spent_txos: u64,
+    /// the changes of the utxo state, in the order they were made
+    utxos: Vec<UtxoChange>,
+    /// the changes of the delegation state, in the order they were made
+    delegation: Vec<DelegationChange>,
+    /// the changes of the update state, in the order they were made
+    update: Vec<UpdateChange>,
+    /// the changes of the SSC state, in the order they were made
+    ssc: Vec<SscChange>,
+    /// the stakes of the crucial slot, if the block recorded them
+    crucial_stakes: Option<Option<CrucialStakes>>,
+    /// the snapshot of the previous epoch, if the block started an epoch
+    epoch_stakes: Option<Stakes>,
+}
+
+impl BlockUndo {
+    /// the block this undo information reverts
+    pub fn block_hash(&self) -> &HeaderHash {
+        &self.block_hash
+    }
+}
+
+/// The state a transaction changed, to revert it from the chain state.
+#[derive(Debug, Clone, Default)]
+pub struct TxUndo {
+    nr_transactions: u64,
+    spent_txos: u64,
+    /// the changes of the utxo state, in the order they were made
+    utxos: Vec<UtxoChange>,
+}
 
 impl ChainState {
     /// Verify a block in the context of the chain. Regardless of
     /// errors, the chain state is updated to reflect the changes
     /// introduced by this block.
-    /// FIXME: we may want to return all errors rather than just the first.
     pub fn verify_block(&mut self, block_hash: &HeaderHash, blk: &Block) -> Result<(), Error> {
-        let mut res = Ok(());
+        first_error(self.verify_block_with_undo(block_hash, blk).1)
+    }
+
+    /// Like `verify_block`, but returning every error found in the
+    /// block, with its location, rather than the first one. The chain
+    /// state is updated the same way.
+    pub fn verify_block_all_errors(&mut self, block_hash: &HeaderHash, blk: &Block) -> Errors {
+        self.verify_block_with_undo(block_hash, blk).1
+    }
+
+    /// Apply a block only if it is valid. On error, the chain state is
+    /// left untouched.
+    pub fn apply_block(
+        &mut self,
+        block_hash: &HeaderHash,
+        blk: &Block,
+    ) -> Result<BlockUndo, Error> {
+        let (undo, errors) = self.verify_block_with_undo(block_hash, blk);
+        match first_error(errors) {
+            Ok(()) => Ok(undo),
+            Err(err) => {
+                self.revert_block(undo);
+                Err(err)
+            }
+        }
+    }
+
+    /// Like `verify_block_all_errors`, also returning what is needed to
+    /// revert the block with `revert_block`.
+    pub fn verify_block_with_undo(
+        &mut self,
+        block_hash: &HeaderHash,
+        blk: &Block,
+    ) -> (BlockUndo, Errors) {
+        let mut errors = vec![];
+
+        let mut undo = BlockUndo {
+            block_hash: block_hash.clone(),
+            last_block: self.last_block.clone(),
+            last_date: self.last_date,
+            last_boundary_block: self.last_boundary_block.clone(),
+            slot_leaders: None,
+            chain_length: self.chain_length,
+            chain_difficulty: self.chain_difficulty,
+            nr_transactions: self.nr_transactions,
+            spent_txos: self.spent_txos,
+            utxos: vec![],
+            delegation: vec![],
+            update: vec![],
+            ssc: vec![],
+            crucial_stakes: None,
+            epoch_stakes: None,
+        };
 
         let epoch_transition = self
             .last_date
@@ -18,19 +137,32 @@ impl ChainState {
             .unwrap_or(false);
 
         if epoch_transition {
-            self.slot_leaders = None;
+            let epoch = blk.header().blockdate().get_epochid();
+            undo.slot_leaders = Some(self.slot_leaders.take());
+            self.apply_pending_delegation(&mut undo.delegation);
+            // The stakes of the new epoch's slot leaders are its snapshot,
+            // weighing its richmen, votes and block issuers.
+            undo.epoch_stakes = Some(self.stakes.start_epoch(epoch));
+            self.update
+                .apply_epoch_boundary(epoch, &self.stakes.epoch_stakes, &mut undo.update);
+            let richmen = ssc_state::richmen(&self.stakes.epoch_stakes, &self.parameters().mpc_thd);
+            undo.ssc = self.ssc.apply_epoch_boundary(epoch, richmen);
         }
 
-        add_error(&mut res, self.do_verify(block_hash, blk));
+        self.do_verify(block_hash, blk, &mut errors);
 
         self.last_block = block_hash.clone();
         self.last_date = Some(blk.header().blockdate());
         // FIXME: count boundary blocks as part of the chain length?
         self.chain_length += 1;
+        self.chain_difficulty = blk.header().difficulty();
 
         match blk {
             Block::BoundaryBlock(blk) => {
                 self.last_boundary_block = Some(block_hash.clone());
+                if undo.slot_leaders.is_none() {
+                    undo.slot_leaders = Some(self.slot_leaders.take());
+                }
                 self.slot_leaders = Some(blk.body.slot_leaders.clone());
             }
 
@@ -41,100 +173,349 @@ impl ChainState {
             }
         };
 
-        // Update the utxos from the transactions.
+        // Update the utxos from the transactions and record the
+        // delegation certificates for the next epoch.
         if let Block::MainBlock(blk) = blk {
-            for txaux in blk.body.tx.iter() {
-                add_error(&mut res, self.verify_tx(txaux));
+            // Keep the stakes of the crucial slot, to elect the slot
+            // leaders of the next epoch from, before the first block
+            // after it changes them.
+            let slot_id = &blk.header.consensus.slot_id;
+            let crucial_slot = self.epoch_slots() - 2 * self.epoch_stability_depth;
+            if slot_id.slotid as usize >= crucial_slot
+                && self.stakes.crucial.as_ref().map(|crucial| crucial.epoch) != Some(slot_id.epoch)
+            {
+                undo.crucial_stakes = Some(self.stakes.record_crucial(slot_id.epoch));
+            }
+
+            // Check the SSC payload against the phase of the epoch and
+            // its richmen, and record its VSS certificates.
+            let changes = self.ssc.apply_payload_all_errors(
+                self.epoch_stability_depth,
+                &(self.vss_min_ttl..=self.vss_max_ttl),
+                &blk.header.consensus.slot_id,
+                &blk.body.ssc,
+                &mut errors,
+            );
+            undo.ssc.extend(changes);
+
+            for (index, txaux) in blk.body.tx.iter().enumerate() {
+                self.verify_tx(index, txaux, &mut undo.utxos, &mut errors);
+            }
+
+            for psk in blk.body.delegation.iter() {
+                let issuer = address::StakeholderId::new(&psk.issuer_pk);
+                let replaced = self.pending_delegation.insert(issuer, psk.delegate_pk);
+                undo.delegation
+                    .push(DelegationChange::Pending(issuer, replaced));
             }
+
+            // Record the update proposal and votes, and the support
+            // of the slot leader for the block version.
+            let slot = blk
+                .header
+                .consensus
+                .slot_id
+                .slot_number(self.epoch_slots() as u64);
+            self.update.apply_payload_all_errors(
+                slot,
+                &self.stakes.epoch_stakes,
+                &blk.body.update,
+                &mut undo.update,
+                &mut errors,
+            );
+            self.update.record_issuer(
+                &blk.header.extra_data.block_version,
+                address::StakeholderId::new(&blk.header.consensus.leader_key),
+                &mut undo.update,
+            );
         }
 
-        res
+        (undo, errors)
+    }
+
+    /// Revert the last block applied to the chain state. Blocks must be
+    /// reverted in the reverse order they were applied in.
+    pub fn revert_block(&mut self, undo: BlockUndo) {
+        assert_eq!(
+            self.last_block, undo.block_hash,
+            "reverting a block that is not the last one"
+        );
+
+        self.revert_utxo_changes(undo.utxos);
+
+        self.last_block = undo.last_block;
+        self.last_date = undo.last_date;
+        self.last_boundary_block = undo.last_boundary_block;
+        if let Some(slot_leaders) = undo.slot_leaders {
+            self.slot_leaders = slot_leaders;
+        }
+        self.chain_length = undo.chain_length;
+        self.chain_difficulty = undo.chain_difficulty;
+        self.nr_transactions = undo.nr_transactions;
+        self.spent_txos = undo.spent_txos;
+        self.revert_delegation_changes(undo.delegation);
+        self.update.revert_changes(undo.update);
+        self.ssc.revert_changes(undo.ssc);
+        if let Some(crucial_stakes) = undo.crucial_stakes {
+            self.stakes.crucial = crucial_stakes;
+        }
+        if let Some(epoch_stakes) = undo.epoch_stakes {
+            self.stakes.epoch_stakes = epoch_stakes;
+        }
     }
 
-    fn do_verify(&self, block_hash: &HeaderHash, blk: &Block) -> Result<(), Error> {
+    fn do_verify(&self, block_hash: &HeaderHash, blk: &Block, errors: &mut Errors) {
         // Perform stateless checks.
-        verify_block(block_hash, blk)?;
+        errors.append(&mut verify_block_all_errors(block_hash, blk));
+
+        let mut error = |error| errors.push(LocatedError::new(ErrorLocation::Header, error));
 
         // Check the protocol magic.
         if blk.get_protocol_magic() != self.protocol_magic {
-            return Err(Error::WrongMagic);
+            error(Error::WrongMagic);
         }
 
         let hdr = blk.header();
         let prev_block = hdr.previous_header();
         if prev_block != self.last_block {
-            return Err(Error::WrongPreviousBlock(
+            error(Error::WrongPreviousBlock(
                 prev_block,
                 self.last_block.clone(),
             ));
         }
 
         // Check the block date.
-        let date = hdr.blockdate();
+        if let Err(err) = verify_block_date(self.last_date, hdr.blockdate()) {
+            error(err);
+        }
 
-        match self.last_date {
-            Some(last_date) => {
-                if date <= last_date {
-                    return Err(Error::BlockDateInPast);
-                }
+        // Check the chain difficulty: the number of main blocks.
+        let expected_difficulty = match blk {
+            Block::BoundaryBlock(_) => u64::from(self.chain_difficulty),
+            Block::MainBlock(_) => u64::from(self.chain_difficulty) + 1,
+        };
+        if u64::from(hdr.difficulty()) != expected_difficulty {
+            error(Error::WrongChainDifficulty);
+        }
 
-                if date.is_boundary() {
-                    if date.get_epochid() == last_date.get_epochid() {
-                        return Err(Error::BlockDateInPast);
-                    } else if date.get_epochid() > last_date.get_epochid() + 1 {
-                        return Err(Error::BlockDateInFuture);
-                    }
+        // Check the header size.
+        match cbor!(&hdr) {
+            Ok(bytes) => {
+                if bytes.len() as u64 > self.parameters().max_header_size {
+                    error(Error::HeaderTooBig);
                 }
             }
+            Err(err) => error(Error::EncodingError(err)),
+        }
+
+        // The blocks of the adopted version cannot have attributes
+        // unknown to that version.
+        if let Block::MainBlock(blk) = blk {
+            let extra_data = &blk.header.extra_data;
+            if extra_data.block_version == self.update.adopted_version
+                && (extra_data.attributes.has_unknown() || blk.extra.attributes.has_unknown())
+            {
+                error(Error::UnknownAttributes);
+            }
+        }
 
-            None => {
-                if date != BlockDate::Boundary(0) {
-                    // FIXME: use epoch_start
-                    return Err(Error::BlockDateInFuture);
+        // Check the block size.
+        if let Block::MainBlock(_) = blk {
+            match cbor!(blk) {
+                Ok(bytes) => {
+                    if bytes.len() as u64 > self.parameters().max_block_size {
+                        error(Error::BlockTooBig);
+                    }
                 }
+                Err(err) => error(Error::EncodingError(err)),
             }
         }
 
-        // Check that the block was signed by the appointed slot leader.
+        // Check the slot leaders of a boundary block against the elected
+        // ones, and that a main block was signed by the appointed slot
+        // leader.
         match blk {
-            Block::BoundaryBlock(_) => {}
+            Block::BoundaryBlock(blk) => {
+                if let Err(err) = self.verify_slot_leaders(blk) {
+                    error(err);
+                }
+            }
 
             Block::MainBlock(blk) => {
                 let slot_id = blk.header.consensus.slot_id.slotid as usize;
 
-                match &self.slot_leaders {
-                    Some(ref slot_leaders) => {
-                        if slot_id >= slot_leaders.len() {
-                            return Err(Error::NonExistentSlot);
-                        }
-
+                if let Some(slot_leaders) = &self.slot_leaders {
+                    if slot_id >= slot_leaders.len() {
+                        error(Error::NonExistentSlot);
+                    } else {
                         let slot_leader = &slot_leaders[slot_id];
-                        // Note: the block signature was already checked in
-                        // verify_block, so here we only check the leader key
-                        // against the genesis block.
-                        if slot_leader
-                            != &address::StakeholderId::new(&blk.header.consensus.leader_key)
-                        {
-                            return Err(Error::WrongSlotLeader);
+                        // Note: the block signature is checked by
+                        // verify_block, so here we only check the
+                        // keys against the slot leader and its
+                        // delegate.
+                        let consensus = &blk.header.consensus;
+                        let valid = match &consensus.block_signature {
+                            BlockSignature::ProxyHeavy(proxy_sig) => {
+                                slot_leader
+                                    == &address::StakeholderId::new(&proxy_sig.psk.issuer_pk)
+                                    && self.delegate_of(slot_leader)
+                                        == Some(&proxy_sig.psk.delegate_pk)
+                            }
+                            BlockSignature::Signature(_) => {
+                                slot_leader == &address::StakeholderId::new(&consensus.leader_key)
+                                    && self.delegate_of(slot_leader).is_none()
+                            }
+                            BlockSignature::ProxyLight(_) => {
+                                slot_leader == &address::StakeholderId::new(&consensus.leader_key)
+                            }
+                        };
+                        if !valid {
+                            error(Error::WrongSlotLeader);
                         }
                     }
-                    None => {}
                 }
             }
         };
+    }
 
-        Ok(())
+    /// Verify a transaction as if it were the next transaction of a
+    /// block, and apply it to the utxo state if it is valid. On error,
+    /// the chain state is left untouched.
+    pub fn apply_tx(&mut self, txaux: &TxAux) -> Result<(), Error> {
+        self.apply_tx_with_undo(txaux).map(|_| ())
+    }
+
+    /// Like `apply_tx`, also returning what is needed to revert the
+    /// transaction with `revert_tx`.
+    pub fn apply_tx_with_undo(&mut self, txaux: &TxAux) -> Result<TxUndo, Error> {
+        txaux.verify(self.protocol_magic)?;
+
+        let mut undo = TxUndo {
+            nr_transactions: self.nr_transactions,
+            spent_txos: self.spent_txos,
+            utxos: vec![],
+        };
+        let mut errors = vec![];
+        self.verify_tx(0, txaux, &mut undo.utxos, &mut errors);
+        match first_error(errors) {
+            Ok(()) => Ok(undo),
+            Err(err) => {
+                self.revert_tx(undo);
+                Err(err)
+            }
+        }
+    }
+
+    /// Revert a transaction applied with `apply_tx_with_undo`.
+    /// Transactions must be reverted in the reverse order they were
+    /// applied in, with no block applied or reverted in between.
+    pub fn revert_tx(&mut self, undo: TxUndo) {
+        self.revert_utxo_changes(undo.utxos);
+        self.nr_transactions = undo.nr_transactions;
+        self.spent_txos = undo.spent_txos;
+    }
+
+    /// Undo the changes of the utxo state, in the reverse order they
+    /// were made.
+    fn revert_utxo_changes(&mut self, utxo_changes: Vec<UtxoChange>) {
+        for change in utxo_changes.into_iter().rev() {
+            match change {
+                UtxoChange::Removed(txo_ptr, txout) => {
+                    self.stakes.add_output(&txout);
+                    self.utxos.insert(txo_ptr, txout);
+                }
+                UtxoChange::Added(txo_ptr, replaced) => {
+                    if let Some(txout) = self.utxos.remove(&txo_ptr) {
+                        self.stakes.remove_output(&txout);
+                    }
+                    if let Some(txout) = replaced {
+                        self.stakes.add_output(&txout);
+                        self.utxos.insert(txo_ptr, txout);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply the delegation certificates issued during the previous
+    /// epoch, recording the changes in `changes`.
+    fn apply_pending_delegation(&mut self, changes: &mut Vec<DelegationChange>) {
+        for (issuer, delegate_pk) in mem::take(&mut self.pending_delegation) {
+            let replaced = if address::StakeholderId::new(&delegate_pk) == issuer {
+                // revocation
+                self.delegation.remove(&issuer)
+            } else {
+                self.delegation.insert(issuer, delegate_pk)
+            };
+            changes.push(DelegationChange::Pending(issuer, Some(delegate_pk)));
+            changes.push(DelegationChange::Delegated(issuer, replaced));
+        }
+    }
+
+    /// Undo the changes of the delegation state, in the reverse order
+    /// they were made.
+    fn revert_delegation_changes(&mut self, changes: Vec<DelegationChange>) {
+        for change in changes.into_iter().rev() {
+            let (delegation, issuer, delegate_pk) = match change {
+                DelegationChange::Delegated(issuer, delegate_pk) => {
+                    (&mut self.delegation, issuer, delegate_pk)
+                }
+                DelegationChange::Pending(issuer, delegate_pk) => {
+                    (&mut self.pending_delegation, issuer, delegate_pk)
+                }
+            };
+            match delegate_pk {
+                Some(delegate_pk) => delegation.insert(issuer, delegate_pk),
+                None => delegation.remove(&issuer),
+            };
+        }
     }
 
     /// Verify that a transaction only spends unspent transaction
-    /// outputs (utxos), and update the utxo state.
-    fn verify_tx(&mut self, txaux: &TxAux) -> Result<(), Error> {
+    /// outputs (utxos), and update the utxo state, recording the
+    /// changes in `utxo_changes`. `tx_index` is the index of the
+    /// transaction in its block.
+    fn verify_tx(
+        &mut self,
+        tx_index: usize,
+        txaux: &TxAux,
+        utxo_changes: &mut Vec<UtxoChange>,
+        errors: &mut Errors,
+    ) {
         self.nr_transactions += 1;
 
-        let mut res = Ok(());
+        let mut error = |location, error| errors.push(LocatedError::new(location, error));
         let tx = &txaux.tx;
         let id = tx.id();
 
+        // Script witnesses are not supported, nor can they be encoded to
+        // check the size of the transaction.
+        let mut script_witnesses = false;
+        for (witness_index, in_witness) in txaux.witness.iter().enumerate() {
+            if let TxInWitness::ScriptWitness(_, _) = in_witness {
+                error(
+                    ErrorLocation::TxWitness(tx_index, witness_index),
+                    Error::UnsupportedScriptWitness,
+                );
+                script_witnesses = true;
+            }
+        }
+        if script_witnesses {
+            return;
+        }
+
+        match cbor!(txaux) {
+            Ok(bytes) => {
+                if bytes.len() as u64 > self.parameters().max_tx_size {
+                    error(ErrorLocation::Tx(tx_index), Error::TxTooBig);
+                }
+            }
+            Err(err) => {
+                error(ErrorLocation::Tx(tx_index), Error::EncodingError(err));
+                return;
+            }
+        }
+
         // Look up the utxos corresponding to the inputs and remove
         // them from the utxo map to prevent double spending. Also
         // check that the utxo address matches the witness
@@ -144,38 +525,44 @@ impl ChainState {
         // verify::verify_block().
         let mut input_amount = coin::Coin::zero();
         let mut nr_redeems = 0;
-        for (txin, in_witness) in tx.inputs.iter().zip(txaux.witness.iter()) {
-            match self.utxos.remove(&txin) {
+        for (input_index, (txin, in_witness)) in
+            tx.inputs.iter().zip(txaux.witness.iter()).enumerate()
+        {
+            let location = ErrorLocation::TxInput(tx_index, input_index);
+            match self.utxos.remove(txin) {
                 None => {
-                    add_error(&mut res, Err(Error::MissingUtxo));
+                    error(location, Error::MissingUtxo);
                 }
                 Some(txout) => {
                     self.spent_txos += 1;
+                    self.stakes.remove_output(&txout);
+                    utxo_changes.push(UtxoChange::Removed(txin.clone(), txout.clone()));
 
                     let witness_address = match in_witness {
-                        TxInWitness::PkWitness(pubkey, _) => address::ExtendedAddr::new(
+                        TxInWitness::PkWitness(pubkey, _) => Some(address::ExtendedAddr::new(
                             address::AddrType::ATPubKey,
                             address::SpendingData::PubKeyASD(*pubkey),
                             txout.address.attributes.clone(),
-                        ),
+                        )),
 
-                        TxInWitness::ScriptWitness(_, _) => {
-                            panic!("script witnesses are not implemented")
-                        }
+                        // rejected above
+                        TxInWitness::ScriptWitness(_, _) => None,
 
                         TxInWitness::RedeemWitness(pubkey, _) => {
                             nr_redeems += 1;
 
-                            address::ExtendedAddr::new(
+                            Some(address::ExtendedAddr::new(
                                 address::AddrType::ATRedeem,
                                 address::SpendingData::RedeemASD(*pubkey),
                                 txout.address.attributes.clone(),
-                            )
+                            ))
                         }
                     };
 
-                    if witness_address != txout.address {
-                        add_error(&mut res, Err(Error::AddressMismatch));
+                    if let Some(witness_address) = witness_address {
+                        if witness_address != txout.address {
+                            error(location, Error::AddressMismatch);
+                        }
                     }
 
                     match input_amount + txout.value {
@@ -183,7 +570,7 @@ impl ChainState {
                             input_amount = x;
                         }
                         Err(coin::Error::OutOfBound(_)) => {
-                            add_error(&mut res, Err(Error::InputsTooBig))
+                            error(ErrorLocation::Tx(tx_index), Error::InputsTooBig)
                         }
                         Err(err) => unreachable!("{}", err),
                     }
@@ -198,7 +585,9 @@ impl ChainState {
                 Ok(x) => {
                     output_amount = x;
                 }
-                Err(coin::Error::OutOfBound(_)) => add_error(&mut res, Err(Error::OutputsTooBig)),
+                Err(coin::Error::OutOfBound(_)) => {
+                    error(ErrorLocation::Tx(tx_index), Error::OutputsTooBig)
+                }
                 Err(err) => unreachable!("{}", err),
             }
         }
@@ -208,10 +597,10 @@ impl ChainState {
         let min_fee = if nr_redeems == tx.inputs.len() {
             coin::Coin::zero()
         } else {
-            match self.fee_policy.calculate_for_txaux(&txaux) {
+            match self.parameters().tx_fee_policy.calculate_for_txaux(txaux) {
                 Ok(fee) => fee.to_coin(),
                 Err(err) => {
-                    add_error(&mut res, Err(Error::FeeError(err)));
+                    error(ErrorLocation::Tx(tx_index), Error::FeeError(err));
                     coin::Coin::zero()
                 }
             }
@@ -220,7 +609,7 @@ impl ChainState {
         let output_plus_fee = match output_amount + min_fee {
             Ok(x) => x,
             Err(coin::Error::OutOfBound(_)) => {
-                add_error(&mut res, Err(Error::OutputsTooBig));
+                error(ErrorLocation::Tx(tx_index), Error::OutputsTooBig);
                 output_amount
             }
             Err(err) => unreachable!("{}", err),
@@ -228,34 +617,469 @@ impl ChainState {
 
         // Check that total outputs + minimal fee <= total inputs.
         if output_plus_fee > input_amount {
-            add_error(&mut res, Err(Error::OutputsExceedInputs));
+            error(ErrorLocation::Tx(tx_index), Error::OutputsExceedInputs);
         }
 
         // Add the outputs to the utxo state.
         for (index, output) in tx.outputs.iter().enumerate() {
-            if self
-                .utxos
-                .insert(
-                    TxoPointer {
-                        id,
-                        index: index as u32,
-                    },
-                    output.clone(),
-                )
-                .is_some()
-            {
-                add_error(&mut res, Err(Error::DuplicateTxo));
+            let txo_ptr = TxoPointer {
+                id,
+                index: index as u32,
+            };
+            let replaced = self.utxos.insert(txo_ptr.clone(), output.clone());
+            self.stakes.add_output(output);
+            if let Some(ref txout) = replaced {
+                self.stakes.remove_output(txout);
+                error(
+                    ErrorLocation::TxOutput(tx_index, index),
+                    Error::DuplicateTxo,
+                );
             }
+            utxo_changes.push(UtxoChange::Added(txo_ptr, replaced));
         }
-
-        res
     }
 }
 
-// FIXME: might be nice to return a list of errors. Currently we only
-// return the first.
-fn add_error(res: &mut Result<(), Error>, err: Result<(), Error>) {
-    if res.is_ok() && err.is_err() {
-        *res = err;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use block::fts::SharedSeed;
+    use block::update::BlockVersionData;
+    use block::{boundary, normal, update};
+    use coin::Coin;
+    use config::{BootStakeholder, GenesisData, ProtocolMagic};
+    use fee;
+    use hash::Blake2b256;
+    use hdwallet::{Seed, XPrv};
+    use redeem;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::time::SystemTime;
+    use tx::{self, Tx, TxWitness};
+
+    fn xprv(seed: u8) -> XPrv {
+        XPrv::generate_from_seed(&Seed::from_bytes([seed; 32]))
+    }
+
+    fn redeem_key() -> redeem::PrivateKey {
+        redeem::PrivateKey::generate(&[7; 32]).unwrap()
+    }
+
+    fn genesis_data() -> GenesisData {
+        let mut avvm_distr = BTreeMap::new();
+        avvm_distr.insert(redeem_key().public(), Coin::new(1000).unwrap());
+        GenesisData {
+            genesis_prev: HeaderHash::new(b"genesis"),
+            epoch_stability_depth: 10,
+            vss_min_ttl: 2,
+            vss_max_ttl: 6,
+            start_time: SystemTime::UNIX_EPOCH,
+            protocol_magic: ProtocolMagic::from(633343913),
+            block_version_data: BlockVersionData::mainnet(20_000, fee::LinearFee::default()),
+            avvm_distr,
+            non_avvm_balances: BTreeMap::new(),
+            boot_stakeholders: BTreeMap::new(),
+            fts_seed: SharedSeed::default(),
+        }
+    }
+
+    /// the chain state of `genesis`, with an unknown seed: the slot
+    /// leaders of the test blocks are not elected
+    fn initial_chain_state(genesis: &GenesisData) -> ChainState {
+        let mut chain_state = ChainState::new(genesis);
+        chain_state.ssc.seed = None;
+        chain_state
+    }
+
+    fn boundary_block(genesis: &GenesisData) -> (HeaderHash, Block) {
+        let body = boundary::Body {
+            slot_leaders: vec![address::StakeholderId::new(&xprv(1).public())],
+        };
+        let header = boundary::BlockHeader::new(
+            genesis.protocol_magic,
+            genesis.genesis_prev.clone(),
+            boundary::BodyProof(Blake2b256::new(&cbor!(&body).unwrap())),
+            boundary::Consensus {
+                epoch: 0,
+                chain_difficulty: ChainDifficulty::from(0),
+            },
+            ExtraData::default(),
+        );
+        let blk = Block::BoundaryBlock(boundary::Block {
+            header,
+            body,
+            extra: ExtraData::default(),
+        });
+        (blk.header().compute_hash(), blk)
+    }
+
+    /// a main block of the first slot spending the AVVM utxo with `txs`
+    fn main_block(
+        genesis: &GenesisData,
+        previous: &HeaderHash,
+        txs: Vec<TxAux>,
+    ) -> (HeaderHash, Block) {
+        let pm = genesis.protocol_magic;
+        let body = normal::Body::new(
+            normal::TxPayload::new(txs),
+            normal::SscPayload::CertificatesPayload(normal::VssCertificates::new(vec![])),
+            normal::DlgPayload(vec![]),
+            update::UpdatePayload {
+                proposal: None,
+                votes: vec![],
+            },
+        );
+        let extra = ExtraData::default();
+        let mut header = normal::BlockHeader::new(
+            pm,
+            previous.clone(),
+            normal::BodyProof::generate_from_body(&body),
+            normal::Consensus {
+                slot_id: EpochSlotId {
+                    epoch: 0,
+                    slotid: 0,
+                },
+                leader_key: xprv(1).public(),
+                chain_difficulty: ChainDifficulty::from(1),
+                block_signature: BlockSignature::Signature(xprv(0).sign(b"")),
+            },
+            HeaderExtraData::new(
+                BlockVersion::new(0, 0, 0),
+                SoftwareVersion::new("cardano-sl", 1).unwrap(),
+                BlockHeaderAttributes::new(),
+                Blake2b256::new(&cbor!(&extra).unwrap()),
+            ),
+        );
+        header.consensus.block_signature =
+            sign::MainToSign::from_header(&header).sign(pm, &xprv(1));
+        let blk = Block::MainBlock(normal::Block::new(header, body, extra));
+        (blk.header().compute_hash(), blk)
+    }
+
+    /// a transaction sending `value` out of the AVVM utxo
+    fn redeem_tx(genesis: &GenesisData, value: u64) -> TxAux {
+        let pm = genesis.protocol_magic;
+        let (id, _) = tx::redeem_pubkey_to_txid(&redeem_key().public(), pm);
+        let tx = Tx::new_with(
+            vec![TxoPointer::new(id, 0)],
+            vec![TxOut::new(
+                address::ExtendedAddr::new(
+                    address::AddrType::ATPubKey,
+                    address::SpendingData::PubKeyASD(xprv(2).public()),
+                    address::Attributes::new_single_key(&xprv(3).public(), None, pm.into()),
+                ),
+                Coin::new(value).unwrap(),
+            )],
+        );
+        let witness = TxInWitness::new_redeem_pk(pm, &redeem_key(), &tx.id());
+        TxAux::new(tx, TxWitness::from(vec![witness]))
+    }
+
+    #[test]
+    fn blocks_are_reverted() {
+        let genesis = genesis_data();
+        let initial = initial_chain_state(&genesis);
+        let mut chain_state = initial.clone();
+
+        let (boundary_hash, boundary) = boundary_block(&genesis);
+        let boundary_undo = chain_state.apply_block(&boundary_hash, &boundary).unwrap();
+        let after_boundary = chain_state.clone();
+
+        let (hash, blk) = main_block(&genesis, &boundary_hash, vec![redeem_tx(&genesis, 1000)]);
+        let undo = chain_state.apply_block(&hash, &blk).unwrap();
+        assert_eq!(undo.block_hash(), &hash);
+        assert_eq!(chain_state.last_block, hash);
+        assert_eq!(chain_state.nr_transactions, 1);
+        assert_eq!(chain_state.utxos.len(), 1);
+        assert_ne!(chain_state.utxos, after_boundary.utxos);
+        assert_eq!(
+            chain_state
+                .stakes
+                .stake_of(&address::StakeholderId::new(&xprv(3).public())),
+            1000
+        );
+
+        chain_state.revert_block(undo);
+        assert_eq!(chain_state, after_boundary);
+        chain_state.revert_block(boundary_undo);
+        assert_eq!(chain_state, initial);
+    }
+
+    #[test]
+    fn epoch_transitions_are_reverted() {
+        let genesis = genesis_data();
+        let mut chain_state = initial_chain_state(&genesis);
+        let (boundary_hash, boundary) = boundary_block(&genesis);
+        chain_state.apply_block(&boundary_hash, &boundary).unwrap();
+
+        let delegate = xprv(4).public();
+        let psk = sign::ProxySecretKey::sign(&xprv(1), delegate, 0, genesis.protocol_magic);
+        let mut builder = builder::MainBlockBuilder::new(EpochSlotId {
+            epoch: 0,
+            slotid: 0,
+        });
+        builder.add_delegation(psk);
+        let blk = builder.make_block(&chain_state, &builder::BlockIssuer::SlotLeader(&xprv(1)));
+        let hash = blk.header().compute_hash();
+        let undo = chain_state.apply_block(&hash, &blk).unwrap();
+        assert_eq!(chain_state.pending_delegation.len(), 1);
+        let before_transition = chain_state.clone();
+
+        let leader = address::StakeholderId::new(&xprv(1).public());
+        let blk = builder::make_boundary_block(&chain_state, 1, vec![leader]);
+        let transition_undo = chain_state
+            .apply_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+        assert_eq!(chain_state.delegate_of(&leader), Some(&delegate));
+        assert!(chain_state.pending_delegation.is_empty());
+
+        chain_state.revert_block(transition_undo);
+        assert_eq!(chain_state, before_transition);
+        chain_state.revert_block(undo);
+        assert!(chain_state.pending_delegation.is_empty());
+    }
+
+    #[test]
+    fn slot_leaders_are_elected() {
+        // the AVVM utxo is split between the boot stakeholders 1 and 2,
+        // delegating to the keys 11 and 12
+        let mut genesis = genesis_data();
+        for n in 1..3 {
+            genesis.boot_stakeholders.insert(
+                address::StakeholderId::new(&xprv(n).public()),
+                BootStakeholder {
+                    weight: 1,
+                    issuer_pk: xprv(n).public(),
+                    delegate_pk: xprv(n + 10).public(),
+                    cert: xprv(n).sign(b""),
+                },
+            );
+        }
+        let mut chain_state = ChainState::new(&genesis);
+        let genesis_stakes = chain_state.stakes.stakes.clone();
+        let genesis_richmen: BTreeSet<_> = (1..3)
+            .map(|n| address::StakeholderId::new(&xprv(n).public()))
+            .collect();
+        assert_eq!(chain_state.ssc.richmen, genesis_richmen);
+        let leaders = fts::follow_the_satoshi(
+            chain_state.epoch_slots(),
+            &genesis.fts_seed,
+            &genesis_stakes,
+        )
+        .unwrap();
+
+        let blk =
+            builder::make_boundary_block(&chain_state, 0, leaders.iter().rev().cloned().collect());
+        match chain_state
+            .clone()
+            .verify_block(&blk.header().compute_hash(), &blk)
+        {
+            Err(Error::WrongSlotLeaders) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        let blk = builder::make_boundary_block(&chain_state, 0, leaders.clone());
+        chain_state
+            .verify_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+
+        // the first block after the crucial slot moves the stake to the
+        // stakeholder 3
+        let slot_id = EpochSlotId {
+            epoch: 0,
+            slotid: 80,
+        };
+        let n = (1..3)
+            .find(|n| leaders[80] == address::StakeholderId::new(&xprv(*n).public()))
+            .unwrap();
+        let psk =
+            sign::ProxySecretKey::sign(&xprv(n), xprv(n + 10).public(), 0, genesis.protocol_magic);
+        let mut builder = builder::MainBlockBuilder::new(slot_id);
+        builder.add_tx(redeem_tx(&genesis, 1000));
+        let blk = builder.make_block(
+            &chain_state,
+            &builder::BlockIssuer::HeavyDelegate(&xprv(n + 10), psk),
+        );
+        let undo = chain_state
+            .apply_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+        assert_eq!(chain_state.stakes.leaders_stakes(1), &genesis_stakes);
+        assert_ne!(chain_state.stakes.stakes, genesis_stakes);
+        assert_eq!(chain_state.stakes.epoch_stakes, genesis_stakes);
+        assert_eq!(chain_state.ssc.richmen, genesis_richmen);
+        let before_revert = chain_state.clone();
+        chain_state.revert_block(undo);
+        assert_eq!(chain_state.stakes.crucial, None);
+        chain_state = before_revert;
+
+        // no secret was committed to: the genesis seed is kept, and the
+        // snapshot of the epoch and its richmen come from the stakes of
+        // the crucial slot
+        let blk = builder::make_boundary_block(&chain_state, 1, leaders);
+        let before_boundary = chain_state.clone();
+        let undo = chain_state
+            .apply_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+        assert_eq!(chain_state.stakes.epoch_stakes, genesis_stakes);
+        assert_eq!(chain_state.ssc.richmen, genesis_richmen);
+        chain_state.revert_block(undo);
+        assert_eq!(chain_state, before_boundary);
+    }
+
+    #[test]
+    fn invalid_blocks_are_not_applied() {
+        let genesis = genesis_data();
+        let mut chain_state = initial_chain_state(&genesis);
+        let (boundary_hash, boundary) = boundary_block(&genesis);
+        chain_state.apply_block(&boundary_hash, &boundary).unwrap();
+        let before = chain_state.clone();
+
+        // the outputs exceed the input
+        let (hash, blk) = main_block(&genesis, &boundary_hash, vec![redeem_tx(&genesis, 1001)]);
+        match chain_state.apply_block(&hash, &blk) {
+            Err(Error::OutputsExceedInputs) => {}
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+        assert_eq!(chain_state, before);
+
+        // while verify_block applies it anyway
+        assert!(chain_state.verify_block(&hash, &blk).is_err());
+        assert_eq!(chain_state.last_block, hash);
+        assert_ne!(chain_state.utxos, before.utxos);
+    }
+
+    #[test]
+    fn all_errors_are_collected() {
+        let genesis = genesis_data();
+        let mut chain_state = initial_chain_state(&genesis);
+        let (boundary_hash, boundary) = boundary_block(&genesis);
+        chain_state.apply_block(&boundary_hash, &boundary).unwrap();
+        let mut other_chain_state = chain_state.clone();
+
+        // the second transaction spends the input of the first one again
+        let tx = redeem_tx(&genesis, 1001);
+        let (hash, blk) = main_block(&genesis, &boundary_hash, vec![tx.clone(), tx]);
+        let errors = chain_state.verify_block_all_errors(&hash, &blk);
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|err| (err.location, format!("{:?}", err.error)))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (ErrorLocation::Tx(0), "OutputsExceedInputs".to_owned()),
+                (ErrorLocation::TxInput(1, 0), "MissingUtxo".to_owned()),
+                (ErrorLocation::Tx(1), "OutputsExceedInputs".to_owned()),
+                (ErrorLocation::TxOutput(1, 0), "DuplicateTxo".to_owned()),
+            ]
+        );
+
+        // the first error is the one reported by verify_block, which
+        // updates the chain state the same way
+        match other_chain_state.verify_block(&hash, &blk) {
+            Err(Error::OutputsExceedInputs) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(other_chain_state, chain_state);
+    }
+
+    #[test]
+    fn script_witnesses_are_rejected() {
+        let genesis = genesis_data();
+        let mut chain_state = initial_chain_state(&genesis);
+        let before = chain_state.clone();
+
+        let tx = redeem_tx(&genesis, 1000).tx;
+        let txaux = TxAux::new(tx, TxWitness::from(vec![TxInWitness::ScriptWitness(0, 0)]));
+        let mut utxo_changes = vec![];
+        let mut errors = vec![];
+        chain_state.verify_tx(3, &txaux, &mut utxo_changes, &mut errors);
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|err| (err.location, format!("{:?}", err.error)))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![(
+                ErrorLocation::TxWitness(3, 0),
+                "UnsupportedScriptWitness".to_owned()
+            )]
+        );
+        assert!(utxo_changes.is_empty());
+        assert_eq!(chain_state.utxos, before.utxos);
+
+        match chain_state.apply_tx(&txaux) {
+            Err(Error::UnsupportedScriptWitness) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn wrong_chain_difficulties_are_rejected() {
+        let genesis = genesis_data();
+        let mut chain_state = initial_chain_state(&genesis);
+        let before = chain_state.clone();
+
+        // a boundary block does not add to the difficulty
+        let (_, mut boundary) = boundary_block(&genesis);
+        if let Block::BoundaryBlock(bblk) = &mut boundary {
+            bblk.header.consensus.chain_difficulty = ChainDifficulty::from(1);
+        }
+        let hash = boundary.header().compute_hash();
+        match chain_state.apply_block(&hash, &boundary) {
+            Err(Error::WrongChainDifficulty) => {}
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+        assert_eq!(chain_state, before);
+
+        let (boundary_hash, boundary) = boundary_block(&genesis);
+        chain_state.apply_block(&boundary_hash, &boundary).unwrap();
+        let before = chain_state.clone();
+
+        // a correctly signed main block claiming one more main block
+        let (_, mut blk) = main_block(&genesis, &boundary_hash, vec![]);
+        if let Block::MainBlock(mblk) = &mut blk {
+            let header = &mut mblk.header;
+            header.consensus.chain_difficulty = ChainDifficulty::from(2);
+            header.consensus.block_signature =
+                sign::MainToSign::from_header(header).sign(genesis.protocol_magic, &xprv(1));
+        }
+        let hash = blk.header().compute_hash();
+        match chain_state.apply_block(&hash, &blk) {
+            Err(Error::WrongChainDifficulty) => {}
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+        assert_eq!(chain_state, before);
+    }
+
+    #[test]
+    fn attributes_and_header_size_are_checked() {
+        let genesis = genesis_data();
+        let mut chain_state = initial_chain_state(&genesis);
+        let (boundary_hash, boundary) = boundary_block(&genesis);
+        chain_state.apply_block(&boundary_hash, &boundary).unwrap();
+
+        // a block of the adopted version with an unknown attribute
+        let (_, mut blk) = main_block(&genesis, &boundary_hash, vec![]);
+        if let Block::MainBlock(mblk) = &mut blk {
+            let header = &mut mblk.header;
+            header
+                .extra_data
+                .attributes
+                .unknown
+                .insert(7, vec![1, 2, 3]);
+            header.consensus.block_signature =
+                sign::MainToSign::from_header(header).sign(genesis.protocol_magic, &xprv(1));
+        }
+        let hash = blk.header().compute_hash();
+        match chain_state.clone().apply_block(&hash, &blk) {
+            Err(Error::UnknownAttributes) => {}
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
+
+        let (hash, blk) = main_block(&genesis, &boundary_hash, vec![]);
+        chain_state.update.parameters.max_header_size = 100;
+        match chain_state.apply_block(&hash, &blk) {
+            Err(Error::HeaderTooBig) => {}
+            res => panic!("unexpected result {:?}", res.map(|_| ())),
+        }
     }
 }
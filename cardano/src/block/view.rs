@@ -0,0 +1,593 @@
+//! Borrowed views over raw blocks
+//!
+//! `RawBlock::decode` allocates the whole block: every transaction,
+//! witness and attribute. The views here only record where the different
+//! parts of the block are in the raw data, so it is cheap to get the hash
+//! of a header or the identifiers of the transactions of a block:
+//!
+//! ```
+//! # extern crate cardano;
+//! # extern crate cbor_event;
+//! # use cardano::block::{view::BlockView, RawBlock};
+//! # fn index(raw: &RawBlock) -> cbor_event::Result<()> {
+//! let block = BlockView::new(raw.as_ref())?;
+//! println!("block {}", block.header().compute_hash());
+//! for txaux in block.transactions() {
+//!     println!("  tx {}", txaux?.id());
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+//!
+//! The parts of the block that are not needed by the views (SSC, delegation
+//! and update payloads...) are skipped over without being validated. Use
+//! `RawBlock::decode` to fully validate a block.
+
+use std::convert::TryFrom;
+use std::io::Cursor;
+
+use cbor_event::{self, de::Deserializer};
+use cryptoxide::{blake2b::Blake2b, digest::Digest};
+
+use super::block::{BlockHeader, RawBlockHeader};
+use super::date::BlockDate;
+use super::types::{ChainDifficulty, EpochSlotId, HeaderHash};
+use cbor::{
+    preserved::Preserved,
+    span::{ArrayReader, Reader},
+};
+use config::ProtocolMagic;
+use hash::Blake2b256;
+use tx::{Tx, TxAux, TxId, TxInWitness};
+
+const BOUNDARY_TAG: u64 = 0;
+const MAIN_TAG: u64 = 1;
+
+fn read_sum_type(reader: &mut Reader) -> cbor_event::Result<u64> {
+    reader.tuple(2, "SumType")?;
+    reader.unsigned_integer()
+}
+
+/// read an unsigned integer, failing if it is out of the range of `T`
+fn read_unsigned<T: TryFrom<u64>>(reader: &mut Reader, name: &str) -> cbor_event::Result<T> {
+    let n = reader.unsigned_integer()?;
+    T::try_from(n)
+        .map_err(|_| cbor_event::Error::CustomError(format!("{} out of range: {}", name, n)))
+}
+
+fn check_complete(reader: &Reader) -> cbor_event::Result<()> {
+    if reader.is_empty() {
+        Ok(())
+    } else {
+        Err(cbor_event::Error::CustomError(format!(
+            "{} bytes of trailing data",
+            reader.remaining().len()
+        )))
+    }
+}
+
+/// Borrowed view of a block header (boundary or normal)
+#[derive(Debug, Clone)]
+pub struct HeaderView<'a> {
+    tag: u64,
+    header: &'a [u8],
+    protocol_magic: ProtocolMagic,
+    previous_header: HeaderHash,
+    date: BlockDate,
+    difficulty: ChainDifficulty,
+}
+impl<'a> HeaderView<'a> {
+    /// view of the header in the given `RawBlockHeader` data
+    pub fn new(raw: &'a [u8]) -> cbor_event::Result<Self> {
+        let mut reader = Reader::new(raw);
+        let tag = read_sum_type(&mut reader)?;
+        let view = Self::read(tag, &mut reader)?;
+        check_complete(&reader)?;
+        Ok(view)
+    }
+
+    fn read(tag: u64, reader: &mut Reader<'a>) -> cbor_event::Result<Self> {
+        let start = reader.offset();
+        reader.tuple(5, "BlockHeader")?;
+        let protocol_magic = ProtocolMagic::from(read_unsigned::<u32>(reader, "ProtocolMagic")?);
+        let previous_header = {
+            let bytes = reader.bytes()?;
+            if bytes.len() != Blake2b256::HASH_SIZE {
+                return Err(cbor_event::Error::NotEnough(
+                    bytes.len(),
+                    Blake2b256::HASH_SIZE,
+                ));
+            }
+            let mut hash = [0; Blake2b256::HASH_SIZE];
+            hash.copy_from_slice(bytes);
+            HeaderHash::from(hash)
+        };
+        let _body_proof = reader.item()?;
+        let (date, difficulty) = match tag {
+            BOUNDARY_TAG => {
+                reader.tuple(2, "Consensus")?;
+                let epoch = reader.unsigned_integer()?;
+                let difficulty = read_difficulty(reader)?;
+                (BlockDate::Boundary(epoch), difficulty)
+            }
+            MAIN_TAG => {
+                reader.tuple(4, "Consensus")?;
+                reader.tuple(2, "SlotId")?;
+                let epoch = reader.unsigned_integer()?;
+                let slotid = read_unsigned(reader, "SlotId")?;
+                let _leader_key = reader.item()?;
+                let difficulty = read_difficulty(reader)?;
+                let _block_signature = reader.item()?;
+                (BlockDate::Normal(EpochSlotId { epoch, slotid }), difficulty)
+            }
+            tag => {
+                return Err(cbor_event::Error::CustomError(format!(
+                    "Unsupported BlockHeader: {}",
+                    tag
+                )))
+            }
+        };
+        let _extra_data = reader.item()?;
+
+        Ok(HeaderView {
+            tag,
+            header: reader.span_from(start),
+            protocol_magic,
+            previous_header,
+            date,
+            difficulty,
+        })
+    }
+
+    pub fn is_boundary_block(&self) -> bool {
+        self.tag == BOUNDARY_TAG
+    }
+
+    /// the CBOR of the boundary or normal header (without the sum type
+    /// wrapping it)
+    pub fn as_slice(&self) -> &'a [u8] {
+        self.header
+    }
+
+    pub fn protocol_magic(&self) -> ProtocolMagic {
+        self.protocol_magic
+    }
+
+    pub fn previous_header(&self) -> &HeaderHash {
+        &self.previous_header
+    }
+
+    pub fn date(&self) -> BlockDate {
+        self.date
+    }
+
+    pub fn difficulty(&self) -> ChainDifficulty {
+        self.difficulty
+    }
+
+    /// Computes the hash of the block header, same as
+    /// `BlockHeader::compute_hash` but without allocating.
+    pub fn compute_hash(&self) -> HeaderHash {
+        // the hash is computed over the sum type: `[tag, header]`
+        let mut hasher = Blake2b::new(Blake2b256::HASH_SIZE);
+        hasher.input(&[0x82, self.tag as u8]);
+        hasher.input(self.header);
+        let mut out = [0; Blake2b256::HASH_SIZE];
+        hasher.result(&mut out);
+        HeaderHash::from(out)
+    }
+
+    pub fn to_raw(&self) -> RawBlockHeader {
+        let mut bytes = Vec::with_capacity(self.header.len() + 2);
+        bytes.extend_from_slice(&[0x82, self.tag as u8]);
+        bytes.extend_from_slice(self.header);
+        RawBlockHeader(bytes)
+    }
+
+    /// fully decode the header
+    pub fn decode(&self) -> cbor_event::Result<BlockHeader> {
+        self.to_raw().decode()
+    }
+}
+
+fn read_difficulty(reader: &mut Reader) -> cbor_event::Result<ChainDifficulty> {
+    reader.tuple(1, "ChainDifficulty")?;
+    Ok(ChainDifficulty::from(reader.unsigned_integer()?))
+}
+
+/// Borrowed view of a block (boundary or normal)
+#[derive(Debug, Clone)]
+pub struct BlockView<'a> {
+    header: HeaderView<'a>,
+    tx_payload: Option<&'a [u8]>,
+}
+impl<'a> BlockView<'a> {
+    /// view of the block in the given `RawBlock` data
+    pub fn new(raw: &'a [u8]) -> cbor_event::Result<Self> {
+        let mut reader = Reader::new(raw);
+        let tag = read_sum_type(&mut reader)?;
+        reader.tuple(3, "Block")?;
+        let header = HeaderView::read(tag, &mut reader)?;
+        let tx_payload = if header.is_boundary_block() {
+            let _slot_leaders = reader.item()?;
+            None
+        } else {
+            reader.tuple(4, "Body")?;
+            let tx_payload = reader.item()?;
+            let _ssc = reader.item()?;
+            let _delegation = reader.item()?;
+            let _update = reader.item()?;
+            Some(tx_payload)
+        };
+        let _extra = reader.item()?;
+        check_complete(&reader)?;
+
+        Ok(BlockView { header, tx_payload })
+    }
+
+    pub fn is_boundary_block(&self) -> bool {
+        self.header.is_boundary_block()
+    }
+
+    pub fn header(&self) -> &HeaderView<'a> {
+        &self.header
+    }
+
+    /// iterate over the transactions of the block (none for boundary
+    /// blocks)
+    pub fn transactions(&self) -> TxAuxIter<'a> {
+        let reader = match self.tx_payload {
+            None => Ok(ArrayReader::empty()),
+            Some(payload) => ArrayReader::new(payload),
+        };
+        match reader {
+            Ok(reader) => TxAuxIter {
+                reader,
+                error: None,
+            },
+            Err(err) => TxAuxIter {
+                reader: ArrayReader::empty(),
+                error: Some(err),
+            },
+        }
+    }
+}
+
+/// Borrowed view of a transaction and its witnesses
+#[derive(Debug, Clone)]
+pub struct TxAuxView<'a> {
+    txaux: &'a [u8],
+    tx: &'a [u8],
+    witness: &'a [u8],
+}
+impl<'a> TxAuxView<'a> {
+    /// view of the given `TxAux` data
+    pub fn new(raw: &'a [u8]) -> cbor_event::Result<Self> {
+        let mut reader = Reader::new(raw);
+        let view = Self::read(&mut reader)?;
+        check_complete(&reader)?;
+        Ok(view)
+    }
+
+    fn read(reader: &mut Reader<'a>) -> cbor_event::Result<Self> {
+        let start = reader.offset();
+        reader.tuple(2, "TxAux")?;
+        let tx = reader.item()?;
+        let witness = reader.item()?;
+        Ok(TxAuxView {
+            txaux: reader.span_from(start),
+            tx,
+            witness,
+        })
+    }
+
+    /// the CBOR of the whole `TxAux`
+    pub fn as_slice(&self) -> &'a [u8] {
+        self.txaux
+    }
+
+    /// the CBOR of the transaction
+    pub fn tx(&self) -> &'a [u8] {
+        self.tx
+    }
+
+    /// the CBOR of the witnesses of the transaction
+    pub fn witness(&self) -> &'a [u8] {
+        self.witness
+    }
+
+    /// compute the identifier of the transaction
+    pub fn id(&self) -> TxId {
+        TxId::new(self.tx)
+    }
+
+    /// iterate over the CBOR of each witness of the transaction
+    pub fn witnesses(&self) -> cbor_event::Result<WitnessIter<'a>> {
+        Ok(WitnessIter(ArrayReader::new(self.witness)?))
+    }
+
+    /// decode the transaction, keeping its raw data
+    pub fn decode_tx(&self) -> cbor_event::Result<Preserved<Tx>> {
+        Preserved::decode(self.tx.to_vec())
+    }
+
+    /// fully decode the transaction and its witnesses
+    pub fn decode(&self) -> cbor_event::Result<TxAux> {
+        Deserializer::from(Cursor::new(self.txaux)).deserialize_complete()
+    }
+}
+
+/// iterator over the transactions of a block, see `BlockView::transactions`
+#[derive(Debug)]
+pub struct TxAuxIter<'a> {
+    reader: ArrayReader<'a>,
+    error: Option<cbor_event::Error>,
+}
+impl<'a> Iterator for TxAuxIter<'a> {
+    type Item = cbor_event::Result<TxAuxView<'a>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
+        self.reader.next_with(TxAuxView::read)
+    }
+}
+
+/// iterator over the witnesses of a transaction, see
+/// `TxAuxView::witnesses`
+#[derive(Debug, Clone)]
+pub struct WitnessIter<'a>(ArrayReader<'a>);
+impl<'a> WitnessIter<'a> {
+    /// decode the next witness
+    pub fn next_decoded(&mut self) -> Option<cbor_event::Result<TxInWitness>> {
+        self.0.next().map(|witness| {
+            witness.and_then(|bytes| Deserializer::from(Cursor::new(bytes)).deserialize_complete())
+        })
+    }
+}
+impl<'a> Iterator for WitnessIter<'a> {
+    type Item = cbor_event::Result<&'a [u8]>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{boundary, normal, types, update, Block};
+    use super::*;
+    use address::ExtendedAddr;
+    use coin::Coin;
+    use config::NetworkMagic;
+    use hdwallet::{self, XPrv};
+    use tx::{TxOut, TxWitness, TxoPointer};
+
+    fn xprv(seed: u8) -> XPrv {
+        XPrv::generate_from_seed(&hdwallet::Seed::from_bytes([seed; hdwallet::SEED_SIZE]))
+    }
+
+    fn txaux(pm: ProtocolMagic, n: u8) -> TxAux {
+        let key = xprv(n);
+        let tx = Tx::new_with(
+            vec![TxoPointer::new(TxId::new(&[n]), u32::from(n))],
+            vec![TxOut::new(
+                ExtendedAddr::new_simple(key.public(), NetworkMagic::from(*pm)),
+                Coin::new(u64::from(n) * 1000).unwrap(),
+            )],
+        );
+        let witness = TxInWitness::new_extended_pk(pm, &key, &tx.id());
+        TxAux::new(tx, TxWitness::from(vec![witness]))
+    }
+
+    fn empty_attributes() -> types::BlockHeaderAttributes {
+        types::BlockHeaderAttributes::new()
+    }
+
+    fn boundary_block(pm: ProtocolMagic) -> Block {
+        let header = boundary::BlockHeader::new(
+            pm,
+            HeaderHash::new(b"previous"),
+            boundary::BodyProof(Blake2b256::new(b"body")),
+            boundary::Consensus {
+                epoch: 12,
+                chain_difficulty: ChainDifficulty::from(4200),
+            },
+            types::ExtraData::default(),
+        );
+        Block::BoundaryBlock(boundary::Block {
+            header,
+            body: boundary::Body {
+                slot_leaders: vec![],
+            },
+            extra: types::ExtraData::default(),
+        })
+    }
+
+    fn main_block(pm: ProtocolMagic, txs: Vec<TxAux>) -> Block {
+        let leader = xprv(42);
+        let body = normal::Body::new(
+            normal::TxPayload::new(txs),
+            normal::SscPayload::CertificatesPayload(normal::VssCertificates::new(vec![])),
+            normal::DlgPayload(vec![]),
+            update::UpdatePayload {
+                proposal: None,
+                votes: vec![],
+            },
+        );
+        let header = normal::BlockHeader::new(
+            pm,
+            HeaderHash::new(b"previous"),
+            normal::BodyProof::generate_from_body(&body),
+            normal::Consensus {
+                slot_id: EpochSlotId {
+                    epoch: 3,
+                    slotid: 1234,
+                },
+                leader_key: leader.public(),
+                chain_difficulty: ChainDifficulty::from(99),
+                block_signature: super::super::sign::BlockSignature::Signature(
+                    leader.sign(b"not verified"),
+                ),
+            },
+            types::HeaderExtraData::new(
+                types::BlockVersion::new(0, 1, 0),
+                types::SoftwareVersion::new("cardano-sl", 1).unwrap(),
+                empty_attributes(),
+                Blake2b256::new(b"extra"),
+            ),
+        );
+        Block::MainBlock(normal::Block::new(
+            header,
+            body,
+            types::ExtraData::default(),
+        ))
+    }
+
+    fn check_header(view: &HeaderView, block: &Block) {
+        let header = block.header();
+        assert_eq!(view.compute_hash(), header.compute_hash());
+        assert_eq!(view.to_raw().as_ref(), &cbor!(&header).unwrap()[..]);
+        assert_eq!(view.is_boundary_block(), block.is_boundary_block());
+        assert_eq!(view.protocol_magic(), block.get_protocol_magic());
+        assert_eq!(u64::from(view.difficulty()), u64::from(header.difficulty()));
+        assert_eq!(
+            view.previous_header(),
+            &chain_core::property::Block::parent_id(block)
+        );
+        assert_eq!(view.date(), chain_core::property::Block::date(block));
+    }
+
+    /// replace the only occurrence of `from` in `bytes` by `to`
+    fn patch(bytes: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+        let positions: Vec<_> = bytes
+            .windows(from.len())
+            .enumerate()
+            .filter(|(_, window)| *window == from)
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(positions.len(), 1);
+        let i = positions[0];
+        [&bytes[..i], to, &bytes[i + from.len()..]].concat()
+    }
+
+    #[test]
+    fn out_of_range_integers_are_rejected() {
+        let block = main_block(ProtocolMagic::from(0x0102_0304), vec![]);
+        let raw = cbor!(&block.header()).unwrap();
+        HeaderView::new(&raw).unwrap();
+
+        // the slot [3, 1234] becomes [3, 66770]
+        let raw_slot = patch(
+            &raw,
+            &[0x82, 0x03, 0x19, 0x04, 0xd2],
+            &[0x82, 0x03, 0x1a, 0x00, 0x01, 0x04, 0xd2],
+        );
+        match HeaderView::new(&raw_slot) {
+            Err(cbor_event::Error::CustomError(ref err)) if err == "SlotId out of range: 66770" => {
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // the protocol magic becomes 0x1_0102_0304
+        let raw_magic = patch(
+            &raw,
+            &[0x1a, 0x01, 0x02, 0x03, 0x04],
+            &[0x1b, 0x00, 0x00, 0x00, 0x01, 0x01, 0x02, 0x03, 0x04],
+        );
+        match HeaderView::new(&raw_magic) {
+            Err(cbor_event::Error::CustomError(ref err)) if err.starts_with("ProtocolMagic") => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn boundary_block_view() {
+        let block = boundary_block(ProtocolMagic::default());
+        let raw = cbor!(&block).unwrap();
+
+        let view = BlockView::new(&raw).unwrap();
+        check_header(view.header(), &block);
+        assert_eq!(view.transactions().count(), 0);
+
+        let raw_header = view.header().to_raw();
+        let header = HeaderView::new(raw_header.as_ref()).unwrap();
+        assert_eq!(header.compute_hash(), raw_header.compute_hash());
+        assert!(header.decode().unwrap().is_boundary_block());
+    }
+
+    #[test]
+    fn main_block_view() {
+        let pm = ProtocolMagic::from(1097911063);
+        let txs = (1..4).map(|n| txaux(pm, n)).collect::<Vec<_>>();
+        let block = main_block(pm, txs.clone());
+        let raw = cbor!(&block).unwrap();
+
+        let view = BlockView::new(&raw).unwrap();
+        check_header(view.header(), &block);
+
+        let views = view
+            .transactions()
+            .collect::<cbor_event::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(views.len(), txs.len());
+        for (view, txaux) in views.iter().zip(txs.iter()) {
+            assert_eq!(view.id(), txaux.tx.id());
+            assert_eq!(view.as_slice(), &cbor!(txaux).unwrap()[..]);
+            assert_eq!(view.tx(), &cbor!(&txaux.tx).unwrap()[..]);
+
+            let witnesses = view
+                .witnesses()
+                .unwrap()
+                .collect::<cbor_event::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(witnesses.len(), txaux.witness.len());
+            for (bytes, witness) in witnesses.iter().zip(txaux.witness.iter()) {
+                assert_eq!(*bytes, &cbor!(witness).unwrap()[..]);
+            }
+            let mut witnesses = view.witnesses().unwrap();
+            let witness = witnesses.next_decoded().unwrap().unwrap();
+            assert!(witness.verify_tx(pm, &txaux.tx));
+            assert!(witnesses.next_decoded().is_none());
+        }
+    }
+
+    #[test]
+    fn invalid_blocks_are_rejected() {
+        let block = main_block(ProtocolMagic::default(), vec![]);
+        let raw = cbor!(&block).unwrap();
+        for len in 0..raw.len() {
+            assert!(BlockView::new(&raw[..len]).is_err());
+        }
+
+        let mut trailing = raw.clone();
+        trailing.push(0);
+        assert!(BlockView::new(&trailing).is_err());
+
+        let mut wrong_tag = raw.clone();
+        wrong_tag[1] = 2;
+        assert!(BlockView::new(&wrong_tag).is_err());
+    }
+
+    #[test]
+    fn invalid_transaction_is_reported_once() {
+        let pm = ProtocolMagic::default();
+        let block = main_block(pm, vec![txaux(pm, 1)]);
+        let mut raw = cbor!(&block).unwrap();
+
+        // turn the `TxAux` tuple into an array of 3 elements
+        let txaux = cbor!(&txaux(pm, 1)).unwrap();
+        let offset = raw
+            .windows(txaux.len())
+            .position(|window| window == &txaux[..])
+            .unwrap();
+        raw[offset] = 0x83;
+        raw.insert(offset + txaux.len(), 0x00);
+        // the block body is skipped over, not validated
+        let view = BlockView::new(&raw).unwrap();
+
+        let mut txs = view.transactions();
+        assert!(txs.next().unwrap().is_err());
+        assert!(txs.next().is_none());
+    }
+}
@@ -0,0 +1,271 @@
+//! Canonical CBOR
+//!
+//! The `Deserialize` implementations of the library are lenient: they
+//! accept non-minimal integers, maps in any order and, in many places,
+//! indefinite lengths where the Haskell node only emits definite ones.
+//! Since most of the hashes of the protocol are computed over the
+//! serialized objects, a peer could send the same object with different
+//! bytes.
+//!
+//! The strict mode of this module only accepts the canonical encoding:
+//!
+//! * every integer, length and tag is encoded in its shortest form;
+//! * map keys are sorted (shortest first, then bytewise) and unique;
+//! * strings and maps have a definite length;
+//! * re-encoding the decoded object gives back the exact same bytes
+//!   (this is what catches indefinite arrays where definite ones are
+//!   expected, and the other way around).
+//!
+//! Note that the last rule means the strict mode also rejects data the
+//! library does not fully preserve when decoding.
+
+use std::io::Cursor;
+
+use cbor_event::{self, de::Deserializer, Type};
+
+use super::span::{Reader, MAX_DEPTH};
+
+fn non_canonical(offset: usize, reason: &str) -> cbor_event::Error {
+    cbor_event::Error::CustomError(format!(
+        "Non canonical CBOR at offset {}: {}",
+        offset, reason
+    ))
+}
+
+/// check the given argument was encoded in the smallest possible
+/// header (`size` bytes, including the initial byte)
+fn is_minimal(arg: u64, size: usize) -> bool {
+    match size {
+        1 => true,
+        2 => arg >= 24,
+        3 => arg > 0xff,
+        5 => arg > 0xffff,
+        _ => arg > 0xffff_ffff,
+    }
+}
+
+/// the canonical ordering of map keys: shortest first, then bytewise
+fn key_is_after(previous: &[u8], key: &[u8]) -> bool {
+    (key.len(), key) > (previous.len(), previous)
+}
+
+fn check_item(reader: &mut Reader, depth: usize) -> cbor_event::Result<()> {
+    if depth > MAX_DEPTH {
+        return Err(non_canonical(reader.offset(), "too deeply nested"));
+    }
+    let start = reader.offset();
+    let mut header = reader.clone();
+    let (ty, arg) = header.header()?;
+    let size = header.offset() - start;
+
+    match (ty, arg) {
+        (Type::Special, Some(arg)) => {
+            if size == 2 && arg < 32 {
+                return Err(non_canonical(start, "non minimal simple value"));
+            }
+            *reader = header;
+            return Ok(());
+        }
+        (_, Some(arg)) if !is_minimal(arg, size) => {
+            return Err(non_canonical(start, "non minimal encoding"));
+        }
+        _ => {}
+    }
+
+    match (ty, arg) {
+        (Type::UnsignedInteger, _) | (Type::NegativeInteger, _) => *reader = header,
+        (Type::Bytes, Some(_)) | (Type::Text, Some(_)) => {
+            reader.item()?;
+        }
+        (Type::Bytes, None) | (Type::Text, None) => {
+            return Err(non_canonical(start, "indefinite length string"));
+        }
+        (Type::Map, None) => return Err(non_canonical(start, "indefinite length map")),
+        (Type::Array, Some(len)) => {
+            *reader = header;
+            for _ in 0..len {
+                check_item(reader, depth + 1)?;
+            }
+        }
+        (Type::Array, None) => {
+            *reader = header;
+            while !reader.is_break()? {
+                check_item(reader, depth + 1)?;
+            }
+            reader.special_break()?;
+        }
+        (Type::Map, Some(len)) => {
+            *reader = header;
+            let mut previous: Option<&[u8]> = None;
+            for _ in 0..len {
+                let key_start = reader.offset();
+                check_item(reader, depth + 1)?;
+                let key = reader.span_from(key_start);
+                if let Some(previous) = previous {
+                    if !key_is_after(previous, key) {
+                        return Err(non_canonical(key_start, "unsorted or duplicated map key"));
+                    }
+                }
+                previous = Some(key);
+                check_item(reader, depth + 1)?;
+            }
+        }
+        (Type::Tag, _) => {
+            *reader = header;
+            check_item(reader, depth + 1)?;
+        }
+        (Type::Special, None) => return Err(non_canonical(start, "unexpected break")),
+        (Type::Special, Some(_)) => unreachable!(),
+    }
+    Ok(())
+}
+
+/// check the given data is a single, canonically encoded, CBOR item.
+///
+/// This only checks the rules that do not depend on the type of the
+/// encoded object, see `decode` for the complete check.
+pub fn check(bytes: &[u8]) -> cbor_event::Result<()> {
+    let mut reader = Reader::new(bytes);
+    check_item(&mut reader, 0)?;
+    if !reader.is_empty() {
+        return Err(non_canonical(reader.offset(), "trailing data"));
+    }
+    Ok(())
+}
+
+/// decode the given data, only accepting its canonical encoding
+pub fn decode<T>(bytes: &[u8]) -> cbor_event::Result<T>
+where
+    T: cbor_event::de::Deserialize + cbor_event::se::Serialize,
+{
+    check(bytes)?;
+    let value: T = Deserializer::from(Cursor::new(bytes)).deserialize_complete()?;
+
+    let encoded = cbor!(&value)?;
+    if encoded != bytes {
+        let offset = encoded
+            .iter()
+            .zip(bytes.iter())
+            .position(|(a, b)| a != b)
+            .unwrap_or_else(|| encoded.len().min(bytes.len()));
+        return Err(non_canonical(
+            offset,
+            "does not match the encoding of the decoded value",
+        ));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cbor_event::Value;
+    use std::collections::BTreeMap;
+
+    fn expect_non_canonical(bytes: &[u8], offset: usize) {
+        match check(bytes) {
+            Err(cbor_event::Error::CustomError(ref msg))
+                if msg.starts_with(&format!("Non canonical CBOR at offset {}:", offset)) => {}
+            res => panic!("{:?}: unexpected result {:?}", bytes, res),
+        }
+    }
+
+    #[test]
+    fn canonical_values_are_accepted() {
+        let mut object = BTreeMap::new();
+        object.insert(cbor_event::ObjectKey::Integer(1), Value::U64(24));
+        object.insert(cbor_event::ObjectKey::Integer(1000), Value::U64(256));
+        object.insert(
+            cbor_event::ObjectKey::Bytes(vec![1, 2]),
+            Value::U64(0x1_0000),
+        );
+        let values = vec![
+            Value::U64(0),
+            Value::U64(23),
+            Value::U64(0x1_0000_0000),
+            Value::I64(-1),
+            Value::Bytes(vec![0; 24]),
+            Value::Text("cardano".to_owned()),
+            Value::Array(vec![Value::U64(1), Value::IArray(vec![])]),
+            Value::Object(object),
+            Value::Tag(24, Box::new(Value::Bytes(vec![0x80]))),
+            Value::Special(cbor_event::Special::Bool(false)),
+        ];
+        for value in values {
+            let bytes = cbor!(&value).unwrap();
+            check(&bytes).unwrap();
+            let decoded: Value = decode(&bytes).unwrap();
+            assert_eq!(cbor!(&decoded).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn non_minimal_encodings_are_rejected() {
+        expect_non_canonical(&[0x18, 0x17], 0);
+        expect_non_canonical(&[0x19, 0x00, 0xff], 0);
+        expect_non_canonical(&[0x1a, 0x00, 0x00, 0xff, 0xff], 0);
+        expect_non_canonical(&[0x1b, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff], 0);
+        expect_non_canonical(&[0x38, 0x00], 0);
+        expect_non_canonical(&[0x82, 0x00, 0x58, 0x01, 0xaa], 2);
+        expect_non_canonical(&[0x98, 0x01, 0x00], 0);
+        expect_non_canonical(&[0xd8, 0x02, 0x00], 0);
+        expect_non_canonical(&[0xf8, 0x14], 0);
+    }
+
+    #[test]
+    fn map_keys_must_be_sorted() {
+        expect_non_canonical(&[0xa2, 0x02, 0x00, 0x01, 0x00], 3);
+        expect_non_canonical(&[0xa2, 0x01, 0x00, 0x01, 0x00], 3);
+        // shorter keys first
+        expect_non_canonical(&[0xa2, 0x18, 0x18, 0x00, 0x01, 0x00], 4);
+        check(&[0xa2, 0x01, 0x00, 0x18, 0x18, 0x00]).unwrap();
+    }
+
+    #[test]
+    fn indefinite_strings_and_maps_are_rejected() {
+        expect_non_canonical(&[0x5f, 0x41, 0x00, 0xff], 0);
+        expect_non_canonical(&[0x7f, 0x61, 0x61, 0xff], 0);
+        expect_non_canonical(&[0xbf, 0xff], 0);
+        check(&[0x9f, 0x01, 0xff]).unwrap();
+    }
+
+    #[test]
+    fn trailing_data_is_rejected() {
+        expect_non_canonical(&[0x01, 0x02], 1);
+    }
+
+    #[test]
+    fn decode_checks_the_reencoding() {
+        use cbor_event::{de::Deserialize, se::Serialize, se::Serializer};
+        use std::io::{BufRead, Write};
+
+        // accepts `n` or `[n]` but is always encoded as `n`
+        #[derive(Debug, PartialEq)]
+        struct Number(u64);
+        impl Deserialize for Number {
+            fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+                if raw.cbor_type()? == Type::Array {
+                    raw.tuple(1, "Number")?;
+                }
+                Ok(Number(raw.unsigned_integer()?))
+            }
+        }
+        impl Serialize for Number {
+            fn serialize<'se, W: Write>(
+                &self,
+                serializer: &'se mut Serializer<W>,
+            ) -> cbor_event::Result<&'se mut Serializer<W>> {
+                serializer.write_unsigned_integer(self.0)
+            }
+        }
+
+        let lenient = [0x81, 0x05];
+        check(&lenient).unwrap();
+        let number: Number = Deserializer::from(Cursor::new(&lenient))
+            .deserialize_complete()
+            .unwrap();
+        assert_eq!(number, Number(5));
+        assert!(decode::<Number>(&lenient).is_err());
+        assert_eq!(decode::<Number>(&[0x05]).unwrap(), Number(5));
+    }
+}
@@ -1 +1,4 @@
+pub mod canonical;
 pub mod hs;
+pub mod preserved;
+pub mod span;
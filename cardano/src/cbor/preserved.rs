@@ -0,0 +1,98 @@
+//! Decoded values along with their original encoding
+//!
+//! Hashing a decoded object means re-serializing it, which only gives back
+//! the received bytes if they were canonically encoded (see
+//! [`canonical`](../canonical/index.html)). A `Preserved` value keeps
+//! the bytes it was decoded from so the hashes (`TxId`, `HeaderHash`...)
+//! are always computed over the exact received bytes.
+
+use std::{
+    io::{Cursor, Write},
+    ops::Deref,
+};
+
+use cbor_event::{
+    self,
+    de::{Deserialize, Deserializer},
+    se::{Serialize, Serializer},
+};
+
+use super::canonical;
+use hash::Blake2b256;
+
+#[derive(Debug, Clone)]
+pub struct Preserved<T> {
+    value: T,
+    bytes: Vec<u8>,
+}
+impl<T: Deserialize> Preserved<T> {
+    /// decode the given bytes, accepting any valid encoding
+    pub fn decode(bytes: Vec<u8>) -> cbor_event::Result<Self> {
+        let value = Deserializer::from(Cursor::new(&bytes)).deserialize_complete()?;
+        Ok(Preserved { value, bytes })
+    }
+}
+impl<T: Deserialize + Serialize> Preserved<T> {
+    /// decode the given bytes, only accepting the canonical encoding
+    pub fn decode_canonical(bytes: Vec<u8>) -> cbor_event::Result<Self> {
+        let value = canonical::decode(&bytes)?;
+        Ok(Preserved { value, bytes })
+    }
+}
+impl<T> Preserved<T> {
+    /// the bytes the value was decoded from
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// hash of the original bytes
+    pub fn hash(&self) -> Blake2b256 {
+        Blake2b256::new(&self.bytes)
+    }
+}
+impl<T> Deref for Preserved<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+impl<T> AsRef<[u8]> for Preserved<T> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+impl<T> Serialize for Preserved<T> {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut Serializer<W>,
+    ) -> cbor_event::Result<&'se mut Serializer<W>> {
+        serializer.write_raw_bytes(&self.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn original_bytes_are_kept() {
+        // `1` encoded on 2 bytes
+        let bytes = vec![0x18, 0x01];
+        let preserved = Preserved::<u64>::decode(bytes.clone()).unwrap();
+        assert_eq!(*preserved, 1);
+        assert_eq!(preserved.as_bytes(), &bytes[..]);
+        assert_eq!(preserved.hash(), Blake2b256::new(&bytes));
+        assert_ne!(
+            preserved.hash(),
+            Blake2b256::new(&cbor!(&*preserved).unwrap())
+        );
+        assert_eq!(cbor!(&preserved).unwrap(), bytes);
+
+        assert!(Preserved::<u64>::decode_canonical(bytes).is_err());
+        assert_eq!(*Preserved::<u64>::decode_canonical(vec![0x01]).unwrap(), 1);
+    }
+}
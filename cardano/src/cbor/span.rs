@@ -0,0 +1,440 @@
+//! Borrowed CBOR reader
+//!
+//! Walk CBOR encoded data in place: instead of decoding the objects,
+//! the reader returns the byte spans of the items it skips over. This
+//! is useful to hash or to index sub-objects (headers, transactions...)
+//! without allocating their decoded representation.
+
+use cbor_event::{self, Len, Type};
+
+/// maximum nesting of arrays, maps and tags the reader accepts
+pub const MAX_DEPTH: usize = 64;
+
+const BREAK: u8 = 0xff;
+
+fn major_type(byte: u8) -> Type {
+    match byte >> 5 {
+        0 => Type::UnsignedInteger,
+        1 => Type::NegativeInteger,
+        2 => Type::Bytes,
+        3 => Type::Text,
+        4 => Type::Array,
+        5 => Type::Map,
+        6 => Type::Tag,
+        _ => Type::Special,
+    }
+}
+
+/// reader over a borrowed slice of CBOR encoded data
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data, offset: 0 }
+    }
+
+    /// number of bytes already read
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// the bytes that have not been read yet
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.offset..]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset == self.data.len()
+    }
+
+    /// the span of data between the given offset and the current position
+    pub fn span_from(&self, start: usize) -> &'a [u8] {
+        &self.data[start..self.offset]
+    }
+
+    fn peek_byte(&self) -> cbor_event::Result<u8> {
+        match self.data.get(self.offset) {
+            Some(byte) => Ok(*byte),
+            None => Err(cbor_event::Error::NotEnough(0, 1)),
+        }
+    }
+
+    fn advance(&mut self, len: u64) -> cbor_event::Result<&'a [u8]> {
+        let remaining = self.data.len() - self.offset;
+        if len > remaining as u64 {
+            return Err(cbor_event::Error::NotEnough(remaining, len as usize));
+        }
+        let start = self.offset;
+        self.offset += len as usize;
+        Ok(&self.data[start..self.offset])
+    }
+
+    /// read the header of the next item: its type and its argument
+    /// (`None` for indefinite length items)
+    pub fn header(&mut self) -> cbor_event::Result<(Type, Option<u64>)> {
+        let byte = self.peek_byte()?;
+        let ty = major_type(byte);
+        let info = byte & 0b0001_1111;
+        let len = match info {
+            0..=23 => {
+                self.offset += 1;
+                return Ok((ty, Some(u64::from(info))));
+            }
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            31 => match ty {
+                Type::Bytes | Type::Text | Type::Array | Type::Map | Type::Special => {
+                    self.offset += 1;
+                    return Ok((ty, None));
+                }
+                _ => return Err(cbor_event::Error::UnknownLenType(info)),
+            },
+            _ => return Err(cbor_event::Error::UnknownLenType(info)),
+        };
+        let start = self.offset;
+        self.offset += 1;
+        match self.advance(len) {
+            Ok(bytes) => Ok((
+                ty,
+                Some(bytes.iter().fold(0, |acc, b| (acc << 8) | u64::from(*b))),
+            )),
+            Err(err) => {
+                self.offset = start;
+                Err(err)
+            }
+        }
+    }
+
+    fn expect_header(&mut self, expected: Type) -> cbor_event::Result<Option<u64>> {
+        let start = self.offset;
+        let (ty, arg) = self.header()?;
+        if ty != expected {
+            self.offset = start;
+            return Err(cbor_event::Error::Expected(expected, ty));
+        }
+        Ok(arg)
+    }
+
+    /// the type of the next item, without consuming it
+    pub fn cbor_type(&self) -> cbor_event::Result<Type> {
+        self.peek_byte().map(major_type)
+    }
+
+    /// check if the next item is the `break` of an indefinite length
+    /// item, without consuming it
+    pub fn is_break(&self) -> cbor_event::Result<bool> {
+        self.peek_byte().map(|byte| byte == BREAK)
+    }
+
+    /// consume the `break` of an indefinite length item
+    pub fn special_break(&mut self) -> cbor_event::Result<()> {
+        let byte = self.peek_byte()?;
+        if byte != BREAK {
+            return Err(cbor_event::Error::Expected(Type::Special, major_type(byte)));
+        }
+        self.offset += 1;
+        Ok(())
+    }
+
+    pub fn unsigned_integer(&mut self) -> cbor_event::Result<u64> {
+        match self.expect_header(Type::UnsignedInteger)? {
+            Some(value) => Ok(value),
+            None => unreachable!(),
+        }
+    }
+
+    pub fn tag(&mut self) -> cbor_event::Result<u64> {
+        match self.expect_header(Type::Tag)? {
+            Some(tag) => Ok(tag),
+            None => unreachable!(),
+        }
+    }
+
+    /// read a definite length byte string, returning its content
+    pub fn bytes(&mut self) -> cbor_event::Result<&'a [u8]> {
+        let start = self.offset;
+        match self.expect_header(Type::Bytes)? {
+            Some(len) => match self.advance(len) {
+                Ok(bytes) => Ok(bytes),
+                Err(err) => {
+                    self.offset = start;
+                    Err(err)
+                }
+            },
+            None => {
+                self.offset = start;
+                Err(cbor_event::Error::IndefiniteLenNotSupported(Type::Bytes))
+            }
+        }
+    }
+
+    /// read the header of an array
+    pub fn array(&mut self) -> cbor_event::Result<Len> {
+        Ok(match self.expect_header(Type::Array)? {
+            Some(len) => Len::Len(len),
+            None => Len::Indefinite,
+        })
+    }
+
+    /// read the header of an array of exactly `len` elements
+    pub fn tuple(&mut self, len: u64, error_location: &'static str) -> cbor_event::Result<()> {
+        let start = self.offset;
+        let found = self.array()?;
+        if found != Len::Len(len) {
+            self.offset = start;
+            return Err(cbor_event::Error::WrongLen(len, found, error_location));
+        }
+        Ok(())
+    }
+
+    /// skip the next item (and all its sub-items), returning its span
+    pub fn item(&mut self) -> cbor_event::Result<&'a [u8]> {
+        let start = self.offset;
+        match self.skip(0) {
+            Ok(()) => Ok(self.span_from(start)),
+            Err(err) => {
+                self.offset = start;
+                Err(err)
+            }
+        }
+    }
+
+    fn skip_until_break(&mut self, depth: usize) -> cbor_event::Result<()> {
+        while !self.is_break()? {
+            self.skip(depth)?;
+        }
+        self.special_break()
+    }
+
+    fn skip(&mut self, depth: usize) -> cbor_event::Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(cbor_event::Error::CustomError(format!(
+                "CBOR item nested more than {} levels deep",
+                MAX_DEPTH
+            )));
+        }
+        let (ty, arg) = self.header()?;
+        match (ty, arg) {
+            (Type::UnsignedInteger, _) | (Type::NegativeInteger, _) => {}
+            (Type::Bytes, Some(len)) | (Type::Text, Some(len)) => {
+                self.advance(len)?;
+            }
+            (Type::Bytes, None) | (Type::Text, None) => {
+                // indefinite strings are made of definite chunks of the same type
+                while !self.is_break()? {
+                    match self.expect_header(ty)? {
+                        Some(len) => self.advance(len)?,
+                        None => return Err(cbor_event::Error::InvalidIndefiniteString),
+                    };
+                }
+                self.special_break()?;
+            }
+            (Type::Array, Some(len)) => {
+                for _ in 0..len {
+                    self.skip(depth + 1)?;
+                }
+            }
+            (Type::Map, Some(len)) => {
+                for _ in 0..len {
+                    self.skip(depth + 1)?;
+                    self.skip(depth + 1)?;
+                }
+            }
+            (Type::Array, None) | (Type::Map, None) => self.skip_until_break(depth + 1)?,
+            (Type::Tag, _) => self.skip(depth + 1)?,
+            (Type::Special, Some(_)) => {}
+            (Type::Special, None) => {
+                return Err(cbor_event::Error::CustomError(
+                    "unexpected CBOR break".to_owned(),
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// iterator over the elements of a (definite or indefinite) array
+#[derive(Debug, Clone)]
+pub struct ArrayReader<'a> {
+    reader: Reader<'a>,
+    remaining: Option<u64>,
+    done: bool,
+}
+impl<'a> ArrayReader<'a> {
+    /// read the header of the array at the beginning of `data`
+    pub fn new(data: &'a [u8]) -> cbor_event::Result<Self> {
+        let mut reader = Reader::new(data);
+        let remaining = match reader.array()? {
+            Len::Len(len) => Some(len),
+            Len::Indefinite => None,
+        };
+        Ok(ArrayReader {
+            reader,
+            remaining,
+            done: false,
+        })
+    }
+
+    /// an array reader with no element
+    pub fn empty() -> Self {
+        ArrayReader {
+            reader: Reader::new(&[]),
+            remaining: Some(0),
+            done: true,
+        }
+    }
+
+    /// read the next element of the array with the given function
+    ///
+    /// Once an error has been returned, the reader stops.
+    pub fn next_with<T, F>(&mut self, f: F) -> Option<cbor_event::Result<T>>
+    where
+        F: FnOnce(&mut Reader<'a>) -> cbor_event::Result<T>,
+    {
+        if self.done {
+            return None;
+        }
+        let has_next = match self.remaining {
+            Some(0) => Ok(false),
+            Some(_) => Ok(true),
+            None => match self.reader.is_break() {
+                Ok(true) => self.reader.special_break().map(|()| false),
+                Ok(false) => Ok(true),
+                Err(err) => Err(err),
+            },
+        };
+        let result = match has_next {
+            Ok(false) => {
+                self.done = true;
+                return None;
+            }
+            Ok(true) => f(&mut self.reader),
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(_) => self.remaining = self.remaining.map(|len| len - 1),
+            Err(_) => self.done = true,
+        }
+        Some(result)
+    }
+}
+impl<'a> Iterator for ArrayReader<'a> {
+    type Item = cbor_event::Result<&'a [u8]>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with(Reader::item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cbor_event::{se::Serializer, Value};
+
+    fn encode(value: &Value) -> Vec<u8> {
+        cbor!(value).unwrap()
+    }
+
+    #[test]
+    fn item_spans_the_whole_item() {
+        let values = vec![
+            Value::U64(0),
+            Value::U64(u64::max_value()),
+            Value::I64(-1000),
+            Value::Bytes(vec![1; 300]),
+            Value::Text("cardano".to_owned()),
+            Value::Array(vec![Value::U64(1), Value::Array(vec![])]),
+            Value::IArray(vec![Value::U64(1), Value::IArray(vec![])]),
+            Value::Tag(24, Box::new(Value::Bytes(vec![0x80]))),
+            Value::Special(cbor_event::Special::Bool(true)),
+            Value::Special(cbor_event::Special::Null),
+        ];
+        for value in values {
+            let mut bytes = encode(&value);
+            let len = bytes.len();
+            bytes.extend_from_slice(&[0x01]);
+
+            let mut reader = Reader::new(&bytes);
+            assert_eq!(reader.item().unwrap().len(), len, "{:?}", value);
+            assert_eq!(reader.remaining(), &[0x01]);
+        }
+    }
+
+    #[test]
+    fn indefinite_strings() {
+        let mut se = Serializer::new_vec();
+        se.write_raw_bytes(&[0x5f, 0x42, 0x01, 0x02, 0x41, 0x03, 0xff])
+            .unwrap();
+        let bytes = se.finalize();
+        assert_eq!(Reader::new(&bytes).item().unwrap().len(), bytes.len());
+
+        // chunks of an indefinite string cannot be indefinite themselves
+        let bytes = [0x5f, 0x5f, 0xff, 0xff];
+        assert!(Reader::new(&bytes).item().is_err());
+    }
+
+    #[test]
+    fn truncated_item_is_an_error() {
+        let bytes = encode(&Value::Array(vec![Value::Bytes(vec![1; 40])]));
+        for len in 0..bytes.len() {
+            let mut reader = Reader::new(&bytes[..len]);
+            assert!(reader.item().is_err());
+            assert_eq!(reader.offset(), 0);
+        }
+    }
+
+    #[test]
+    fn nesting_is_bounded() {
+        let mut bytes = vec![0x81; MAX_DEPTH * 2];
+        bytes.push(0x00);
+        assert!(Reader::new(&bytes).item().is_err());
+    }
+
+    #[test]
+    fn array_reader() {
+        for value in vec![
+            Value::Array(vec![Value::U64(1), Value::Bytes(vec![2]), Value::U64(3)]),
+            Value::IArray(vec![Value::U64(1), Value::Bytes(vec![2]), Value::U64(3)]),
+        ] {
+            let bytes = encode(&value);
+            let items = ArrayReader::new(&bytes)
+                .unwrap()
+                .collect::<cbor_event::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(items, vec![&[0x01][..], &[0x41, 0x02][..], &[0x03][..]]);
+        }
+        assert!(ArrayReader::new(&[0x01]).is_err());
+        assert_eq!(ArrayReader::empty().count(), 0);
+    }
+
+    #[test]
+    fn array_reader_stops_on_error() {
+        // an indefinite array which is never terminated
+        let bytes = [0x9f, 0x01, 0x02];
+        let mut reader = ArrayReader::new(&bytes).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), &[0x01]);
+        assert_eq!(reader.next().unwrap().unwrap(), &[0x02]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn scalars() {
+        let bytes = [0x82, 0x18, 0x2a, 0xc6, 0x43, 0x01, 0x02, 0x03];
+        let mut reader = Reader::new(&bytes);
+        reader.tuple(2, "test").unwrap();
+        assert_eq!(reader.unsigned_integer().unwrap(), 42);
+        assert_eq!(reader.tag().unwrap(), 6);
+        assert_eq!(reader.bytes().unwrap(), &[1, 2, 3]);
+        assert!(reader.is_empty());
+
+        let mut reader = Reader::new(&bytes);
+        assert!(reader.tuple(3, "test").is_err());
+        assert!(reader.unsigned_integer().is_err());
+        assert_eq!(reader.offset(), 0);
+    }
+}
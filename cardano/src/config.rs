@@ -131,16 +131,34 @@ impl Default for Config {
 /// genesis data is not stored in the chain as a block.)
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GenesisData {
-    // FIXME: genesis_prev shouldn't be here since it's computed *from* the GenesisData.
+    /// The genesis hash. It is computed from the rest of the data when
+    /// building or parsing genesis data, and is not part of its JSON.
     pub genesis_prev: block::HeaderHash,
     pub epoch_stability_depth: usize, // a.k.a. 'k'
+    /// The minimum number of epochs a VSS certificate is valid for.
+    pub vss_min_ttl: u32,
+    /// The maximum number of epochs a VSS certificate is valid for.
+    pub vss_max_ttl: u32,
     pub start_time: SystemTime,
-    pub slot_duration: Duration,
     pub protocol_magic: ProtocolMagic,
-    pub fee_policy: fee::LinearFee,
+    /// The initial protocol parameters, until a software update changes
+    /// them.
+    pub block_version_data: block::update::BlockVersionData,
     pub avvm_distr: BTreeMap<redeem::PublicKey, coin::Coin>, // AVVM = Ada Voucher Vending Machine
     pub non_avvm_balances: BTreeMap<address::Addr, coin::Coin>,
     pub boot_stakeholders: BTreeMap<address::StakeholderId, BootStakeholder>,
+    /// The seed of the slot leader election of the genesis epoch.
+    pub fts_seed: block::fts::SharedSeed,
+}
+
+impl GenesisData {
+    pub fn slot_duration(&self) -> Duration {
+        Duration::from_millis(self.block_version_data.slot_duration)
+    }
+
+    pub fn fee_policy(&self) -> fee::LinearFee {
+        self.block_version_data.tx_fee_policy
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -26,7 +26,10 @@ use cbor_event::{
 };
 use hdwallet::XPub;
 
-use util::{hex, securemem};
+use util::{hex, securemem::SecureMem};
+
+#[cfg(feature = "generic-serialization")]
+use serde;
 
 const NONCE: &'static [u8] = b"serokellfore";
 const SALT: &'static [u8] = b"address-hashing";
@@ -128,9 +131,8 @@ impl cbor_event::Deserialize for Path {
 pub const HDKEY_SIZE: usize = 32;
 
 /// The key to encrypt and decrypt HD payload
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
-#[cfg_attr(feature = "generic-serialization", derive(Serialize, Deserialize))]
-pub struct HDKey([u8; HDKEY_SIZE]);
+#[derive(PartialEq, Eq, Clone)]
+pub struct HDKey(SecureMem);
 impl AsRef<[u8]> for HDKey {
     fn as_ref(&self) -> &[u8] {
         self.0.as_ref()
@@ -140,22 +142,20 @@ impl HDKey {
     /// Create a new `HDKey` from an extended public key
     pub fn new(root_pub: &XPub) -> Self {
         let mut mac = Hmac::new(Sha512::new(), root_pub.as_ref());
-        let mut result = [0; HDKEY_SIZE];
+        let mut result = SecureMem::new(HDKEY_SIZE);
         let iters = 500;
         pbkdf2(&mut mac, &SALT[..], iters, &mut result);
         HDKey(result)
     }
 
     /// create a `HDKey` by taking ownership of the given bytes
-    pub fn from_bytes(bytes: [u8; HDKEY_SIZE]) -> Self {
-        HDKey(bytes)
+    pub fn from_bytes(mut bytes: [u8; HDKEY_SIZE]) -> Self {
+        HDKey(SecureMem::take(&mut bytes))
     }
     /// create a `HDKey` from the given slice
     pub fn from_slice(bytes: &[u8]) -> Result<Self> {
         if bytes.len() == HDKEY_SIZE {
-            let mut v = [0u8; HDKEY_SIZE];
-            v[0..HDKEY_SIZE].clone_from_slice(bytes);
-            Ok(HDKey::from_bytes(v))
+            Ok(HDKey(SecureMem::from_slice(bytes)))
         } else {
             Err(Error::InvalidHDKeySize(bytes.len()))
         }
@@ -206,9 +206,36 @@ impl HDKey {
         Path::from_cbor(&out)
     }
 }
-impl Drop for HDKey {
-    fn drop(&mut self) {
-        securemem::zero(&mut self.0);
+impl fmt::Debug for HDKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("HDKey").field(&self.0).finish()
+    }
+}
+#[cfg(feature = "generic-serialization")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "HDKey")]
+struct SerdeHDKey([u8; HDKEY_SIZE]);
+#[cfg(feature = "generic-serialization")]
+impl serde::Serialize for HDKey {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut key = SerdeHDKey([0; HDKEY_SIZE]);
+        key.0.copy_from_slice(self.as_ref());
+        let result = key.serialize(serializer);
+        ::util::securemem::zero(&mut key.0);
+        result
+    }
+}
+#[cfg(feature = "generic-serialization")]
+impl<'de> serde::Deserialize<'de> for HDKey {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let key = SerdeHDKey::deserialize(deserializer)?;
+        Ok(HDKey::from_bytes(key.0))
     }
 }
 
@@ -267,6 +294,14 @@ impl fmt::Debug for HDAddressPayload {
 mod tests {
     use super::*;
     use hdwallet;
+    use util::securemem::assert_not_leaked;
+
+    #[test]
+    fn hdkey_is_not_formatted() {
+        let secret = [0x7e; HDKEY_SIZE];
+        let key = HDKey::from_bytes(secret);
+        assert_not_leaked(&format!("{:?} {:#?}", key, key), &secret);
+    }
 
     #[test]
     fn encrypt() {
@@ -15,7 +15,6 @@ use cryptoxide::ed25519::signature_extended;
 use cryptoxide::hmac::Hmac;
 use cryptoxide::mac::Mac;
 use cryptoxide::sha2::Sha512;
-use cryptoxide::util::fixed_time_eq;
 
 use bip::bip39;
 
@@ -26,7 +25,10 @@ use std::{
     io::{BufRead, Write},
     result,
 };
-use util::{hex, securemem};
+use util::{
+    ed25519_batch, hex,
+    securemem::{self, SecureMem},
+};
 
 use cbor_event::{self, de::Deserializer, se::Serializer};
 
@@ -131,9 +133,7 @@ impl Default for DerivationScheme {
 
 /// Seed used to generate the root private key of the HDWallet.
 ///
-#[derive(Debug)]
-#[cfg_attr(feature = "generic-serialization", derive(Serialize, Deserialize))]
-pub struct Seed([u8; SEED_SIZE]);
+pub struct Seed(SecureMem);
 impl Seed {
     /// create a Seed by taking ownership of the given array
     ///
@@ -145,8 +145,8 @@ impl Seed {
     ///
     /// assert!(seed.as_ref().len() == SEED_SIZE);
     /// ```
-    pub fn from_bytes(buf: [u8; SEED_SIZE]) -> Self {
-        Seed(buf)
+    pub fn from_bytes(mut buf: [u8; SEED_SIZE]) -> Self {
+        Seed(SecureMem::take(&mut buf))
     }
 
     /// create a Seed by copying the given slice into a new array
@@ -164,16 +164,23 @@ impl Seed {
         if buf.len() != SEED_SIZE {
             return Err(Error::InvalidSeedSize(buf.len()));
         }
-        let mut v = [0u8; SEED_SIZE];
-        v[..].clone_from_slice(buf);
-        Ok(Seed::from_bytes(v))
+        Ok(Seed(SecureMem::from_slice(buf)))
     }
 }
 impl Clone for Seed {
     fn clone(&self) -> Self {
-        let mut bytes = [0; SEED_SIZE];
-        bytes.copy_from_slice(self.as_ref());
-        Seed::from_bytes(bytes)
+        Seed(self.0.clone())
+    }
+}
+impl PartialEq for Seed {
+    fn eq(&self, rhs: &Seed) -> bool {
+        self.0 == rhs.0
+    }
+}
+impl Eq for Seed {}
+impl fmt::Debug for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Seed").field(&self.0).finish()
     }
 }
 impl AsRef<[u8]> for Seed {
@@ -181,16 +188,38 @@ impl AsRef<[u8]> for Seed {
         &self.0
     }
 }
-impl Drop for Seed {
-    fn drop(&mut self) {
-        securemem::zero(&mut self.0);
+#[cfg(feature = "generic-serialization")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Seed")]
+struct SerdeSeed([u8; SEED_SIZE]);
+#[cfg(feature = "generic-serialization")]
+impl serde::Serialize for Seed {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut seed = SerdeSeed([0; SEED_SIZE]);
+        seed.0.copy_from_slice(self.as_ref());
+        let result = seed.serialize(serializer);
+        securemem::zero(&mut seed.0);
+        result
+    }
+}
+#[cfg(feature = "generic-serialization")]
+impl<'de> serde::Deserialize<'de> for Seed {
+    fn deserialize<D>(deserializer: D) -> result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let seed = SerdeSeed::deserialize(deserializer)?;
+        Ok(Seed::from_bytes(seed.0))
     }
 }
 
 /// HDWallet extended private key
 ///
 /// Effectively this is ed25519 extended secret key (64 bytes) followed by a chain code (32 bytes)
-pub struct XPrv([u8; XPRV_SIZE]);
+pub struct XPrv(SecureMem);
 impl XPrv {
     /// create the Root private key `XPrv` of the HDWallet associated to this `Seed`
     ///
@@ -215,13 +244,13 @@ impl XPrv {
         let mut mac = Hmac::new(Sha512::new(), bytes);
 
         let mut iter = 1;
-        let mut out = [0u8; XPRV_SIZE];
+        let mut out = SecureMem::new(XPRV_SIZE);
+        let mut block = [0u8; 64];
 
         loop {
             let s = format!("Root Seed Chain {}", iter);
             mac.reset();
             mac.input(s.as_bytes());
-            let mut block = [0u8; 64];
             mac.raw_result(&mut block);
             mk_ed25519_extended(&mut out[0..64], &block[0..32]);
 
@@ -231,18 +260,20 @@ impl XPrv {
             }
             iter = iter + 1;
         }
+        mac.reset();
+        securemem::zero(&mut block);
 
-        Self::from_bytes(out)
+        XPrv(out)
     }
 
     pub fn generate_from_bip39(bytes: &bip39::Seed) -> Self {
-        let mut out = [0u8; XPRV_SIZE];
+        let mut out = SecureMem::new(XPRV_SIZE);
 
         mk_ed25519_extended(&mut out[0..64], &bytes.as_ref()[0..32]);
         out[31] &= 0b1101_1111; // set 3rd highest bit to 0 as per the spec
         out[64..96].clone_from_slice(&bytes.as_ref()[32..64]);
 
-        Self::from_bytes(out)
+        XPrv(out)
     }
 
     /// takes the given raw bytes and perform some modifications to normalize
@@ -260,8 +291,8 @@ impl XPrv {
     //
     // This function does not perform any validity check and should not be used outside
     // of this module.
-    fn from_bytes(bytes: [u8; XPRV_SIZE]) -> Self {
-        XPrv(bytes)
+    fn from_bytes(mut bytes: [u8; XPRV_SIZE]) -> Self {
+        XPrv(SecureMem::take(&mut bytes))
     }
 
     /// Create a `XPrv` by taking ownership of the given array
@@ -269,9 +300,10 @@ impl XPrv {
     /// This function may returns an error if it does not have the expected
     /// format.
     pub fn from_bytes_verified(bytes: [u8; XPRV_SIZE]) -> Result<Self> {
-        let scalar = &bytes[0..32];
-        let last = scalar[31];
-        let first = scalar[0];
+        // take the secret first so it is wiped on every return path
+        let xprv = XPrv::from_bytes(bytes);
+        let last = xprv.0[31];
+        let first = xprv.0[0];
 
         if (last & 0b1110_0000) != 0b0100_0000 {
             return Err(Error::InvalidXPrv("expected 3 highest bits to be 0b010"));
@@ -280,7 +312,7 @@ impl XPrv {
             return Err(Error::InvalidXPrv("expected 3 lowest bits to be 0b000"));
         }
 
-        Ok(XPrv(bytes))
+        Ok(xprv)
     }
 
     /// Create a `XPrv` from the given slice. This slice must be of size `XPRV_SIZE`
@@ -290,16 +322,30 @@ impl XPrv {
         if bytes.len() != XPRV_SIZE {
             return Err(Error::InvalidXPrvSize(bytes.len()));
         }
-        let mut buf = [0u8; XPRV_SIZE];
-        buf[..].clone_from_slice(bytes);
-        Ok(XPrv::from_bytes(buf))
+        Ok(XPrv(SecureMem::from_slice(bytes)))
     }
 
     /// Create a `XPrv` from a given hexadecimal string
     ///
     fn from_hex(hex: &str) -> Result<Self> {
-        let input = hex::decode(hex)?;
-        Self::from_slice(&input)
+        let mut input = hex::decode(hex)?;
+        let xprv = Self::from_slice(&input);
+        securemem::zero(&mut input);
+        xprv
+    }
+
+    /// Export the `XPrv` in hexadecimal, the format parsed by its
+    /// `FromStr` implementation. The returned string is not wiped on drop.
+    ///
+    /// ```
+    /// use cardano::hdwallet::{XPrv, Seed};
+    ///
+    /// let xprv = XPrv::generate_from_seed(&Seed::from_bytes([0;32]));
+    ///
+    /// assert_eq!(xprv.to_hex().parse::<XPrv>().unwrap(), xprv);
+    /// ```
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.as_ref())
     }
 
     /// Get the associated `XPub`
@@ -349,23 +395,24 @@ impl XPrv {
 }
 impl PartialEq for XPrv {
     fn eq(&self, rhs: &XPrv) -> bool {
-        fixed_time_eq(self.as_ref(), rhs.as_ref())
+        self.0 == rhs.0
     }
 }
 impl Eq for XPrv {}
 impl Clone for XPrv {
     fn clone(&self) -> Self {
-        Self::from_slice(self.as_ref()).expect("it is already a safely constructed XPrv")
+        XPrv(self.0.clone())
     }
 }
 impl fmt::Debug for XPrv {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", hex::encode(self.as_ref()))
+        f.debug_tuple("XPrv").field(&self.0).finish()
     }
 }
+// never display the key, use `to_hex` to export it
 impl fmt::Display for XPrv {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", hex::encode(self.as_ref()))
+        write!(f, "<XPrv>")
     }
 }
 impl ::std::str::FromStr for XPrv {
@@ -379,11 +426,6 @@ impl AsRef<[u8]> for XPrv {
         &self.0
     }
 }
-impl Drop for XPrv {
-    fn drop(&mut self) {
-        securemem::zero(&mut self.0);
-    }
-}
 #[cfg(feature = "generic-serialization")]
 impl serde::Serialize for XPrv {
     #[inline]
@@ -503,9 +545,38 @@ impl XPub {
         derive_public(self, index, scheme)
     }
 }
+
+/// verify all the given `(public key, message, signature)` at once
+///
+/// This is faster than calling `XPub::verify` on each element, but only
+/// tells if every signature is valid, not which one is not. The result
+/// is always the one of `XPub::verify`: the batches the fast batch
+/// equation cannot decide (see `ed25519_batch`) are checked one
+/// signature at a time.
+///
+/// ```
+/// use cardano::hdwallet::{self, XPrv, Seed, Signature};
+///
+/// let xprv = XPrv::generate_from_seed(&Seed::from_bytes([0;32]));
+/// let xpub = xprv.public();
+/// let signature1 : Signature<()> = xprv.sign(b"message 1");
+/// let signature2 : Signature<()> = xprv.sign(b"message 2");
+///
+/// assert!(hdwallet::verify_batch(&[
+///     (&xpub, b"message 1", &signature1),
+///     (&xpub, b"message 2", &signature2),
+/// ]));
+/// ```
+pub fn verify_batch<T>(batch: &[(&XPub, &[u8], &Signature<T>)]) -> bool {
+    let entries: Vec<_> = batch
+        .iter()
+        .map(|(xpub, message, signature)| (&xpub.as_ref()[0..32], *message, signature.as_ref()))
+        .collect();
+    ed25519_batch::verify_batch(&entries)
+}
 impl PartialEq for XPub {
     fn eq(&self, rhs: &XPub) -> bool {
-        fixed_time_eq(self.as_ref(), rhs.as_ref())
+        securemem::constant_time_eq(self.as_ref(), rhs.as_ref())
     }
 }
 impl Eq for XPub {}
@@ -658,7 +729,7 @@ impl<T> Signature<T> {
 }
 impl<T> PartialEq for Signature<T> {
     fn eq(&self, rhs: &Signature<T>) -> bool {
-        fixed_time_eq(self.as_ref(), rhs.as_ref())
+        securemem::constant_time_eq(self.as_ref(), rhs.as_ref())
     }
 }
 impl<T> Eq for Signature<T> {}
@@ -813,7 +884,8 @@ fn serialize_index(i: u32, derivation_scheme: DerivationScheme) -> [u8; 4] {
     }
 }
 
-fn mk_xprv(out: &mut [u8; XPRV_SIZE], kl: &[u8], kr: &[u8], cc: &[u8]) {
+fn mk_xprv(out: &mut [u8], kl: &[u8], kr: &[u8], cc: &[u8]) {
+    assert!(out.len() == XPRV_SIZE);
     assert!(kl.len() == 32);
     assert!(kr.len() == 32);
     assert!(cc.len() == CHAIN_CODE_SIZE);
@@ -961,25 +1033,26 @@ fn derive_private(xprv: &XPrv, index: DerivationIndex, scheme: DerivationScheme)
 
     let mut zout = [0u8; 64];
     zmac.raw_result(&mut zout);
-    let zl = &zout[0..32];
-    let zr = &zout[32..64];
 
     // left = kl + 8 * trunc28(zl)
-    let left = add_28_mul8(kl, zl, scheme);
+    let mut left = add_28_mul8(kl, &zout[0..32], scheme);
     // right = zr + kr
-    let right = add_256bits(kr, zr, scheme);
+    let mut right = add_256bits(kr, &zout[32..64], scheme);
 
     let mut iout = [0u8; 64];
     imac.raw_result(&mut iout);
-    let cc = &iout[32..];
 
-    let mut out = [0u8; XPRV_SIZE];
-    mk_xprv(&mut out, &left, &right, cc);
+    let mut out = SecureMem::new(XPRV_SIZE);
+    mk_xprv(&mut out, &left, &right, &iout[32..]);
 
     imac.reset();
     zmac.reset();
+    securemem::zero(&mut zout);
+    securemem::zero(&mut iout);
+    securemem::zero(&mut left);
+    securemem::zero(&mut right);
 
-    XPrv::from_bytes(out)
+    XPrv(out)
 }
 
 fn point_of_trunc28_mul8(sk: &[u8], scheme: DerivationScheme) -> [u8; 32] {
@@ -1058,6 +1131,34 @@ fn mk_public_key(extended_secret: &[u8]) -> [u8; PUBLIC_KEY_SIZE] {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use util::securemem::assert_not_leaked;
+
+    #[test]
+    fn secrets_are_not_formatted() {
+        let seed = Seed::from_bytes([0x5a; SEED_SIZE]);
+        assert_not_leaked(&format!("{:?}", seed), seed.as_ref());
+
+        let xprv = XPrv::generate_from_seed(&seed);
+        let formatted = format!("{} {:?} {:#?}", xprv, xprv, xprv);
+        assert_not_leaked(&formatted, xprv.as_ref());
+        assert_not_leaked(&formatted, &xprv.as_ref()[0..32]);
+    }
+
+    #[test]
+    fn secrets_equality() {
+        let xprv1 = XPrv::generate_from_seed(&Seed::from_bytes([1; SEED_SIZE]));
+        let xprv2 = XPrv::generate_from_seed(&Seed::from_bytes([2; SEED_SIZE]));
+        assert_eq!(xprv1, xprv1.clone());
+        assert_ne!(xprv1, xprv2);
+        assert_eq!(
+            Seed::from_bytes([1; SEED_SIZE]),
+            Seed::from_bytes([1; SEED_SIZE])
+        );
+        assert_ne!(
+            Seed::from_bytes([1; SEED_SIZE]),
+            Seed::from_bytes([2; SEED_SIZE])
+        );
+    }
 
     const D1: [u8; XPRV_SIZE] = [
         0xf8, 0xa2, 0x92, 0x31, 0xee, 0x38, 0xd6, 0xc5, 0xbf, 0x71, 0x5d, 0x5b, 0xac, 0x21, 0xc7,
@@ -1182,7 +1283,8 @@ mod tests {
             let mut bytes = [0; XPRV_SIZE];
             super::super::wallet::keygen::generate_seed(&entropy, b"trezor", &mut bytes);
             let xprv = XPrv::normalize_bytes(bytes);
-            let bytes = xprv.0;
+            let mut bytes = [0; XPRV_SIZE];
+            bytes.copy_from_slice(xprv.as_ref());
             // calling the from_bytes verified to check the xprv
             // is valid
             let _ = XPrv::from_bytes_verified(bytes).unwrap();
@@ -1295,6 +1397,37 @@ mod bench {
             let _ = pk.derive(DerivationScheme::V2, 0);
         })
     }
+
+    fn signatures(count: u32) -> Vec<(XPub, Vec<u8>, Signature<()>)> {
+        let seed = Seed::from_bytes([0; SEED_SIZE]);
+        let sk = XPrv::generate_from_seed(&seed);
+        (0..count)
+            .map(|i| {
+                let sk = sk.derive(DerivationScheme::V2, i);
+                let message = format!("message {}", i).into_bytes();
+                let signature = sk.sign(&message);
+                (sk.public(), message, signature)
+            })
+            .collect()
+    }
+    #[bench]
+    fn verify_64_individually(b: &mut test::Bencher) {
+        let signatures = signatures(64);
+        b.iter(|| {
+            signatures
+                .iter()
+                .all(|(pk, message, signature)| pk.verify(message, signature))
+        })
+    }
+    #[bench]
+    fn verify_64_batch(b: &mut test::Bencher) {
+        let signatures = signatures(64);
+        let batch: Vec<_> = signatures
+            .iter()
+            .map(|(pk, message, signature)| (pk, &message[..], signature))
+            .collect();
+        b.iter(|| verify_batch(&batch))
+    }
 }
 
 #[cfg(test)]
@@ -56,6 +56,7 @@ pub mod hash;
 pub mod hdpayload;
 pub mod hdwallet;
 pub mod input_selection;
+pub mod mempool;
 pub mod paperwallet;
 pub mod redeem;
 pub mod tx;
@@ -0,0 +1,539 @@
+//! Transaction pool
+//!
+//! The transactions received from the network, waiting to be included
+//! in a block. A transaction is admitted only if it is valid against the
+//! utxos of the chain state extended with the transactions already in
+//! the pool, in the order they were admitted: it may spend the outputs
+//! of pooled transactions, but not the inputs they already spend.
+//!
+//! The pooled transactions are applied to the chain state, their utxo
+//! changes forming an overlay over the utxos of the chain that is
+//! reverted, rather than the chain state being copied, when they have
+//! to be validated again.
+//!
+//! When the chain state changes (a block is applied or rolled back),
+//! the pooled transactions are validated again against the new chain
+//! state and the ones that became invalid are dropped, along with the
+//! transactions depending on them. The transactions of the rolled back
+//! blocks are not added back to the pool: they have to be resubmitted
+//! with `add_tx`.
+//!
+//! A full pool does not admit new transactions. The pooled ones are kept
+//! until they are included in a block, expire or become invalid, so that
+//! a flood of new transactions cannot push them out of the pool.
+
+use block::{self, ChainState, TxUndo};
+use std::collections::{BTreeMap, BTreeSet};
+use std::mem;
+use std::time::{Duration, SystemTime};
+use std::{error, fmt, result};
+use tx::{TxAux, TxId};
+
+#[derive(Debug)]
+pub enum Error {
+    AlreadyInPool,
+    /// the pool has reached its transaction count or size limit
+    PoolFull,
+    /// the transaction is not valid against the chain state and the
+    /// transactions of the pool
+    Invalid(block::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::AlreadyInPool => write!(f, "Transaction is already in the pool"),
+            Error::PoolFull => write!(f, "Transaction pool is full"),
+            Error::Invalid(_) => write!(f, "Transaction is invalid"),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self {
+            Error::Invalid(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// The limits past which transactions are not admitted into the pool,
+/// or are evicted from it for `max_age`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MempoolLimits {
+    pub max_txs: usize,
+    /// the maximum total size of the transactions, in bytes
+    pub max_bytes: usize,
+    /// how long a transaction may wait to be included in a block
+    pub max_age: Duration,
+}
+
+impl Default for MempoolLimits {
+    fn default() -> Self {
+        MempoolLimits {
+            max_txs: 200,
+            max_bytes: 2_000_000,
+            max_age: Duration::from_secs(3600),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PooledTx {
+    txaux: TxAux,
+    size: usize,
+    received: SystemTime,
+    /// the changes the transaction made to the chain state
+    undo: TxUndo,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mempool {
+    limits: MempoolLimits,
+    /// the state of the chain the transactions are to be added to, with
+    /// the pooled transactions applied
+    state: ChainState,
+    txs: BTreeMap<TxId, PooledTx>,
+    /// the identifiers of the pooled transactions, in the order they
+    /// were admitted
+    order: Vec<TxId>,
+    size: usize,
+}
+
+impl Mempool {
+    pub fn new(chain_state: ChainState, limits: MempoolLimits) -> Self {
+        Mempool {
+            limits,
+            state: chain_state,
+            txs: BTreeMap::new(),
+            order: vec![],
+            size: 0,
+        }
+    }
+
+    /// the chain state with the pooled transactions applied
+    pub fn state(&self) -> &ChainState {
+        &self.state
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// the total size of the pooled transactions, in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn contains(&self, id: &TxId) -> bool {
+        self.txs.contains_key(id)
+    }
+
+    pub fn get(&self, id: &TxId) -> Option<&TxAux> {
+        self.txs.get(id).map(|pooled| &pooled.txaux)
+    }
+
+    /// The pooled transactions, in the order they were admitted.
+    pub fn iter(&self) -> impl Iterator<Item = &TxAux> {
+        self.order.iter().map(move |id| &self.txs[id].txaux)
+    }
+
+    /// Admit a transaction received at `now`, the expired transactions
+    /// being evicted first. The transaction is rejected with `PoolFull`
+    /// if it would exceed the limits of the pool, which is left as is.
+    pub fn add_tx(&mut self, txaux: TxAux, now: SystemTime) -> Result<TxId> {
+        let id = txaux.tx.id();
+        if self.contains(&id) {
+            return Err(Error::AlreadyInPool);
+        }
+        self.remove_expired(now);
+
+        let size = cbor!(&txaux)
+            .map_err(|err| Error::Invalid(block::Error::EncodingError(err)))?
+            .len();
+        if self.len() >= self.limits.max_txs || self.size + size > self.limits.max_bytes {
+            return Err(Error::PoolFull);
+        }
+        let undo = self
+            .state
+            .apply_tx_with_undo(&txaux)
+            .map_err(Error::Invalid)?;
+
+        self.txs.insert(
+            id,
+            PooledTx {
+                txaux,
+                size,
+                received: now,
+                undo,
+            },
+        );
+        self.order.push(id);
+        self.size += size;
+        Ok(id)
+    }
+
+    /// Remove a transaction, and the transactions depending on it.
+    /// Returns the identifiers of the removed transactions.
+    pub fn remove_tx(&mut self, id: &TxId) -> Vec<TxId> {
+        if !self.contains(id) {
+            return vec![];
+        }
+        self.drop_txs(&[*id].iter().cloned().collect())
+    }
+
+    /// Evict the transactions older than the maximum age, and the
+    /// transactions depending on them. Returns the identifiers of the
+    /// evicted transactions.
+    pub fn remove_expired(&mut self, now: SystemTime) -> Vec<TxId> {
+        let max_age = self.limits.max_age;
+        let expired: BTreeSet<TxId> = self
+            .txs
+            .iter()
+            .filter(|(_, pooled)| pooled.received + max_age <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        if expired.is_empty() {
+            return vec![];
+        }
+        self.drop_txs(&expired)
+    }
+
+    /// Follow the chain to a new state, after a block was applied or
+    /// rolled back. Returns the identifiers of the transactions dropped
+    /// because they are no longer valid: the ones included in the
+    /// applied blocks, conflicting with them, or depending on the
+    /// transactions of the rolled back blocks.
+    pub fn set_chain_state(&mut self, chain_state: ChainState) -> Vec<TxId> {
+        // The changes of the pooled transactions were made to the
+        // previous state: they are dropped along with it.
+        self.state = chain_state;
+        let txs = self.take_txs();
+        self.readmit(txs, &BTreeSet::new())
+    }
+
+    /// Select the transactions of the next block, in the order they were
+    /// admitted, up to a total size of `max_bytes`. The transactions
+    /// depending on a transaction left out are left out as well.
+    pub fn select_txs(&self, max_bytes: usize) -> Vec<TxAux> {
+        let mut selected = vec![];
+        let mut left_out = BTreeSet::new();
+        let mut size = 0;
+        for id in self.order.iter() {
+            let pooled = &self.txs[id];
+            let depends_on_left_out = pooled
+                .txaux
+                .tx
+                .inputs
+                .iter()
+                .any(|input| left_out.contains(&input.id));
+            if depends_on_left_out || size + pooled.size > max_bytes {
+                left_out.insert(*id);
+                continue;
+            }
+            size += pooled.size;
+            selected.push(pooled.txaux.clone());
+        }
+        selected
+    }
+
+    /// Drop the given transactions, then apply the remaining ones to the
+    /// chain state again, dropping the ones that are no longer valid.
+    fn drop_txs(&mut self, ids: &BTreeSet<TxId>) -> Vec<TxId> {
+        let mut txs = self.take_txs();
+        // Revert the pooled transactions, the last admitted first, to get
+        // back to the state of the chain.
+        for (_, pooled) in txs.iter_mut().rev() {
+            self.state.revert_tx(mem::take(&mut pooled.undo));
+        }
+        self.readmit(txs, ids)
+    }
+
+    /// Remove all the transactions from the pool, in the order they were
+    /// admitted, leaving the chain state as is.
+    fn take_txs(&mut self) -> Vec<(TxId, PooledTx)> {
+        let mut txs = vec![];
+        for id in self.order.drain(..) {
+            let pooled = self.txs.remove(&id).unwrap();
+            txs.push((id, pooled));
+        }
+        txs
+    }
+
+    /// Apply the given transactions to the chain state again, in order,
+    /// except the given ones and the ones that are no longer valid.
+    /// Returns the identifiers of the dropped transactions.
+    fn readmit(&mut self, txs: Vec<(TxId, PooledTx)>, ids: &BTreeSet<TxId>) -> Vec<TxId> {
+        let mut dropped = vec![];
+        for (id, mut pooled) in txs {
+            if !ids.contains(&id) {
+                if let Ok(undo) = self.state.apply_tx_with_undo(&pooled.txaux) {
+                    pooled.undo = undo;
+                    self.txs.insert(id, pooled);
+                    self.order.push(id);
+                    continue;
+                }
+            }
+            self.size -= pooled.size;
+            dropped.push(id);
+        }
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use address::{AddrType, Attributes, ExtendedAddr, SpendingData, StakeholderId};
+    use block::builder::{make_boundary_block, BlockIssuer, MainBlockBuilder};
+    use block::fts::SharedSeed;
+    use block::update::BlockVersionData;
+    use block::{EpochSlotId, HeaderHash};
+    use coin::Coin;
+    use config::{GenesisData, ProtocolMagic};
+    use fee::{LinearFee, Milli};
+    use hdwallet::{Seed, XPrv};
+    use redeem;
+    use tx::{self, Tx, TxInWitness, TxOut, TxWitness, TxoPointer};
+
+    fn xprv(seed: u8) -> XPrv {
+        XPrv::generate_from_seed(&Seed::from_bytes([seed; 32]))
+    }
+
+    fn redeem_key() -> redeem::PrivateKey {
+        redeem::PrivateKey::generate(&[7; 32]).unwrap()
+    }
+
+    fn genesis_data() -> GenesisData {
+        let mut avvm_distr = BTreeMap::new();
+        avvm_distr.insert(redeem_key().public(), Coin::new(1000).unwrap());
+        GenesisData {
+            genesis_prev: HeaderHash::new(b"genesis"),
+            epoch_stability_depth: 10,
+            vss_min_ttl: 2,
+            vss_max_ttl: 6,
+            start_time: SystemTime::UNIX_EPOCH,
+            protocol_magic: ProtocolMagic::from(633343913),
+            block_version_data: BlockVersionData::mainnet(
+                20_000,
+                LinearFee::new(Milli::integral(0), Milli::integral(0)),
+            ),
+            avvm_distr,
+            non_avvm_balances: BTreeMap::new(),
+            boot_stakeholders: BTreeMap::new(),
+            fts_seed: SharedSeed::default(),
+        }
+    }
+
+    fn output(seed: u8, value: u64) -> TxOut {
+        TxOut::new(
+            ExtendedAddr::new(
+                AddrType::ATPubKey,
+                SpendingData::PubKeyASD(xprv(seed).public()),
+                Attributes::new_bootstrap_era(None, genesis_data().protocol_magic.into()),
+            ),
+            Coin::new(value).unwrap(),
+        )
+    }
+
+    /// a transaction sending `value` out of the AVVM utxo to `xprv(2)`
+    fn redeem_tx(value: u64) -> TxAux {
+        let pm = genesis_data().protocol_magic;
+        let (id, _) = tx::redeem_pubkey_to_txid(&redeem_key().public(), pm);
+        let tx = Tx::new_with(vec![TxoPointer::new(id, 0)], vec![output(2, value)]);
+        let witness = TxInWitness::new_redeem_pk(pm, &redeem_key(), &tx.id());
+        TxAux::new(tx, TxWitness::from(vec![witness]))
+    }
+
+    /// a transaction spending the first output of `parent`, owned by
+    /// `xprv(signer)`
+    fn spending_tx(parent: &TxAux, signer: u8, outputs: Vec<TxOut>) -> TxAux {
+        let pm = genesis_data().protocol_magic;
+        let tx = Tx::new_with(vec![TxoPointer::new(parent.tx.id(), 0)], outputs);
+        let witness = TxInWitness::new_extended_pk(pm, &xprv(signer), &tx.id());
+        TxAux::new(tx, TxWitness::from(vec![witness]))
+    }
+
+    /// a transaction sending `value` out of the first output of `parent`
+    /// to `xprv(3)`
+    fn child_tx(parent: &TxAux, value: u64) -> TxAux {
+        spending_tx(parent, 2, vec![output(3, value)])
+    }
+
+    fn secs(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn mempool(limits: MempoolLimits) -> Mempool {
+        Mempool::new(ChainState::new(&genesis_data()), limits)
+    }
+
+    fn assert_invalid(res: Result<TxId>, expected: block::Error) {
+        match res {
+            Err(Error::Invalid(ref err)) if format!("{:?}", err) == format!("{:?}", expected) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn transactions_are_validated_against_the_pool() {
+        let mut mempool = mempool(MempoolLimits::default());
+        let parent = redeem_tx(1000);
+        let id = mempool.add_tx(parent.clone(), secs(0)).unwrap();
+        assert_eq!(id, parent.tx.id());
+        match mempool.add_tx(parent.clone(), secs(0)) {
+            Err(Error::AlreadyInPool) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // double spend of the AVVM utxo
+        assert_invalid(
+            mempool.add_tx(redeem_tx(999), secs(0)),
+            block::Error::MissingUtxo,
+        );
+        assert_invalid(
+            mempool.add_tx(child_tx(&parent, 1001), secs(0)),
+            block::Error::OutputsExceedInputs,
+        );
+        mempool.add_tx(child_tx(&parent, 1000), secs(0)).unwrap();
+        assert_eq!(mempool.len(), 2);
+        assert_eq!(
+            mempool.iter().cloned().collect::<Vec<_>>(),
+            vec![parent.clone(), child_tx(&parent, 1000)]
+        );
+
+        // removing a transaction removes its dependents, and reverts
+        // their changes to the chain state
+        assert_eq!(
+            mempool.remove_tx(&parent.tx.id()),
+            vec![parent.tx.id(), child_tx(&parent, 1000).tx.id()]
+        );
+        assert!(mempool.is_empty());
+        assert_eq!(mempool.size(), 0);
+        assert_eq!(mempool.state(), &ChainState::new(&genesis_data()));
+    }
+
+    #[test]
+    fn transactions_are_evicted_on_limits() {
+        let mut mempool = mempool(MempoolLimits {
+            max_txs: 1,
+            ..MempoolLimits::default()
+        });
+        let parent = redeem_tx(1000);
+        mempool.add_tx(parent.clone(), secs(0)).unwrap();
+        // a full pool rejects new transactions, keeping the pooled ones
+        let state = mempool.state().clone();
+        match mempool.add_tx(child_tx(&parent, 1000), secs(1)) {
+            Err(Error::PoolFull) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(
+            mempool.iter().cloned().collect::<Vec<_>>(),
+            vec![parent.clone()]
+        );
+        assert_eq!(mempool.state(), &state);
+
+        let max_age = MempoolLimits::default().max_age.as_secs();
+        assert_eq!(mempool.remove_expired(secs(max_age - 1)), vec![]);
+        // the parent expired, so the child spends a missing output
+        assert_invalid(
+            mempool.add_tx(child_tx(&parent, 1000), secs(max_age)),
+            block::Error::MissingUtxo,
+        );
+        assert!(mempool.is_empty());
+        assert_eq!(mempool.state(), &ChainState::new(&genesis_data()));
+
+        // a pool reaching its size limit rejects new transactions as well
+        let mut mempool = Mempool::new(
+            ChainState::new(&genesis_data()),
+            MempoolLimits {
+                max_bytes: cbor!(&parent).unwrap().len(),
+                ..MempoolLimits::default()
+            },
+        );
+        mempool.add_tx(parent.clone(), secs(0)).unwrap();
+        match mempool.add_tx(child_tx(&parent, 1000), secs(1)) {
+            Err(Error::PoolFull) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn transactions_follow_the_chain() {
+        let genesis = genesis_data();
+        let mut chain_state = ChainState::new(&genesis);
+        // the slot leaders of the test blocks are not elected
+        chain_state.ssc.seed = None;
+        let blk = make_boundary_block(&chain_state, 0, vec![StakeholderId::new(&xprv(1).public())]);
+        chain_state
+            .apply_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+
+        let mut mempool = Mempool::new(chain_state.clone(), MempoolLimits::default());
+        let parent = redeem_tx(1000);
+        let child = child_tx(&parent, 1000);
+        mempool.add_tx(parent.clone(), secs(0)).unwrap();
+        mempool.add_tx(child.clone(), secs(0)).unwrap();
+
+        // the parent is included in a block
+        let mut builder = MainBlockBuilder::new(EpochSlotId {
+            epoch: 0,
+            slotid: 0,
+        });
+        for txaux in mempool.select_txs(cbor!(&parent).unwrap().len()) {
+            builder.add_tx(txaux);
+        }
+        let blk = builder.make_block(&chain_state, &BlockIssuer::SlotLeader(&xprv(1)));
+        let undo = chain_state
+            .apply_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+        assert_eq!(
+            mempool.set_chain_state(chain_state.clone()),
+            vec![parent.tx.id()]
+        );
+        assert_eq!(
+            mempool.iter().cloned().collect::<Vec<_>>(),
+            vec![child.clone()]
+        );
+
+        // and rolled back
+        chain_state.revert_block(undo);
+        assert_eq!(mempool.set_chain_state(chain_state), vec![child.tx.id()]);
+        assert!(mempool.is_empty());
+    }
+
+    #[test]
+    fn selection_fits_the_block_size() {
+        let mut mempool = mempool(MempoolLimits::default());
+        let parent = redeem_tx(1000);
+        let child = spending_tx(&parent, 2, vec![output(3, 500), output(4, 500)]);
+        let grandchild = spending_tx(&child, 3, vec![output(5, 500)]);
+        for txaux in vec![parent.clone(), child.clone(), grandchild.clone()] {
+            mempool.add_tx(txaux, secs(0)).unwrap();
+        }
+        let size = |txaux: &TxAux| cbor!(txaux).unwrap().len();
+        assert!(size(&grandchild) < size(&child));
+
+        assert_eq!(
+            mempool.select_txs(mempool.size()),
+            vec![parent.clone(), child.clone(), grandchild.clone()]
+        );
+        assert_eq!(
+            mempool.select_txs(size(&parent) + size(&child)),
+            vec![parent.clone(), child.clone()]
+        );
+        // the grandchild fits, but not without the child
+        assert_eq!(
+            mempool.select_txs(size(&parent) + size(&grandchild)),
+            vec![parent.clone()]
+        );
+        assert_eq!(mempool.select_txs(size(&parent) - 1), vec![]);
+    }
+}
@@ -1,5 +1,14 @@
-use cbor_event::se;
+//! Merkle tree of the transactions of a block, and inclusion proofs
+//!
+//! The tree is not balanced: the left subtree of a node holds the largest
+//! power of two of elements strictly smaller than the number of elements
+//! of the node. Its shape is hence determined by its number of elements,
+//! and an inclusion proof only needs the index of the element, the number
+//! of elements and the hashes of the siblings on the path to the root.
+
+use cbor_event::{self, de::Deserializer, se};
 use hash::Blake2b256;
+use std::io::{BufRead, Write};
 
 pub type Hash = Blake2b256;
 
@@ -13,6 +22,28 @@ pub enum MerkleNode {
     Leaf(Hash),
 }
 
+fn leaf_hash<T>(x: &T) -> Hash
+where
+    T: se::Serialize,
+{
+    let bs = vec![0u8];
+    let mut se = se::Serializer::new(bs);
+    x.serialize(&mut se).unwrap();
+    Hash::new(&se.finalize())
+}
+
+fn branch_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut bs = vec![1u8];
+    bs.extend(left.as_hash_bytes());
+    bs.extend(right.as_hash_bytes());
+    Hash::new(&bs)
+}
+
+/// The number of elements of the left subtree of a node of `len` elements.
+fn left_len(len: usize) -> usize {
+    len.checked_next_power_of_two().unwrap() >> 1
+}
+
 impl MerkleTree {
     pub fn new<T>(xs: &[T]) -> Self
     where
@@ -27,8 +58,41 @@ impl MerkleTree {
 
     pub fn get_root_hash(&self) -> Hash {
         match self {
-            MerkleTree::Empty => Hash::new(&vec![]), // FIXME: cache
-            MerkleTree::Tree(_, node) => node.get_root_hash().clone(),
+            MerkleTree::Empty => Hash::new(&[]),
+            MerkleTree::Tree(_, node) => *node.get_root_hash(),
+        }
+    }
+
+    /// The number of elements of the tree.
+    pub fn len(&self) -> usize {
+        match self {
+            MerkleTree::Empty => 0,
+            MerkleTree::Tree(len, _) => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The proof that the element at `index` is included in the tree, or
+    /// `None` if there is no such element or if the tree has more
+    /// elements than a proof can count.
+    pub fn make_proof(&self, index: usize) -> Option<MerkleProof> {
+        match self {
+            MerkleTree::Empty => None,
+            MerkleTree::Tree(len, _) if index >= *len => None,
+            MerkleTree::Tree(len, _) if *len as u64 > u64::from(u32::MAX) => None,
+            MerkleTree::Tree(len, node) => {
+                let mut path = vec![];
+                node.collect_path(index, *len, &mut path);
+                path.reverse();
+                Some(MerkleProof {
+                    index: index as u32,
+                    number: *len as u32,
+                    path,
+                })
+            }
         }
     }
 }
@@ -41,18 +105,13 @@ impl MerkleNode {
         if xs.is_empty() {
             panic!("make_tree applied to empty list")
         } else if xs.len() == 1 {
-            let bs = vec![0u8];
-            let mut se = se::Serializer::new(bs);
-            xs[0].serialize(&mut se).unwrap();
-            MerkleNode::Leaf(Hash::new(&se.finalize()))
+            MerkleNode::Leaf(leaf_hash(&xs[0]))
         } else {
-            let i = xs.len().checked_next_power_of_two().unwrap() >> 1;
+            let i = left_len(xs.len());
             let a = MerkleNode::make_tree(&xs[0..i]);
             let b = MerkleNode::make_tree(&xs[i..]);
-            let mut bs = vec![1u8];
-            bs.extend(a.get_root_hash().as_hash_bytes());
-            bs.extend(b.get_root_hash().as_hash_bytes());
-            MerkleNode::Branch(Hash::new(&bs), Box::new(a), Box::new(b))
+            let hash = branch_hash(a.get_root_hash(), b.get_root_hash());
+            MerkleNode::Branch(hash, Box::new(a), Box::new(b))
         }
     }
 
@@ -62,4 +121,192 @@ impl MerkleNode {
             MerkleNode::Leaf(hash) => hash,
         }
     }
+
+    /// Push the hashes of the siblings on the path from this node (of
+    /// `len` elements) to the element at `index`, top-down.
+    fn collect_path(&self, index: usize, len: usize, path: &mut Vec<Hash>) {
+        match self {
+            MerkleNode::Leaf(_) => {}
+            MerkleNode::Branch(_, a, b) => {
+                let i = left_len(len);
+                if index < i {
+                    path.push(*b.get_root_hash());
+                    a.collect_path(index, i, path)
+                } else {
+                    path.push(*a.get_root_hash());
+                    b.collect_path(index - i, len - i, path)
+                }
+            }
+        }
+    }
+}
+
+/// The proof that an element is included in a merkle tree.
+///
+/// It is serialized in CBOR as `[index, number, [sibling hash]]`, the
+/// sibling hashes going from the leaf to the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    /// The index of the element in the tree
+    pub index: u32,
+    /// The number of elements of the tree
+    pub number: u32,
+    /// The hashes of the siblings of the nodes on the path from the
+    /// element to the root
+    pub path: Vec<Hash>,
+}
+
+impl MerkleProof {
+    /// Compute the root hash of the tree from the element, or `None` if
+    /// the proof is malformed (the index is out of bounds, or the number
+    /// of hashes does not match the depth of the element).
+    pub fn root_hash<T>(&self, x: &T) -> Option<Hash>
+    where
+        T: se::Serialize,
+    {
+        let mut index = self.index as usize;
+        let mut len = self.number as usize;
+        if index >= len {
+            return None;
+        }
+
+        // whether the element is in the left subtree, top-down
+        let mut lefts = vec![];
+        while len > 1 {
+            let i = left_len(len);
+            if index < i {
+                lefts.push(true);
+                len = i;
+            } else {
+                lefts.push(false);
+                index -= i;
+                len -= i;
+            }
+        }
+        if lefts.len() != self.path.len() {
+            return None;
+        }
+
+        let mut hash = leaf_hash(x);
+        for (left, sibling) in lefts.iter().rev().zip(self.path.iter()) {
+            hash = if *left {
+                branch_hash(&hash, sibling)
+            } else {
+                branch_hash(sibling, &hash)
+            };
+        }
+        Some(hash)
+    }
+
+    /// Check that `x` is the element at `self.index` of the tree of the
+    /// given root hash.
+    pub fn verify<T>(&self, x: &T, root: &Hash) -> bool
+    where
+        T: se::Serialize,
+    {
+        self.root_hash(x).as_ref() == Some(root)
+    }
+}
+
+impl se::Serialize for MerkleProof {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut se::Serializer<W>,
+    ) -> cbor_event::Result<&'se mut se::Serializer<W>> {
+        serializer
+            .write_array(cbor_event::Len::Len(3))?
+            .write_unsigned_integer(self.index as u64)?
+            .write_unsigned_integer(self.number as u64)?;
+        se::serialize_fixed_array(self.path.iter(), serializer)
+    }
+}
+impl cbor_event::de::Deserialize for MerkleProof {
+    fn deserialize<R: BufRead>(raw: &mut Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(3, "MerkleProof")?;
+        let index = deserialize_u32(raw, "index")?;
+        let number = deserialize_u32(raw, "number")?;
+        let path = raw.deserialize()?;
+        Ok(MerkleProof {
+            index,
+            number,
+            path,
+        })
+    }
+}
+
+fn deserialize_u32<R: BufRead>(raw: &mut Deserializer<R>, field: &str) -> cbor_event::Result<u32> {
+    let n = raw.unsigned_integer()?;
+    if n > u64::from(u32::MAX) {
+        return Err(cbor_event::Error::CustomError(format!(
+            "MerkleProof {} out of range: {}",
+            field, n
+        )));
+    }
+    Ok(n as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proofs_are_verified() {
+        for len in 1..18u64 {
+            let xs: Vec<u64> = (0..len).collect();
+            let tree = MerkleTree::new(&xs);
+            let root = tree.get_root_hash();
+            for (i, x) in xs.iter().enumerate() {
+                let proof = tree.make_proof(i).unwrap();
+                assert!(proof.verify(x, &root), "{} of {}", i, len);
+                assert!(!proof.verify(&(x + 100), &root));
+
+                let bytes = cbor!(&proof).unwrap();
+                let decoded: MerkleProof = Deserializer::from(std::io::Cursor::new(bytes))
+                    .deserialize()
+                    .unwrap();
+                assert_eq!(decoded, proof);
+            }
+            assert!(tree.make_proof(xs.len()).is_none());
+        }
+        assert!(MerkleTree::new::<u64>(&[]).make_proof(0).is_none());
+    }
+
+    #[test]
+    fn malformed_proofs_are_rejected() {
+        let xs: Vec<u64> = (0..5).collect();
+        let tree = MerkleTree::new(&xs);
+        let root = tree.get_root_hash();
+        let proof = tree.make_proof(4).unwrap();
+        assert_eq!(proof.path.len(), 1);
+
+        let mut wrong_index = proof.clone();
+        wrong_index.index = 3;
+        assert!(!wrong_index.verify(&4u64, &root));
+
+        let mut out_of_bounds = proof.clone();
+        out_of_bounds.index = 5;
+        assert!(out_of_bounds.root_hash(&4u64).is_none());
+
+        let mut too_long = proof.clone();
+        too_long.path.push(root);
+        assert!(too_long.root_hash(&4u64).is_none());
+    }
+
+    #[test]
+    fn out_of_range_integers_are_rejected() {
+        let mut serializer = se::Serializer::new_vec();
+        serializer
+            .write_array(cbor_event::Len::Len(3))
+            .unwrap()
+            .write_unsigned_integer(0)
+            .unwrap()
+            .write_unsigned_integer(1 << 32)
+            .unwrap()
+            .write_array(cbor_event::Len::Len(0))
+            .unwrap();
+        let bytes = serializer.finalize();
+        let res: cbor_event::Result<MerkleProof> =
+            Deserializer::from(std::io::Cursor::new(bytes)).deserialize();
+        assert!(res.is_err());
+    }
 }
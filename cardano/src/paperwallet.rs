@@ -1,6 +1,7 @@
 use cryptoxide::hmac::Hmac;
 use cryptoxide::pbkdf2::pbkdf2;
 use cryptoxide::sha2::Sha512;
+use util::securemem::SecureMem;
 
 const ITERS: u32 = 10000;
 pub const IV_SIZE: usize = 8;
@@ -36,15 +37,14 @@ pub fn scramble(iv: &[u8], password: &[u8], input: &[u8]) -> Vec<u8> {
 
 /// Try to reverse the scramble operation, using
 /// the first `IV_SIZE` bytes as IV, and the rest as the shielded input.
-pub fn unscramble(password: &[u8], input: &[u8]) -> Vec<u8> {
+///
+/// The unscrambled secret is returned in a `SecureMem`, wiped on drop.
+pub fn unscramble(password: &[u8], input: &[u8]) -> SecureMem {
     assert!(input.len() > IV_SIZE);
 
     let out_sz = input.len() - IV_SIZE;
 
-    let mut out = Vec::with_capacity(out_sz);
-    for _ in 0..out_sz {
-        out.push(0);
-    }
+    let mut out = SecureMem::new(out_sz);
 
     gen(&input[0..IV_SIZE], password, &mut out[0..out_sz]);
     for i in 0..out_sz {
@@ -11,7 +11,10 @@ use cbor_event::{self, de::Deserializer, se::Serializer};
 use cryptoxide::ed25519;
 #[cfg(feature = "generic-serialization")]
 use serde;
-use util::hex;
+use util::{
+    ed25519_batch, hex,
+    securemem::{self, SecureMem},
+};
 
 use std::{
     cmp, fmt,
@@ -92,6 +95,18 @@ impl PublicKey {
         ed25519::verify(bytes, &self.0, signature.as_ref())
     }
 }
+
+/// verify all the given `(public key, message, signature)` at once
+///
+/// Returns `true` only if all the signatures are valid, i.e. if
+/// `PublicKey::verify` accepts each of them.
+pub fn verify_batch(batch: &[(&PublicKey, &[u8], &Signature)]) -> bool {
+    let entries: Vec<_> = batch
+        .iter()
+        .map(|(public_key, message, signature)| (public_key.as_ref(), *message, signature.as_ref()))
+        .collect();
+    ed25519_batch::verify_batch(&entries)
+}
 impl AsRef<[u8]> for PublicKey {
     fn as_ref(&self) -> &[u8] {
         &self.0
@@ -110,16 +125,17 @@ impl fmt::Display for PublicKey {
 
 pub const PRIVATEKEY_SIZE: usize = 32;
 
-#[derive(Clone)]
-pub struct PrivateKey([u8; PRIVATEKEY_SIZE]);
+#[derive(Clone, PartialEq, Eq)]
+pub struct PrivateKey(SecureMem);
 impl fmt::Debug for PrivateKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", hex::encode(self.as_ref()))
+        f.debug_tuple("PrivateKey").field(&self.0).finish()
     }
 }
+// never display the key, use `to_hex` to export it
 impl fmt::Display for PrivateKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", hex::encode(self.as_ref()))
+        write!(f, "<PrivateKey>")
     }
 }
 impl AsRef<[u8]> for PrivateKey {
@@ -131,22 +147,28 @@ impl PrivateKey {
     /// takes the given raw bytes and perform some modifications to normalize
     /// it properly to a Private Key.
     ///
-    pub fn from_bytes(bytes: [u8; PRIVATEKEY_SIZE]) -> Self {
-        PrivateKey(bytes)
+    pub fn from_bytes(mut bytes: [u8; PRIVATEKEY_SIZE]) -> Self {
+        PrivateKey(SecureMem::take(&mut bytes))
     }
 
     pub fn from_slice(bytes: &[u8]) -> Result<Self> {
         if bytes.len() != PRIVATEKEY_SIZE {
             return Err(Error::InvalidPrivateKeySize(bytes.len()));
         }
-        let mut buf = [0; PRIVATEKEY_SIZE];
-        buf[0..PRIVATEKEY_SIZE].clone_from_slice(bytes);
-        Ok(Self::from_bytes(buf))
+        Ok(PrivateKey(SecureMem::from_slice(bytes)))
     }
 
     pub fn from_hex(hex: &str) -> Result<Self> {
-        let bytes = hex::decode(hex)?;
-        Self::from_slice(&bytes)
+        let mut bytes = hex::decode(hex)?;
+        let key = Self::from_slice(&bytes);
+        securemem::zero(&mut bytes);
+        key
+    }
+
+    /// export the key in hexadecimal, the format read by `from_hex`. The
+    /// returned string is not wiped on drop.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.as_ref())
     }
 
     pub fn generate(seed: &[u8]) -> Result<Self> {
@@ -154,13 +176,16 @@ impl PrivateKey {
     }
 
     pub fn public(&self) -> PublicKey {
-        let (_, pk) = ed25519::keypair(&self.0);
+        let (mut sk, pk) = ed25519::keypair(&self.0);
+        securemem::zero(&mut sk);
         PublicKey::from_bytes(pk)
     }
 
     pub fn sign(&self, bytes: &[u8]) -> Signature {
-        let (sk, _) = ed25519::keypair(&self.0);
-        Signature::from_bytes(ed25519::signature(bytes, &sk))
+        let (mut sk, _) = ed25519::keypair(&self.0);
+        let signature = ed25519::signature(bytes, &sk);
+        securemem::zero(&mut sk);
+        Signature::from_bytes(signature)
     }
 }
 
@@ -506,6 +531,15 @@ impl<'de> serde::Deserialize<'de> for Signature {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use util::securemem::assert_not_leaked;
+
+    #[test]
+    fn private_key_is_not_formatted() {
+        let secret = [0x3c; PRIVATEKEY_SIZE];
+        let key = PrivateKey::from_bytes(secret);
+        assert_not_leaked(&format!("{} {:?} {:#?}", key, key, key), &secret);
+        assert_eq!(PrivateKey::from_hex(&key.to_hex()).unwrap(), key);
+    }
 
     use quickcheck::{Arbitrary, Gen};
 
@@ -13,6 +13,7 @@ use std::{
 
 use crate::{
     address::{AddrType, Attributes, ExtendedAddr, SpendingData},
+    cbor::preserved::Preserved,
     coin::{self, Coin},
     config::ProtocolMagic,
     hash::Blake2b256,
@@ -178,7 +179,7 @@ impl TxInWitness {
     /// verify the signature against the given transation `Tx`
     ///
     pub fn verify_tx(&self, protocol_magic: ProtocolMagic, tx: &Tx) -> bool {
-        let vec = Self::prepare_byte_to_sign(protocol_magic, self.get_sign_tag(), &tx.id());
+        let vec = self.signed_data(protocol_magic, tx);
         match self {
             &TxInWitness::PkWitness(ref pk, ref sig) => pk.verify(&vec, sig),
             &TxInWitness::ScriptWitness(_, _) => unimplemented!(),
@@ -186,6 +187,12 @@ impl TxInWitness {
         }
     }
 
+    /// the bytes signed by this witness for the given transaction `Tx`
+    ///
+    pub fn signed_data(&self, protocol_magic: ProtocolMagic, tx: &Tx) -> Vec<u8> {
+        Self::prepare_byte_to_sign(protocol_magic, self.get_sign_tag(), &tx.id())
+    }
+
     fn get_sign_tag(&self) -> SigningTag {
         match self {
             &TxInWitness::PkWitness(_, _) => SigningTag::Tx,
@@ -386,6 +393,13 @@ impl Tx {
         Ok(total)
     }
 }
+impl Preserved<Tx> {
+    /// the identifier of the transaction, computed over the data it was
+    /// decoded from
+    pub fn id(&self) -> TxId {
+        TxId::new(self.as_bytes())
+    }
+}
 impl cbor_event::se::Serialize for Tx {
     fn serialize<'se, W: Write>(
         &self,
@@ -610,6 +624,12 @@ impl TxProof {
             witnesses_hash: Blake2b256::new(&out[..]),
         }
     }
+
+    /// Check the proof that `tx` is included in the transactions of
+    /// this proof, which was made by `TxPayload::make_inclusion_proof`.
+    pub fn verify_inclusion(&self, tx: &Tx, proof: &merkle::MerkleProof) -> bool {
+        proof.number == self.number && proof.verify(tx, &self.root)
+    }
 }
 impl fmt::Display for TxProof {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
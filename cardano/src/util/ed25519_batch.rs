@@ -0,0 +1,696 @@
+//! Ed25519 batch signature verification
+//!
+//! Verifying `n` signatures one by one costs `n` double scalar
+//! multiplications. Here all the signatures are folded into a single
+//! randomized linear combination
+//!
+//! ```text
+//! 8·((Σ zᵢ·sᵢ)·B - Σ (zᵢ·hᵢ)·Aᵢ - Σ zᵢ·Rᵢ) == 0
+//! ```
+//!
+//! which is computed with one multi-scalar multiplication sharing the
+//! point doublings between every term. The `zᵢ` are 128 bits scalars
+//! derived by hashing the whole batch, so a forger cannot choose
+//! signatures that cancel each other out.
+//!
+//! The batch equation is cofactored, the small order components of the
+//! points cancelling out whatever the random coefficients, while
+//! `cryptoxide::ed25519::verify` (and so `XPub::verify`, the redeem keys
+//! and the Haskell node) checks the cofactorless equation. The two only
+//! agree when every `Aᵢ` and `Rᵢ` is torsion free, so the batch is only a
+//! fast path: it is trusted when it holds for canonically encoded,
+//! torsion free points, and every other batch is decided by verifying
+//! the signatures one by one with `cryptoxide::ed25519::verify`.
+
+use cryptoxide::blake2b::Blake2b;
+use cryptoxide::curve25519::{sc_muladd, sc_reduce, Fe};
+use cryptoxide::digest::Digest;
+use cryptoxide::ed25519;
+use cryptoxide::sha2::Sha512;
+use std::collections::BTreeSet;
+
+pub const PUBLIC_KEY_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 64;
+
+/// verify all the given `(public_key, message, signature)` triples at once.
+///
+/// Returns `true` only if every signature is valid, i.e. if
+/// `cryptoxide::ed25519::verify` would accept each of them. An empty
+/// batch is valid. A triple with a public key or a signature of the
+/// wrong size is invalid.
+pub fn verify_batch(entries: &[(&[u8], &[u8], &[u8])]) -> bool {
+    if entries.is_empty() {
+        return true;
+    }
+    if verify_combination(entries, &batch_coefficients(entries)) {
+        return true;
+    }
+    entries.iter().all(|(public_key, message, signature)| {
+        public_key.len() == PUBLIC_KEY_SIZE
+            && signature.len() == SIGNATURE_SIZE
+            && ed25519::verify(message, public_key, signature)
+    })
+}
+
+/// check the linear combination of the signature equations with the
+/// given coefficients.
+///
+/// Returns `false` for anything the cofactorless equation could decide
+/// differently: non canonical encodings and points with a small order
+/// component, as well as invalid signatures.
+fn verify_combination(entries: &[(&[u8], &[u8], &[u8])], coefficients: &[[u8; 32]]) -> bool {
+    // the public keys already known to be torsion free
+    let mut torsion_free_keys = BTreeSet::new();
+    // one term for the base point, then two terms (Aᵢ and Rᵢ) per signature
+    let mut points = Vec::with_capacity(1 + 2 * entries.len());
+    let mut scalars = Vec::with_capacity(1 + 2 * entries.len());
+    let mut base_scalar = [0u8; 32];
+
+    for ((public_key, message, signature), z) in entries.iter().zip(coefficients.iter()) {
+        if public_key.len() != PUBLIC_KEY_SIZE || signature.len() != SIGNATURE_SIZE {
+            return false;
+        }
+        if public_key.iter().all(|b| *b == 0) {
+            return false;
+        }
+        let (r_bytes, s_bytes) = signature.split_at(32);
+        if !scalar_is_canonical(s_bytes) {
+            return false;
+        }
+
+        let a = match Point::decompress(public_key) {
+            None => return false,
+            Some(a) => a,
+        };
+        let r = match Point::decompress(r_bytes) {
+            None => return false,
+            Some(r) => r,
+        };
+        if !r.is_torsion_free() {
+            return false;
+        }
+        if !torsion_free_keys.contains(public_key) {
+            if !a.is_torsion_free() {
+                return false;
+            }
+            torsion_free_keys.insert(*public_key);
+        }
+
+        let mut h = [0u8; 64];
+        let mut hasher = Sha512::new();
+        hasher.input(r_bytes);
+        hasher.input(public_key);
+        hasher.input(message);
+        hasher.result(&mut h);
+        sc_reduce(&mut h);
+
+        // base_scalar += z * s
+        let mut acc = [0u8; 32];
+        sc_muladd(&mut acc, z, s_bytes, &base_scalar);
+        base_scalar = acc;
+
+        // -(z * h) * A
+        let mut zh = [0u8; 32];
+        sc_muladd(&mut zh, z, &h[0..32], &[0u8; 32]);
+        points.push(a.negate());
+        scalars.push(zh);
+
+        // -z * R
+        points.push(r.negate());
+        scalars.push(*z);
+    }
+
+    points.push(Point::base());
+    scalars.push(base_scalar);
+
+    Point::multiscalar_mul(&scalars, &points)
+        .mul_by_cofactor()
+        .is_identity()
+}
+
+/// derive one 128 bits coefficient per entry from the hash of the
+/// whole batch.
+fn batch_coefficients(entries: &[(&[u8], &[u8], &[u8])]) -> Vec<[u8; 32]> {
+    let mut transcript = Blake2b::new(32);
+    transcript.input(b"cardano-ed25519-batch");
+    for (public_key, message, signature) in entries.iter() {
+        transcript.input(public_key);
+        transcript.input(signature);
+        transcript.input(&(message.len() as u64).to_le_bytes());
+        transcript.input(message);
+    }
+    let mut seed = [0u8; 32];
+    transcript.result(&mut seed);
+
+    (0..entries.len() as u64)
+        .map(|index| {
+            let mut h = Blake2b::new(16);
+            h.input(&seed);
+            h.input(&index.to_le_bytes());
+            let mut z = [0u8; 32];
+            h.result(&mut z[0..16]);
+            z
+        })
+        .collect()
+}
+
+/// the order of the base point `L`, little endian
+const GROUP_ORDER: [u8; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
+];
+
+/// check that the scalar `s` is strictly lower than `L`
+fn scalar_is_canonical(s: &[u8]) -> bool {
+    for (byte, order) in s.iter().zip(GROUP_ORDER.iter()).rev() {
+        if byte < order {
+            return true;
+        }
+        if byte > order {
+            return false;
+        }
+    }
+    false
+}
+
+const FE_ZERO: Fe = Fe([0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+const FE_ONE: Fe = Fe([1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+const FE_TWO: Fe = Fe([2, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+const FE_D: Fe = Fe([
+    -10913610, 13857413, -15372611, 6949391, 114729, -8787816, -6275908, -3247719, -18696448,
+    -12055116,
+]);
+const FE_D2: Fe = Fe([
+    -21827239, -5839606, -30745221, 13898782, 229458, 15978800, -12551817, -6495438, 29715968,
+    9444199,
+]);
+const FE_SQRTM1: Fe = Fe([
+    -32595792, -7943725, 9377950, 3500415, 12389472, -272473, -25146209, -2005654, 326686, 11406482,
+]);
+
+/// compressed form of the ed25519 base point
+const BASE_POINT: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+
+fn fe_square(f: Fe) -> Fe {
+    f * f
+}
+
+fn fe_square_times(f: Fe, n: usize) -> Fe {
+    let mut r = f;
+    for _ in 0..n {
+        r = fe_square(r);
+    }
+    r
+}
+
+fn fe_neg(f: Fe) -> Fe {
+    FE_ZERO - f
+}
+
+fn fe_is_zero(f: Fe) -> bool {
+    f.to_bytes().iter().all(|b| *b == 0)
+}
+
+fn fe_is_negative(f: Fe) -> bool {
+    (f.to_bytes()[0] & 1) == 1
+}
+
+/// `f ^ ((p - 5) / 8)`, i.e. `f ^ (2^252 - 3)`
+fn fe_pow22523(f: Fe) -> Fe {
+    let t0 = fe_square(f);
+    let t1 = fe_square_times(t0, 2);
+    let t1 = f * t1;
+    let t0 = t0 * t1;
+    let t0 = fe_square(t0);
+    let t0 = t1 * t0;
+    let t1 = fe_square_times(t0, 5);
+    let t0 = t1 * t0;
+    let t1 = fe_square_times(t0, 10);
+    let t1 = t1 * t0;
+    let t2 = fe_square_times(t1, 20);
+    let t1 = t2 * t1;
+    let t1 = fe_square_times(t1, 10);
+    let t0 = t1 * t0;
+    let t1 = fe_square_times(t0, 50);
+    let t1 = t1 * t0;
+    let t2 = fe_square_times(t1, 100);
+    let t1 = t2 * t1;
+    let t1 = fe_square_times(t1, 50);
+    let t0 = t1 * t0;
+    let t0 = fe_square_times(t0, 2);
+    t0 * f
+}
+
+/// point in extended twisted Edwards coordinates (X:Y:Z:T), `XY = ZT`
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+/// point ready to be added to an extended point
+#[derive(Clone, Copy)]
+struct Cached {
+    y_plus_x: Fe,
+    y_minus_x: Fe,
+    z: Fe,
+    t2d: Fe,
+}
+
+/// intermediate result of an addition or a doubling
+struct Completed {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    fn identity() -> Self {
+        Point {
+            x: FE_ZERO,
+            y: FE_ONE,
+            z: FE_ONE,
+            t: FE_ZERO,
+        }
+    }
+
+    fn base() -> Self {
+        // the base point is a constant valid encoding
+        Point::decompress(&BASE_POINT).unwrap()
+    }
+
+    /// decode a compressed point, rejecting non canonical encodings
+    /// and points that are not on the curve.
+    fn decompress(s: &[u8]) -> Option<Self> {
+        let y = Fe::from_bytes(s);
+
+        // `Fe::from_bytes` ignores the sign bit and reduces modulo p:
+        // a canonical encoding is the one we can round trip.
+        let mut canonical = y.to_bytes();
+        canonical[31] |= s[31] & 0x80;
+        if canonical[..] != s[..] {
+            return None;
+        }
+
+        let yy = fe_square(y);
+        let u = yy - FE_ONE;
+        let v = (yy * FE_D) + FE_ONE;
+        let v3 = fe_square(v) * v;
+        let mut x = fe_square(v3) * v * u;
+        x = fe_pow22523(x);
+        x = x * v3 * u;
+
+        let vxx = fe_square(x) * v;
+        if !fe_is_zero(vxx - u) {
+            if !fe_is_zero(vxx + u) {
+                return None;
+            }
+            x = x * FE_SQRTM1;
+        }
+
+        let sign = (s[31] >> 7) == 1;
+        if sign && fe_is_zero(x) {
+            return None;
+        }
+        if fe_is_negative(x) != sign {
+            x = fe_neg(x);
+        }
+
+        Some(Point {
+            x,
+            y,
+            z: FE_ONE,
+            t: x * y,
+        })
+    }
+
+    fn negate(&self) -> Self {
+        Point {
+            x: fe_neg(self.x),
+            y: self.y,
+            z: self.z,
+            t: fe_neg(self.t),
+        }
+    }
+
+    fn is_identity(&self) -> bool {
+        fe_is_zero(self.x) && fe_is_zero(self.y - self.z)
+    }
+
+    /// check that the point has no small order component, i.e. `L·P == 0`
+    fn is_torsion_free(&self) -> bool {
+        Point::multiscalar_mul(&[GROUP_ORDER], &[*self]).is_identity()
+    }
+
+    /// multiply by the cofactor 8, clearing the small order component
+    fn mul_by_cofactor(&self) -> Self {
+        self.double()
+            .to_point()
+            .double()
+            .to_point()
+            .double()
+            .to_point()
+    }
+
+    fn to_cached(self) -> Cached {
+        Cached {
+            y_plus_x: self.y + self.x,
+            y_minus_x: self.y - self.x,
+            z: self.z,
+            t2d: self.t * FE_D2,
+        }
+    }
+
+    fn add_cached(&self, q: &Cached) -> Completed {
+        let a = (self.y + self.x) * q.y_plus_x;
+        let b = (self.y - self.x) * q.y_minus_x;
+        let c = q.t2d * self.t;
+        let zz = self.z * q.z;
+        let d = zz + zz;
+        Completed {
+            x: a - b,
+            y: a + b,
+            z: d + c,
+            t: d - c,
+        }
+    }
+
+    fn sub_cached(&self, q: &Cached) -> Completed {
+        let a = (self.y + self.x) * q.y_minus_x;
+        let b = (self.y - self.x) * q.y_plus_x;
+        let c = q.t2d * self.t;
+        let zz = self.z * q.z;
+        let d = zz + zz;
+        Completed {
+            x: a - b,
+            y: a + b,
+            z: d - c,
+            t: d + c,
+        }
+    }
+
+    fn double(&self) -> Completed {
+        let xx = fe_square(self.x);
+        let yy = fe_square(self.y);
+        // multiplying (rather than adding) keeps the limbs small enough
+        // for the subtraction below to be a valid `Fe::mul` input.
+        let zz2 = fe_square(self.z) * FE_TWO;
+        let xy = fe_square(self.x + self.y);
+        let y = yy + xx;
+        let z = yy - xx;
+        Completed {
+            x: xy - y,
+            y,
+            z,
+            t: zz2 - z,
+        }
+    }
+
+    /// odd multiples `P, 3P, 5P, ..., 15P` of the point
+    fn odd_multiples(&self) -> [Cached; 8] {
+        let mut table = [self.to_cached(); 8];
+        let p2 = self.double().to_point();
+        for i in 1..8 {
+            table[i] = p2.add_cached(&table[i - 1]).to_point().to_cached();
+        }
+        table
+    }
+
+    /// compute `Σ scalars[i] * points[i]` with interleaved sliding
+    /// windows, sharing the doublings between all the terms.
+    fn multiscalar_mul(scalars: &[[u8; 32]], points: &[Point]) -> Self {
+        let digits: Vec<[i8; 256]> = scalars.iter().map(slide).collect();
+        let tables: Vec<[Cached; 8]> = points.iter().map(|p| p.odd_multiples()).collect();
+
+        let top = match digits
+            .iter()
+            .filter_map(|d| d.iter().rposition(|x| *x != 0))
+            .max()
+        {
+            None => return Point::identity(),
+            Some(top) => top,
+        };
+
+        let mut r = Point::identity();
+        for i in (0..=top).rev() {
+            r = r.double().to_point();
+            for (d, table) in digits.iter().zip(tables.iter()) {
+                let digit = d[i];
+                if digit > 0 {
+                    r = r.add_cached(&table[(digit / 2) as usize]).to_point();
+                } else if digit < 0 {
+                    r = r.sub_cached(&table[(-digit / 2) as usize]).to_point();
+                }
+            }
+        }
+        r
+    }
+}
+
+impl Completed {
+    fn to_point(&self) -> Point {
+        Point {
+            x: self.x * self.t,
+            y: self.y * self.z,
+            z: self.z * self.t,
+            t: self.x * self.y,
+        }
+    }
+}
+
+/// signed sliding window representation of a scalar, every digit is
+/// either 0 or odd in `[-15, 15]`.
+fn slide(a: &[u8; 32]) -> [i8; 256] {
+    let mut r = [0i8; 256];
+    for (i, digit) in r.iter_mut().enumerate() {
+        *digit = (1 & (a[i >> 3] >> (i & 7))) as i8;
+    }
+    for i in 0..256 {
+        if r[i] == 0 {
+            continue;
+        }
+        let mut b = 1;
+        while b <= 6 && i + b < 256 {
+            if r[i + b] != 0 {
+                if r[i] + (r[i + b] << b) <= 15 {
+                    r[i] += r[i + b] << b;
+                    r[i + b] = 0;
+                } else if r[i] - (r[i + b] << b) >= -15 {
+                    r[i] -= r[i + b] << b;
+                    for digit in r[i + b..].iter_mut() {
+                        if *digit == 0 {
+                            *digit = 1;
+                            break;
+                        }
+                        *digit = 0;
+                    }
+                } else {
+                    break;
+                }
+            }
+            b += 1;
+        }
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cryptoxide::ed25519;
+
+    fn keypair(seed: u8) -> ([u8; 64], [u8; 32]) {
+        ed25519::keypair(&[seed; 32])
+    }
+
+    fn signed(count: usize) -> Vec<([u8; 32], Vec<u8>, [u8; 64])> {
+        (0..count)
+            .map(|i| {
+                let (sk, pk) = keypair(i as u8);
+                let message = format!("message number {}", i).into_bytes();
+                let signature = ed25519::signature(&message, &sk);
+                (pk, message, signature)
+            })
+            .collect()
+    }
+
+    fn as_entries(sigs: &[([u8; 32], Vec<u8>, [u8; 64])]) -> Vec<(&[u8], &[u8], &[u8])> {
+        sigs.iter()
+            .map(|(pk, msg, sig)| (&pk[..], &msg[..], &sig[..]))
+            .collect()
+    }
+
+    #[test]
+    fn empty_batch() {
+        assert!(verify_batch(&[]));
+    }
+
+    #[test]
+    fn base_point_roundtrip() {
+        let b = Point::base();
+        let mut encoded = (b.y * b.z.invert()).to_bytes();
+        let x = b.x * b.z.invert();
+        encoded[31] |= (fe_is_negative(x) as u8) << 7;
+        assert_eq!(encoded, BASE_POINT);
+    }
+
+    #[test]
+    fn valid_batches() {
+        for count in &[1, 2, 3, 16, 33] {
+            let sigs = signed(*count);
+            assert!(verify_batch(&as_entries(&sigs)), "batch of {}", count);
+        }
+    }
+
+    #[test]
+    fn invalid_message() {
+        let mut sigs = signed(10);
+        sigs[7].1[0] ^= 1;
+        assert!(!verify_batch(&as_entries(&sigs)));
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let mut sigs = signed(10);
+        sigs[3].2[40] ^= 1;
+        assert!(!verify_batch(&as_entries(&sigs)));
+    }
+
+    #[test]
+    fn swapped_signatures() {
+        let mut sigs = signed(4);
+        let sig = sigs[0].2;
+        sigs[0].2 = sigs[1].2;
+        sigs[1].2 = sig;
+        assert!(!verify_batch(&as_entries(&sigs)));
+    }
+
+    #[test]
+    fn non_canonical_scalar() {
+        let mut sigs = signed(2);
+        sigs[1].2[32..64].copy_from_slice(&GROUP_ORDER);
+        assert!(!verify_batch(&as_entries(&sigs)));
+    }
+
+    #[test]
+    fn wrong_sizes() {
+        let sigs = signed(1);
+        let (pk, msg, sig) = &sigs[0];
+        assert!(!verify_batch(&[(&pk[0..31], &msg[..], &sig[..])]));
+        assert!(!verify_batch(&[(&pk[..], &msg[..], &sig[0..63])]));
+    }
+
+    /// compressed encoding of a point
+    fn compress(p: &Point) -> [u8; 32] {
+        let z_inv = p.z.invert();
+        let mut encoded = (p.y * z_inv).to_bytes();
+        encoded[31] |= (fe_is_negative(p.x * z_inv) as u8) << 7;
+        encoded
+    }
+
+    fn reduced(bytes: &[u8]) -> [u8; 32] {
+        let mut h = [0u8; 64];
+        let mut hasher = Sha512::new();
+        hasher.input(bytes);
+        hasher.result(&mut h);
+        sc_reduce(&mut h);
+        let mut scalar = [0u8; 32];
+        scalar.copy_from_slice(&h[0..32]);
+        scalar
+    }
+
+    #[test]
+    fn small_order_components() {
+        // a point of order 8
+        let torsion = Point::decompress(&[
+            0xc7, 0x17, 0x6a, 0x70, 0x3d, 0x4d, 0xd8, 0x4f, 0xba, 0x3c, 0x0b, 0x76, 0x0d, 0x10,
+            0x67, 0x0f, 0x2a, 0x20, 0x53, 0xfa, 0x2c, 0x39, 0xcc, 0xc6, 0x4e, 0xc7, 0xfd, 0x77,
+            0x92, 0xac, 0x03, 0x7a,
+        ])
+        .unwrap();
+        assert!(!torsion.is_identity());
+        assert!(torsion.mul_by_cofactor().is_identity());
+
+        // a public key `a·B + T`, signing with `a`: the cofactorless
+        // verification only accepts the signatures whose `h` is a
+        // multiple of 8 while the cofactored batch equation holds for
+        // all of them, so the batch has to fall back on the single
+        // verification to agree with it.
+        let a = reduced(b"secret");
+        let public_key = compress(
+            &Point::multiscalar_mul(&[a], &[Point::base()])
+                .add_cached(&torsion.to_cached())
+                .to_point(),
+        );
+        assert!(!Point::decompress(&public_key).unwrap().is_torsion_free());
+        let mut cofactorless = 0;
+        for i in 0..64u8 {
+            let message = [i];
+            let r = reduced(&[b'r', i]);
+            let r_bytes = compress(&Point::multiscalar_mul(&[r], &[Point::base()]));
+            let mut hasher = Sha512::new();
+            hasher.input(&r_bytes);
+            hasher.input(&public_key);
+            hasher.input(&message);
+            let mut h = [0u8; 64];
+            hasher.result(&mut h);
+            sc_reduce(&mut h);
+            let mut signature = [0u8; 64];
+            signature[0..32].copy_from_slice(&r_bytes);
+            sc_muladd(&mut signature[32..64], &h[0..32], &a, &r);
+
+            let expected = ed25519::verify(&message, &public_key, &signature);
+            if expected {
+                cofactorless += 1;
+            }
+            let sigs = signed(3);
+            let mut entries = as_entries(&sigs);
+            entries.insert(1, (&public_key[..], &message[..], &signature[..]));
+            assert!(
+                !verify_combination(&entries, &batch_coefficients(&entries)),
+                "message {}",
+                i
+            );
+            assert_eq!(verify_batch(&entries), expected, "message {}", i);
+        }
+        assert!(cofactorless > 0 && cofactorless < 64);
+    }
+
+    #[test]
+    fn honest_points_are_torsion_free() {
+        for (public_key, _, signature) in signed(4) {
+            assert!(Point::decompress(&public_key).unwrap().is_torsion_free());
+            assert!(Point::decompress(&signature[0..32])
+                .unwrap()
+                .is_torsion_free());
+        }
+    }
+
+    quickcheck! {
+        fn batch_agrees_with_cofactorless_verification(messages: Vec<(u8, Vec<u8>, bool)>) -> bool {
+            let sigs: Vec<([u8; 32], Vec<u8>, [u8; 64])> = messages
+                .iter()
+                .map(|(seed, msg, corrupt)| {
+                    let (sk, pk) = keypair(*seed);
+                    let mut signature = ed25519::signature(msg, &sk);
+                    if *corrupt {
+                        signature[0] ^= 0x10;
+                    }
+                    (pk, msg.clone(), signature)
+                })
+                .collect();
+            let expected = sigs
+                .iter()
+                .all(|(pk, msg, sig)| ed25519::verify(msg, pk, sig));
+            let entries = as_entries(&sigs);
+            let fast_path = verify_combination(&entries, &batch_coefficients(&entries));
+            fast_path == expected && verify_batch(&entries) == expected
+        }
+    }
+}
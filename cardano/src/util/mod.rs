@@ -1,6 +1,7 @@
 pub mod base58;
 pub mod bits;
 pub mod diff_maps;
+pub mod ed25519_batch;
 pub mod hex;
 pub mod securemem;
 pub mod try_from_slice;
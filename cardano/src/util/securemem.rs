@@ -1,12 +1,216 @@
+//! Secret memory helpers
+//!
+//! Every secret of the library (seeds, extended private keys, redeem
+//! private keys, HD payload keys...) is kept in a [`SecureMem`] buffer:
+//!
+//! * the buffer lives on the heap, at a fixed address, so moving the
+//!   owning type around does not leave copies of the secret behind;
+//! * the memory is zeroed when the buffer is dropped;
+//! * comparing two buffers is done in constant time;
+//! * the formatting traits never display the content of the buffer;
+//! * with the `with-mlock` feature, on Linux, the memory is locked so it
+//!   is not swapped to disk (this is best effort: it may fail if the
+//!   process is over its `RLIMIT_MEMLOCK`).
+//!
+//! [`SecureMem`]: ./struct.SecureMem.html
+
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic,
+};
+
 /// zero the given slice.
 ///
-/// We assume the compiler won't optimise out the call to this function
+/// The writes are volatile so the compiler does not optimise them out,
+/// even when the memory is about to be released.
 pub fn zero(to_zero: &mut [u8]) {
-    // the unsafety of this call is bounded to the existence of the pointer
-    // and the accuracy of the length of the array.
-    //
-    // since to_zero existence is bound to live at least as long as the call
-    // of this function and that we use the length (in bytes) of the given
-    // slice, this call is safe.
-    unsafe { ::std::ptr::write_bytes(to_zero.as_mut_ptr(), 0, to_zero.len()) }
+    for byte in to_zero.iter_mut() {
+        // the unsafety of this call is bounded to the existence of the
+        // pointer, which is a valid mutable reference.
+        unsafe { ::std::ptr::write_volatile(byte, 0) }
+    }
+    atomic::compiler_fence(atomic::Ordering::SeqCst);
+}
+
+/// compare the two given slices in constant time (with regard to the
+/// content of the slices, not their length).
+///
+/// ```
+/// use cardano::util::securemem::constant_time_eq;
+///
+/// assert!(constant_time_eq(b"secret", b"secret"));
+/// assert!(!constant_time_eq(b"secret", b"Secret"));
+/// assert!(!constant_time_eq(b"secret", b"secrets"));
+/// ```
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let diff = a
+        .iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    // prevent the compiler from short-circuiting on the accumulator
+    unsafe { ::std::ptr::read_volatile(&diff) == 0 }
+}
+
+#[cfg(all(feature = "with-mlock", target_os = "linux"))]
+mod lock {
+    use std::os::raw::{c_int, c_void};
+
+    extern "C" {
+        fn mlock(addr: *const c_void, len: usize) -> c_int;
+        fn munlock(addr: *const c_void, len: usize) -> c_int;
+    }
+
+    pub fn lock(bytes: &[u8]) {
+        if !bytes.is_empty() {
+            // failing to lock the memory is not an error: the secret is
+            // still zeroed on drop.
+            unsafe { mlock(bytes.as_ptr() as *const c_void, bytes.len()) };
+        }
+    }
+
+    pub fn unlock(bytes: &[u8]) {
+        if !bytes.is_empty() {
+            unsafe { munlock(bytes.as_ptr() as *const c_void, bytes.len()) };
+        }
+    }
+}
+
+#[cfg(not(all(feature = "with-mlock", target_os = "linux")))]
+mod lock {
+    pub fn lock(_: &[u8]) {}
+    pub fn unlock(_: &[u8]) {}
+}
+
+/// fixed size buffer holding secret bytes
+///
+/// ```
+/// use cardano::util::securemem::SecureMem;
+///
+/// let mut key = [42u8; 32];
+/// let secret = SecureMem::take(&mut key);
+///
+/// assert_eq!(key, [0; 32]);
+/// assert_eq!(secret.len(), 32);
+/// assert_eq!(format!("{:?}", secret), "SecureMem(<32 bytes>)");
+/// ```
+pub struct SecureMem(Box<[u8]>);
+impl SecureMem {
+    /// allocate a new buffer of `len` bytes, all set to zero.
+    pub fn new(len: usize) -> Self {
+        let bytes = vec![0; len].into_boxed_slice();
+        lock::lock(&bytes);
+        SecureMem(bytes)
+    }
+
+    /// create a buffer by copying the given slice
+    pub fn from_slice(bytes: &[u8]) -> Self {
+        let mut mem = Self::new(bytes.len());
+        mem.copy_from_slice(bytes);
+        mem
+    }
+
+    /// create a buffer by copying the given slice, then zero the slice.
+    ///
+    /// Use this to move a secret built on the stack into the buffer.
+    pub fn take(bytes: &mut [u8]) -> Self {
+        let mem = Self::from_slice(bytes);
+        zero(bytes);
+        mem
+    }
+}
+impl Clone for SecureMem {
+    fn clone(&self) -> Self {
+        Self::from_slice(self)
+    }
+}
+impl PartialEq for SecureMem {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(self, other)
+    }
+}
+impl Eq for SecureMem {}
+impl fmt::Debug for SecureMem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecureMem(<{} bytes>)", self.0.len())
+    }
+}
+impl Deref for SecureMem {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl DerefMut for SecureMem {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+impl AsRef<[u8]> for SecureMem {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+impl AsMut<[u8]> for SecureMem {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+impl Drop for SecureMem {
+    fn drop(&mut self) {
+        zero(&mut self.0);
+        lock::unlock(&self.0);
+    }
+}
+
+/// check the formatted output of a secret does not contain the secret,
+/// in any of the usual representations (hexadecimal, list of bytes).
+#[cfg(test)]
+pub fn assert_not_leaked(formatted: &str, secret: &[u8]) {
+    use util::hex;
+
+    let encoded = hex::encode(secret);
+    assert!(!formatted.contains(&encoded), "hex leak: {}", formatted);
+    assert!(
+        !formatted.contains(&encoded.to_uppercase()),
+        "hex leak: {}",
+        formatted
+    );
+    let bytes = format!("{:?}", secret);
+    let bytes = &bytes[1..bytes.len() - 1];
+    assert!(!formatted.contains(bytes), "bytes leak: {}", formatted);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_zeroes_the_source() {
+        let mut bytes = [0x42; 16];
+        let mem = SecureMem::take(&mut bytes);
+        assert_eq!(bytes, [0; 16]);
+        assert_eq!(&mem[..], &[0x42; 16][..]);
+    }
+
+    #[test]
+    fn equality() {
+        let a = SecureMem::from_slice(b"0123456789abcdef");
+        let b = a.clone();
+        let c = SecureMem::from_slice(b"0123456789abcdeF");
+        let d = SecureMem::from_slice(b"0123456789abcde");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(a, d);
+    }
+
+    #[test]
+    fn debug_does_not_leak() {
+        let secret = [0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89];
+        let mem = SecureMem::from_slice(&secret);
+        assert_not_leaked(&format!("{:?} {:#?}", mem, mem), &secret);
+    }
 }
@@ -83,6 +83,12 @@ impl fmt::Display for Signature {
         write!(f, "{}", hex::encode(self.as_ref()))
     }
 }
+impl PartialEq for Signature {
+    fn eq(&self, other: &Self) -> bool {
+        PartialEq::eq(&self.0[..], &other.0[..])
+    }
+}
+impl Eq for Signature {}
 impl AsRef<[u8]> for Signature {
     fn as_ref(&self) -> &[u8] {
         &self.0
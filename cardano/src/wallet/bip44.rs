@@ -7,6 +7,7 @@ use config::{NetworkMagic, ProtocolMagic};
 use hdwallet::{DerivationIndex, DerivationScheme, Result, XPrv, XPub, XPRV_SIZE};
 use std::{collections::BTreeMap, ops::Deref};
 use tx::{TxId, TxInWitness};
+use util::securemem;
 
 use super::keygen;
 use super::scheme;
@@ -97,6 +98,7 @@ impl Wallet {
         let mut seed = [0u8; XPRV_SIZE];
         keygen::generate_seed(entropy, password, &mut seed);
         let xprv = XPrv::normalize_bytes(seed);
+        securemem::zero(&mut seed);
         Wallet::from_root_key(xprv, derivation_scheme)
     }
 
@@ -14,6 +14,7 @@ use input_selection;
 use std::{error, fmt, iter, ops::Deref};
 use tx::{self, Tx, TxAux, TxId, TxInWitness};
 use txutils::{self, OutputPolicy};
+use util::securemem;
 
 use super::scheme;
 
@@ -361,19 +362,25 @@ impl RootKey {
         let mnemonics = bip39::Mnemonics::from_string(dic, mnemonics_phrase)?;
         let entropy = bip39::Entropy::from_mnemonics(&mnemonics)?;
 
-        let entropy_bytes = cbor_event::Value::Bytes(Vec::from(entropy.as_ref()));
-        let entropy_cbor = cbor!(&entropy_bytes)?;
-        let seed: Vec<u8> = {
+        let mut entropy_cbor = {
+            let mut se = cbor_event::se::Serializer::new_vec();
+            se.write_bytes(entropy.as_ref())?;
+            se.finalize()
+        };
+        let mut seed: Vec<u8> = {
             let mut blake2b = cryptoxide::blake2b::Blake2b::new(32);
             blake2b.input(&entropy_cbor);
             let mut out = [0; 32];
             blake2b.result(&mut out);
             let mut se = cbor_event::se::Serializer::new_vec();
-            se.write_bytes(&Vec::from(&out[..]))?;
+            se.write_bytes(&out[..])?;
+            securemem::zero(&mut out);
             se.finalize()
         };
 
         let xprv = XPrv::generate_from_daedalus_seed(&seed);
+        securemem::zero(&mut entropy_cbor);
+        securemem::zero(&mut seed);
         Ok(RootKey::new(xprv, derivation_scheme))
     }
 
@@ -0,0 +1,70 @@
+//! Run the library against the conformance vectors of
+//! `test-vectors/conformance`.
+//!
+//! The vectors can be regenerated with:
+//!
+//! ```text
+//! cargo run --example generate_test_vectors -- test-vectors/conformance
+//! ```
+
+extern crate cardano;
+#[macro_use]
+extern crate cbor_event;
+#[macro_use]
+extern crate serde_json;
+
+mod vectors;
+
+use std::{fs, path::PathBuf};
+
+fn conformance_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("test-vectors")
+        .join("conformance")
+}
+
+#[test]
+fn conformance_vectors() {
+    let mut kinds = Vec::new();
+    for entry in fs::read_dir(conformance_dir()).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().map_or(true, |ext| ext != "json") {
+            continue;
+        }
+        let content: serde_json::Value =
+            serde_json::from_reader(fs::File::open(&path).unwrap()).unwrap();
+        let count =
+            vectors::check_file(&content).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        assert!(count > 0, "{}: no vectors", path.display());
+        kinds.push(content["kind"].as_str().unwrap().to_owned());
+    }
+
+    kinds.sort();
+    let mut expected = vec![
+        vectors::ADDRESSES,
+        vectors::BLOCK_HEADERS,
+        vectors::DERIVATIONS,
+        vectors::ROOT_KEYS,
+        vectors::TRANSACTIONS,
+    ];
+    expected.sort();
+    assert_eq!(kinds, expected);
+}
+
+#[test]
+fn generated_vectors_are_valid() {
+    for (kind, content) in vectors::generate() {
+        vectors::check_file(&content).unwrap_or_else(|e| panic!("{}: {}", kind, e));
+    }
+}
+
+#[test]
+fn mismatching_vector_is_reported() {
+    let mut content = vectors::generate()
+        .into_iter()
+        .find(|(kind, _)| *kind == vectors::ADDRESSES)
+        .unwrap()
+        .1;
+    content["vectors"][0]["network_magic"] = json!(42);
+    assert!(vectors::check_file(&content).is_err());
+}
@@ -0,0 +1,624 @@
+//! Conformance test vectors
+//!
+//! The vectors live in `test-vectors/conformance`, one JSON file per kind
+//! of vector:
+//!
+//! ```json
+//! { "kind": "root_keys", "vectors": [ { ... }, { ... } ] }
+//! ```
+//!
+//! The supported kinds are:
+//!
+//! * `root_keys`: mnemonics (and password) to the root private key of a
+//!   wallet. `V1` is the Daedalus (random index) scheme, `V2` the BIP44
+//!   (Icarus) scheme;
+//! * `derivations`: a root private key and a derivation path to the
+//!   derived public key;
+//! * `addresses`: a public key, the optional network magic and the
+//!   optional HD payload to the base58 address;
+//! * `transactions`: inputs, outputs and signing keys to the `TxId` and
+//!   the CBOR of the signed transaction (`TxAux`);
+//! * `block_headers`: the CBOR of a block header to its hash.
+//!
+//! This module is shared between the `conformance` test, which checks the
+//! library against every vector, and the `generate_test_vectors` example
+//! which emits the vectors from the current implementation.
+
+#![allow(dead_code)]
+
+use cardano::{
+    address::{AddrType, Attributes, ExtendedAddr, SpendingData},
+    bip::bip39::{self, dictionary},
+    block::{boundary, normal, sign, types, update, BlockHeader, HeaderHash, RawBlockHeader},
+    coin::Coin,
+    config::{NetworkMagic, ProtocolMagic},
+    hash::Blake2b256,
+    hdpayload::{HDAddressPayload, HDKey, Path},
+    hdwallet::{DerivationScheme, XPrv, XPub, XPRV_SIZE},
+    tx::{Tx, TxAux, TxId, TxInWitness, TxOut, TxWitness, TxoPointer},
+    util::hex,
+    wallet::{bip44, rindex},
+};
+use serde_json::Value;
+use std::{collections::BTreeMap, str::FromStr};
+
+pub type Result<T> = ::std::result::Result<T, String>;
+
+pub const ROOT_KEYS: &str = "root_keys";
+pub const DERIVATIONS: &str = "derivations";
+pub const ADDRESSES: &str = "addresses";
+pub const TRANSACTIONS: &str = "transactions";
+pub const BLOCK_HEADERS: &str = "block_headers";
+
+/// check the library against every vector of the given file content.
+///
+/// Returns the number of vectors checked.
+pub fn check_file(content: &Value) -> Result<usize> {
+    let kind = str_field(content, "kind")?;
+    let vectors = field(content, "vectors")?
+        .as_array()
+        .ok_or_else(|| "`vectors` is not an array".to_owned())?;
+    for (i, vector) in vectors.iter().enumerate() {
+        check(kind, vector).map_err(|e| format!("{} vector #{}: {}", kind, i, e))?;
+    }
+    Ok(vectors.len())
+}
+
+/// check the library against the given vector
+pub fn check(kind: &str, vector: &Value) -> Result<()> {
+    match kind {
+        ROOT_KEYS => check_root_key(vector),
+        DERIVATIONS => check_derivation(vector),
+        ADDRESSES => check_address(vector),
+        TRANSACTIONS => check_transaction(vector),
+        BLOCK_HEADERS => check_block_header(vector),
+        _ => Err(format!("unknown kind of vector `{}`", kind)),
+    }
+}
+
+/// generate the vectors from the current implementation.
+///
+/// The inputs are deterministic so running the generator twice
+/// gives the same vectors.
+pub fn generate() -> Vec<(&'static str, Value)> {
+    let root_keys = generate_root_keys();
+    let derivations = generate_derivations(&root_keys);
+    let addresses = generate_addresses(&derivations);
+    let transactions = generate_transactions(&derivations);
+    let block_headers = generate_block_headers(&derivations);
+
+    vec![
+        (ROOT_KEYS, root_keys),
+        (DERIVATIONS, derivations),
+        (ADDRESSES, addresses),
+        (TRANSACTIONS, transactions),
+        (BLOCK_HEADERS, block_headers),
+    ]
+    .into_iter()
+    .map(|(kind, vectors)| (kind, json!({ "kind": kind, "vectors": vectors })))
+    .collect()
+}
+
+/* ************************************************************************* *
+ *                               Root keys                                   *
+ * ************************************************************************* */
+
+fn root_key(mnemonics: &str, password: &str, scheme: DerivationScheme) -> Result<XPrv> {
+    match scheme {
+        DerivationScheme::V1 => {
+            if !password.is_empty() {
+                return Err("V1 root keys do not support passwords".to_owned());
+            }
+            rindex::RootKey::from_daedalus_mnemonics(scheme, &dictionary::ENGLISH, mnemonics)
+                .map(rindex::RootKey::into_xprv)
+                .map_err(|e| format!("invalid mnemonics: {}", e))
+        }
+        DerivationScheme::V2 => {
+            let mnemonics = bip39::Mnemonics::from_string(&dictionary::ENGLISH, mnemonics)
+                .map_err(|e| format!("invalid mnemonics: {}", e))?;
+            let entropy = bip39::Entropy::from_mnemonics(&mnemonics)
+                .map_err(|e| format!("invalid mnemonics: {}", e))?;
+            let wallet = bip44::Wallet::from_entropy(&entropy, password.as_bytes(), scheme);
+            Ok((**wallet).clone())
+        }
+    }
+}
+
+fn check_root_key(vector: &Value) -> Result<()> {
+    let mnemonics = str_field(vector, "mnemonics")?;
+    let password = str_field(vector, "password")?;
+    let scheme = scheme_field(vector)?;
+
+    let xprv = root_key(mnemonics, password, scheme)?;
+    expect(vector, "root_xprv", &hex::encode(xprv.as_ref()))
+}
+
+fn generate_root_keys() -> Value {
+    let mut vectors = Vec::new();
+    for (i, &(size, password)) in [(16, ""), (20, ""), (24, "cardano"), (28, ""), (32, "")]
+        .iter()
+        .enumerate()
+    {
+        let bytes = Blake2b256::new(format!("conformance entropy {}", i).as_bytes());
+        let entropy = bip39::Entropy::from_slice(&bytes.as_ref()[..size]).unwrap();
+        let mnemonics = entropy.to_mnemonics().to_string(&dictionary::ENGLISH);
+
+        let mut schemes = vec![DerivationScheme::V2];
+        if password.is_empty() {
+            schemes.push(DerivationScheme::V1);
+        }
+        for scheme in schemes {
+            let xprv = root_key(&mnemonics, password, scheme).unwrap();
+            vectors.push(json!({
+                "mnemonics": &*mnemonics,
+                "password": password,
+                "scheme": scheme_to_str(scheme),
+                "root_xprv": hex::encode(xprv.as_ref()),
+            }));
+        }
+    }
+    Value::Array(vectors)
+}
+
+/* ************************************************************************* *
+ *                               Derivations                                 *
+ * ************************************************************************* */
+
+fn derive_xprv(root: &XPrv, scheme: DerivationScheme, path: &[u32]) -> XPrv {
+    path.iter()
+        .fold(root.clone(), |xprv, index| xprv.derive(scheme, *index))
+}
+
+fn check_derivation(vector: &Value) -> Result<()> {
+    let root = xprv_field(vector, "root_xprv")?;
+    let scheme = scheme_field(vector)?;
+    let path = path_field(vector, "path")?;
+
+    let xpub = derive_xprv(&root, scheme, &path).public();
+    expect(vector, "xpub", &format!("{}", xpub))?;
+
+    // soft derivation has to give the same result from the public key
+    if path.iter().all(|index| *index < 0x8000_0000) {
+        let mut public = root.public();
+        for index in path.iter() {
+            public = public
+                .derive(scheme, *index)
+                .map_err(|e| format!("public derivation failed: {}", e))?;
+        }
+        if public != xpub {
+            return Err(format!(
+                "public derivation mismatch: expected {} got {}",
+                xpub, public
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn generate_derivations(root_keys: &Value) -> Value {
+    const H: u32 = 0x8000_0000;
+    let bip44_paths: &[&[u32]] = &[
+        &[],
+        &[H | 44, H | 1815, H],
+        &[H | 44, H | 1815, H, 0, 0],
+        &[H | 44, H | 1815, H | 1, 1, 42],
+    ];
+    let rindex_paths: &[&[u32]] = &[&[], &[H, H | 1], &[H | 0x7fff_ffff, H | 12]];
+    let soft_paths: &[&[u32]] = &[&[0], &[1, 2, 3]];
+
+    let mut vectors = Vec::new();
+    for root_key in root_keys.as_array().unwrap() {
+        let root = xprv_field(root_key, "root_xprv").unwrap();
+        let scheme = scheme_field(root_key).unwrap();
+        let paths = match scheme {
+            DerivationScheme::V1 => rindex_paths,
+            DerivationScheme::V2 => bip44_paths,
+        };
+        for path in paths.iter().chain(soft_paths.iter()) {
+            let xpub = derive_xprv(&root, scheme, path).public();
+            vectors.push(json!({
+                "root_xprv": hex::encode(root.as_ref()),
+                "scheme": scheme_to_str(scheme),
+                "path": path,
+                "xpub": format!("{}", xpub),
+            }));
+        }
+    }
+    Value::Array(vectors)
+}
+
+/* ************************************************************************* *
+ *                               Addresses                                   *
+ * ************************************************************************* */
+
+fn check_address(vector: &Value) -> Result<()> {
+    let xpub = xpub_field(vector, "xpub")?;
+    let network_magic = match field(vector, "network_magic")? {
+        Value::Null => NetworkMagic::NoMagic,
+        v => NetworkMagic::Magic(as_u32(v, "network_magic")?),
+    };
+    let payload = match field(vector, "hd_payload")? {
+        Value::Null => None,
+        v => Some(HDAddressPayload::from_vec(as_hex(v, "hd_payload")?)),
+    };
+
+    let address = ExtendedAddr::new(
+        AddrType::ATPubKey,
+        SpendingData::PubKeyASD(xpub),
+        Attributes::new_bootstrap_era(payload, network_magic),
+    );
+    expect(vector, "address", &format!("{}", address))?;
+
+    let expected = str_field(vector, "address")?;
+    let decoded = ExtendedAddr::from_str(expected)
+        .map_err(|e| format!("cannot decode address {}: {:?}", expected, e))?;
+    if decoded != address {
+        return Err(format!(
+            "decoded address mismatch: expected {:?} got {:?}",
+            address, decoded
+        ));
+    }
+    Ok(())
+}
+
+fn generate_addresses(derivations: &Value) -> Value {
+    let mut vectors = Vec::new();
+    for derivation in derivations.as_array().unwrap() {
+        let root = xprv_field(derivation, "root_xprv").unwrap();
+        let path = path_field(derivation, "path").unwrap();
+        let xpub = xpub_field(derivation, "xpub").unwrap();
+
+        // addresses of the random index scheme embed the encrypted
+        // derivation path
+        let payload = match scheme_field(derivation).unwrap() {
+            DerivationScheme::V1 if !path.is_empty() => {
+                Some(HDKey::new(&root.public()).encrypt_path(&Path::new(path)))
+            }
+            _ => None,
+        };
+
+        for network_magic in [None, Some(1_097_911_063u32)].iter() {
+            let address = ExtendedAddr::new(
+                AddrType::ATPubKey,
+                SpendingData::PubKeyASD(xpub.clone()),
+                Attributes::new_bootstrap_era(
+                    payload.clone(),
+                    network_magic.map_or(NetworkMagic::NoMagic, NetworkMagic::Magic),
+                ),
+            );
+            vectors.push(json!({
+                "xpub": format!("{}", xpub),
+                "network_magic": network_magic,
+                "hd_payload": payload.as_ref().map(|p| hex::encode(p.as_ref())),
+                "address": format!("{}", address),
+            }));
+        }
+    }
+    Value::Array(vectors)
+}
+
+/* ************************************************************************* *
+ *                               Transactions                                *
+ * ************************************************************************* */
+
+fn signed_transaction(pm: ProtocolMagic, tx: Tx, keys: &[XPrv]) -> TxAux {
+    let id = tx.id();
+    let witnesses = keys
+        .iter()
+        .map(|key| TxInWitness::new_extended_pk(pm, key, &id))
+        .collect::<Vec<_>>();
+    TxAux::new(tx, TxWitness::from(witnesses))
+}
+
+fn check_transaction(vector: &Value) -> Result<()> {
+    let pm = ProtocolMagic::from(u32_field(vector, "protocol_magic")?);
+    let mut inputs = Vec::new();
+    for input in array_field(vector, "inputs")? {
+        let id = TxId::from_str(str_field(input, "id")?)
+            .map_err(|e| format!("invalid input id: {}", e))?;
+        inputs.push(TxoPointer::new(id, u32_field(input, "index")?));
+    }
+    let mut outputs = Vec::new();
+    for output in array_field(vector, "outputs")? {
+        let address = ExtendedAddr::from_str(str_field(output, "address")?)
+            .map_err(|e| format!("invalid output address: {:?}", e))?;
+        let value = Coin::new(u64_field(output, "value")?)
+            .map_err(|e| format!("invalid output value: {}", e))?;
+        outputs.push(TxOut::new(address, value));
+    }
+    let mut keys = Vec::new();
+    for key in array_field(vector, "signing_keys")? {
+        keys.push(as_xprv(key, "signing_keys")?);
+    }
+
+    let tx = Tx::new_with(inputs, outputs);
+    expect(vector, "txid", &format!("{}", tx.id()))?;
+
+    let txaux = signed_transaction(pm, tx, &keys);
+    for (i, witness) in txaux.witness.iter().enumerate() {
+        if !witness.verify_tx(pm, &txaux.tx) {
+            return Err(format!("witness #{} does not verify", i));
+        }
+    }
+    let bytes = cbor!(&txaux).map_err(|e| format!("cannot encode TxAux: {:?}", e))?;
+    expect(vector, "txaux", &hex::encode(&bytes))
+}
+
+fn generate_transactions(derivations: &Value) -> Value {
+    let keys = derivations
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|d| scheme_field(d).unwrap() == DerivationScheme::V2)
+        .map(|d| {
+            let root = xprv_field(d, "root_xprv").unwrap();
+            derive_xprv(&root, DerivationScheme::V2, &path_field(d, "path").unwrap())
+        })
+        .collect::<Vec<_>>();
+    let address = |key: &XPrv, network_magic| ExtendedAddr::new_simple(key.public(), network_magic);
+
+    let mut vectors = Vec::new();
+    for &(pm, n_inputs, n_outputs) in [
+        (764_824_073u32, 1, 1),
+        (764_824_073, 2, 3),
+        (1_097_911_063, 3, 2),
+    ]
+    .iter()
+    {
+        let network_magic = NetworkMagic::from(pm);
+        let signing_keys = keys[..n_inputs].to_vec();
+        let inputs = (0..n_inputs)
+            .map(|i| {
+                let id = TxId::new(format!("conformance input {}", i).as_bytes());
+                TxoPointer::new(id, i as u32)
+            })
+            .collect::<Vec<_>>();
+        let outputs = (0..n_outputs)
+            .map(|i| {
+                let key = &keys[keys.len() - 1 - i];
+                let value = Coin::new(1_000_000 * (i as u64 + 1) + 42).unwrap();
+                TxOut::new(address(key, network_magic), value)
+            })
+            .collect::<Vec<_>>();
+
+        let tx = Tx::new_with(inputs.clone(), outputs.clone());
+        let txid = tx.id();
+        let txaux = signed_transaction(ProtocolMagic::from(pm), tx, &signing_keys);
+
+        vectors.push(json!({
+            "protocol_magic": pm,
+            "inputs": inputs.iter().map(|i| json!({
+                "id": format!("{}", i.id),
+                "index": i.index,
+            })).collect::<Vec<_>>(),
+            "outputs": outputs.iter().map(|o| json!({
+                "address": format!("{}", o.address),
+                "value": u64::from(o.value),
+            })).collect::<Vec<_>>(),
+            "signing_keys": signing_keys.iter()
+                .map(|k| hex::encode(k.as_ref()))
+                .collect::<Vec<_>>(),
+            "txid": format!("{}", txid),
+            "txaux": hex::encode(&cbor!(&txaux).unwrap()),
+        }));
+    }
+    Value::Array(vectors)
+}
+
+/* ************************************************************************* *
+ *                               Block headers                               *
+ * ************************************************************************* */
+
+fn check_block_header(vector: &Value) -> Result<()> {
+    let bytes = hex_field(vector, "header")?;
+    let raw = RawBlockHeader::from_dat(bytes.clone());
+    let header = raw
+        .decode()
+        .map_err(|e| format!("cannot decode header: {:?}", e))?;
+
+    let encoded = cbor!(&header).map_err(|e| format!("cannot encode header: {:?}", e))?;
+    if encoded != bytes {
+        return Err(format!(
+            "header does not roundtrip: got {}",
+            hex::encode(&encoded)
+        ));
+    }
+    if let BlockHeader::MainBlockHeader(header) = &header {
+        if let sign::BlockSignature::Signature(signature) = &header.consensus.block_signature {
+            let data = sign::MainToSign::from_header(header).signature_data(header.protocol_magic);
+            if !header.consensus.leader_key.verify(&data, signature) {
+                return Err("invalid block signature".to_owned());
+            }
+        }
+    }
+    expect(vector, "hash", &format!("{}", raw.compute_hash()))?;
+    expect(vector, "hash", &format!("{}", header.compute_hash()))
+}
+
+fn generate_block_headers(derivations: &Value) -> Value {
+    let leader = {
+        let derivation = &derivations.as_array().unwrap()[0];
+        xprv_field(derivation, "root_xprv").unwrap()
+    };
+    let pm = ProtocolMagic::from(764_824_073);
+    let empty_attributes = types::BlockHeaderAttributes::new;
+
+    let mut headers = Vec::new();
+    let mut previous = HeaderHash::new(b"conformance genesis");
+    for epoch in 0..2 {
+        let boundary = boundary::BlockHeader::new(
+            pm,
+            previous.clone(),
+            boundary::BodyProof(Blake2b256::new(format!("epoch {}", epoch).as_bytes())),
+            boundary::Consensus {
+                epoch,
+                chain_difficulty: types::ChainDifficulty::from(epoch * 3),
+            },
+            types::ExtraData::default(),
+        );
+        let boundary = BlockHeader::BoundaryBlockHeader(boundary);
+        previous = boundary.compute_hash();
+        headers.push(boundary);
+
+        for slotid in 0..2 {
+            let body = normal::Body::new(
+                normal::TxPayload::empty(),
+                normal::SscPayload::CertificatesPayload(normal::VssCertificates::new(vec![])),
+                normal::DlgPayload(vec![]),
+                update::UpdatePayload {
+                    proposal: None,
+                    votes: vec![],
+                },
+            );
+            let extra_data = types::HeaderExtraData::new(
+                types::BlockVersion::new(0, 1, 0),
+                types::SoftwareVersion::new("cardano-sl", 1).unwrap(),
+                empty_attributes(),
+                Blake2b256::new(
+                    &cbor!(cbor_event::Value::Array(vec![cbor_event::Value::Object(
+                        BTreeMap::new()
+                    )]))
+                    .unwrap(),
+                ),
+            );
+            let mut header = normal::BlockHeader::new(
+                pm,
+                previous.clone(),
+                normal::BodyProof::generate_from_body(&body),
+                normal::Consensus {
+                    slot_id: types::EpochSlotId { epoch, slotid },
+                    leader_key: leader.public(),
+                    chain_difficulty: types::ChainDifficulty::from(
+                        epoch * 3 + u64::from(slotid) + 1,
+                    ),
+                    block_signature: sign::BlockSignature::Signature(
+                        leader.sign(&[]), // replaced below
+                    ),
+                },
+                extra_data,
+            );
+            header.consensus.block_signature =
+                sign::MainToSign::from_header(&header).sign(pm, &leader);
+
+            let header = BlockHeader::MainBlockHeader(header);
+            previous = header.compute_hash();
+            headers.push(header);
+        }
+    }
+
+    Value::Array(
+        headers
+            .iter()
+            .map(|header| {
+                json!({
+                    "header": hex::encode(&cbor!(header).unwrap()),
+                    "hash": format!("{}", header.compute_hash()),
+                })
+            })
+            .collect(),
+    )
+}
+
+/* ************************************************************************* *
+ *                               JSON helpers                                *
+ * ************************************************************************* */
+
+fn expect(vector: &Value, name: &str, got: &str) -> Result<()> {
+    let expected = str_field(vector, name)?;
+    if expected == got {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{}` mismatch: expected {} got {}",
+            name, expected, got
+        ))
+    }
+}
+
+fn field<'a>(vector: &'a Value, name: &str) -> Result<&'a Value> {
+    vector
+        .get(name)
+        .ok_or_else(|| format!("missing field `{}`", name))
+}
+
+fn str_field<'a>(vector: &'a Value, name: &str) -> Result<&'a str> {
+    field(vector, name)?
+        .as_str()
+        .ok_or_else(|| format!("`{}` is not a string", name))
+}
+
+fn array_field<'a>(vector: &'a Value, name: &str) -> Result<&'a Vec<Value>> {
+    field(vector, name)?
+        .as_array()
+        .ok_or_else(|| format!("`{}` is not an array", name))
+}
+
+fn u64_field(vector: &Value, name: &str) -> Result<u64> {
+    field(vector, name)?
+        .as_u64()
+        .ok_or_else(|| format!("`{}` is not an unsigned integer", name))
+}
+
+fn as_u32(value: &Value, name: &str) -> Result<u32> {
+    value
+        .as_u64()
+        .filter(|v| *v <= u64::from(u32::max_value()))
+        .map(|v| v as u32)
+        .ok_or_else(|| format!("`{}` is not a 32 bits unsigned integer", name))
+}
+
+fn u32_field(vector: &Value, name: &str) -> Result<u32> {
+    as_u32(field(vector, name)?, name)
+}
+
+fn as_hex(value: &Value, name: &str) -> Result<Vec<u8>> {
+    let s = value
+        .as_str()
+        .ok_or_else(|| format!("`{}` is not a string", name))?;
+    hex::decode(s).map_err(|e| format!("`{}` is not hexadecimal: {}", name, e))
+}
+
+fn hex_field(vector: &Value, name: &str) -> Result<Vec<u8>> {
+    as_hex(field(vector, name)?, name)
+}
+
+fn as_xprv(value: &Value, name: &str) -> Result<XPrv> {
+    let bytes = as_hex(value, name)?;
+    if bytes.len() != XPRV_SIZE {
+        return Err(format!("`{}` is not {} bytes long", name, XPRV_SIZE));
+    }
+    let mut buf = [0; XPRV_SIZE];
+    buf.copy_from_slice(&bytes);
+    XPrv::from_bytes_verified(buf).map_err(|e| format!("`{}` is not a valid XPrv: {}", name, e))
+}
+
+fn xprv_field(vector: &Value, name: &str) -> Result<XPrv> {
+    as_xprv(field(vector, name)?, name)
+}
+
+fn xpub_field(vector: &Value, name: &str) -> Result<XPub> {
+    XPub::from_hex(str_field(vector, name)?)
+        .map_err(|e| format!("`{}` is not a valid XPub: {}", name, e))
+}
+
+fn path_field(vector: &Value, name: &str) -> Result<Vec<u32>> {
+    array_field(vector, name)?
+        .iter()
+        .map(|index| as_u32(index, name))
+        .collect()
+}
+
+fn scheme_field(vector: &Value) -> Result<DerivationScheme> {
+    match str_field(vector, "scheme")? {
+        "V1" => Ok(DerivationScheme::V1),
+        "V2" => Ok(DerivationScheme::V2),
+        s => Err(format!("unknown derivation scheme `{}`", s)),
+    }
+}
+
+fn scheme_to_str(scheme: DerivationScheme) -> &'static str {
+    match scheme {
+        DerivationScheme::V1 => "V1",
+        DerivationScheme::V2 => "V2",
+    }
+}
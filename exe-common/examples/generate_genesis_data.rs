@@ -6,11 +6,12 @@ extern crate serde_derive;
 extern crate serde_json;
 
 use cardano::{
-    address, block, coin, config, fee,
+    address, coin,
     hdwallet::{self, Seed, XPrv},
+    util::hex,
 };
+use exe_common::genesisdata::build::GenesisDataBuilder;
 use rand::{thread_rng, Rng};
-use std::collections::BTreeMap;
 use std::env;
 use std::fs::File;
 use std::io::Write;
@@ -43,40 +44,28 @@ fn main() {
 
     let protocol_magic = 328429219.into();
 
-    let mut boot_stakeholders = BTreeMap::new();
+    let mut builder = GenesisDataBuilder::new(
+        protocol_magic,
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1548089245),
+    );
 
     for n in 0..nr_nodes {
         let stakeholder_prv = generate_key();
-        let stakeholder_pk = stakeholder_prv.public();
         let delegate_prv = generate_key();
         let delegate_pk = delegate_prv.public();
 
         write_file(
             &dest_dir.join(format!("stakeholder-{}.xprv", n)),
-            &stakeholder_prv.to_string(),
+            &hex::encode(stakeholder_prv.as_ref()),
         );
         write_file(
             &dest_dir.join(format!("delegate-{}.xprv", n)),
-            &delegate_prv.to_string(),
+            &hex::encode(delegate_prv.as_ref()),
         );
 
-        let stakeholder_id = address::StakeholderId::new(&stakeholder_pk);
-
-        let psk =
-            block::sign::ProxySecretKey::sign(&stakeholder_prv, delegate_pk, 0, protocol_magic);
-
-        boot_stakeholders.insert(
-            stakeholder_id,
-            config::BootStakeholder {
-                weight: 1,
-                issuer_pk: psk.issuer_pk,
-                delegate_pk: psk.delegate_pk,
-                cert: psk.cert,
-            },
-        );
+        builder.add_boot_stakeholder(&stakeholder_prv, delegate_pk, 1);
     }
 
-    let mut non_avvm_balances = BTreeMap::new();
     let mut boot_addresses = vec![];
 
     for _ in 0..nr_addresses {
@@ -85,29 +74,18 @@ fn main() {
         let addr: address::Addr =
             address::ExtendedAddr::new_simple(addr_prv.public(), protocol_magic.into()).into();
         boot_addresses.push(BootAddress {
-            xprv: addr_prv.to_string(),
+            xprv: hex::encode(addr_prv.as_ref()),
             addr: addr.to_string(),
         });
-        non_avvm_balances.insert(addr, coin::Coin::new(19999999999999).unwrap());
+        builder.add_non_avvm_balance(addr, coin::Coin::new(19999999999999).unwrap());
     }
 
-    let genesis_data = config::GenesisData {
-        genesis_prev: cardano::block::HeaderHash::new(&[0; cardano::hash::Blake2b256::HASH_SIZE]),
-        epoch_stability_depth: 2160,
-        start_time: SystemTime::UNIX_EPOCH + Duration::from_secs(1548089245),
-        slot_duration: Duration::from_millis(20000),
-        protocol_magic,
-        fee_policy: fee::LinearFee::new(fee::Milli::integral(155381), fee::Milli::new(43, 946)),
-        avvm_distr: BTreeMap::new(),
-        non_avvm_balances,
-        boot_stakeholders,
-    };
-
-    let (genesis_data, genesis_hash) = exe_common::genesisdata::print::print(genesis_data).unwrap();
+    let (genesis_data, genesis_json) = builder.make_genesis_data().unwrap();
+    let genesis_hash = genesis_data.genesis_prev;
 
     eprintln!("Genesis hash = {}", genesis_hash);
 
-    write_file(&dest_dir.join("genesis.json"), &genesis_data);
+    write_file(&dest_dir.join("genesis.json"), &genesis_json);
 
     write_file(
         &dest_dir.join("addresses.json"),
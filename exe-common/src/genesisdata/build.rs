@@ -0,0 +1,200 @@
+//! Construction of new genesis data
+//!
+//! The genesis hash (`GenesisData::genesis_prev`) is the hash of the
+//! canonical JSON of the genesis data, so it is computed when building
+//! the genesis data rather than provided.
+
+use cardano::address::{Addr, StakeholderId};
+use cardano::block::{fts::SharedSeed, sign::ProxySecretKey, update::BlockVersionData, HeaderHash};
+use cardano::coin::Coin;
+use cardano::config::{BootStakeWeight, BootStakeholder, GenesisData, ProtocolMagic};
+use cardano::fee::LinearFee;
+use cardano::hash::Blake2b256;
+use cardano::hdwallet::{XPrv, XPub};
+use cardano::redeem;
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use genesisdata::{print, Result};
+
+/// Build genesis data step by step.
+#[derive(Debug, Clone)]
+pub struct GenesisDataBuilder {
+    genesis_data: GenesisData,
+}
+
+impl GenesisDataBuilder {
+    /// Genesis data without balances nor boot stakeholders, with the
+    /// protocol constants and the block version data of the mainnet.
+    pub fn new(protocol_magic: ProtocolMagic, start_time: SystemTime) -> Self {
+        GenesisDataBuilder {
+            genesis_data: GenesisData {
+                genesis_prev: HeaderHash::new(&[0; Blake2b256::HASH_SIZE]),
+                epoch_stability_depth: 2160,
+                vss_min_ttl: 2,
+                vss_max_ttl: 6,
+                start_time,
+                protocol_magic,
+                block_version_data: BlockVersionData::mainnet(20_000, LinearFee::default()),
+                avvm_distr: BTreeMap::new(),
+                non_avvm_balances: BTreeMap::new(),
+                boot_stakeholders: BTreeMap::new(),
+                fts_seed: SharedSeed::default(),
+            },
+        }
+    }
+
+    /// Set the security parameter `k`.
+    pub fn set_epoch_stability_depth(&mut self, k: usize) {
+        self.genesis_data.epoch_stability_depth = k
+    }
+
+    /// Set the seed of the slot leader election of the genesis epoch.
+    pub fn set_fts_seed(&mut self, fts_seed: SharedSeed) {
+        self.genesis_data.fts_seed = fts_seed
+    }
+
+    /// Set the range of epochs the VSS certificates are valid for.
+    pub fn set_vss_ttl(&mut self, min_ttl: u32, max_ttl: u32) {
+        self.genesis_data.vss_min_ttl = min_ttl;
+        self.genesis_data.vss_max_ttl = max_ttl;
+    }
+
+    /// Set the slot duration, which is stored in milliseconds.
+    pub fn set_slot_duration(&mut self, slot_duration: Duration) {
+        self.genesis_data.block_version_data.slot_duration =
+            slot_duration.as_secs() * 1000 + u64::from(slot_duration.subsec_millis())
+    }
+
+    pub fn set_fee_policy(&mut self, fee_policy: LinearFee) {
+        self.genesis_data.block_version_data.tx_fee_policy = fee_policy
+    }
+
+    /// Set the initial protocol parameters. This also sets the slot
+    /// duration and the fee policy.
+    pub fn set_block_version_data(&mut self, block_version_data: BlockVersionData) {
+        self.genesis_data.block_version_data = block_version_data
+    }
+
+    /// Set the balance of an AVVM redemption key.
+    pub fn add_avvm_balance(&mut self, key: redeem::PublicKey, balance: Coin) {
+        self.genesis_data.avvm_distr.insert(key, balance);
+    }
+
+    /// Set the balance of an address.
+    pub fn add_non_avvm_balance(&mut self, address: Addr, balance: Coin) {
+        self.genesis_data.non_avvm_balances.insert(address, balance);
+    }
+
+    /// Add a boot stakeholder, delegating to `delegate_pk` with a
+    /// heavyweight delegation certificate signed with `issuer`.
+    pub fn add_boot_stakeholder(
+        &mut self,
+        issuer: &XPrv,
+        delegate_pk: XPub,
+        weight: BootStakeWeight,
+    ) -> StakeholderId {
+        let psk = ProxySecretKey::sign(issuer, delegate_pk, 0, self.genesis_data.protocol_magic);
+        let id = StakeholderId::new(&psk.issuer_pk);
+        self.genesis_data.boot_stakeholders.insert(
+            id,
+            BootStakeholder {
+                weight,
+                issuer_pk: psk.issuer_pk,
+                delegate_pk: psk.delegate_pk,
+                cert: psk.cert,
+            },
+        );
+        id
+    }
+
+    /// Build the genesis data, computing its hash. Also returns its
+    /// canonical JSON, as read by `parse::parse`.
+    pub fn make_genesis_data(self) -> Result<(GenesisData, String)> {
+        let mut genesis_data = self.genesis_data;
+        let (json, genesis_hash) = print::print(genesis_data.clone())?;
+        genesis_data.genesis_prev = genesis_hash;
+        Ok((genesis_data, json))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cardano::address::ExtendedAddr;
+    use cardano::hdwallet::Seed;
+    use genesisdata::{parse, Error};
+
+    fn xprv(seed: u8) -> XPrv {
+        XPrv::generate_from_seed(&Seed::from_bytes([seed; 32]))
+    }
+
+    fn builder() -> GenesisDataBuilder {
+        let protocol_magic = ProtocolMagic::from(328429219);
+        let mut builder = GenesisDataBuilder::new(
+            protocol_magic,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1548089245),
+        );
+        builder.set_epoch_stability_depth(10);
+        builder.set_vss_ttl(1, 4);
+        builder.set_slot_duration(Duration::from_millis(2500));
+        builder.add_avvm_balance(
+            redeem::PrivateKey::generate(&[7; 32]).unwrap().public(),
+            Coin::new(1000).unwrap(),
+        );
+        builder.add_non_avvm_balance(
+            ExtendedAddr::new_simple(xprv(1).public(), protocol_magic.into()).into(),
+            Coin::new(2000).unwrap(),
+        );
+        builder.add_boot_stakeholder(&xprv(2), xprv(3).public(), 1);
+        builder.add_boot_stakeholder(&xprv(4), xprv(5).public(), 2);
+        builder
+    }
+
+    #[test]
+    fn built_genesis_data_is_parsed_back() {
+        let (genesis_data, json) = builder().make_genesis_data().unwrap();
+        assert_eq!(genesis_data.boot_stakeholders.len(), 2);
+        assert_eq!(genesis_data.slot_duration(), Duration::from_millis(2500));
+        assert_eq!(genesis_data.block_version_data.max_tx_size, 4096);
+        assert_eq!(parse::parse(json.as_bytes()).unwrap(), genesis_data);
+        assert_eq!(
+            parse::genesis_hash(json.as_bytes()).unwrap(),
+            genesis_data.genesis_prev
+        );
+
+        // the hash does not depend on the formatting of the JSON
+        let pretty = format!(" {} ", json.replace(",", ", "));
+        assert_eq!(
+            parse::genesis_hash(pretty.as_bytes()).unwrap(),
+            genesis_data.genesis_prev
+        );
+    }
+
+    #[test]
+    fn malformed_genesis_data_is_rejected() {
+        let (_, json) = builder().make_genesis_data().unwrap();
+
+        match parse::parse(&json.as_bytes()[1..]) {
+            Err(Error::JsonError(_)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // a certificate signed for another protocol magic
+        let mut other = builder();
+        other.genesis_data.protocol_magic = ProtocolMagic::from(1);
+        other.add_boot_stakeholder(&xprv(6), xprv(7).public(), 1);
+        let other_cert = other.genesis_data.boot_stakeholders
+            [&StakeholderId::new(&xprv(6).public())]
+            .cert
+            .to_string();
+        let mut builder = builder();
+        let id = builder.add_boot_stakeholder(&xprv(6), xprv(7).public(), 1);
+        let (genesis_data, json) = builder.make_genesis_data().unwrap();
+        let cert = genesis_data.boot_stakeholders[&id].cert.to_string();
+        match parse::parse(json.replace(&cert, &other_cert).as_bytes()) {
+            Err(Error::InvalidCertificate(ref err_id)) if *err_id == id => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
@@ -1,4 +1,75 @@
+use cardano::address::StakeholderId;
+use serde_json;
+use std::{error, fmt, result};
+
+pub mod build;
 pub mod data;
 pub mod parse;
 pub mod print;
 pub mod raw;
+
+#[derive(Debug)]
+pub enum Error {
+    /// the genesis data is not valid JSON, or misses some fields
+    JsonError(serde_json::Error),
+    InvalidAvvmKey(String),
+    InvalidAddress(String),
+    InvalidBalance(String),
+    InvalidFee(String),
+    InvalidSlotDuration(String),
+    InvalidFtsSeed(String),
+    /// a field of the block version data is not a number, or not a
+    /// valid coin portion
+    InvalidBlockVersionData(&'static str, String),
+    InvalidStakeholderId(String),
+    MissingHeavyDelegation(StakeholderId),
+    InvalidHeavyDelegation(StakeholderId),
+    /// the delegation certificate was not issued by the stakeholder
+    /// or its signature is invalid
+    InvalidCertificate(StakeholderId),
+}
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::JsonError(e)
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::JsonError(_) => write!(f, "Invalid genesis data JSON"),
+            Error::InvalidAvvmKey(key) => write!(f, "Invalid AVVM key {}", key),
+            Error::InvalidAddress(address) => write!(f, "Invalid address {}", address),
+            Error::InvalidBalance(balance) => write!(f, "Invalid balance {}", balance),
+            Error::InvalidFee(fee) => write!(f, "Invalid fee policy value {}", fee),
+            Error::InvalidSlotDuration(duration) => {
+                write!(f, "Invalid slot duration {}", duration)
+            }
+            Error::InvalidFtsSeed(seed) => write!(f, "Invalid FTS seed {}", seed),
+            Error::InvalidBlockVersionData(field, value) => {
+                write!(f, "Invalid block version data {} {}", field, value)
+            }
+            Error::InvalidStakeholderId(id) => write!(f, "Invalid stakeholder id {}", id),
+            Error::MissingHeavyDelegation(id) => {
+                write!(f, "No heavy delegation for boot stakeholder {}", id)
+            }
+            Error::InvalidHeavyDelegation(id) => {
+                write!(f, "Invalid heavy delegation of boot stakeholder {}", id)
+            }
+            Error::InvalidCertificate(id) => write!(
+                f,
+                "Invalid heavy delegation certificate of boot stakeholder {}",
+                id
+            ),
+        }
+    }
+}
+impl error::Error for Error {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self {
+            Error::JsonError(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
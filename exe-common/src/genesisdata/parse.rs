@@ -1,4 +1,5 @@
 use base64;
+use cardano::util::hex;
 use cardano::{address, block, coin, config, fee, hdwallet, redeem};
 use serde_json;
 use std::collections::BTreeMap;
@@ -6,35 +7,77 @@ use std::io::Read;
 use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
-use genesisdata::raw;
+use genesisdata::{raw, Error, Result};
 
-pub fn parse<R: Read>(json: R) -> config::GenesisData {
-    // FIXME: use Result
-
-    let data_value: serde_json::Value = serde_json::from_reader(json).unwrap();
+pub fn parse<R: Read>(json: R) -> Result<config::GenesisData> {
+    let data_value: serde_json::Value = serde_json::from_reader(json)?;
     let genesis_prev = block::HeaderHash::new(data_value.to_string().as_bytes());
-    let data: raw::GenesisData = serde_json::from_value(data_value.clone()).unwrap();
+    let data: raw::GenesisData = serde_json::from_value(data_value)?;
 
     let protocol_magic = config::ProtocolMagic::from(data.protocolConsts.protocolMagic);
 
-    let parse_fee_constant = |s: &str| {
-        let n = s.parse::<u64>().unwrap();
-        assert!(n % 1000000 == 0);
-        fee::Milli::new(n / 1000000000, n / 1000000 % 1000)
+    let parse_fee_constant = |s: &str| match s.parse::<u64>() {
+        Ok(n) if n % 1000000 == 0 => Ok(fee::Milli::new(n / 1000000000, n / 1000000 % 1000)),
+        _ => Err(Error::InvalidFee(s.to_owned())),
+    };
+
+    let parse_balance = |s: &str| {
+        s.parse::<u64>()
+            .ok()
+            .and_then(|n| coin::Coin::new(n).ok())
+            .ok_or_else(|| Error::InvalidBalance(s.to_owned()))
     };
 
     let mut avvm_distr = BTreeMap::new();
     for (avvm, balance) in &data.avvmDistr {
-        avvm_distr.insert(
-            redeem::PublicKey::from_slice(&base64::decode_config(avvm, base64::URL_SAFE).unwrap())
-                .unwrap(),
-            coin::Coin::new(balance.parse::<u64>().unwrap()).unwrap(),
-        );
+        let key = base64::decode_config(avvm, base64::URL_SAFE)
+            .ok()
+            .and_then(|bytes| redeem::PublicKey::from_slice(&bytes).ok())
+            .ok_or_else(|| Error::InvalidAvvmKey(avvm.clone()))?;
+        avvm_distr.insert(key, parse_balance(balance)?);
     }
 
-    let slot_duration = {
-        let v = data.blockVersionData.slotDuration.parse::<u64>().unwrap();
-        Duration::from_millis(v)
+    let block_version_data = {
+        let bvd = &data.blockVersionData;
+        let parse_u64 = |field: &'static str, s: &str| {
+            s.parse::<u64>()
+                .map_err(|_| Error::InvalidBlockVersionData(field, s.to_owned()))
+        };
+        let parse_portion = |field: &'static str, s: &str| {
+            s.parse::<u64>()
+                .ok()
+                .and_then(|n| block::types::CoinPortion::new(n).ok())
+                .ok_or_else(|| Error::InvalidBlockVersionData(field, s.to_owned()))
+        };
+        block::update::BlockVersionData {
+            script_version: bvd.scriptVersion,
+            slot_duration: bvd
+                .slotDuration
+                .parse::<u64>()
+                .map_err(|_| Error::InvalidSlotDuration(bvd.slotDuration.clone()))?,
+            max_block_size: parse_u64("maxBlockSize", &bvd.maxBlockSize)?,
+            max_header_size: parse_u64("maxHeaderSize", &bvd.maxHeaderSize)?,
+            max_tx_size: parse_u64("maxTxSize", &bvd.maxTxSize)?,
+            max_proposal_size: parse_u64("maxProposalSize", &bvd.maxProposalSize)?,
+            mpc_thd: parse_portion("mpcThd", &bvd.mpcThd)?,
+            heavy_del_thd: parse_portion("heavyDelThd", &bvd.heavyDelThd)?,
+            update_vote_thd: parse_portion("updateVoteThd", &bvd.updateVoteThd)?,
+            update_proposal_thd: parse_portion("updateProposalThd", &bvd.updateProposalThd)?,
+            update_implicit: parse_u64("updateImplicit", &bvd.updateImplicit)?,
+            softfork_rule: block::update::SoftforkRule {
+                init_thd: parse_portion("softforkRule.initThd", &bvd.softforkRule.initThd)?,
+                min_thd: parse_portion("softforkRule.minThd", &bvd.softforkRule.minThd)?,
+                thd_decrement: parse_portion(
+                    "softforkRule.thdDecrement",
+                    &bvd.softforkRule.thdDecrement,
+                )?,
+            },
+            tx_fee_policy: fee::LinearFee::new(
+                parse_fee_constant(&bvd.txFeePolicy.summand)?,
+                parse_fee_constant(&bvd.txFeePolicy.multiplier)?,
+            ),
+            unlock_stake_epoch: parse_u64("unlockStakeEpoch", &bvd.unlockStakeEpoch)?,
+        }
     };
 
     let start_time = {
@@ -44,36 +87,47 @@ pub fn parse<R: Read>(json: R) -> config::GenesisData {
 
     let mut non_avvm_balances = BTreeMap::new();
     for (address, balance) in &data.nonAvvmBalances {
-        non_avvm_balances.insert(
-            address::ExtendedAddr::from_str(address).unwrap().into(),
-            coin::Coin::new(balance.parse::<u64>().unwrap()).unwrap(),
-        );
+        let addr = address::ExtendedAddr::from_str(address)
+            .map_err(|_| Error::InvalidAddress(address.clone()))?;
+        non_avvm_balances.insert(addr.into(), parse_balance(balance)?);
     }
 
+    let fts_seed = hex::decode(&data.ftsSeed)
+        .map(block::fts::SharedSeed::from_bytes)
+        .map_err(|_| Error::InvalidFtsSeed(data.ftsSeed.clone()))?;
+
     let mut boot_stakeholders = BTreeMap::new();
 
     for (stakeholder_id, weight) in &data.bootStakeholders {
-        let heavy = data.heavyDelegation.get(stakeholder_id).unwrap();
-
-        let stakeholder_id = address::StakeholderId::from_str(stakeholder_id).unwrap();
+        let id = address::StakeholderId::from_str(stakeholder_id)
+            .map_err(|_| Error::InvalidStakeholderId(stakeholder_id.clone()))?;
+        let heavy = data
+            .heavyDelegation
+            .get(stakeholder_id)
+            .ok_or(Error::MissingHeavyDelegation(id))?;
 
+        let decode_xpub = |s: &str| {
+            base64::decode(s)
+                .ok()
+                .and_then(|bytes| hdwallet::XPub::from_slice(&bytes).ok())
+                .ok_or(Error::InvalidHeavyDelegation(id))
+        };
         let psk = cardano::block::sign::ProxySecretKey {
             omega: 0,
-            issuer_pk: hdwallet::XPub::from_slice(&base64::decode(&heavy.issuerPk).unwrap())
-                .unwrap(),
-            delegate_pk: hdwallet::XPub::from_slice(&base64::decode(&heavy.delegatePk).unwrap())
-                .unwrap(),
-            cert: hdwallet::Signature::<()>::from_hex(&heavy.cert).unwrap(),
+            issuer_pk: decode_xpub(&heavy.issuerPk)?,
+            delegate_pk: decode_xpub(&heavy.delegatePk)?,
+            cert: hdwallet::Signature::<()>::from_hex(&heavy.cert)
+                .map_err(|_| Error::InvalidHeavyDelegation(id))?,
         };
 
-        // Check that the stakeholder ID corresponds to the issuer public key.
-        assert_eq!(stakeholder_id, address::StakeholderId::new(&psk.issuer_pk));
-
-        // Check that the certificate is correct.
-        assert!(psk.verify(protocol_magic));
+        // Check that the stakeholder ID corresponds to the issuer public
+        // key, and that the certificate is correct.
+        if id != address::StakeholderId::new(&psk.issuer_pk) || !psk.verify(protocol_magic) {
+            return Err(Error::InvalidCertificate(id));
+        }
 
         boot_stakeholders.insert(
-            stakeholder_id,
+            id,
             config::BootStakeholder {
                 weight: *weight,
                 issuer_pk: psk.issuer_pk,
@@ -83,31 +137,36 @@ pub fn parse<R: Read>(json: R) -> config::GenesisData {
         );
     }
 
-    config::GenesisData {
+    Ok(config::GenesisData {
         genesis_prev,
         epoch_stability_depth: data.protocolConsts.k,
+        vss_min_ttl: data.protocolConsts.vssMinTTL,
+        vss_max_ttl: data.protocolConsts.vssMaxTTL,
         protocol_magic,
-        fee_policy: fee::LinearFee::new(
-            parse_fee_constant(&data.blockVersionData.txFeePolicy.summand),
-            parse_fee_constant(&data.blockVersionData.txFeePolicy.multiplier),
-        ),
+        block_version_data,
         avvm_distr,
         non_avvm_balances,
         start_time,
-        slot_duration,
         boot_stakeholders,
-    }
+        fts_seed,
+    })
 }
 
-pub fn canonicalize_json<R: Read>(json: R) -> String {
-    let data: serde_json::Value = serde_json::from_reader(json).unwrap();
-    data.to_string()
+pub fn canonicalize_json<R: Read>(json: R) -> Result<String> {
+    let data: serde_json::Value = serde_json::from_reader(json)?;
+    Ok(data.to_string())
+}
+
+/// The genesis hash: the hash of the canonical JSON of the genesis data.
+pub fn genesis_hash<R: Read>(json: R) -> Result<block::HeaderHash> {
+    Ok(block::HeaderHash::new(canonicalize_json(json)?.as_bytes()))
 }
 
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use cardano::block::update::BlockVersionData;
     use cardano::{coin, fee::Milli};
 
     #[test]
@@ -121,7 +180,8 @@ mod test {
             super::super::data::get_genesis_data(&genesis_hash)
                 .unwrap()
                 .as_bytes(),
-        );
+        )
+        .unwrap();
 
         assert_eq!(genesis_data.epoch_stability_depth, 2160);
         assert_eq!(
@@ -132,11 +192,17 @@ mod test {
                 .as_secs(),
             1506450213
         );
-        assert_eq!(genesis_data.slot_duration.as_secs(), 20);
-        assert_eq!(genesis_data.slot_duration.subsec_millis(), 0);
+        assert_eq!(genesis_data.slot_duration().as_secs(), 20);
+        assert_eq!(genesis_data.slot_duration().subsec_millis(), 0);
         assert_eq!(genesis_data.protocol_magic, 633343913.into());
-        assert_eq!(genesis_data.fee_policy.coefficient, Milli::new(43, 946));
-        assert_eq!(genesis_data.fee_policy.constant, Milli::integral(155381));
+        assert_eq!(genesis_data.fee_policy().coefficient, Milli::new(43, 946));
+        assert_eq!(genesis_data.fee_policy().constant, Milli::integral(155381));
+        assert_eq!(genesis_data.vss_min_ttl, 2);
+        assert_eq!(genesis_data.vss_max_ttl, 6);
+        assert_eq!(
+            genesis_data.block_version_data,
+            BlockVersionData::mainnet(20_000, genesis_data.fee_policy())
+        );
 
         assert_eq!(
             base64::encode_config(
@@ -160,7 +226,8 @@ mod test {
             super::super::data::get_genesis_data(&genesis_hash)
                 .unwrap()
                 .as_bytes(),
-        );
+        )
+        .unwrap();
 
         assert_eq!(
             genesis_data
@@ -172,5 +239,52 @@ mod test {
                 .1,
             &coin::Coin::new(5428571428571429).unwrap()
         );
+        assert_eq!(genesis_data.block_version_data.max_tx_size, 65536);
+    }
+
+    /// The expected leaders were computed by this implementation, not
+    /// taken from the chain: they still have to be checked against the
+    /// slot leaders of the mainnet boundary block of epoch 0, which are
+    /// not part of the test data.
+    #[test]
+    pub fn mainnet_genesis_slot_leaders() {
+        let genesis_hash = cardano::block::HeaderHash::from_str(
+            &"5f20df933584822601f9e3f8c024eb5eb252fe8cefb24d1317dc3d432e940ebb",
+        )
+        .unwrap();
+
+        let genesis_data = super::parse(
+            super::super::data::get_genesis_data(&genesis_hash)
+                .unwrap()
+                .as_bytes(),
+        )
+        .unwrap();
+        let chain_state = cardano::block::ChainState::new(&genesis_data);
+
+        let leaders = cardano::block::fts::follow_the_satoshi(
+            genesis_data.epoch_stability_depth * 10,
+            &genesis_data.fts_seed,
+            &chain_state.stakes.stakes,
+        )
+        .unwrap();
+
+        assert_eq!(leaders.len(), 21600);
+        assert_eq!(
+            leaders
+                .iter()
+                .take(8)
+                .map(|leader| leader.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "5071d8802ddd05c59f4db907bd1749e82e6242caf6512b20a8368fcf",
+                "43011479a595b300e0726910d0b602ffcdd20466a3b8ceeacd3fbc26",
+                "43011479a595b300e0726910d0b602ffcdd20466a3b8ceeacd3fbc26",
+                "af2800c124e599d6dec188a75f8bfde397ebb778163a18240371f2d1",
+                "5411c7bf87c252609831a337a713e4859668cba7bba70a9c3ef7c398",
+                "65904a89e6d0e5f881513d1736945e051b76f095eca138ee869d543d",
+                "43011479a595b300e0726910d0b602ffcdd20466a3b8ceeacd3fbc26",
+                "43011479a595b300e0726910d0b602ffcdd20466a3b8ceeacd3fbc26",
+            ]
+        );
     }
 }
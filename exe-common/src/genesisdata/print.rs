@@ -1,12 +1,12 @@
-use cardano::{block::HeaderHash, config};
-use genesisdata::{parse, raw};
+use cardano::block::{types::CoinPortion, HeaderHash};
+use cardano::config;
+use cardano::util::hex;
+use genesisdata::{parse, raw, Result};
 use std::time::SystemTime;
 
 /// Return a canonical JSON representation of the given genesis data,
 /// as well as the corresponding genesis hash.
-pub fn print(
-    mut genesis_data: config::GenesisData,
-) -> Result<(String, HeaderHash), std::io::Error> {
+pub fn print(mut genesis_data: config::GenesisData) -> Result<(String, HeaderHash)> {
     let raw = raw::GenesisData {
         avvmDistr: genesis_data
             .avvm_distr
@@ -45,31 +45,52 @@ pub fn print(
         protocolConsts: raw::ProtocolConsts {
             k: genesis_data.epoch_stability_depth,
             protocolMagic: *genesis_data.protocol_magic,
+            vssMaxTTL: genesis_data.vss_max_ttl,
+            vssMinTTL: genesis_data.vss_min_ttl,
         },
         startTime: genesis_data
             .start_time
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
             .as_secs(),
-        blockVersionData: raw::BlockVersionData {
-            slotDuration: (genesis_data.slot_duration.as_secs() as u64 * 1000
-                + genesis_data.slot_duration.subsec_millis() as u64)
-                .to_string(),
-            txFeePolicy: raw::TxFeePolicy {
-                summand: (genesis_data.fee_policy.constant.as_millis() * 1000000).to_string(),
-                multiplier: (genesis_data.fee_policy.coefficient.as_millis() * 1000000).to_string(),
-            },
+        blockVersionData: {
+            let bvd = &genesis_data.block_version_data;
+            let portion = |p: CoinPortion| u64::from(p).to_string();
+            raw::BlockVersionData {
+                heavyDelThd: portion(bvd.heavy_del_thd),
+                maxBlockSize: bvd.max_block_size.to_string(),
+                maxHeaderSize: bvd.max_header_size.to_string(),
+                maxProposalSize: bvd.max_proposal_size.to_string(),
+                maxTxSize: bvd.max_tx_size.to_string(),
+                mpcThd: portion(bvd.mpc_thd),
+                scriptVersion: bvd.script_version,
+                slotDuration: bvd.slot_duration.to_string(),
+                softforkRule: raw::SoftforkRule {
+                    initThd: portion(bvd.softfork_rule.init_thd),
+                    minThd: portion(bvd.softfork_rule.min_thd),
+                    thdDecrement: portion(bvd.softfork_rule.thd_decrement),
+                },
+                txFeePolicy: raw::TxFeePolicy {
+                    summand: (bvd.tx_fee_policy.constant.as_millis() * 1000000).to_string(),
+                    multiplier: (bvd.tx_fee_policy.coefficient.as_millis() * 1000000).to_string(),
+                },
+                unlockStakeEpoch: bvd.unlock_stake_epoch.to_string(),
+                updateImplicit: bvd.update_implicit.to_string(),
+                updateProposalThd: portion(bvd.update_proposal_thd),
+                updateVoteThd: portion(bvd.update_vote_thd),
+            }
         },
+        ftsSeed: hex::encode(genesis_data.fts_seed.as_ref()),
     };
 
     let json = serde_json::to_string(&raw)?;
 
     // Compute the hash over the canonical JSON.
-    let canon_json = parse::canonicalize_json(json.as_bytes());
+    let canon_json = parse::canonicalize_json(json.as_bytes())?;
     let genesis_hash = HeaderHash::new(canon_json.as_bytes());
 
     genesis_data.genesis_prev = genesis_hash.clone(); // ugly
-    assert_eq!(genesis_data, parse::parse(json.as_bytes()));
+    assert_eq!(genesis_data, parse::parse(json.as_bytes())?);
 
     Ok((canon_json, genesis_hash))
 }
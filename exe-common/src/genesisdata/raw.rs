@@ -11,6 +11,7 @@ pub struct GenesisData {
     pub protocolConsts: ProtocolConsts,
     pub startTime: u64,
     pub blockVersionData: BlockVersionData,
+    pub ftsSeed: String,
 }
 
 #[allow(non_snake_case)]
@@ -18,27 +19,27 @@ pub struct GenesisData {
 pub struct ProtocolConsts {
     pub k: usize,
     pub protocolMagic: u32,
-    //pub vssMaxTTL: u32,
-    //pub vssMinTTL: u32,
+    pub vssMaxTTL: u32,
+    pub vssMinTTL: u32,
 }
 
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct BlockVersionData {
-    //pub heavyDelThd: String,
-    //pub maxBlockSize: String,
-    //pub maxHeaderSize: String,
-    //pub maxProposalSize: String,
-    //pub maxTxSize: String,
-    //pub mpcThd: String,
-    //pub scriptVersion: u32,
+    pub heavyDelThd: String,
+    pub maxBlockSize: String,
+    pub maxHeaderSize: String,
+    pub maxProposalSize: String,
+    pub maxTxSize: String,
+    pub mpcThd: String,
+    pub scriptVersion: u16,
     pub slotDuration: String,
-    //pub softforkRule: SoftforkRule,
+    pub softforkRule: SoftforkRule,
     pub txFeePolicy: TxFeePolicy,
-    //pub unlockStakeEpoch: String,
-    //pub updateImplicit: String,
-    //pub updateProposalThd: String,
-    //pub updateVoteThd: String,
+    pub unlockStakeEpoch: String,
+    pub updateImplicit: String,
+    pub updateProposalThd: String,
+    pub updateVoteThd: String,
 }
 
 #[allow(non_snake_case)]
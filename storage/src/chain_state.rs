@@ -1,5 +1,10 @@
 use super::{Error, Result, Storage};
-use cardano::block::{Block, BlockDate, ChainState, EpochId, EpochSlotId, HeaderHash, Utxos};
+use cardano::block::ssc_state::SscState;
+use cardano::block::stake_state::StakeState;
+use cardano::block::update_state::UpdateState;
+use cardano::block::{
+    Block, BlockDate, ChainState, Delegation, EpochId, EpochSlotId, HeaderHash, Utxos,
+};
 use cardano::config::GenesisData;
 use cardano::tx::TxoPointer;
 use cbor_event::{de, se, Len};
@@ -9,7 +14,7 @@ use std::io::{Read, Write};
 use storage_units::utils::{error::StorageError, magic};
 
 const FILE_TYPE: magic::FileType = 0x5554584f; // = UTXO
-const VERSION: magic::Version = 3;
+const VERSION: magic::Version = 4;
 
 /// Write the chain state to disk. To reduce storage requirements (in
 /// particular of the utxo state), we actually write a delta between
@@ -57,7 +62,7 @@ pub fn write_chain_state(
     Ok(())
 }
 
-const NR_FIELDS: u64 = 10;
+const NR_FIELDS: u64 = 15;
 
 /// Write the chain state delta between chain_state and the state at
 /// 'parent_block'.
@@ -105,6 +110,14 @@ pub fn write_chain_state_delta<W: Write>(
         .serialize(&chain_state.spent_txos)?;
     se::serialize_fixed_array(removed_utxos.iter(), &mut serializer)?;
     se::serialize_fixed_map(added_utxos.iter(), &mut serializer)?;
+    // The delegation state and the stake distribution are small, so
+    // store them in full.
+    se::serialize_fixed_map(chain_state.delegation.iter(), &mut serializer)?;
+    se::serialize_fixed_map(chain_state.pending_delegation.iter(), &mut serializer)?;
+    serializer
+        .serialize(&chain_state.update)?
+        .serialize(&chain_state.ssc)?
+        .serialize(&chain_state.stakes)?;
 
     Ok(())
 }
@@ -126,7 +139,7 @@ pub fn read_chain_state(
     // from the boundary block.
     if let Some(last_boundary_block) = &chain_state.last_boundary_block {
         let hash = last_boundary_block.as_hash_bytes();
-        chain_state.slot_leaders = match storage.read_block(hash).unwrap().decode()? {
+        chain_state.slot_leaders = match storage.read_block(hash)?.decode()? {
             Block::BoundaryBlock(blk) => {
                 assert_eq!(
                     blk.header.consensus.epoch,
@@ -138,6 +151,13 @@ pub fn read_chain_state(
         };
     }
 
+    // Nor the chain difficulty, which is in the last block.
+    chain_state.chain_difficulty = storage
+        .read_block(block_hash.as_hash_bytes())?
+        .decode()?
+        .header()
+        .difficulty();
+
     Ok(chain_state)
 }
 
@@ -176,6 +196,11 @@ fn do_get_chain_state(
     chain_state.chain_length = file.chain_length;
     chain_state.nr_transactions = file.nr_transactions;
     chain_state.spent_txos = file.spent_txos;
+    chain_state.delegation = file.delegation;
+    chain_state.pending_delegation = file.pending_delegation;
+    chain_state.update = file.update;
+    chain_state.ssc = file.ssc;
+    chain_state.stakes = file.stakes;
 
     Ok(chain_state)
 }
@@ -191,10 +216,21 @@ pub struct ChainStateFile {
     pub spent_txos: u64,
     pub removed_utxos: Vec<TxoPointer>,
     pub added_utxos: Utxos,
+    pub delegation: Delegation,
+    pub pending_delegation: Delegation,
+    pub update: UpdateState,
+    pub ssc: SscState,
+    pub stakes: StakeState,
 }
 
 pub fn decode_chain_state_file<R: Read>(file: &mut R) -> Result<ChainStateFile> {
-    magic::check_header(file, FILE_TYPE, VERSION, VERSION)?;
+    // The files of the previous versions lack parts of the state needed
+    // to verify blocks, which cannot be recovered from the file alone:
+    // the chain state has to be rebuilt from the blocks.
+    let version = magic::check_header(file, FILE_TYPE, 0, VERSION)?;
+    if version < VERSION {
+        return Err(Error::ChainStateVersionUnsupported(version));
+    }
 
     let mut data = vec![];
     file.read_to_end(&mut data)?;
@@ -218,6 +254,11 @@ pub fn decode_chain_state_file<R: Read>(file: &mut R) -> Result<ChainStateFile>
     let spent_txos = raw.deserialize()?;
     let removed_utxos = raw.deserialize()?;
     let added_utxos = raw.deserialize()?;
+    let delegation = raw.deserialize()?;
+    let pending_delegation = raw.deserialize()?;
+    let update = raw.deserialize()?;
+    let ssc = raw.deserialize()?;
+    let stakes = raw.deserialize()?;
 
     Ok(ChainStateFile {
         parent,
@@ -229,6 +270,11 @@ pub fn decode_chain_state_file<R: Read>(file: &mut R) -> Result<ChainStateFile>
         spent_txos,
         removed_utxos,
         added_utxos,
+        delegation,
+        pending_delegation,
+        update,
+        ssc,
+        stakes,
     })
 }
 
@@ -311,3 +357,18 @@ pub fn restore_chain_state(
         return Ok(chain_state);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn previous_versions_are_unsupported() {
+        let mut bytes = vec![];
+        magic::write_header(&mut bytes, FILE_TYPE, 3).unwrap();
+        match decode_chain_state_file(&mut &bytes[..]) {
+            Err(Error::ChainStateVersionUnsupported(3)) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}
@@ -43,20 +43,25 @@ pub fn epoch_create(
     let mut rp = reffile::Lookup::new();
     let mut reader = packreader_init(&storage.config, packref);
 
-    let mut current_slotid = BlockDate::Boundary(epochid);
+    // The refpack has the boundary block first, then a block (or a
+    // missing hash) per slot.
+    let mut next_index = 0;
     let mut last_block = None;
     while let Some(rblk) = packreader_block_next(&mut reader).unwrap() {
         let blk = rblk.decode().unwrap();
         let hdr = blk.header();
         let hash = hdr.compute_hash();
-        let blockdate = hdr.blockdate();
+        let index = match hdr.blockdate() {
+            BlockDate::Boundary(_) => 0,
+            BlockDate::Normal(slot) => slot.slotid as usize + 1,
+        };
 
-        while current_slotid != blockdate {
+        while next_index < index {
             rp.append_missing_hash();
-            current_slotid = current_slotid.next();
+            next_index += 1;
         }
         rp.append_hash(header_to_blockhash(&hash));
-        current_slotid = current_slotid.next();
+        next_index += 1;
 
         last_block = Some(hash);
     }
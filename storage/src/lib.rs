@@ -2,6 +2,7 @@
 extern crate log;
 extern crate cardano;
 extern crate cbor_event;
+extern crate cryptoxide;
 extern crate rand;
 extern crate storage_units;
 
@@ -13,11 +14,13 @@ pub mod pack;
 pub mod refpack;
 pub mod tag;
 pub mod types;
+pub mod utxo_snapshot;
 use std::{fs, io, result};
 
 pub use config::StorageConfig;
 
 use cardano::block::{Block, BlockDate, EpochId, HeaderHash, RawBlock, SlotId};
+use cardano::tx::TxoPointer;
 use std::{collections::BTreeMap, error, fmt};
 
 use storage_units::utils::error::StorageError;
@@ -45,6 +48,16 @@ pub enum Error {
     EpochSlotRewind(EpochId, SlotId),
     EpochChainInvalid(BlockDate, HeaderHash, HeaderHash),
     NoSuchTag,
+
+    // ** Chain state errors
+    /// the chain state file was written by an older version, whose
+    /// state cannot be completed: the chain state must be rebuilt
+    ChainStateVersionUnsupported(magic::Version),
+
+    // ** UTxO snapshot errors
+    UtxoSnapshotAtGenesis,
+    UtxoSnapshotUnsorted(TxoPointer),
+    UtxoSnapshotCorrupted,
 }
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
@@ -80,6 +93,10 @@ impl fmt::Display for Error {
             Error::EpochSlotRewind(eid, sid) => write!(f, "Cannot pack block {} because is prior to {} already packed", sid, eid),
             Error::EpochChainInvalid(bd, rhh, ehh) => write!(f, "Cannot pack block {} ({}) because it does not follow the blockchain hash (expected: {})", bd, ehh, rhh),
             Error::NoSuchTag => write!(f, "Tag not found"),
+            Error::ChainStateVersionUnsupported(v) => write!(f, "Unsupported chain state version `{}`, rebuild required", v),
+            Error::UtxoSnapshotAtGenesis => write!(f, "Cannot take a UTxO snapshot of the genesis state"),
+            Error::UtxoSnapshotUnsorted(ptr) => write!(f, "UTxO snapshot entry {} is not sorted", ptr),
+            Error::UtxoSnapshotCorrupted => write!(f, "UTxO snapshot does not match its checksum"),
         }
     }
 }
@@ -96,6 +113,10 @@ impl error::Error for Error {
             Error::EpochSlotRewind(_, _) => None,
             Error::EpochChainInvalid(_, _, _) => None,
             Error::NoSuchTag => None,
+            Error::ChainStateVersionUnsupported(_) => None,
+            Error::UtxoSnapshotAtGenesis => None,
+            Error::UtxoSnapshotUnsorted(_) => None,
+            Error::UtxoSnapshotCorrupted => None,
         }
     }
 }
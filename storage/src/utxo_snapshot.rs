@@ -0,0 +1,496 @@
+//! UTxO snapshots
+//!
+//! A snapshot is the full UTxO set at a given block, in a standalone
+//! format that does not depend on the rest of the storage, so that a new
+//! instance can start from a trusted snapshot instead of replaying the
+//! chain, and so that the snapshots of different implementations can be
+//! compared. After the magic header, a snapshot is made of the following
+//! CBOR items:
+//!
+//! * `[tip hash, epoch, slot]`, the tip being the block the snapshot was
+//!   taken at, and the slot 0 for a boundary block or the slot number
+//!   plus one for a main block;
+//! * the rest of the chain state at the tip, needed to verify the blocks
+//!   following it (see `TipState`);
+//! * an indefinite array of the `[txo pointer, tx output]` entries, in
+//!   strictly increasing order of txo pointer;
+//! * `[number of entries, checksum]`, the checksum being the Blake2b-256
+//!   hash of the concatenated encodings of the entries.
+//!
+//! The checksum only depends on the UTxO set, and can be computed with
+//! `utxos_checksum` to compare UTxO sets without writing snapshots. The
+//! stake distribution is not stored, as it is derived from the UTxOs.
+
+use super::{Error, Result};
+use cardano::address::StakeholderId;
+use cardano::block::ssc_state::SscState;
+use cardano::block::stake_state::{CrucialStakes, StakeState};
+use cardano::block::update_state::UpdateState;
+use cardano::block::{
+    BlockDate, ChainDifficulty, ChainState, Delegation, EpochSlotId, HeaderHash, Utxos,
+};
+use cardano::config::GenesisData;
+use cardano::hash::Blake2b256;
+use cardano::tx::{TxOut, TxoPointer};
+use cbor_event::{de, se, Len, Special, Type};
+use cryptoxide::blake2b::Blake2b;
+use cryptoxide::digest::Digest;
+use std::io::{BufRead, BufReader, Read, Write};
+use storage_units::utils::magic;
+
+const FILE_TYPE: magic::FileType = 0x55545853; // = UTXS
+const VERSION: magic::Version = 1;
+
+/// The chain state at the tip of a snapshot, but the UTxOs and the
+/// stake distribution they imply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TipState {
+    pub last_boundary_block: Option<HeaderHash>,
+    pub slot_leaders: Option<Vec<StakeholderId>>,
+    pub chain_length: u64,
+    pub chain_difficulty: ChainDifficulty,
+    pub delegation: Delegation,
+    pub pending_delegation: Delegation,
+    pub update: UpdateState,
+    pub ssc: SscState,
+    pub crucial_stakes: Option<CrucialStakes>,
+    pub nr_transactions: u64,
+    pub spent_txos: u64,
+}
+
+impl<'a> From<&'a ChainState> for TipState {
+    fn from(chain_state: &'a ChainState) -> Self {
+        TipState {
+            last_boundary_block: chain_state.last_boundary_block.clone(),
+            slot_leaders: chain_state.slot_leaders.clone(),
+            chain_length: chain_state.chain_length,
+            chain_difficulty: chain_state.chain_difficulty,
+            delegation: chain_state.delegation.clone(),
+            pending_delegation: chain_state.pending_delegation.clone(),
+            update: chain_state.update.clone(),
+            ssc: chain_state.ssc.clone(),
+            crucial_stakes: chain_state.stakes.crucial.clone(),
+            nr_transactions: chain_state.nr_transactions,
+            spent_txos: chain_state.spent_txos,
+        }
+    }
+}
+
+impl se::Serialize for TipState {
+    fn serialize<'se, W: Write>(
+        &self,
+        serializer: &'se mut se::Serializer<W>,
+    ) -> cbor_event::Result<&'se mut se::Serializer<W>> {
+        let serializer = serializer
+            .write_array(Len::Len(11))?
+            .serialize(&self.last_boundary_block)?;
+        // encoded as an `Option`
+        let serializer = match &self.slot_leaders {
+            None => serializer.write_array(Len::Len(0))?,
+            Some(slot_leaders) => se::serialize_fixed_array(
+                slot_leaders.iter(),
+                serializer.write_array(Len::Len(1))?,
+            )?,
+        };
+        let serializer = serializer
+            .serialize(&self.chain_length)?
+            .serialize(&self.chain_difficulty)?;
+        let serializer = se::serialize_fixed_map(self.delegation.iter(), serializer)?;
+        let serializer = se::serialize_fixed_map(self.pending_delegation.iter(), serializer)?;
+        serializer
+            .serialize(&self.update)?
+            .serialize(&self.ssc)?
+            .serialize(&self.crucial_stakes)?
+            .serialize(&self.nr_transactions)?
+            .serialize(&self.spent_txos)
+    }
+}
+
+impl de::Deserialize for TipState {
+    fn deserialize<R: BufRead>(raw: &mut de::Deserializer<R>) -> cbor_event::Result<Self> {
+        raw.tuple(11, "utxo snapshot tip state")?;
+        Ok(TipState {
+            last_boundary_block: raw.deserialize()?,
+            slot_leaders: raw.deserialize()?,
+            chain_length: raw.deserialize()?,
+            chain_difficulty: raw.deserialize()?,
+            delegation: raw.deserialize()?,
+            pending_delegation: raw.deserialize()?,
+            update: raw.deserialize()?,
+            ssc: raw.deserialize()?,
+            crucial_stakes: raw.deserialize()?,
+            nr_transactions: raw.deserialize()?,
+            spent_txos: raw.deserialize()?,
+        })
+    }
+}
+
+/// The content of a snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxoSnapshot {
+    pub tip: HeaderHash,
+    pub date: BlockDate,
+    pub state: TipState,
+    pub utxos: Utxos,
+    pub checksum: Blake2b256,
+}
+
+/// Computes the checksum of the entries of a UTxO set, as the one
+/// terminating its snapshots.
+struct Checksum(Blake2b);
+
+impl Checksum {
+    fn new() -> Self {
+        Checksum(Blake2b::new(Blake2b256::HASH_SIZE))
+    }
+
+    /// Add an entry and return its encoding.
+    fn add(&mut self, ptr: &TxoPointer, output: &TxOut) -> Result<Vec<u8>> {
+        let mut serializer = se::Serializer::new_vec();
+        serializer
+            .write_array(Len::Len(2))?
+            .serialize(ptr)?
+            .serialize(output)?;
+        let bytes = serializer.finalize();
+        self.0.input(&bytes);
+        Ok(bytes)
+    }
+
+    fn result(mut self) -> Blake2b256 {
+        let mut hash = [0; Blake2b256::HASH_SIZE];
+        self.0.result(&mut hash);
+        Blake2b256::from(hash)
+    }
+}
+
+/// The checksum of the snapshots of `utxos`.
+pub fn utxos_checksum(utxos: &Utxos) -> Result<Blake2b256> {
+    let mut checksum = Checksum::new();
+    for (ptr, output) in utxos.iter() {
+        checksum.add(ptr, output)?;
+    }
+    Ok(checksum.result())
+}
+
+/// Write the snapshot of the UTxOs of `chain_state`, which must be at a
+/// block, and return its checksum.
+pub fn write_utxo_snapshot<W: Write>(
+    chain_state: &ChainState,
+    writer: &mut W,
+) -> Result<Blake2b256> {
+    match chain_state.last_date {
+        Some(date) => write_entries(
+            &chain_state.last_block,
+            date,
+            &TipState::from(chain_state),
+            chain_state.utxos.iter(),
+            writer,
+        ),
+        None => Err(Error::UtxoSnapshotAtGenesis),
+    }
+}
+
+fn write_entries<'a, W, I>(
+    tip: &HeaderHash,
+    date: BlockDate,
+    state: &TipState,
+    entries: I,
+    writer: &mut W,
+) -> Result<Blake2b256>
+where
+    W: Write,
+    I: Iterator<Item = (&'a TxoPointer, &'a TxOut)>,
+{
+    magic::write_header(writer, FILE_TYPE, VERSION)?;
+
+    let mut serializer = se::Serializer::new(writer);
+    serializer
+        .write_array(Len::Len(3))?
+        .serialize(tip)?
+        .serialize(&date.get_epochid())?
+        .serialize(&match date {
+            BlockDate::Boundary(_) => 0u16,
+            BlockDate::Normal(s) => s.slotid + 1,
+        })?
+        .serialize(state)?;
+
+    let mut nr_utxos = 0u64;
+    let mut checksum = Checksum::new();
+    serializer.write_array(Len::Indefinite)?;
+    for (ptr, output) in entries {
+        serializer.write_raw_bytes(&checksum.add(ptr, output)?)?;
+        nr_utxos += 1;
+    }
+    serializer.write_special(Special::Break)?;
+
+    let checksum = checksum.result();
+    serializer
+        .write_array(Len::Len(2))?
+        .serialize(&nr_utxos)?
+        .serialize(&checksum)?;
+
+    Ok(checksum)
+}
+
+/// Read a snapshot, checking that its entries are sorted and match its
+/// checksum.
+pub fn read_utxo_snapshot<R: Read>(reader: &mut R) -> Result<UtxoSnapshot> {
+    magic::check_header(reader, FILE_TYPE, VERSION, VERSION)?;
+
+    let mut raw = de::Deserializer::from(BufReader::new(reader));
+
+    raw.tuple(3, "utxo snapshot tip")?;
+    let tip = raw.deserialize()?;
+    let epoch = raw.deserialize()?;
+    let date = match raw.deserialize()? {
+        0 => BlockDate::Boundary(epoch),
+        n => BlockDate::Normal(EpochSlotId {
+            epoch,
+            slotid: n - 1,
+        }),
+    };
+    let state = raw.deserialize()?;
+
+    let mut utxos = Utxos::new();
+    let mut checksum = Checksum::new();
+    if raw.array()? != Len::Indefinite {
+        return Err(Error::CborBlockError(cbor_event::Error::CustomError(
+            "expected an indefinite array of utxo snapshot entries".to_string(),
+        )));
+    }
+    while raw.cbor_type()? != Type::Special {
+        raw.tuple(2, "utxo snapshot entry")?;
+        let ptr: TxoPointer = raw.deserialize()?;
+        let output: TxOut = raw.deserialize()?;
+        checksum.add(&ptr, &output)?;
+        if let Some((last, _)) = utxos.iter().next_back() {
+            if last >= &ptr {
+                return Err(Error::UtxoSnapshotUnsorted(ptr));
+            }
+        }
+        utxos.insert(ptr, output);
+    }
+    if raw.special()? != Special::Break {
+        return Err(Error::CborBlockError(cbor_event::Error::CustomError(
+            "expected the end of the utxo snapshot entries".to_string(),
+        )));
+    }
+
+    raw.tuple(2, "utxo snapshot checksum")?;
+    let nr_utxos: u64 = raw.deserialize()?;
+    let expected: Blake2b256 = raw.deserialize()?;
+    let checksum = checksum.result();
+    if nr_utxos != utxos.len() as u64 || checksum != expected {
+        return Err(Error::UtxoSnapshotCorrupted);
+    }
+
+    Ok(UtxoSnapshot {
+        tip,
+        date,
+        state,
+        utxos,
+        checksum,
+    })
+}
+
+/// Make the chain state at the tip of a snapshot, from which the blocks
+/// following the tip can be verified.
+pub fn import_utxo_snapshot<R: Read>(
+    genesis_data: &GenesisData,
+    reader: &mut R,
+) -> Result<ChainState> {
+    let snapshot = read_utxo_snapshot(reader)?;
+
+    debug!(
+        "importing utxo snapshot at {} ({}), {} utxos, checksum {}",
+        snapshot.tip,
+        snapshot.date,
+        snapshot.utxos.len(),
+        snapshot.checksum
+    );
+
+    let state = snapshot.state;
+    let mut chain_state = ChainState::new(genesis_data);
+    chain_state.last_block = snapshot.tip;
+    chain_state.last_date = Some(snapshot.date);
+    chain_state.last_boundary_block = state.last_boundary_block;
+    chain_state.slot_leaders = state.slot_leaders;
+    chain_state.chain_length = state.chain_length;
+    chain_state.chain_difficulty = state.chain_difficulty;
+    chain_state.delegation = state.delegation;
+    chain_state.pending_delegation = state.pending_delegation;
+    chain_state.update = state.update;
+    chain_state.ssc = state.ssc;
+    chain_state.stakes = StakeState::from_utxos(genesis_data, &snapshot.utxos);
+    chain_state.stakes.crucial = state.crucial_stakes;
+    chain_state.nr_transactions = state.nr_transactions;
+    chain_state.spent_txos = state.spent_txos;
+    chain_state.utxos = snapshot.utxos;
+    Ok(chain_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cardano::address;
+    use cardano::block::builder::{self, BlockIssuer, MainBlockBuilder};
+    use cardano::block::fts::SharedSeed;
+    use cardano::block::update::BlockVersionData;
+    use cardano::coin::Coin;
+    use cardano::config::ProtocolMagic;
+    use cardano::fee::LinearFee;
+    use cardano::hdwallet::{Seed, XPrv};
+    use cardano::redeem;
+    use cardano::tx::{self, Tx, TxAux, TxInWitness, TxWitness};
+    use std::collections::BTreeMap;
+    use std::time::SystemTime;
+
+    fn genesis_data() -> GenesisData {
+        GenesisData {
+            genesis_prev: HeaderHash::new(b"genesis"),
+            epoch_stability_depth: 10,
+            vss_min_ttl: 2,
+            vss_max_ttl: 6,
+            start_time: SystemTime::UNIX_EPOCH,
+            protocol_magic: ProtocolMagic::from(633343913),
+            block_version_data: BlockVersionData::mainnet(20_000, LinearFee::default()),
+            avvm_distr: BTreeMap::new(),
+            non_avvm_balances: BTreeMap::new(),
+            boot_stakeholders: BTreeMap::new(),
+            fts_seed: SharedSeed::default(),
+        }
+    }
+
+    fn chain_state(genesis_data: &GenesisData) -> ChainState {
+        let mut chain_state = ChainState::new(genesis_data);
+        chain_state.last_block = HeaderHash::new(b"tip");
+        chain_state.last_date = Some(BlockDate::Normal(EpochSlotId {
+            epoch: 3,
+            slotid: 12,
+        }));
+        for i in 0..5u8 {
+            let xprv = XPrv::generate_from_seed(&Seed::from_bytes([i; 32]));
+            let address = address::ExtendedAddr::new(
+                address::AddrType::ATPubKey,
+                address::SpendingData::PubKeyASD(xprv.public()),
+                address::Attributes::new_bootstrap_era(None, genesis_data.protocol_magic.into()),
+            );
+            chain_state.utxos.insert(
+                TxoPointer::new(Blake2b256::new(&[i]), u32::from(i)),
+                TxOut::new(address, Coin::new(1000 * u64::from(i)).unwrap()),
+            );
+        }
+        chain_state.stakes = StakeState::from_utxos(genesis_data, &chain_state.utxos);
+        chain_state
+    }
+
+    #[test]
+    fn snapshots_are_round_tripped() {
+        let genesis_data = genesis_data();
+        let chain_state = chain_state(&genesis_data);
+
+        let mut bytes = vec![];
+        let checksum = write_utxo_snapshot(&chain_state, &mut bytes).unwrap();
+        assert_eq!(checksum, utxos_checksum(&chain_state.utxos).unwrap());
+
+        let snapshot = read_utxo_snapshot(&mut &bytes[..]).unwrap();
+        assert_eq!(snapshot.tip, chain_state.last_block);
+        assert_eq!(Some(snapshot.date), chain_state.last_date);
+        assert_eq!(snapshot.checksum, checksum);
+
+        let imported = import_utxo_snapshot(&genesis_data, &mut &bytes[..]).unwrap();
+        assert_eq!(imported, chain_state);
+
+        match write_utxo_snapshot(&ChainState::new(&genesis_data), &mut vec![]) {
+            Err(Error::UtxoSnapshotAtGenesis) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn blocks_are_verified_after_an_import() {
+        let mut genesis_data = genesis_data();
+        let redeem_key = redeem::PrivateKey::generate(&[7; 32]).unwrap();
+        genesis_data
+            .avvm_distr
+            .insert(redeem_key.public(), Coin::new(1000).unwrap());
+        let pm = genesis_data.protocol_magic;
+        let leader = XPrv::generate_from_seed(&Seed::from_bytes([1; 32]));
+        let slot = |slotid| EpochSlotId { epoch: 0, slotid };
+
+        let mut chain_state = ChainState::new(&genesis_data);
+        // the slot leaders of the test blocks are not elected
+        chain_state.ssc.seed = None;
+        let slot_leaders = vec![StakeholderId::new(&leader.public()); 100];
+        let blk = builder::make_boundary_block(&chain_state, 0, slot_leaders);
+        chain_state
+            .verify_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+        let blk = MainBlockBuilder::new(slot(0))
+            .make_block(&chain_state, &BlockIssuer::SlotLeader(&leader));
+        chain_state
+            .verify_block(&blk.header().compute_hash(), &blk)
+            .unwrap();
+
+        let mut bytes = vec![];
+        write_utxo_snapshot(&chain_state, &mut bytes).unwrap();
+        let mut imported = import_utxo_snapshot(&genesis_data, &mut &bytes[..]).unwrap();
+        assert_eq!(imported, chain_state);
+
+        // the next block redeems the AVVM utxo
+        let (id, _) = tx::redeem_pubkey_to_txid(&redeem_key.public(), pm);
+        let tx = Tx::new_with(
+            vec![TxoPointer::new(id, 0)],
+            vec![TxOut::new(
+                address::ExtendedAddr::new(
+                    address::AddrType::ATPubKey,
+                    address::SpendingData::PubKeyASD(leader.public()),
+                    address::Attributes::new_single_key(&leader.public(), None, pm.into()),
+                ),
+                Coin::new(1000).unwrap(),
+            )],
+        );
+        let witness = TxInWitness::new_redeem_pk(pm, &redeem_key, &tx.id());
+        let mut builder = MainBlockBuilder::new(slot(1));
+        builder.add_tx(TxAux::new(tx, TxWitness::from(vec![witness])));
+        let blk = builder.make_block(&imported, &BlockIssuer::SlotLeader(&leader));
+        let hash = blk.header().compute_hash();
+        imported.verify_block(&hash, &blk).unwrap();
+        chain_state.verify_block(&hash, &blk).unwrap();
+        assert_eq!(imported, chain_state);
+        assert_eq!(imported.chain_length, 3);
+        assert_eq!(u64::from(imported.chain_difficulty), 2);
+    }
+
+    #[test]
+    fn corrupted_snapshots_are_rejected() {
+        let chain_state = chain_state(&genesis_data());
+        let mut bytes = vec![];
+        write_utxo_snapshot(&chain_state, &mut bytes).unwrap();
+
+        // flip a bit of the last byte of the checksum
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        match read_utxo_snapshot(&mut &corrupted[..]) {
+            Err(Error::UtxoSnapshotCorrupted) => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        // swap the first two entries
+        let mut entries: Vec<_> = chain_state.utxos.iter().collect();
+        entries.swap(0, 1);
+        let mut swapped = vec![];
+        write_entries(
+            &chain_state.last_block,
+            chain_state.last_date.unwrap(),
+            &TipState::from(&chain_state),
+            entries.iter().cloned(),
+            &mut swapped,
+        )
+        .unwrap();
+        match read_utxo_snapshot(&mut &swapped[..]) {
+            Err(Error::UtxoSnapshotUnsorted(ref ptr)) if ptr == entries[1].0 => {}
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+}